@@ -1,13 +1,13 @@
 use frame_benchmarking::whitelisted_caller;
 use sc_service::ChainType;
 use sp_consensus_aura::sr25519::AuthorityId as AuraId;
-use sp_core::{sr25519, Pair, Public, H160, U256};
+use sp_core::{sr25519, Get, Pair, Public, H160, U256};
 use sp_finality_grandpa::AuthorityId as GrandpaId;
 use sp_runtime::traits::{IdentifyAccount, Verify};
 use std::{collections::BTreeMap, str::FromStr};
 use webb_runtime::{
-	AccountId, AuraConfig, BalancesConfig, EVMConfig, GenesisConfig, GrandpaConfig, Signature, SudoConfig,
-	SystemConfig, WASM_BINARY,
+	AccountId, AuraConfig, BalancesConfig, EVMConfig, GenesisConfig, GrandpaConfig, Mixer, MixerConfig, MixerRewards,
+	MixerSizes, NativeCurrencyId, Signature, SudoConfig, SystemConfig, WASM_BINARY,
 };
 
 // The URL for the telemetry server.
@@ -62,6 +62,8 @@ pub fn development_config() -> Result<ChainSpec, String> {
 					get_account_id_from_seed::<sr25519::Public>("Alice//stash"),
 					get_account_id_from_seed::<sr25519::Public>("Bob//stash"),
 					whitelisted_caller(),
+					// funds the mixer deposit rewards
+					MixerRewards::account_id(),
 				],
 				true,
 			)
@@ -166,5 +168,15 @@ fn testnet_genesis(
 		dynamic_fee: Default::default(),
 		ethereum: Default::default(),
 		evm: EVMConfig { accounts: evm_accounts },
+		mixer: MixerConfig {
+			// One mixer for each of the default sizes, all sharing the default key,
+			// which is generated when the genesis is built
+			keys: vec![None],
+			mixers: MixerSizes::get()
+				.into_iter()
+				.map(|size| (NativeCurrencyId::get(), size, Mixer::default_setup(), 0))
+				.collect(),
+		},
+		treasury: Default::default(),
 	}
 }
@@ -13,11 +13,12 @@ use sp_api::ProvideRuntimeApi;
 use sp_blockchain::{Error as BlockChainError, HeaderBackend, HeaderMetadata};
 use sp_transaction_pool::TransactionPool;
 use std::collections::BTreeMap;
-use webb_runtime::{opaque::Block, AccountId, Balance, BlockNumber, Hash, Index};
+use webb_runtime::{opaque::Block, AccountId, Balance, BlockNumber, CurrencyId, Hash, Index, Runtime};
 
 use fc_rpc::{OverrideHandle, RuntimeApiStorageOverride, SchemaV1Override, StorageOverride};
 use jsonrpc_pubsub::manager::SubscriptionManager;
 use merkle_rpc::{MerkleApi, MerkleClient};
+use mixer_rpc::{MixerApi, MixerClient};
 use pallet_ethereum::EthereumStorageSchema;
 use sc_finality_grandpa::{FinalityProofProvider, GrandpaJustificationStream, SharedAuthoritySet, SharedVoterState};
 use sc_finality_grandpa_rpc::GrandpaRpcHandler;
@@ -95,7 +96,9 @@ where
 	C::Api: pallet_transaction_payment_rpc::TransactionPaymentRuntimeApi<Block, Balance>,
 	C::Api: fp_rpc::EthereumRuntimeRPCApi<Block>,
 	C::Api: BlockBuilder<Block>,
-	C::Api: merkle::MerkleApi<Block>,
+	C::Api: merkle::MerkleApi<Block, BlockNumber>,
+	C::Api: mixer::MixerApi<Block, AccountId, mixer::WithdrawProof<Runtime>, CurrencyId, Balance, BlockNumber>,
+	C::Api: sp_api::Metadata<Block>,
 	P: TransactionPool<Block = Block> + 'static,
 	SC: SelectChain<Block> + 'static,
 	B: sc_client_api::Backend<Block> + Send + Sync + 'static,
@@ -200,6 +203,9 @@ where
 	)));
 
 	io.extend_with(MerkleApi::to_delegate(MerkleClient::new(client.clone())));
+	io.extend_with(MixerApi::to_delegate(
+		MixerClient::<_, _, mixer::WithdrawProof<Runtime>>::new(client.clone()),
+	));
 
 	io.extend_with(sc_finality_grandpa_rpc::GrandpaApi::to_delegate(
 		GrandpaRpcHandler::new(
@@ -23,7 +23,6 @@
 //! - Depositing some currency into the mixer.
 //! - Withdrawing the deposit from the mixer.
 //! - Stopping mixer operations.
-//! - Transfering the admin of the mixer.
 //!
 //! ### Terminology
 //!
@@ -38,9 +37,51 @@
 //! - `deposit` - Deposit a fixed amount of cryptocurrency into the mixer.
 //! - `withdraw` - Provide a zero-knowladge proof of the deposit and withdraw
 //!   from the mixer.
-//! - `set_stopped` - Stops the operation of all mixers.
-//! - `transfer_admin` - Transfers the admin role from sender to specified
-//!   account.
+//! - `set_all_withdraw_only` - Puts every mixer in or out of withdraw-only
+//!   mode, so deposits can be halted without trapping deposited funds.
+//! - `set_mixer_stopped` - Stops or restarts a single mixer. A stopped mixer
+//!   refuses deposits and withdrawals.
+//! - `set_withdraw_only` - Puts a single mixer in or out of withdraw-only mode,
+//!   in which deposits are refused and valid withdrawals keep working.
+//! - `set_max_fee` - Sets the highest relayer fee a mixer accepts on
+//!   withdrawals. It is zero until set, so new mixers only accept withdrawals
+//!   without a fee.
+//! - `set_limits` - Sets the maximum commitments per deposit, deposits per
+//!   block and TVL of a mixer.
+//! - `set_protocol_fee` - Sets or removes the protocol fee of a mixer.
+//!
+//! Creating, stopping and configuring mixers is restricted to the
+//! `AdminOrigin`, e.g. root, a collective or a multisig.
+//!
+//! ### Relayer fees
+//!
+//! A withdraw proof can carry a `fee`, paid out of the deposit to the relayer,
+//! and a `refund`, paid in the native currency by the sender to the recipient.
+//! Both are bound into the proof through the relayer public input, see
+//! [`Pallet::relayer_input`].
+//!
+//! Like the mixer pallet, the recipient and relayer inputs are mapped to field
+//! elements with `public_input_to_field` before the proof is verified.
+//!
+//! ### Protocol fees
+//!
+//! A mixer can take a [`ProtocolFee`] in its asset on every deposit, sent to
+//! the `Treasury` account. It is charged on top of the deposit size, which is
+//! what the mixer keeps for each commitment, so withdrawals are unaffected.
+//! The fees taken so far are kept per mixer in `AccruedFees`.
+//!
+//! ### Limits
+//!
+//! Each mixer can be given [`MixerLimits`] on the commitments per deposit
+//! call, the deposits per block and its TVL, which `deposit` enforces. None
+//! are set by default.
+//!
+//! ### Runtime API
+//!
+//! - `AssetsMixerApi::mixer_state` - Returns whether a mixer is active, in
+//!   withdraw-only mode or stopped.
+//! - `AssetsMixerApi::mixer_limits` - Returns the deposit and TVL limits of a
+//!   mixer.
 
 #![cfg_attr(not(feature = "std"), no_std)]
 
@@ -57,7 +98,7 @@ use bulletproofs::BulletproofGens;
 use codec::{Decode, Encode};
 use frame_support::{
 	dispatch, ensure,
-	traits::{tokens::fungibles, Get, ReservableCurrency},
+	traits::{tokens::fungibles, Currency, ExistenceRequirement, Get, ReservableCurrency},
 	weights::Weight,
 	PalletId,
 };
@@ -65,13 +106,17 @@ use frame_system::ensure_signed;
 use fungibles::{Inspect, Transfer};
 use merkle::{
 	utils::{
-		keys::ScalarBytes,
-		permissions::ensure_admin,
+		keys::{public_input_to_field, ScalarBytes},
 		setup::{Backend, Curve, HashFunction, Setup},
 	},
-	Pallet as MerklePallet, Tree as TreeTrait,
+	Pallet as MerklePallet, SpendsNullifier, Tree as TreeTrait,
+};
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+use sp_runtime::{
+	traits::{AccountIdConversion, BlakeTwo256, Hash, Saturating, Zero},
+	Permill,
 };
-use sp_runtime::traits::{AccountIdConversion, Saturating, Zero};
 use sp_std::prelude::*;
 use traits::ExtendedMixer;
 use weights::WeightInfo;
@@ -106,13 +151,15 @@ pub mod pallet {
 		/// The small deposit length
 		#[pallet::constant]
 		type DepositLength: Get<Self::BlockNumber>;
-		/// Default admin key
-		#[pallet::constant]
-		type DefaultAdmin: Get<Self::AccountId>;
+		/// Origin that can create, stop and configure the mixers
+		type AdminOrigin: EnsureOrigin<Self::Origin>;
 		/// Weight information for extrinsics in this pallet
 		type WeightInfo: WeightInfo;
 		// Available mixes sizes (Size is determend by the deposit amount)
 		type MixerSizes: Get<Vec<BalanceOf<Self>>>;
+		/// Account receiving the protocol fees, e.g. the account of
+		/// `pallet_treasury`
+		type Treasury: Get<Self::AccountId>;
 	}
 
 	/// Flag indicating if the mixer trees are created
@@ -135,21 +182,95 @@ pub mod pallet {
 	#[pallet::getter(fn mixer_group_ids)]
 	pub type MixerTreeIds<T: Config> = StorageValue<_, Vec<T::TreeId>, ValueQuery>;
 
-	/// Administrator of the mixer pallet.
-	/// This account that can stop/start operations of the mixer
-	#[pallet::storage]
-	#[pallet::getter(fn admin)]
-	pub type Admin<T: Config> = StorageValue<_, T::AccountId, ValueQuery>;
-
 	/// The TVL per group
 	#[pallet::storage]
 	#[pallet::getter(fn total_value_locked)]
 	pub type TotalValueLocked<T: Config> = StorageMap<_, Blake2_128Concat, T::TreeId, BalanceOf<T>, ValueQuery>;
 
+	/// The highest relayer fee each mixer accepts, zero until set by the
+	/// `AdminOrigin`
+	#[pallet::storage]
+	#[pallet::getter(fn max_fee)]
+	pub type MaxFees<T: Config> = StorageMap<_, Blake2_128Concat, T::TreeId, BalanceOf<T>, ValueQuery>;
+
+	/// The deposit and TVL limits of each mixer
+	#[pallet::storage]
+	#[pallet::getter(fn limits)]
+	pub type Limits<T: Config> = StorageMap<_, Blake2_128Concat, T::TreeId, MixerLimits<BalanceOf<T>>, ValueQuery>;
+
+	/// The protocol fee each mixer takes on deposits, if any
+	#[pallet::storage]
+	#[pallet::getter(fn protocol_fee)]
+	pub type ProtocolFees<T: Config> =
+		StorageMap<_, Blake2_128Concat, T::TreeId, ProtocolFee<BalanceOf<T>>, OptionQuery>;
+
+	/// The sum of the protocol fees each mixer has taken
+	#[pallet::storage]
+	#[pallet::getter(fn accrued_fees)]
+	pub type AccruedFees<T: Config> = StorageMap<_, Blake2_128Concat, T::TreeId, BalanceOf<T>, ValueQuery>;
+
+	/// The last block each mixer had deposits in and their number in it
+	#[pallet::storage]
+	#[pallet::getter(fn block_deposits)]
+	pub type BlockDeposits<T: Config> = StorageMap<_, Blake2_128Concat, T::TreeId, (T::BlockNumber, u32), OptionQuery>;
+
+	/// Whether each mixer only accepts withdrawals
+	#[pallet::storage]
+	#[pallet::getter(fn withdraw_only)]
+	pub type WithdrawOnly<T: Config> = StorageMap<_, Blake2_128Concat, T::TreeId, bool, ValueQuery>;
+
+	/// The storage layout of the pallet, so each migration runs once
+	#[pallet::storage]
+	pub type StorageVersion<T: Config> = StorageValue<_, Releases, ValueQuery>;
+
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	#[pallet::metadata(T::AccountId = "AccountId", T::TreeId = "TreeId", BalanceOf<T> = "Balance")]
 	pub enum Event<T: Config> {
+		/// Mixer was stopped or restarted
+		StoppedSet(
+			/// Id of the tree
+			T::TreeId,
+			/// Whether the mixer is stopped
+			bool,
+		),
+		/// Mixer entered or left withdraw-only mode
+		WithdrawOnlySet(
+			/// Id of the tree
+			T::TreeId,
+			/// Whether the mixer only accepts withdrawals
+			bool,
+		),
+		/// Highest relayer fee of the mixer was set
+		MaxFeeSet(
+			/// Id of the tree
+			T::TreeId,
+			/// New highest relayer fee of the mixer
+			BalanceOf<T>,
+		),
+		/// Deposit and TVL limits of the mixer were set
+		LimitsSet(
+			/// Id of the tree
+			T::TreeId,
+			/// New limits of the mixer
+			MixerLimits<BalanceOf<T>>,
+		),
+		/// Protocol fee of the mixer was set or removed
+		ProtocolFeeSet(
+			/// Id of the tree
+			T::TreeId,
+			/// New protocol fee of the mixer
+			Option<ProtocolFee<BalanceOf<T>>>,
+		),
+		/// Protocol fee was sent to the treasury
+		ProtocolFeeTaken(
+			/// Id of the tree
+			T::TreeId,
+			/// Account id of the sender
+			T::AccountId,
+			/// Fee taken
+			BalanceOf<T>,
+		),
 		/// New deposit added to the specific mixer
 		Deposit(
 			/// Id of the tree
@@ -169,6 +290,8 @@ pub mod pallet {
 			T::AccountId,
 			/// Account id of the relayer
 			T::AccountId,
+			/// Fee paid to the relayer
+			BalanceOf<T>,
 			/// Merkle root
 			ScalarBytes,
 		),
@@ -190,6 +313,18 @@ pub mod pallet {
 		UnauthorizedCall,
 		/// Mixer is stopped
 		MixerStopped,
+		/// Mixer only accepts withdrawals
+		MixerWithdrawOnly,
+		/// Relayer fee is above the maximum of the mixer
+		FeeExceedsMaximum,
+		/// Maximum fee is above the deposit size of the mixer
+		InvalidMaxFee,
+		/// Deposit inserts more commitments than the mixer accepts per call
+		TooManyCommitments,
+		/// Mixer already took its maximum number of deposits in this block
+		BlockDepositLimitReached,
+		/// Deposit would take the TVL of the mixer above its maximum
+		TvlLimitExceeded,
 	}
 
 	#[pallet::pallet]
@@ -261,13 +396,17 @@ pub mod pallet {
 		/// Multiple deposits can be inserted together since `data_points` is an
 		/// array.
 		///
-		/// Fails in case the mixer is stopped or not initialized.
+		/// The sender also pays the protocol fee of the mixer, if any, for
+		/// each data point.
+		///
+		/// Fails in case the mixer is stopped, in withdraw-only mode or not
+		/// initialized, or if the deposit is above one of its limits.
 		///
 		/// Weights:
 		/// - Dependent on argument: `data_points`
 		///
 		/// - Base weight: 417_168_400_000
-		/// - DB weights: 8 reads, 5 writes
+		/// - DB weights: 11 reads, 9 writes
 		/// - Additional weights: 21_400_442_000 * data_points.len()
 		#[pallet::weight(<T as Config>::WeightInfo::deposit(data_points.len() as u32))]
 		pub fn deposit(
@@ -277,6 +416,7 @@ pub mod pallet {
 		) -> DispatchResultWithPostInfo {
 			let sender = ensure_signed(origin)?;
 			ensure!(!<MerklePallet<T>>::stopped(mixer_id), Error::<T>::MixerStopped);
+			ensure!(!Self::withdraw_only(mixer_id), Error::<T>::MixerWithdrawOnly);
 			// get mixer info, should always exist if the module is initialized
 			let mixer_info = Self::get_mixer(mixer_id)?;
 			// ensure the sender has enough balance to cover deposit
@@ -288,8 +428,13 @@ pub mod pallet {
 				.iter()
 				.map(|_| mixer_info.fixed_deposit_size)
 				.fold(Zero::zero(), |acc, elt| acc + elt);
-			println!("{:?}", deposit);
-			ensure!(balance >= deposit, Error::<T>::InsufficientBalance);
+			Self::ensure_within_limits(mixer_id, data_points.len() as u32, deposit)?;
+			let fee_per_deposit = Self::protocol_fee_per_deposit(mixer_id, mixer_info.fixed_deposit_size);
+			let fee: BalanceOf<T> = data_points
+				.iter()
+				.map(|_| fee_per_deposit)
+				.fold(Zero::zero(), |acc, elt| acc.saturating_add(elt));
+			ensure!(balance >= deposit.saturating_add(fee), Error::<T>::InsufficientBalance);
 			// transfer the deposit to the module and keep the account
 			let keep_alive = false;
 			T::AssetSystem::transfer(
@@ -299,9 +444,17 @@ pub mod pallet {
 				deposit,
 				keep_alive,
 			)?;
+			// the protocol fee goes to the treasury, not the mixer
+			if !fee.is_zero() {
+				T::AssetSystem::transfer(mixer_info.currency_id, &sender, &T::Treasury::get(), fee, keep_alive)?;
+				AccruedFees::<T>::mutate(mixer_id, |accrued| *accrued = accrued.saturating_add(fee));
+				Self::deposit_event(Event::ProtocolFeeTaken(mixer_id, sender.clone(), fee));
+			}
 			// update the total value locked
 			let tvl = Self::total_value_locked(mixer_id);
 			<TotalValueLocked<T>>::insert(mixer_id, tvl.saturating_add(deposit));
+			let in_block = Self::deposits_in_block(mixer_id).saturating_add(data_points.len() as u32);
+			BlockDeposits::<T>::insert(mixer_id, (<frame_system::Pallet<T>>::block_number(), in_block));
 			// add elements to the mixer group's merkle tree and save the leaves
 			T::Tree::add_members(Self::account_id(), mixer_id.into(), data_points.clone())?;
 
@@ -325,38 +478,32 @@ pub mod pallet {
 		#[pallet::weight(<T as Config>::WeightInfo::withdraw())]
 		pub fn withdraw(origin: OriginFor<T>, withdraw_proof: WithdrawProof<T>) -> DispatchResultWithPostInfo {
 			let sender = ensure_signed(origin)?;
-			ensure!(
-				!<MerklePallet<T>>::stopped(withdraw_proof.mixer_id),
-				Error::<T>::MixerStopped
-			);
-			let recipient = withdraw_proof.recipient.unwrap_or(sender.clone());
-			let relayer = withdraw_proof.relayer.unwrap_or(sender.clone());
-			// get mixer info, should fail if tree isn't initialized
-			let mixer_info = Self::get_mixer(withdraw_proof.mixer_id)?;
-			// check if the nullifier has been used
-			T::Tree::has_used_nullifier(withdraw_proof.mixer_id.into(), withdraw_proof.nullifier_hash.clone())?;
-			// Verify the zero-knowledge proof of membership provided
-			T::Tree::verify_zk(
-				withdraw_proof.mixer_id.into(),
-				withdraw_proof.cached_block,
-				withdraw_proof.cached_root.clone(),
-				withdraw_proof.comms,
-				withdraw_proof.nullifier_hash.clone(),
-				withdraw_proof.proof_bytes,
-				withdraw_proof.leaf_index_commitments,
-				withdraw_proof.proof_commitments,
-				recipient.encode().to_vec(),
-				relayer.encode().to_vec(),
-			)?;
-			// transfer the fixed deposit size to the sender
+			let recipient = withdraw_proof.recipient.clone().unwrap_or(sender.clone());
+			let relayer = withdraw_proof.relayer.clone().unwrap_or(sender.clone());
+			// run every check on the proof before touching any state
+			let mixer_info = Self::validate_withdraw(&withdraw_proof, &recipient, &relayer)?;
+			// transfer the fixed deposit size, minus the relayer fee, to the recipient
+			let fee = withdraw_proof.fee;
 			let keep_alive = false;
 			T::AssetSystem::transfer(
 				mixer_info.currency_id,
 				&Self::account_id(),
 				&recipient,
-				mixer_info.fixed_deposit_size,
+				mixer_info.fixed_deposit_size.saturating_sub(fee),
 				keep_alive,
 			)?;
+			if !fee.is_zero() {
+				T::AssetSystem::transfer(mixer_info.currency_id, &Self::account_id(), &relayer, fee, keep_alive)?;
+			}
+			// the refund is paid by the sender, usually the relayer itself
+			if !withdraw_proof.refund.is_zero() {
+				T::Currency::transfer(
+					&sender,
+					&recipient,
+					withdraw_proof.refund,
+					ExistenceRequirement::AllowDeath,
+				)?;
+			}
 			// update the total value locked
 			let tvl = Self::total_value_locked(withdraw_proof.mixer_id);
 			<TotalValueLocked<T>>::insert(
@@ -375,6 +522,7 @@ pub mod pallet {
 				sender,
 				recipient,
 				relayer,
+				fee,
 				withdraw_proof.cached_root,
 			));
 			Ok(().into())
@@ -387,7 +535,7 @@ pub mod pallet {
 			setup: Setup,
 			size: BalanceOf<T>,
 		) -> DispatchResultWithPostInfo {
-			ensure_admin(origin, &Self::admin())?;
+			T::AdminOrigin::ensure_origin(origin)?;
 
 			<Self as ExtendedMixer<_>>::create_new(Self::account_id(), currency_id, setup, size)?;
 			Ok(().into())
@@ -401,15 +549,104 @@ pub mod pallet {
 			size: BalanceOf<T>,
 			key_id: T::KeyId,
 		) -> DispatchResultWithPostInfo {
-			ensure_admin(origin, &Self::admin())?;
+			T::AdminOrigin::ensure_origin(origin)?;
 
 			let tree_id = <Self as ExtendedMixer<_>>::create_new(Self::account_id(), currency_id, setup, size)?;
 			T::Tree::initialize_tree(tree_id, key_id)?;
 			Ok(().into())
 		}
 
-		/// Stops the operation of all the mixers managed by the pallet.
-		/// Can only be called by the admin or the root origin.
+		/// Sets the highest relayer fee the mixer with id of `mixer_id`
+		/// accepts. Can only be called by the `AdminOrigin`.
+		///
+		/// Fails if the fee is above the deposit size of the mixer.
+		///
+		/// Weights:
+		/// - Independent of the arguments.
+		///
+		/// - Base weight: 17_000_000
+		/// - DB weights: 1 read, 1 write
+		#[pallet::weight(<T as Config>::WeightInfo::set_max_fee())]
+		pub fn set_max_fee(
+			origin: OriginFor<T>,
+			mixer_id: T::TreeId,
+			max_fee: BalanceOf<T>,
+		) -> DispatchResultWithPostInfo {
+			T::AdminOrigin::ensure_origin(origin)?;
+			let mixer_info = MixerTrees::<T>::get(mixer_id);
+			ensure!(mixer_info.fixed_deposit_size > Zero::zero(), Error::<T>::NoMixerForId);
+			ensure!(max_fee <= mixer_info.fixed_deposit_size, Error::<T>::InvalidMaxFee);
+			MaxFees::<T>::insert(mixer_id, max_fee);
+			Self::deposit_event(Event::MaxFeeSet(mixer_id, max_fee));
+			Ok(().into())
+		}
+
+		/// Sets the deposit and TVL limits of the mixer with id of
+		/// `mixer_id`, `None` leaving a limit unset. Can only be called by the
+		/// `AdminOrigin`.
+		///
+		/// Weights:
+		/// - Independent of the arguments.
+		///
+		/// - Base weight: 18_000_000
+		/// - DB weights: 1 read, 1 write
+		#[pallet::weight(<T as Config>::WeightInfo::set_limits())]
+		pub fn set_limits(
+			origin: OriginFor<T>,
+			mixer_id: T::TreeId,
+			limits: MixerLimits<BalanceOf<T>>,
+		) -> DispatchResultWithPostInfo {
+			T::AdminOrigin::ensure_origin(origin)?;
+			ensure!(MixerTrees::<T>::contains_key(mixer_id), Error::<T>::NoMixerForId);
+			Limits::<T>::insert(mixer_id, limits.clone());
+			Self::deposit_event(Event::LimitsSet(mixer_id, limits));
+			Ok(().into())
+		}
+
+		/// Sets the protocol fee the mixer with id of `mixer_id` takes on
+		/// deposits, `None` removing it. Can only be called by the
+		/// `AdminOrigin`.
+		///
+		/// Weights:
+		/// - Independent of the arguments.
+		///
+		/// - Base weight: 18_000_000
+		/// - DB weights: 1 read, 1 write
+		#[pallet::weight(<T as Config>::WeightInfo::set_protocol_fee())]
+		pub fn set_protocol_fee(
+			origin: OriginFor<T>,
+			mixer_id: T::TreeId,
+			fee: Option<ProtocolFee<BalanceOf<T>>>,
+		) -> DispatchResultWithPostInfo {
+			T::AdminOrigin::ensure_origin(origin)?;
+			ensure!(MixerTrees::<T>::contains_key(mixer_id), Error::<T>::NoMixerForId);
+			ProtocolFees::<T>::set(mixer_id, fee.clone());
+			Self::deposit_event(Event::ProtocolFeeSet(mixer_id, fee));
+			Ok(().into())
+		}
+
+		/// Puts every mixer managed by the pallet in or out of withdraw-only
+		/// mode. Can only be called by the `AdminOrigin`.
+		///
+		/// Weights:
+		/// - Independent of the arguments.
+		///
+		/// - Base weight: 30_000_000
+		/// - DB weights: 1 read, 4 writes
+		#[pallet::weight(<T as Config>::WeightInfo::set_all_withdraw_only())]
+		pub fn set_all_withdraw_only(origin: OriginFor<T>, withdraw_only: bool) -> DispatchResultWithPostInfo {
+			T::AdminOrigin::ensure_origin(origin)?;
+			// Stopping every mixer would also block withdrawals and trap the
+			// deposits, so only deposits are halted
+			for mixer_id in MixerTreeIds::<T>::get() {
+				WithdrawOnly::<T>::insert(mixer_id, withdraw_only);
+				Self::deposit_event(Event::WithdrawOnlySet(mixer_id, withdraw_only));
+			}
+			Ok(().into())
+		}
+
+		/// Stops or restarts the mixer with id of `mixer_id`. Can only be
+		/// called by the `AdminOrigin`.
 		///
 		/// Weights:
 		/// - Independent of the arguments.
@@ -417,36 +654,119 @@ pub mod pallet {
 		/// - Base weight: 36_000_000
 		/// - DB weights: 6 reads, 4 writes
 		#[pallet::weight(<T as Config>::WeightInfo::set_stopped())]
-		pub fn set_stopped(origin: OriginFor<T>, stopped: bool) -> DispatchResultWithPostInfo {
-			// Ensure the caller is admin or root
-			ensure_admin(origin, &Self::admin())?;
-			// Set the mixer state, `stopped` can be true or false
-			let mixer_ids = MixerTreeIds::<T>::get();
-			for i in 0..mixer_ids.len() {
-				T::Tree::set_stopped(Self::account_id(), mixer_ids[i], stopped)?;
-			}
+		pub fn set_mixer_stopped(
+			origin: OriginFor<T>,
+			mixer_id: T::TreeId,
+			stopped: bool,
+		) -> DispatchResultWithPostInfo {
+			T::AdminOrigin::ensure_origin(origin)?;
+			ensure!(MixerTrees::<T>::contains_key(mixer_id), Error::<T>::NoMixerForId);
+			T::Tree::set_stopped(Self::account_id(), mixer_id, stopped)?;
+			Self::deposit_event(Event::StoppedSet(mixer_id, stopped));
 			Ok(().into())
 		}
 
-		/// Transfers the admin from the caller to the specified `to` account.
-		/// Can only be called by the current admin or the root origin.
+		/// Puts the mixer with id of `mixer_id` in or out of withdraw-only
+		/// mode. Can only be called by the `AdminOrigin`.
 		///
 		/// Weights:
 		/// - Independent of the arguments.
 		///
-		/// - Base weight: 7_000_000
+		/// - Base weight: 16_000_000
 		/// - DB weights: 1 read, 1 write
-		#[pallet::weight(<T as Config>::WeightInfo::transfer_admin())]
-		pub fn transfer_admin(origin: OriginFor<T>, to: T::AccountId) -> DispatchResultWithPostInfo {
-			// Ensures that the caller is the root or the current admin
-			ensure_admin(origin, &Self::admin())?;
-			// Updating the admin
-			Admin::<T>::set(to);
+		#[pallet::weight(<T as Config>::WeightInfo::set_withdraw_only())]
+		pub fn set_withdraw_only(
+			origin: OriginFor<T>,
+			mixer_id: T::TreeId,
+			withdraw_only: bool,
+		) -> DispatchResultWithPostInfo {
+			T::AdminOrigin::ensure_origin(origin)?;
+			ensure!(MixerTrees::<T>::contains_key(mixer_id), Error::<T>::NoMixerForId);
+			WithdrawOnly::<T>::insert(mixer_id, withdraw_only);
+			Self::deposit_event(Event::WithdrawOnlySet(mixer_id, withdraw_only));
 			Ok(().into())
 		}
 	}
 }
 
+/// Signed extension that keeps a single `withdraw` per nullifier in the
+/// transaction pool and rejects withdrawals of already used nullifiers before
+/// they are included. See [`merkle::CheckNullifier`].
+pub type CheckNullifier<T> = merkle::CheckNullifier<T, Call<T>>;
+
+impl<T: Config> SpendsNullifier<T::TreeId> for Call<T> {
+	const IDENTIFIER: &'static str = "CheckAssetsMixerNullifier";
+
+	fn spent_nullifier(&self) -> Option<(T::TreeId, ScalarBytes)> {
+		match self {
+			Call::withdraw(withdraw_proof) => Some((withdraw_proof.mixer_id, withdraw_proof.nullifier_hash.clone())),
+			_ => None,
+		}
+	}
+}
+
+sp_api::decl_runtime_apis! {
+	pub trait AssetsMixerApi<Balance> where
+		Balance: codec::Codec,
+	{
+		/// Get whether the mixer is active, in withdraw-only mode or stopped.
+		fn mixer_state(mixer_id: u32) -> Result<MixerState, dispatch::DispatchError>;
+		/// Get the deposit and TVL limits of the mixer.
+		fn mixer_limits(mixer_id: u32) -> Result<MixerLimits<Balance>, dispatch::DispatchError>;
+	}
+}
+
+/// Storage layouts of the pallet, each reached by one of the
+/// [`migrations`]
+#[cfg_attr(feature = "std", derive(Debug))]
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Releases {
+	/// Privileged calls checked against the `Admin` account
+	V1_0_0,
+	/// `Admin` account removed by [`migrations::remove_admin`]
+	V2_0_0,
+}
+
+impl Default for Releases {
+	fn default() -> Self {
+		Releases::V1_0_0
+	}
+}
+
+/// Operating state of a mixer
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq)]
+pub enum MixerState {
+	/// Deposits and withdrawals are accepted
+	Active,
+	/// Deposits are refused, withdrawals are accepted
+	WithdrawOnly,
+	/// Deposits and withdrawals are refused
+	Stopped,
+}
+
+/// Protocol fee of a mixer, taken on every deposit
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+pub enum ProtocolFee<Balance> {
+	/// Share of the deposit size
+	Proportional(Permill),
+	/// Flat amount per deposit
+	Flat(Balance),
+}
+
+/// Deposit and TVL limits of a mixer, each `None` if it doesn't apply
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+#[derive(Encode, Decode, Clone, PartialEq, Eq, Default)]
+pub struct MixerLimits<Balance> {
+	/// Maximum number of commitments in a single deposit call
+	pub max_commitments_per_deposit: Option<u32>,
+	/// Maximum number of deposits in a single block
+	pub max_deposits_per_block: Option<u32>,
+	/// Maximum total value locked
+	pub max_total_value_locked: Option<Balance>,
+}
+
 /// Proof data for withdrawal
 #[derive(Encode, Decode, PartialEq, Clone)]
 pub struct WithdrawProof<T: Config> {
@@ -468,8 +788,12 @@ pub struct WithdrawProof<T: Config> {
 	proof_commitments: Vec<ScalarBytes>,
 	/// The recipient to withdraw amount of currency to
 	recipient: Option<T::AccountId>,
-	/// The recipient to withdraw amount of currency to
+	/// The relayer submitting the withdrawal on behalf of the recipient
 	relayer: Option<T::AccountId>,
+	/// The fee paid to the relayer out of the deposit
+	fee: BalanceOf<T>,
+	/// The native currency amount paid by the sender to the recipient
+	refund: NativeBalanceOf<T>,
 }
 
 impl<T: Config> WithdrawProof<T> {
@@ -484,6 +808,8 @@ impl<T: Config> WithdrawProof<T> {
 		proof_commitments: Vec<ScalarBytes>,
 		recipient: Option<T::AccountId>,
 		relayer: Option<T::AccountId>,
+		fee: BalanceOf<T>,
+		refund: NativeBalanceOf<T>,
 	) -> Self {
 		Self {
 			mixer_id,
@@ -496,6 +822,8 @@ impl<T: Config> WithdrawProof<T> {
 			proof_commitments,
 			recipient,
 			relayer,
+			fee,
+			refund,
 		}
 	}
 }
@@ -515,6 +843,8 @@ pub type BalanceOf<T> =
 /// Type alias for the webb_traits::MultiCurrency::CurrencyId type
 pub type CurrencyIdOf<T> =
 	<<T as pallet::Config>::AssetSystem as fungibles::Inspect<<T as frame_system::Config>::AccountId>>::AssetId;
+/// Type alias for the balance of the native currency
+pub type NativeBalanceOf<T> = <<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
 
 /// Info about the mixer and it's leaf data
 #[derive(Encode, Decode, PartialEq)]
@@ -555,6 +885,64 @@ impl<T: Config> Pallet<T> {
 		T::PalletId::get().into_account()
 	}
 
+	/// Returns whether the mixer with id of `mixer_id` is active, in
+	/// withdraw-only mode or stopped.
+	pub fn mixer_state(mixer_id: T::TreeId) -> Result<MixerState, dispatch::DispatchError> {
+		ensure!(MixerTrees::<T>::contains_key(mixer_id), Error::<T>::NoMixerForId);
+		Ok(if <MerklePallet<T>>::stopped(mixer_id) {
+			MixerState::Stopped
+		} else if Self::withdraw_only(mixer_id) {
+			MixerState::WithdrawOnly
+		} else {
+			MixerState::Active
+		})
+	}
+
+	/// Returns the deposit and TVL limits of the mixer with id of `mixer_id`.
+	pub fn mixer_limits(mixer_id: T::TreeId) -> Result<MixerLimits<BalanceOf<T>>, dispatch::DispatchError> {
+		ensure!(MixerTrees::<T>::contains_key(mixer_id), Error::<T>::NoMixerForId);
+		Ok(Self::limits(mixer_id))
+	}
+
+	/// Protocol fee of a single deposit of `deposit_size` into the mixer
+	pub fn protocol_fee_per_deposit(mixer_id: T::TreeId, deposit_size: BalanceOf<T>) -> BalanceOf<T> {
+		match Self::protocol_fee(mixer_id) {
+			Some(ProtocolFee::Proportional(share)) => share * deposit_size,
+			Some(ProtocolFee::Flat(amount)) => amount,
+			None => Zero::zero(),
+		}
+	}
+
+	/// Number of deposits into the mixer in the current block
+	pub fn deposits_in_block(mixer_id: T::TreeId) -> u32 {
+		match Self::block_deposits(mixer_id) {
+			Some((block_number, count)) if block_number == <frame_system::Pallet<T>>::block_number() => count,
+			_ => 0,
+		}
+	}
+
+	/// Checks that `count` more commitments worth `amount` in total fit in
+	/// the limits of the mixer.
+	pub fn ensure_within_limits(mixer_id: T::TreeId, count: u32, amount: BalanceOf<T>) -> dispatch::DispatchResult {
+		let limits = Self::limits(mixer_id);
+		if let Some(max) = limits.max_commitments_per_deposit {
+			ensure!(count <= max, Error::<T>::TooManyCommitments);
+		}
+		if let Some(max) = limits.max_deposits_per_block {
+			ensure!(
+				Self::deposits_in_block(mixer_id).saturating_add(count) <= max,
+				Error::<T>::BlockDepositLimitReached
+			);
+		}
+		if let Some(max) = limits.max_total_value_locked {
+			ensure!(
+				Self::total_value_locked(mixer_id).saturating_add(amount) <= max,
+				Error::<T>::TvlLimitExceeded
+			);
+		}
+		Ok(())
+	}
+
 	pub fn get_mixer(mixer_id: T::TreeId) -> Result<MixerInfo<T>, dispatch::DispatchError> {
 		let mixer_info = MixerTrees::<T>::get(mixer_id);
 		// ensure mixer_info has a non-zero deposit, otherwise, the mixer doesn't exist
@@ -567,14 +955,57 @@ impl<T: Config> Pallet<T> {
 		Ok(mixer_info)
 	}
 
+	/// Public input binding the relayer to the fee and refund of a
+	/// withdrawal, before it is mapped with `public_input_to_field`.
+	/// Withdrawals without a fee or refund use the plain relayer encoding.
+	pub fn relayer_input(relayer: &T::AccountId, fee: BalanceOf<T>, refund: NativeBalanceOf<T>) -> ScalarBytes {
+		if fee.is_zero() && refund.is_zero() {
+			relayer.encode()
+		} else {
+			BlakeTwo256::hash_of(&(relayer, fee, refund)).as_ref().to_vec()
+		}
+	}
+
+	/// Checks that the mixer is running and initialized, that the fee is
+	/// within the mixer's maximum, that the nullifier is unused and that the
+	/// zero-knowledge proof verifies against a cached root. Returns the info
+	/// of the mixer being withdrawn from.
+	pub fn validate_withdraw(
+		withdraw_proof: &WithdrawProof<T>,
+		recipient: &T::AccountId,
+		relayer: &T::AccountId,
+	) -> Result<MixerInfo<T>, dispatch::DispatchError> {
+		ensure!(
+			!<MerklePallet<T>>::stopped(withdraw_proof.mixer_id),
+			Error::<T>::MixerStopped
+		);
+		// get mixer info, should fail if tree isn't initialized
+		let mixer_info = Self::get_mixer(withdraw_proof.mixer_id)?;
+		ensure!(
+			withdraw_proof.fee <= Self::max_fee(withdraw_proof.mixer_id),
+			Error::<T>::FeeExceedsMaximum
+		);
+		// check if the nullifier has been used
+		T::Tree::has_used_nullifier(withdraw_proof.mixer_id.into(), withdraw_proof.nullifier_hash.clone())?;
+		// Verify the zero-knowledge proof of membership provided
+		T::Tree::verify_zk(
+			withdraw_proof.mixer_id.into(),
+			withdraw_proof.cached_block,
+			withdraw_proof.cached_root.clone(),
+			withdraw_proof.comms.clone(),
+			withdraw_proof.nullifier_hash.clone(),
+			withdraw_proof.proof_bytes.clone(),
+			withdraw_proof.leaf_index_commitments.clone(),
+			withdraw_proof.proof_commitments.clone(),
+			public_input_to_field(&recipient.encode()),
+			public_input_to_field(&Self::relayer_input(relayer, withdraw_proof.fee, withdraw_proof.refund)),
+		)?;
+		Ok(mixer_info)
+	}
+
 	pub fn initialize_first_stage() -> dispatch::DispatchResult {
 		ensure!(!Self::first_stage_initialized(), Error::<T>::AlreadyInitialized);
 
-		// Get default admin from trait params
-		let default_admin = T::DefaultAdmin::get();
-		// Initialize the admin in storage with default one
-		Admin::<T>::set(default_admin);
-
 		// Getting the sizes from the config
 		let sizes = T::MixerSizes::get();
 
@@ -630,3 +1061,25 @@ impl<T: Config> ExtendedMixer<T> for Pallet<T> {
 		Ok(mixer_id)
 	}
 }
+
+/// Migrations of the assets mixer pallet
+pub mod migrations {
+	use super::*;
+	use frame_support::{storage::migration::remove_storage_prefix, traits::PalletInfo};
+
+	/// Removes the `Admin` account, which is superseded by the `AdminOrigin`.
+	/// Runs once, moving the storage to [`Releases::V2_0_0`].
+	///
+	/// Meant to be called from the `OnRuntimeUpgrade` of runtimes that
+	/// deploy the pallet.
+	pub fn remove_admin<T: Config>() -> Weight {
+		if StorageVersion::<T>::get() >= Releases::V2_0_0 {
+			return T::DbWeight::get().reads(1);
+		}
+		StorageVersion::<T>::put(Releases::V2_0_0);
+		if let Some(pallet_name) = <T as frame_system::Config>::PalletInfo::name::<Pallet<T>>() {
+			remove_storage_prefix(pallet_name.as_bytes(), b"Admin", &[]);
+		}
+		T::DbWeight::get().reads_writes(1, 2)
+	}
+}
@@ -2,10 +2,10 @@ use super::*;
 
 use crate as pallet_mixer;
 use frame_benchmarking::whitelisted_caller;
-use frame_support::{construct_runtime, parameter_types, weights::Weight, PalletId};
+use frame_support::{construct_runtime, ord_parameter_types, parameter_types, weights::Weight, PalletId};
 use frame_system::{
 	mocking::{MockBlock, MockUncheckedExtrinsic},
-	EnsureRoot,
+	EnsureOneOf, EnsureRoot, EnsureSignedBy,
 };
 use merkle::weights::Weights as MerkleWeights;
 
@@ -82,6 +82,7 @@ parameter_types! {
 	pub const MaxReserves: u32 = 50;
 	pub const MaxTreeDepth: u8 = 32;
 	pub const CacheBlockLength: u64 = 5;
+	pub const MaxPrunedRoots: u32 = 100;
 	// Minimum deposit length is 1 month w/ 6 second blocks
 	pub const MinimumDepositLength: u64 = 10 * 60 * 24 * 28;
 }
@@ -126,28 +127,33 @@ impl merkle::Config for Test {
 	type CacheBlockLength = CacheBlockLength;
 	type Event = Event;
 	type KeyId = u32;
+	type MaxPrunedRoots = MaxPrunedRoots;
 	type MaxTreeDepth = MaxTreeDepth;
-	type Randomness = Randomness;
 	type TreeId = u32;
 	type WeightInfo = MerkleWeights<Self>;
 }
 
 parameter_types! {
 	pub const MixerPalletId: PalletId = PalletId(*b"py/mixer");
-	pub const DefaultAdmin: u64 = 4;
 	pub MixerSizes: Vec<Balance> = [1_000, 10_000, 100_000, 1_000_000].to_vec();
 	pub const DefaultCurrencyId: AssetId = 0;
+	pub const TreasuryAccount: AccountId = 99;
+}
+
+ord_parameter_types! {
+	pub const AdminAccount: AccountId = 4;
 }
 
 impl Config for Test {
+	type AdminOrigin = EnsureOneOf<AccountId, EnsureRoot<AccountId>, EnsureSignedBy<AdminAccount, AccountId>>;
 	type AssetSystem = Assets;
 	type Currency = Balances;
-	type DefaultAdmin = DefaultAdmin;
 	type DefaultCurrencyId = DefaultCurrencyId;
 	type DepositLength = MinimumDepositLength;
 	type Event = Event;
 	type MixerSizes = MixerSizes;
 	type PalletId = MixerPalletId;
+	type Treasury = TreasuryAccount;
 	type Tree = MerkleTrees;
 	type WeightInfo = Weights<Self>;
 }
@@ -1,6 +1,7 @@
 use super::*;
 use crate::mock::{
-	new_test_ext, AccountId, AssetId, Assets, Balance, Balances, MerkleTrees, Mixer, MixerCall, Origin, System, Test,
+	new_test_ext, AccountId, AssetId, Assets, Balance, Balances, Call, MerkleTrees, Mixer, MixerCall, Origin, System,
+	Test, TreasuryAccount,
 };
 use bulletproofs::{r1cs::Prover, BulletproofGens, PedersenGens};
 use bulletproofs_gadgets::{
@@ -13,15 +14,25 @@ use bulletproofs_gadgets::{
 use curve25519_dalek::scalar::Scalar;
 use frame_support::{
 	assert_err, assert_ok,
+	storage::migration::{get_storage_value, put_storage_value},
 	traits::{OnFinalize, UnfilteredDispatchable},
 };
 use frame_system::RawOrigin;
 use merkle::{
-	utils::keys::{slice_to_bytes_32, ScalarBytes},
+	utils::keys::{public_input_to_field, slice_to_bytes_32, ScalarBytes},
 	HighestCachedBlock,
 };
 use merlin::Transcript;
-use sp_runtime::{traits::BadOrigin, DispatchError};
+use sp_runtime::{
+	traits::{BadOrigin, SignedExtension},
+	transaction_validity::InvalidTransaction,
+	DispatchError, Permill,
+};
+
+/// Field element a proof has to bind for the given public input bytes
+fn input_scalar(bytes: &[u8]) -> Scalar {
+	Scalar::from_bytes_mod_order(slice_to_bytes_32(&public_input_to_field(bytes)))
+}
 
 fn default_hasher(bp_gens: BulletproofGens) -> Poseidon {
 	let width = 6;
@@ -68,45 +79,44 @@ fn should_initialize_successfully_on_finalize() {
 }
 
 #[test]
-fn should_be_able_to_change_admin_with_root() {
+fn should_only_allow_admin_origin_to_manage_mixers() {
 	new_test_ext().execute_with(|| {
-		let call = Box::new(MixerCall::transfer_admin(2));
-		let res = call.dispatch_bypass_filter(RawOrigin::Root.into());
-		assert_ok!(res);
-		let admin = Mixer::admin();
-		assert_eq!(admin, 2);
-
-		let call = Box::new(MixerCall::transfer_admin(3));
-		let res = call.dispatch_bypass_filter(RawOrigin::Signed(0).into());
-		assert_err!(res, BadOrigin);
+		let setup = Setup::new(HashFunction::PoseidonDefault, Backend::Bulletproofs(Curve::Curve25519));
+		assert_err!(Mixer::create_new(Origin::signed(1), 0, setup.clone(), 1_000), BadOrigin);
+		assert_err!(Mixer::set_all_withdraw_only(Origin::signed(1), true), BadOrigin);
+		assert_ok!(Mixer::create_new(Origin::signed(4), 0, setup.clone(), 1_000));
+		assert_ok!(Mixer::create_new(Origin::root(), 0, setup, 10_000));
+		assert_eq!(Mixer::mixer_group_ids(), vec![0, 1]);
 	})
 }
 
 #[test]
-fn should_be_able_to_stop_mixers_with_root() {
+fn should_remove_admin_on_migration() {
 	new_test_ext().execute_with(|| {
-		assert_ok!(Mixer::initialize_first_stage());
-		let call = Box::new(MixerCall::set_stopped(true));
-		let res = call.dispatch_bypass_filter(RawOrigin::Root.into());
-		assert_ok!(res);
-
-		for i in 0..4 {
-			let stopped = MerkleTrees::stopped(i);
-			assert!(stopped);
-		}
+		put_storage_value(b"Mixer", b"Admin", &[], 4u64);
+		migrations::remove_admin::<Test>();
+		assert_eq!(get_storage_value::<u64>(b"Mixer", b"Admin", &[]), None);
+		assert_eq!(StorageVersion::<Test>::get(), Releases::V2_0_0);
+
+		// it only runs once
+		put_storage_value(b"Mixer", b"Admin", &[], 4u64);
+		migrations::remove_admin::<Test>();
+		assert_eq!(get_storage_value::<u64>(b"Mixer", b"Admin", &[]), Some(4));
 	})
 }
 
 #[test]
-fn should_be_able_to_change_admin() {
+fn should_be_able_to_set_all_mixers_withdraw_only_with_root() {
 	new_test_ext().execute_with(|| {
-		let default_admin = 4;
 		assert_ok!(Mixer::initialize_first_stage());
-		assert_err!(Mixer::transfer_admin(Origin::signed(1), 2), BadOrigin);
-		assert_ok!(Mixer::transfer_admin(Origin::signed(default_admin), 2));
-		let admin = Mixer::admin();
+		let call = Box::new(MixerCall::set_all_withdraw_only(true));
+		let res = call.dispatch_bypass_filter(RawOrigin::Root.into());
+		assert_ok!(res);
 
-		assert_eq!(admin, 2);
+		for i in 0..4 {
+			assert!(Mixer::withdraw_only(i));
+			assert!(!MerkleTrees::stopped(i));
+		}
 	})
 }
 
@@ -126,7 +136,7 @@ fn should_stop_and_start_mixer() {
 		assert_ok!(Mixer::deposit(Origin::signed(0), 0, vec![leaf.clone()]));
 
 		// Stopping deposits and withdrawal
-		assert_ok!(Mixer::set_stopped(Origin::signed(default_admin), true));
+		assert_ok!(Mixer::set_mixer_stopped(Origin::signed(default_admin), 0, true));
 		assert_err!(
 			Mixer::deposit(Origin::signed(0), 0, vec![]),
 			Error::<Test>::MixerStopped
@@ -145,17 +155,173 @@ fn should_stop_and_start_mixer() {
 					Vec::new(),
 					None,
 					None,
+					0,
+					0,
 				)
 			),
 			Error::<Test>::MixerStopped
 		);
 
 		// Starting mixer
-		assert_ok!(Mixer::set_stopped(Origin::signed(default_admin), false));
+		assert_ok!(Mixer::set_mixer_stopped(Origin::signed(default_admin), 0, false));
 		assert_ok!(Mixer::deposit(Origin::signed(0), 0, vec![leaf]));
 	})
 }
 
+#[test]
+fn should_stop_single_mixer_and_refuse_deposits_in_withdraw_only_mode() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let default_admin = 4;
+		let currency_id = 0;
+		assert_ok!(Assets::force_create(Origin::root(), currency_id, 1, true, 1));
+		assert_ok!(Assets::mint(Origin::signed(1), currency_id, 0, 10000000));
+
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let tree_id = 0;
+		let mut tree = FixedDepositTreeBuilder::new().build();
+
+		assert_err!(Mixer::set_mixer_stopped(Origin::signed(0), tree_id, true), BadOrigin);
+		assert_err!(
+			Mixer::set_withdraw_only(Origin::root(), 100, true),
+			Error::<Test>::NoMixerForId
+		);
+		assert_err!(Mixer::mixer_state(100), Error::<Test>::NoMixerForId);
+
+		// stopping one mixer leaves the others running
+		assert_ok!(Mixer::set_mixer_stopped(Origin::signed(default_admin), tree_id, true));
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::StoppedSet(tree_id, true)));
+		assert_eq!(Mixer::mixer_state(tree_id), Ok(MixerState::Stopped));
+		assert_eq!(Mixer::mixer_state(1), Ok(MixerState::Active));
+		assert_err!(
+			Mixer::deposit(Origin::signed(0), tree_id, vec![]),
+			Error::<Test>::MixerStopped
+		);
+		let leaf = tree.generate_secrets().to_bytes().to_vec();
+		assert_ok!(Mixer::deposit(Origin::signed(0), 1, vec![leaf]));
+		assert_ok!(Mixer::set_mixer_stopped(Origin::signed(default_admin), tree_id, false));
+
+		assert_ok!(Mixer::set_withdraw_only(Origin::root(), tree_id, true));
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::WithdrawOnlySet(tree_id, true)));
+		assert_eq!(Mixer::mixer_state(tree_id), Ok(MixerState::WithdrawOnly));
+		let leaf = tree.generate_secrets().to_bytes().to_vec();
+		assert_err!(
+			Mixer::deposit(Origin::signed(0), tree_id, vec![leaf.clone()]),
+			Error::<Test>::MixerWithdrawOnly
+		);
+
+		assert_ok!(Mixer::set_withdraw_only(Origin::root(), tree_id, false));
+		assert_eq!(Mixer::mixer_state(tree_id), Ok(MixerState::Active));
+		assert_ok!(Mixer::deposit(Origin::signed(0), tree_id, vec![leaf]));
+	})
+}
+
+#[test]
+fn should_enforce_mixer_limits() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let currency_id = 0;
+		assert_ok!(Assets::force_create(Origin::root(), currency_id, 1, true, 1));
+		assert_ok!(Assets::mint(Origin::signed(1), currency_id, 1, 10000000));
+
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let mut tree = FixedDepositTreeBuilder::new().build();
+		let mut leaves =
+			|n: usize| -> Vec<ScalarBytes> { (0..n).map(|_| tree.generate_secrets().to_bytes().to_vec()).collect() };
+
+		let tree_id = 0;
+		let deposit_size = Mixer::get_mixer(tree_id).unwrap().fixed_deposit_size;
+		let limits = MixerLimits {
+			max_commitments_per_deposit: Some(2),
+			max_deposits_per_block: Some(2),
+			max_total_value_locked: Some(3 * deposit_size),
+		};
+		assert_err!(Mixer::set_limits(Origin::signed(1), tree_id, limits.clone()), BadOrigin);
+		assert_ok!(Mixer::set_limits(Origin::root(), tree_id, limits.clone()));
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::LimitsSet(
+			tree_id,
+			limits.clone(),
+		)));
+		assert_eq!(Mixer::mixer_limits(tree_id), Ok(limits));
+		assert_err!(Mixer::mixer_limits(100), Error::<Test>::NoMixerForId);
+
+		assert_err!(
+			Mixer::deposit(Origin::signed(1), tree_id, leaves(3)),
+			Error::<Test>::TooManyCommitments
+		);
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, leaves(2)));
+		assert_err!(
+			Mixer::deposit(Origin::signed(1), tree_id, leaves(1)),
+			Error::<Test>::BlockDepositLimitReached
+		);
+
+		System::set_block_number(2);
+		assert_err!(
+			Mixer::deposit(Origin::signed(1), tree_id, leaves(2)),
+			Error::<Test>::TvlLimitExceeded
+		);
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, leaves(1)));
+		assert_eq!(Mixer::total_value_locked(tree_id), 3 * deposit_size);
+	})
+}
+
+#[test]
+fn should_take_protocol_fee_on_deposit() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let currency_id = 0;
+		assert_ok!(Assets::force_create(Origin::root(), currency_id, 1, true, 1));
+		assert_ok!(Assets::mint(Origin::signed(1), currency_id, 1, 10000000));
+
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let mut tree = FixedDepositTreeBuilder::new().build();
+		let mut leaves =
+			|n: usize| -> Vec<ScalarBytes> { (0..n).map(|_| tree.generate_secrets().to_bytes().to_vec()).collect() };
+
+		let tree_id = 0;
+		let treasury = TreasuryAccount::get();
+		let deposit_size = Mixer::get_mixer(tree_id).unwrap().fixed_deposit_size;
+		let fee = ProtocolFee::Proportional(Permill::from_percent(2));
+		assert_err!(
+			Mixer::set_protocol_fee(Origin::signed(1), tree_id, Some(fee.clone())),
+			BadOrigin
+		);
+		assert_err!(
+			Mixer::set_protocol_fee(Origin::root(), 100, Some(fee.clone())),
+			Error::<Test>::NoMixerForId
+		);
+		assert_ok!(Mixer::set_protocol_fee(Origin::root(), tree_id, Some(fee.clone())));
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::ProtocolFeeSet(
+			tree_id,
+			Some(fee),
+		)));
+
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, leaves(2)));
+		assert_eq!(Assets::balance(currency_id, 1), 10000000 - 2 * deposit_size - 40);
+		assert_eq!(Assets::balance(currency_id, treasury), 40);
+		assert_eq!(Mixer::accrued_fees(tree_id), 40);
+		// the fee doesn't count towards what the mixer holds
+		assert_eq!(Mixer::total_value_locked(tree_id), 2 * deposit_size);
+		assert_eq!(Assets::balance(currency_id, Mixer::account_id()), 2 * deposit_size);
+
+		assert_ok!(Mixer::set_protocol_fee(
+			Origin::root(),
+			tree_id,
+			Some(ProtocolFee::Flat(7))
+		));
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, leaves(1)));
+		System::assert_has_event(crate::mock::Event::Mixer(crate::Event::ProtocolFeeTaken(tree_id, 1, 7)));
+		assert_eq!(Mixer::accrued_fees(tree_id), 47);
+
+		assert_ok!(Mixer::set_protocol_fee(Origin::root(), tree_id, None));
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, leaves(1)));
+		assert_eq!(Assets::balance(currency_id, treasury), 47);
+	})
+}
+
 #[test]
 fn should_fail_to_deposit_with_insufficient_balance() {
 	new_test_ext().execute_with(|| {
@@ -235,8 +401,8 @@ fn should_withdraw_from_each_mixer_successfully() {
 			let (proof, (comms_cr, nullifier_hash, leaf_index_comms_cr, proof_comms_cr)) = ftree.prove_zk(
 				Scalar::from_bytes_mod_order(slice_to_bytes_32(&root)),
 				Scalar::from_bytes_mod_order(slice_to_bytes_32(&leaf)),
-				Scalar::from(2u32),
-				Scalar::zero(),
+				input_scalar(&2u64.encode()),
+				input_scalar(&0u64.encode()),
 				&ftree.hash_params.bp_gens,
 				prover,
 			);
@@ -265,6 +431,8 @@ fn should_withdraw_from_each_mixer_successfully() {
 					proof_comms,
 					Some(2),
 					Some(0),
+					0,
+					0,
 				)
 			));
 			let balance_after = Assets::balance(currency_id, 2);
@@ -276,6 +444,151 @@ fn should_withdraw_from_each_mixer_successfully() {
 	})
 }
 
+#[test]
+fn should_pay_relayer_fee_and_refund_on_withdraw() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let currency_id = 0;
+		assert_ok!(Assets::force_create(Origin::root(), currency_id, 1, true, 1));
+		assert_ok!(Assets::mint(Origin::signed(1), currency_id, 1, 10000000));
+
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let pc_gens = PedersenGens::default();
+
+		let tree_id = 0;
+		let key_id = 0;
+		let params = MerkleTrees::get_verifying_key(key_id).unwrap();
+		let bp_gens = merkle::utils::keys::from_bytes_to_bp_gens(&params);
+		let h = default_hasher(bp_gens);
+
+		let recipient: AccountId = 2;
+		let relayer: AccountId = 1;
+		let fee: Balance = 100;
+		let refund: Balance = 50;
+
+		assert_err!(
+			Mixer::set_max_fee(Origin::signed(relayer), tree_id, fee),
+			BadOrigin
+		);
+		assert_err!(
+			Mixer::set_max_fee(Origin::root(), tree_id, 1_001),
+			Error::<Test>::InvalidMaxFee
+		);
+		assert_ok!(Mixer::set_max_fee(Origin::root(), tree_id, fee));
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::MaxFeeSet(tree_id, fee)));
+
+		let mut prover_transcript = Transcript::new(b"zk_membership_proof");
+		let prover = Prover::new(&pc_gens, &mut prover_transcript);
+		let mut ftree = FixedDepositTreeBuilder::new().hash_params(h.clone()).depth(32).build();
+
+		let leaf = ftree.generate_secrets().to_bytes();
+		ftree.tree.add_leaves(vec![leaf], None);
+
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, vec![leaf.to_vec()]));
+
+		let root = MerkleTrees::get_merkle_root(tree_id).unwrap();
+		let relayer_input = Mixer::relayer_input(&relayer, fee, refund);
+		let (proof, (comms_cr, nullifier_hash, leaf_index_comms_cr, proof_comms_cr)) = ftree.prove_zk(
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&root)),
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&leaf)),
+			input_scalar(&recipient.encode()),
+			input_scalar(&relayer_input),
+			&ftree.hash_params.bp_gens,
+			prover,
+		);
+
+		let comms: Vec<ScalarBytes> = comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let leaf_index_comms: Vec<ScalarBytes> = leaf_index_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let proof_comms: Vec<ScalarBytes> = proof_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let withdraw_proof = |fee: Balance, refund: Balance| {
+			WithdrawProof::<Test>::new(
+				tree_id,
+				1,
+				root.clone(),
+				comms.clone(),
+				nullifier_hash.to_bytes().to_vec(),
+				proof.to_bytes(),
+				leaf_index_comms.clone(),
+				proof_comms.clone(),
+				Some(recipient),
+				Some(relayer),
+				fee,
+				refund,
+			)
+		};
+
+		assert_err!(
+			Mixer::withdraw(Origin::signed(relayer), withdraw_proof(fee + 1, refund)),
+			Error::<Test>::FeeExceedsMaximum
+		);
+		assert_err!(
+			Mixer::withdraw(Origin::signed(relayer), withdraw_proof(fee - 1, refund)),
+			merkle::Error::<Test>::ZkVerificationFailed
+		);
+
+		let deposit_size = Mixer::get_mixer(tree_id).unwrap().fixed_deposit_size;
+		let recipient_assets_before = Assets::balance(currency_id, recipient);
+		let relayer_assets_before = Assets::balance(currency_id, relayer);
+		let recipient_native_before = Balances::free_balance(recipient);
+		assert_ok!(Mixer::withdraw(Origin::signed(relayer), withdraw_proof(fee, refund)));
+		assert_eq!(
+			Assets::balance(currency_id, recipient),
+			recipient_assets_before + deposit_size - fee
+		);
+		assert_eq!(Assets::balance(currency_id, relayer), relayer_assets_before + fee);
+		assert_eq!(Balances::free_balance(recipient), recipient_native_before + refund);
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::Withdraw(
+			tree_id,
+			relayer,
+			recipient,
+			relayer,
+			fee,
+			root,
+		)));
+	})
+}
+
+#[test]
+fn should_keep_one_withdrawal_per_nullifier_in_pool() {
+	new_test_ext().execute_with(|| {
+		let tree_id = 0;
+		let withdraw_call = |nullifier_hash: ScalarBytes, recipient: AccountId| {
+			Call::Mixer(MixerCall::withdraw(WithdrawProof::<Test>::new(
+				tree_id,
+				0,
+				Vec::new(),
+				Vec::new(),
+				nullifier_hash,
+				Vec::new(),
+				Vec::new(),
+				Vec::new(),
+				Some(recipient),
+				None,
+				0,
+				0,
+			)))
+		};
+		let validate = |call: Call| CheckNullifier::<Test>::new().validate(&1, &call, &Default::default(), 0);
+		let nullifier = Scalar::from(1u32).to_bytes().to_vec();
+
+		// withdrawals of the same nullifier conflict, whatever else they carry
+		let first = validate(withdraw_call(nullifier.clone(), 1)).unwrap();
+		let second = validate(withdraw_call(nullifier.clone(), 2)).unwrap();
+		let other = validate(withdraw_call(Scalar::from(2u32).to_bytes().to_vec(), 1)).unwrap();
+		assert_eq!(first.provides.len(), 1);
+		assert_eq!(first.provides, second.provides);
+		assert_ne!(first.provides, other.provides);
+
+		// other calls are left alone
+		let transfer = validate(Call::Balances(pallet_balances::Call::transfer(2, 1))).unwrap();
+		assert!(transfer.provides.is_empty());
+
+		merkle::UsedNullifiers::<Test>::insert((tree_id, nullifier.clone()), true);
+		assert_eq!(validate(withdraw_call(nullifier, 1)), InvalidTransaction::Stale.into());
+	})
+}
+
 #[test]
 fn should_cache_roots_if_no_new_deposits_show() {
 	new_test_ext().execute_with(|| {
@@ -412,8 +725,8 @@ fn should_make_mixer_with_non_native_token() {
 		let (proof, (comms_cr, nullifier_hash, leaf_index_comms_cr, proof_comms_cr)) = ftree.prove_zk(
 			Scalar::from_bytes_mod_order(slice_to_bytes_32(&root)),
 			Scalar::from_bytes_mod_order(slice_to_bytes_32(&leaf)),
-			Scalar::from(recipient),
-			Scalar::zero(),
+			input_scalar(&recipient.encode()),
+			input_scalar(&0u64.encode()),
 			&ftree.hash_params.bp_gens,
 			prover,
 		);
@@ -442,6 +755,8 @@ fn should_make_mixer_with_non_native_token() {
 				proof_comms,
 				Some(recipient),
 				Some(0),
+				0,
+				0,
 			)
 		));
 		let balance_after = Assets::balance(currency_id, &recipient);
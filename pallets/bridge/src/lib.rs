@@ -4,20 +4,20 @@
 #[cfg(test)]
 pub mod mock;
 
-// #[cfg(test)]
-// pub mod tests;
+#[cfg(test)]
+pub mod tests;
 
 pub mod traits;
 pub use traits::*;
 
 use codec::{Decode, Encode};
-use frame_support::{dispatch, ensure, traits::Get, PalletId};
+use frame_support::{dispatch, ensure, traits::Get, weights::Weight, PalletId};
 use frame_system::ensure_signed;
 use pallet_merkle::{
-	utils::{keys::ScalarBytes, permissions::ensure_admin},
-	Tree as TreeTrait,
+	utils::keys::{public_input_to_field, ScalarBytes},
+	SpendsNullifier, Tree as TreeTrait,
 };
-use sp_runtime::traits::{AccountIdConversion, AtLeast32Bit, One, Zero};
+use sp_runtime::traits::{AccountIdConversion, AtLeast32Bit, BlakeTwo256, Hash, One, Zero};
 use sp_std::prelude::*;
 use webb_tokens::traits::ExtendedTokenSystem;
 use webb_traits::MultiCurrency;
@@ -56,9 +56,8 @@ pub mod pallet {
 		/// Native currency id
 		#[pallet::constant]
 		type NativeCurrencyId: Get<CurrencyIdOf<Self>>;
-		/// Default admin key
-		#[pallet::constant]
-		type DefaultAdmin: Get<<Self as frame_system::Config>::AccountId>;
+		/// Origin that can stop and restart the anchors
+		type AdminOrigin: EnsureOrigin<Self::Origin>;
 		/// The overarching merkle tree trait
 		type Tree: TreeTrait<Self>;
 	}
@@ -100,11 +99,9 @@ pub mod pallet {
 	pub type ReverseWrappedTokenRegistry<T: Config> =
 		StorageMap<_, Blake2_128Concat, CurrencyIdOf<T>, Vec<CurrencyIdOf<T>>>;
 
-	/// Administrator of the bridge pallet.
-	/// This account that can stop/start operations of the bridge
+	/// The storage layout of the pallet, so each migration runs once
 	#[pallet::storage]
-	#[pallet::getter(fn admin)]
-	pub type Admin<T: Config> = StorageValue<_, <T as frame_system::Config>::AccountId, ValueQuery>;
+	pub type StorageVersion<T: Config> = StorageValue<_, Releases, ValueQuery>;
 
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
@@ -132,6 +129,15 @@ pub mod pallet {
 			/// Merkle root
 			ScalarBytes,
 		),
+		/// Deposit of the specific bridge anchor spent into a new leaf
+		Remix(
+			/// Id of the anchor
+			T::TreeId,
+			/// Account id of the sender
+			<T as frame_system::Config>::AccountId,
+			/// Merkle root
+			ScalarBytes,
+		),
 	}
 
 	#[pallet::error]
@@ -150,6 +156,10 @@ pub mod pallet {
 		UnauthorizedCall,
 		/// Anchor is stopped
 		AnchorStopped,
+		/// Proof doesn't decode as a withdraw proof of the anchor
+		InvalidWithdrawProof,
+		/// Remixes can't carry a recipient
+		InvalidRemix,
 	}
 	#[pallet::pallet]
 	#[pallet::generate_store(pub(super) trait Store)]
@@ -214,7 +224,7 @@ pub mod pallet {
 		}
 
 		/// Stops the operation of all the anchors managed by the pallet.
-		/// Can only be called by the admin or the root origin.
+		/// Can only be called by the `AdminOrigin`.
 		///
 		/// Weights:
 		/// - Independent of the arguments.
@@ -223,8 +233,7 @@ pub mod pallet {
 		/// - DB weights: 6 reads, 4 writes
 		#[pallet::weight(5_000_000)]
 		pub fn set_stopped(origin: OriginFor<T>, stopped: bool) -> DispatchResultWithPostInfo {
-			// Ensure the caller is admin or root
-			ensure_admin(origin, &Self::admin())?;
+			T::AdminOrigin::ensure_origin(origin)?;
 			// Set the anchor state, `stopped` can be true or false
 			let tree_ids = BridgeTreeIds::<T>::get();
 			for i in 0..tree_ids.len() {
@@ -233,26 +242,6 @@ pub mod pallet {
 			Ok(().into())
 		}
 
-		/// Transfers the admin from the caller to the specified `to` account.
-		/// Can only be called by the current admin or the root origin.
-		///
-		/// Weights:
-		/// - Independent of the arguments.
-		///
-		/// - Base weight: 7_000_000
-		/// - DB weights: 1 read, 1 write
-		#[pallet::weight(5_000_000)]
-		pub fn transfer_admin(
-			origin: OriginFor<T>,
-			to: <T as frame_system::Config>::AccountId,
-		) -> DispatchResultWithPostInfo {
-			// Ensures that the caller is the root or the current admin
-			ensure_admin(origin, &Self::admin())?;
-			// Updating the admin
-			Admin::<T>::set(to);
-			Ok(().into())
-		}
-
 		#[pallet::weight(5_000_000)]
 		pub fn wrap_and_deposit(
 			origin: OriginFor<T>,
@@ -447,18 +436,49 @@ impl<T: Config> Pallet<T> {
 		Ok(anchor_info)
 	}
 
+	/// Recipient public input of remixes, binding the proof to the anchor and
+	/// the leaf it is remixed into.
+	pub fn remix_input(tree_id: T::TreeId, leaf: &ScalarBytes) -> ScalarBytes {
+		BlakeTwo256::hash_of(&(b"remix", tree_id, leaf)).as_ref().to_vec()
+	}
+
+	/// Relayer public input of remixes, tagged so a remix proof can't be
+	/// replayed as a withdrawal to an account encoding to the remix input.
+	pub fn remix_relayer_input(relayer: &<T as frame_system::Config>::AccountId) -> ScalarBytes {
+		BlakeTwo256::hash_of(&(b"remix", relayer)).as_ref().to_vec()
+	}
+
 	pub fn initialize() -> dispatch::DispatchResult {
 		ensure!(!Self::initialised(), Error::<T>::AlreadyInitialised);
 
-		// Get default admin from trait params
-		let default_admin = T::DefaultAdmin::get();
-		// Initialize the admin in storage with default one
-		Admin::<T>::set(default_admin);
 		Initialised::<T>::set(true);
 		Ok(())
 	}
 }
 
+/// Signed extension that keeps a single `withdraw_zk` or `remix_zk` per
+/// nullifier in the transaction pool and rejects withdrawals of already used
+/// nullifiers before they are included. See [`pallet_merkle::CheckNullifier`].
+///
+/// Proofs that don't decode as a [`WithdrawProof`] carry no nullifier and are
+/// left to fail at dispatch.
+pub type CheckNullifier<T> = pallet_merkle::CheckNullifier<T, Call<T>>;
+
+impl<T: Config> SpendsNullifier<T::TreeId> for Call<T> {
+	const IDENTIFIER: &'static str = "CheckBridgeNullifier";
+
+	fn spent_nullifier(&self) -> Option<(T::TreeId, ScalarBytes)> {
+		match self {
+			Call::withdraw_zk(tree_id, proof) | Call::remix_zk(tree_id, proof, _) => {
+				WithdrawProof::<T>::decode(&mut &proof[..])
+					.ok()
+					.map(|withdraw_proof| (*tree_id, withdraw_proof.nullifier_hash().clone()))
+			}
+			_ => None,
+		}
+	}
+}
+
 impl<T: Config> PrivacyBridgeSystem for Pallet<T> {
 	type AccountId = <T as frame_system::Config>::AccountId;
 	type Balance = BalanceOf<T>;
@@ -598,6 +618,31 @@ impl<T: Config> PrivacyBridgeSystem for Pallet<T> {
 		proof: Vec<u8>,
 		leaf: Self::Scalar,
 	) -> Result<(), dispatch::DispatchError> {
+		ensure!(!T::Tree::is_stopped(tree_id), Error::<T>::AnchorStopped);
+		Self::get_anchor_info(tree_id)?;
+		let withdraw_proof =
+			WithdrawProof::<T>::decode(&mut &proof[..]).map_err(|_| Error::<T>::InvalidWithdrawProof)?;
+		ensure!(withdraw_proof.mixer_id == tree_id, Error::<T>::InvalidWithdrawProof);
+		// the new leaf stands in for the recipient, nothing is paid out
+		ensure!(withdraw_proof.recipient.is_none(), Error::<T>::InvalidRemix);
+		let relayer = withdraw_proof.relayer.clone().unwrap_or(account_id.clone());
+		T::Tree::has_used_nullifier(tree_id, withdraw_proof.nullifier_hash.clone())?;
+		T::Tree::verify_zk(
+			tree_id,
+			withdraw_proof.cached_block,
+			withdraw_proof.cached_root.clone(),
+			withdraw_proof.comms,
+			withdraw_proof.nullifier_hash.clone(),
+			withdraw_proof.proof_bytes,
+			withdraw_proof.leaf_index_commitments,
+			withdraw_proof.proof_commitments,
+			public_input_to_field(&Self::remix_input(tree_id, &leaf)),
+			public_input_to_field(&Self::remix_relayer_input(&relayer)),
+		)?;
+		// spend the deposit and add the new leaf, the anchor keeps the funds
+		T::Tree::add_nullifier(Self::account_id(), tree_id, withdraw_proof.nullifier_hash)?;
+		T::Tree::add_members(Self::account_id(), tree_id, vec![leaf])?;
+		Self::deposit_event(Event::Remix(tree_id, account_id, withdraw_proof.cached_root));
 		Ok(())
 	}
 
@@ -672,3 +717,26 @@ impl<T: Config> GovernableBridgeSystem for Pallet<T> {
 		Ok(())
 	}
 }
+
+/// Migrations of the bridge pallet
+pub mod migrations {
+	use super::*;
+	use frame_support::{storage::migration::remove_storage_prefix, traits::PalletInfo};
+
+	/// Removes the bridge `Admin` account, `set_stopped` checks the
+	/// `AdminOrigin` now. Runs once, moving the storage to
+	/// [`Releases::V2_0_0`].
+	///
+	/// Meant to be called from the `OnRuntimeUpgrade` of runtimes that
+	/// deploy the pallet.
+	pub fn remove_admin<T: Config>() -> Weight {
+		if StorageVersion::<T>::get() >= Releases::V2_0_0 {
+			return T::DbWeight::get().reads(1);
+		}
+		StorageVersion::<T>::put(Releases::V2_0_0);
+		if let Some(pallet_name) = <T as frame_system::Config>::PalletInfo::name::<Pallet<T>>() {
+			remove_storage_prefix(pallet_name.as_bytes(), b"Admin", &[]);
+		}
+		T::DbWeight::get().reads_writes(1, 2)
+	}
+}
@@ -79,6 +79,7 @@ parameter_types! {
 	pub const MaxReserves: u32 = 50;
 	pub const MaxTreeDepth: u8 = 32;
 	pub const CacheBlockLength: u64 = 5;
+	pub const MaxPrunedRoots: u32 = 100;
 	// Minimum deposit length is 1 month w/ 6 second blocks
 	pub const MinimumDepositLength: u64 = 10 * 60 * 24 * 28;
 }
@@ -138,22 +139,21 @@ impl pallet_merkle::Config for Test {
 	type CacheBlockLength = CacheBlockLength;
 	type Event = Event;
 	type KeyId = u32;
+	type MaxPrunedRoots = MaxPrunedRoots;
 	type MaxTreeDepth = MaxTreeDepth;
-	type Randomness = Randomness;
 	type TreeId = u32;
 	type WeightInfo = MerkleWeights<Self>;
 }
 
 parameter_types! {
 	pub const BridgePalletId: PalletId = PalletId(*b"py/brdge");
-	pub const DefaultAdmin: u64 = 4;
 	pub const NativeCurrencyId: CurrencyId = 0;
 }
 
 impl Config for Test {
+	type AdminOrigin = frame_system::EnsureRoot<AccountId>;
 	type ChainId = u32;
 	type Currency = Tokens;
-	type DefaultAdmin = DefaultAdmin;
 	type Event = Event;
 	type NativeCurrencyId = NativeCurrencyId;
 	type PalletId = BridgePalletId;
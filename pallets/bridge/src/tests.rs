@@ -0,0 +1,164 @@
+use super::*;
+use crate::mock::{new_test_ext, AccountId, Balance, Bridge, MerkleTrees, NativeCurrencyId, Origin, System, Test};
+use bulletproofs::{r1cs::Prover, BulletproofGens, PedersenGens};
+use bulletproofs_gadgets::{
+	fixed_deposit_tree::builder::FixedDepositTreeBuilder,
+	poseidon::{
+		builder::{Poseidon, PoseidonBuilder},
+		sbox::PoseidonSbox,
+	},
+};
+use curve25519_dalek::scalar::Scalar;
+use frame_support::{
+	assert_err, assert_ok,
+	storage::migration::{get_storage_value, put_storage_value},
+};
+use merlin::Transcript;
+use pallet_merkle::utils::{
+	keys::{get_bp_gen_bytes, slice_to_bytes_32},
+	setup::{Backend, Curve, HashFunction, Setup},
+};
+
+/// Field element a proof has to bind for the given public input bytes
+fn input_scalar(bytes: &[u8]) -> Scalar {
+	Scalar::from_bytes_mod_order(slice_to_bytes_32(&public_input_to_field(bytes)))
+}
+
+fn default_hasher(bp_gens: BulletproofGens) -> Poseidon {
+	let width = 6;
+	PoseidonBuilder::new(width)
+		.bulletproof_gens(bp_gens)
+		.sbox(PoseidonSbox::Exponentiation3)
+		.build()
+}
+
+/// Creates an initialised anchor with the given deposit size
+fn create_anchor(size: Balance) -> u32 {
+	let setup = Setup::new(HashFunction::PoseidonDefault, Backend::Bulletproofs(Curve::Curve25519));
+	let tree_id = <MerkleTrees as TreeTrait<Test>>::create_tree(Bridge::account_id(), true, setup, 32).unwrap();
+	let key = get_bp_gen_bytes(&BulletproofGens::new(16400, 1));
+	let key_id = <MerkleTrees as TreeTrait<Test>>::add_verifying_key(key).unwrap();
+	assert_ok!(<MerkleTrees as TreeTrait<Test>>::initialize_tree(tree_id, key_id));
+	Anchors::<Test>::insert(tree_id, AnchorInfo {
+		size,
+		currency_id: NativeCurrencyId::get(),
+	});
+	tree_id
+}
+
+#[test]
+fn should_remix_deposit_into_new_leaf() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(Bridge::initialize());
+		let tree_id = create_anchor(1_000);
+
+		let h = default_hasher(BulletproofGens::new(16400, 1));
+		let pc_gens = PedersenGens::default();
+		let relayer: AccountId = 1;
+
+		let mut ftree = FixedDepositTreeBuilder::new().hash_params(h.clone()).depth(32).build();
+		let leaf = ftree.generate_secrets().to_bytes();
+		ftree.tree.add_leaves(vec![leaf], None);
+		assert_ok!(<MerkleTrees as TreeTrait<Test>>::add_members(
+			Bridge::account_id(),
+			tree_id,
+			vec![leaf.to_vec()]
+		));
+
+		// the new leaf the deposit is remixed into
+		let mut new_ftree = FixedDepositTreeBuilder::new().hash_params(h).depth(32).build();
+		let new_leaf = new_ftree.generate_secrets().to_bytes().to_vec();
+
+		let root = MerkleTrees::get_merkle_root(tree_id).unwrap();
+		let remix_input = Bridge::remix_input(tree_id, &new_leaf);
+		let mut prover_transcript = Transcript::new(b"zk_membership_proof");
+		let prover = Prover::new(&pc_gens, &mut prover_transcript);
+		let (proof, (comms_cr, nullifier_hash, leaf_index_comms_cr, proof_comms_cr)) = ftree.prove_zk(
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&root)),
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&leaf)),
+			input_scalar(&remix_input),
+			input_scalar(&Bridge::remix_relayer_input(&relayer)),
+			&ftree.hash_params.bp_gens,
+			prover,
+		);
+
+		let comms: Vec<ScalarBytes> = comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let leaf_index_comms: Vec<ScalarBytes> = leaf_index_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let proof_comms: Vec<ScalarBytes> = proof_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let remix_proof = |recipient: Option<AccountId>| {
+			WithdrawProof::<Test>::new(
+				tree_id,
+				1,
+				root.clone(),
+				comms.clone(),
+				nullifier_hash.to_bytes().to_vec(),
+				proof.to_bytes(),
+				leaf_index_comms.clone(),
+				proof_comms.clone(),
+				recipient,
+				Some(relayer),
+			)
+			.encode()
+		};
+
+		assert_err!(
+			Bridge::remix_zk(Origin::signed(relayer), tree_id, remix_proof(Some(2)), new_leaf.clone()),
+			Error::<Test>::InvalidRemix
+		);
+		// the proof is bound to the new leaf, so it can't be redirected
+		assert_err!(
+			Bridge::remix_zk(Origin::signed(relayer), tree_id, remix_proof(None), leaf.to_vec()),
+			pallet_merkle::Error::<Test>::ZkVerificationFailed
+		);
+		// nor verified as a withdrawal to an account encoding to the remix
+		// input, since the relayer input is tagged
+		assert_err!(
+			<MerkleTrees as TreeTrait<Test>>::verify_zk(
+				tree_id,
+				1,
+				root.clone(),
+				comms.clone(),
+				nullifier_hash.to_bytes().to_vec(),
+				proof.to_bytes(),
+				leaf_index_comms.clone(),
+				proof_comms.clone(),
+				public_input_to_field(&remix_input),
+				public_input_to_field(&relayer.encode()),
+			),
+			pallet_merkle::Error::<Test>::ZkVerificationFailed
+		);
+
+		assert_ok!(Bridge::remix_zk(
+			Origin::signed(relayer),
+			tree_id,
+			remix_proof(None),
+			new_leaf.clone()
+		));
+		System::assert_last_event(crate::mock::Event::Bridge(crate::Event::Remix(
+			tree_id,
+			relayer,
+			root.clone(),
+		)));
+		assert_eq!(MerkleTrees::get_tree(tree_id).unwrap().leaf_count, 2);
+		assert_err!(
+			Bridge::remix_zk(Origin::signed(relayer), tree_id, remix_proof(None), new_leaf),
+			pallet_merkle::Error::<Test>::AlreadyUsedNullifier
+		);
+	})
+}
+
+#[test]
+fn should_remove_admin_on_migration() {
+	new_test_ext().execute_with(|| {
+		put_storage_value(b"Bridge", b"Admin", &[], 4u64);
+		migrations::remove_admin::<Test>();
+		assert_eq!(get_storage_value::<u64>(b"Bridge", b"Admin", &[]), None);
+		assert_eq!(StorageVersion::<Test>::get(), Releases::V2_0_0);
+
+		// it only runs once
+		put_storage_value(b"Bridge", b"Admin", &[], 4u64);
+		migrations::remove_admin::<Test>();
+		assert_eq!(get_storage_value::<u64>(b"Bridge", b"Admin", &[]), Some(4));
+	})
+}
@@ -22,25 +22,25 @@ impl<T: Config> core::default::Default for AnchorInfo<T> {
 #[derive(Encode, Decode, PartialEq, Clone)]
 pub struct WithdrawProof<T: Config> {
 	/// The mixer id this withdraw proof corresponds to
-	mixer_id: T::TreeId,
+	pub(crate) mixer_id: T::TreeId,
 	/// The cached block for the cached root being proven against
-	cached_block: <T as frame_system::Config>::BlockNumber,
+	pub(crate) cached_block: <T as frame_system::Config>::BlockNumber,
 	/// The cached root being proven against
-	cached_root: ScalarBytes,
+	pub(crate) cached_root: ScalarBytes,
 	/// The individual scalar commitments (to the randomness and nullifier)
-	comms: Vec<ScalarBytes>,
+	pub(crate) comms: Vec<ScalarBytes>,
 	/// The nullifier hash with itself
-	nullifier_hash: ScalarBytes,
+	pub(crate) nullifier_hash: ScalarBytes,
 	/// The proof in bytes representation
-	proof_bytes: Vec<u8>,
+	pub(crate) proof_bytes: Vec<u8>,
 	/// The leaf index scalar commitments to decide on which side to hash
-	leaf_index_commitments: Vec<ScalarBytes>,
+	pub(crate) leaf_index_commitments: Vec<ScalarBytes>,
 	/// The scalar commitments to merkle proof path elements
-	proof_commitments: Vec<ScalarBytes>,
+	pub(crate) proof_commitments: Vec<ScalarBytes>,
 	/// The recipient to withdraw amount of currency to
-	recipient: Option<<T as frame_system::Config>::AccountId>,
+	pub(crate) recipient: Option<<T as frame_system::Config>::AccountId>,
 	/// The recipient to withdraw amount of currency to
-	relayer: Option<<T as frame_system::Config>::AccountId>,
+	pub(crate) relayer: Option<<T as frame_system::Config>::AccountId>,
 }
 
 impl<T: Config> WithdrawProof<T> {
@@ -69,4 +69,27 @@ impl<T: Config> WithdrawProof<T> {
 			relayer,
 		}
 	}
+
+	/// The nullifier hash spent by the withdrawal
+	pub fn nullifier_hash(&self) -> &ScalarBytes {
+		&self.nullifier_hash
+	}
+}
+
+/// Storage layouts of the pallet, each reached by one of the
+/// [`migrations`](crate::migrations)
+#[cfg_attr(feature = "std", derive(Debug))]
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Releases {
+	/// `set_stopped` checked against the `Admin` account
+	V1_0_0,
+	/// `Admin` account removed by
+	/// [`migrations::remove_admin`](crate::migrations::remove_admin)
+	V2_0_0,
+}
+
+impl Default for Releases {
+	fn default() -> Self {
+		Releases::V1_0_0
+	}
 }
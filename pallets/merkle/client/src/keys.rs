@@ -14,6 +14,19 @@ pub fn slice_to_bytes_32(vec: &[u8]) -> [u8; 32] {
 	bytes_array
 }
 
+/// Maps the bytes of an account-like public input, such as a withdrawal
+/// recipient or relayer, to a canonical field element.
+///
+/// The bytes are hashed with blake2 and the hash is truncated to 251 bits,
+/// which is below the order of the Curve25519, BN254 and BLS12-381 scalar
+/// fields. Every backend therefore reads the same element without a modular
+/// reduction, and two inputs only share an element if their hashes collide.
+pub fn public_input_to_field(bytes: &[u8]) -> ScalarBytes {
+	let mut hash = sp_io::hashing::blake2_256(bytes);
+	hash[31] &= 0x07;
+	hash.to_vec()
+}
+
 pub fn get_bp_gen_bytes(bp_gens: &BulletproofGens) -> Vec<u8> {
 	let g_vec_bytes = bp_gens
 		.G_vec
@@ -131,3 +144,33 @@ pub fn from_bytes_to_bp_gens(mut input: &[u8]) -> BulletproofGens {
 		H_vec: vec_of_vecs_h,
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use arkworks_gadgets::prelude::{
+		ark_bls12_381::Fr as Bls381,
+		ark_bn254::Fr as Bn254Fr,
+		ark_ff::{to_bytes, PrimeField},
+	};
+	use curve25519_dalek::scalar::Scalar;
+
+	#[test]
+	fn should_map_public_inputs_to_canonical_elements() {
+		for input in [vec![], vec![0u8; 32], vec![0xffu8; 32], b"recipient".to_vec()].iter() {
+			let bytes = public_input_to_field(input);
+			assert!(Scalar::from_canonical_bytes(slice_to_bytes_32(&bytes)).is_some());
+			assert_eq!(to_bytes![Bn254Fr::from_le_bytes_mod_order(&bytes)].unwrap(), bytes);
+			assert_eq!(to_bytes![Bls381::from_le_bytes_mod_order(&bytes)].unwrap(), bytes);
+		}
+	}
+
+	#[test]
+	fn should_not_collide_accounts_equal_modulo_the_field() {
+		let account = [0xffu8; 32];
+		let reduced = Scalar::from_bytes_mod_order(account).to_bytes();
+		assert_ne!(account, reduced);
+		assert_eq!(Scalar::from_bytes_mod_order(account), Scalar::from_bytes_mod_order(reduced));
+		assert_ne!(public_input_to_field(&account), public_input_to_field(&reduced));
+	}
+}
@@ -0,0 +1,57 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! # Merkle Client
+//!
+//! Incremental merkle tree logic and hashing setups used by the merkle pallet,
+//! without any FRAME dependency.
+//!
+//! The pallet inserts leaves and checks paths through [`tree`], so the
+//! [`tree::LocalTree`] built off-chain by replaying `NewMembers` events
+//! produces the exact same roots and paths as the chain.
+//!
+//! - [`keys`] - Scalar byte helpers, the public input field mapping and
+//!   bulletproofs generators encoding.
+//! - [`reward`] - Reward claim circuit of the bulletproofs mixers.
+//! - [`setup`] - Hash functions, backends, zero trees and zk verification.
+//! - [`transaction`] - Notes and transaction circuit of the shielded pools.
+//! - [`tree`] - Incremental tree insertion and an in-memory tree.
+
+pub mod keys;
+pub mod reward;
+pub mod setup;
+pub mod transaction;
+pub mod tree;
+
+/// Errors returned by the tree and setup functions
+#[cfg_attr(feature = "std", derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+	/// Invalid merkle path length
+	InvalidPathLength,
+	/// Invalid commitments (private inputs) specified for the zk proof
+	InvalidPrivateInputs,
+	/// Invalid public inputs specified for the zk proof
+	InvalidPublicInputs,
+	/// Failed to generate zero (empty) tree
+	ZeroTreeGenFailed,
+	/// Failed to hash two values
+	HashingFailed,
+	/// Failed to verify zero-knowladge proof
+	ZkVerificationFailed,
+	/// Invalid verifier key
+	InvalidVerifierKey,
+	/// Unsatisfied constraint system
+	ConstraintSystemUnsatisfied,
+	/// Invalid zero-knowladge data
+	InvalidZkProof,
+	/// Invalid depth of the tree specified
+	InvalidTreeDepth,
+	/// Tree is full
+	ExceedsMaxLeaves,
+	/// Leaf index is not in the tree
+	InvalidLeafIndex,
+	/// Leaves were replayed out of order
+	UnexpectedLeafIndex,
+	/// Error for unimplemented functionality
+	Unimplemented,
+}
@@ -0,0 +1,291 @@
+//! Reward claim circuit of the bulletproofs mixers.
+//!
+//! A claim proves that a deposit `H(r, nullifier)` is under a root, like a
+//! withdrawal, but reveals the reward nullifier `H(nullifier, domain)` with
+//! [`REWARD_NULLIFIER_DOMAIN`] instead of the nullifier hash
+//! `H(nullifier, nullifier)`. Both are derived from the secret nullifier, so
+//! the claim and the withdrawal of the same deposit can't be linked.
+#[cfg(feature = "std")]
+use crate::keys::{slice_to_bytes_32, ScalarBytes};
+use crate::{setup::verifier_rng, Error};
+#[cfg(feature = "std")]
+use bulletproofs::r1cs::Prover;
+use bulletproofs::r1cs::{ConstraintSystem, LinearCombination, R1CSError, R1CSProof, Verifier};
+#[cfg(feature = "std")]
+use bulletproofs_gadgets::poseidon::allocate_statics_for_prover;
+use bulletproofs_gadgets::{
+	poseidon::{allocate_statics_for_verifier, builder::Poseidon, Poseidon_hash_2, Poseidon_hash_2_constraints},
+	utils::{AllocatedQuantity, AllocatedScalar},
+};
+use curve25519_dalek::{ristretto::CompressedRistretto, scalar::Scalar};
+use merlin::Transcript;
+#[cfg(feature = "std")]
+use rand_chacha::rand_core::{CryptoRng, RngCore};
+use sp_std::prelude::*;
+
+/// Label shared by the reward claim prover and verifier transcripts
+pub const ZK_REWARD_PROOF_LABEL: &[u8] = b"zk_reward_proof";
+
+/// Second input of the hash deriving reward nullifiers. Nullifier hashes
+/// hash the nullifier with itself, so the two never collide.
+pub const REWARD_NULLIFIER_DOMAIN: [u8; 32] = *b"webb-mixer-reward-nullifier-v1\0\0";
+
+/// Zero statics padding the inputs of the width 6 hasher
+const NUM_STATICS: usize = 4;
+
+fn domain() -> Scalar {
+	Scalar::from_bytes_mod_order(REWARD_NULLIFIER_DOMAIN)
+}
+
+/// Reward nullifier of the deposit with the given secret nullifier
+pub fn reward_nullifier(nullifier: Scalar, hasher: &Poseidon) -> Scalar {
+	Poseidon_hash_2(nullifier, domain(), hasher)
+}
+
+/// Constrains `r` and `nullifier` to a deposit under `root` whose reward
+/// nullifier is `reward_nullifier`.
+///
+/// `leaf_is_left` and `path_nodes` hold the path from the deposit up to the
+/// root, in the order of [`LocalTree::path`](crate::tree::LocalTree::path),
+/// and must have the same length. The recipient and relayer aren't used by
+/// the circuit, they are bound to the proof by a multiplication.
+pub fn reward_verif_gadget<CS: ConstraintSystem>(
+	cs: &mut CS,
+	recipient: &Scalar,
+	relayer: &Scalar,
+	root: &Scalar,
+	reward_nullifier: &Scalar,
+	r: AllocatedScalar,
+	nullifier: AllocatedScalar,
+	leaf_is_left: Vec<AllocatedScalar>,
+	path_nodes: Vec<AllocatedScalar>,
+	statics: Vec<AllocatedQuantity>,
+	hasher: &Poseidon,
+) -> Result<(), R1CSError> {
+	let statics: Vec<LinearCombination> = statics.iter().map(|s| s.variable.into()).collect();
+	let leaf = Poseidon_hash_2_constraints(
+		cs,
+		r.variable.into(),
+		nullifier.variable.into(),
+		statics.clone(),
+		hasher,
+	)?;
+	let computed_reward_nullifier = Poseidon_hash_2_constraints(
+		cs,
+		nullifier.variable.into(),
+		LinearCombination::from(domain()),
+		statics.clone(),
+		hasher,
+	)?;
+	cs.constrain(computed_reward_nullifier - LinearCombination::from(*reward_nullifier));
+
+	let mut node = leaf;
+	for (is_left, sibling) in leaf_is_left.into_iter().zip(path_nodes) {
+		let bit: LinearCombination = is_left.variable.into();
+		let (_, _, bit_check) = cs.multiply(bit.clone(), LinearCombination::from(Scalar::one()) - bit.clone());
+		cs.constrain(bit_check.into());
+
+		// left is the node when the bit is set and the sibling otherwise
+		let sibling: LinearCombination = sibling.variable.into();
+		let (_, _, picked) = cs.multiply(bit, node.clone() - sibling.clone());
+		let left = sibling.clone() + picked;
+		let right = node + sibling - left.clone();
+		node = Poseidon_hash_2_constraints(cs, left, right, statics.clone(), hasher)?;
+	}
+	cs.constrain(node - LinearCombination::from(*root));
+
+	let _ = cs.multiply(LinearCombination::from(*recipient), LinearCombination::from(*relayer));
+	Ok(())
+}
+
+/// Verifies a reward claim proof against `root`. `comms` are the commitments
+/// to `r` and the nullifier of the deposit.
+pub fn verify_reward_proof(
+	depth: usize,
+	root: Scalar,
+	comms: Vec<CompressedRistretto>,
+	reward_nullifier: Scalar,
+	proof_bytes: Vec<u8>,
+	leaf_is_left_commitments: Vec<CompressedRistretto>,
+	path_node_commitments: Vec<CompressedRistretto>,
+	recipient: Scalar,
+	relayer: Scalar,
+	hasher: &Poseidon,
+) -> Result<(), Error> {
+	if comms.len() != 2 {
+		return Err(Error::InvalidPrivateInputs);
+	}
+	if leaf_is_left_commitments.len() != depth || path_node_commitments.len() != depth {
+		return Err(Error::InvalidPathLength);
+	}
+	let mut rng_inputs: Vec<&[u8]> = vec![
+		ZK_REWARD_PROOF_LABEL,
+		root.as_bytes(),
+		reward_nullifier.as_bytes(),
+		recipient.as_bytes(),
+		relayer.as_bytes(),
+	];
+	rng_inputs.extend(comms.iter().map(|c| &c.as_bytes()[..]));
+	rng_inputs.extend(leaf_is_left_commitments.iter().map(|c| &c.as_bytes()[..]));
+	rng_inputs.extend(path_node_commitments.iter().map(|c| &c.as_bytes()[..]));
+	rng_inputs.push(&proof_bytes);
+	let mut rng = verifier_rng(&rng_inputs);
+
+	let mut verifier_transcript = Transcript::new(ZK_REWARD_PROOF_LABEL);
+	let mut verifier = Verifier::new(&mut verifier_transcript);
+	let mut allocate = |comm: CompressedRistretto| AllocatedScalar {
+		variable: verifier.commit(comm),
+		assignment: None,
+	};
+	let r = allocate(comms[0]);
+	let nullifier = allocate(comms[1]);
+	let leaf_is_left: Vec<_> = leaf_is_left_commitments.into_iter().map(&mut allocate).collect();
+	let path_nodes: Vec<_> = path_node_commitments.into_iter().map(&mut allocate).collect();
+	let statics = allocate_statics_for_verifier(&mut verifier, NUM_STATICS, &hasher.pc_gens);
+	reward_verif_gadget(
+		&mut verifier,
+		&recipient,
+		&relayer,
+		&root,
+		&reward_nullifier,
+		r,
+		nullifier,
+		leaf_is_left,
+		path_nodes,
+		statics,
+		hasher,
+	)
+	.map_err(|_| Error::ConstraintSystemUnsatisfied)?;
+
+	let proof = R1CSProof::from_bytes(&proof_bytes).map_err(|_| Error::InvalidZkProof)?;
+	verifier
+		.verify_with_rng(&proof, &hasher.pc_gens, &hasher.bp_gens, &mut rng)
+		.map_err(|_| Error::ZkVerificationFailed)
+}
+
+/// Reward claim proof with the commitments and public inputs it is verified
+/// with
+#[cfg(feature = "std")]
+pub struct RewardProof {
+	pub proof: R1CSProof,
+	pub comms: Vec<CompressedRistretto>,
+	pub leaf_is_left_commitments: Vec<CompressedRistretto>,
+	pub path_node_commitments: Vec<CompressedRistretto>,
+	pub reward_nullifier: Scalar,
+}
+
+/// Proves that the deposit of `r` and `nullifier` is under the root reached
+/// by `path`, as returned by
+/// [`LocalTree::path`](crate::tree::LocalTree::path).
+#[cfg(feature = "std")]
+pub fn prove_reward<R: RngCore + CryptoRng>(
+	r: Scalar,
+	nullifier: Scalar,
+	path: &[(bool, ScalarBytes)],
+	root: Scalar,
+	recipient: Scalar,
+	relayer: Scalar,
+	hasher: &Poseidon,
+	rng: &mut R,
+) -> Result<RewardProof, Error> {
+	let reward_nullifier = reward_nullifier(nullifier, hasher);
+	let mut prover_transcript = Transcript::new(ZK_REWARD_PROOF_LABEL);
+	let mut prover = Prover::new(&hasher.pc_gens, &mut prover_transcript);
+
+	let mut commit = |value: Scalar| {
+		let (comm, variable) = prover.commit(value, Scalar::random(&mut *rng));
+		let alloc = AllocatedScalar {
+			variable,
+			assignment: Some(value),
+		};
+		(comm, alloc)
+	};
+	let (r_comm, r_alloc) = commit(r);
+	let (nullifier_comm, nullifier_alloc) = commit(nullifier);
+	let (leaf_is_left_commitments, leaf_is_left): (Vec<_>, Vec<_>) = path
+		.iter()
+		.map(|(is_left, _)| commit(Scalar::from(*is_left as u8)))
+		.unzip();
+	let (path_node_commitments, path_nodes): (Vec<_>, Vec<_>) = path
+		.iter()
+		.map(|(_, node)| commit(Scalar::from_bytes_mod_order(slice_to_bytes_32(node))))
+		.unzip();
+	let statics = allocate_statics_for_prover(&mut prover, NUM_STATICS);
+	reward_verif_gadget(
+		&mut prover,
+		&recipient,
+		&relayer,
+		&root,
+		&reward_nullifier,
+		r_alloc,
+		nullifier_alloc,
+		leaf_is_left,
+		path_nodes,
+		statics,
+		hasher,
+	)
+	.map_err(|_| Error::ConstraintSystemUnsatisfied)?;
+	let proof = prover
+		.prove_with_rng(&hasher.bp_gens, rng)
+		.map_err(|_| Error::ConstraintSystemUnsatisfied)?;
+
+	Ok(RewardProof {
+		proof,
+		comms: vec![r_comm, nullifier_comm],
+		leaf_is_left_commitments,
+		path_node_commitments,
+		reward_nullifier,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		keys::get_bp_gen_bytes,
+		setup::{default_bulletproofs_poseidon_hasher, Backend, Curve, HashFunction, Setup},
+		tree::LocalTree,
+	};
+	use bulletproofs::BulletproofGens;
+	use rand_chacha::{rand_core::SeedableRng, ChaChaRng};
+
+	#[test]
+	fn should_verify_reward_proof_without_revealing_the_nullifier_hash() {
+		let bp_gens = BulletproofGens::new(4096, 1);
+		let hasher = default_bulletproofs_poseidon_hasher(bp_gens.clone());
+		let setup = Setup::new(HashFunction::PoseidonDefault, Backend::Bulletproofs(Curve::Curve25519));
+		let mut tree = LocalTree::new(setup, 3, get_bp_gen_bytes(&bp_gens)).unwrap();
+		let mut rng = ChaChaRng::from_seed([1u8; 32]);
+		let (r, nullifier) = (Scalar::random(&mut rng), Scalar::random(&mut rng));
+		tree.insert(Scalar::from(7u32).to_bytes().to_vec()).unwrap();
+		tree.insert(Poseidon_hash_2(r, nullifier, &hasher).to_bytes().to_vec())
+			.unwrap();
+		let root = Scalar::from_bytes_mod_order(slice_to_bytes_32(tree.root()));
+		let (recipient, relayer) = (Scalar::from(1u32), Scalar::from(2u32));
+		let path = tree.path(1).unwrap();
+		let claim = prove_reward(r, nullifier, &path, root, recipient, relayer, &hasher, &mut rng).unwrap();
+		let nullifier_hash = Poseidon_hash_2(nullifier, nullifier, &hasher);
+		assert_ne!(claim.reward_nullifier, nullifier_hash);
+
+		let verify = |reward_nullifier: Scalar, relayer: Scalar| {
+			verify_reward_proof(
+				3,
+				root,
+				claim.comms.clone(),
+				reward_nullifier,
+				claim.proof.to_bytes(),
+				claim.leaf_is_left_commitments.clone(),
+				claim.path_node_commitments.clone(),
+				recipient,
+				relayer,
+				&hasher,
+			)
+		};
+		assert_eq!(verify(claim.reward_nullifier, relayer), Ok(()));
+		assert_eq!(verify(nullifier_hash, relayer), Err(Error::ZkVerificationFailed));
+		assert_eq!(
+			verify(claim.reward_nullifier, recipient),
+			Err(Error::ZkVerificationFailed)
+		);
+	}
+}
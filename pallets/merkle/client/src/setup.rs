@@ -0,0 +1,739 @@
+use arkworks_gadgets::mimc::MiMCParameters;
+use crate::{
+	keys::{from_bytes_to_bp_gens, slice_to_bytes_32, ScalarBytes},
+	reward,
+	transaction::Note,
+	Error,
+};
+use ark_crypto_primitives::CRH as CRHTrait;
+use ark_groth16::{Proof, VerifyingKey};
+use ark_serialize::CanonicalDeserialize;
+use arkworks_gadgets::{
+	merkle_tree::gen_empty_hashes,
+	poseidon::PoseidonParameters,
+	prelude::{
+		ark_bls12_381::{Bls12_381, Fr as Bls381},
+		ark_bn254::{Bn254, Fr as Bn254Fr},
+		ark_ff::to_bytes,
+	},
+	setup::{
+		common::{
+			setup_params_x17_3, setup_params_x17_5, setup_params_x5_3, setup_params_x5_5, setup_mimc_220, verify_groth16,
+			Curve as CurveEnum, PoseidonCRH_x17_3, PoseidonCRH_x17_5, PoseidonCRH_x5_3, PoseidonCRH_x5_5, TreeConfig_x17,
+			TreeConfig_x5, MiMCCRH_220, MiMCTreeConfig_220,
+		},
+		mixer::get_public_inputs,
+	},
+	utils::to_field_elements,
+};
+use bulletproofs::{
+	r1cs::{R1CSProof, Verifier},
+	BulletproofGens, PedersenGens,
+};
+use bulletproofs_gadgets::{
+	fixed_deposit_tree::mixer_verif_gadget,
+	poseidon::{
+		allocate_statics_for_verifier,
+		builder::{Poseidon, PoseidonBuilder},
+		PoseidonSbox, Poseidon_hash_2,
+	},
+	smt::gen_zero_tree,
+	utils::AllocatedScalar,
+};
+use codec::{Decode, Encode};
+use curve25519_dalek::{ristretto::CompressedRistretto, scalar::Scalar};
+use lazy_static::lazy_static;
+use merlin::Transcript;
+use rand_chacha::{rand_core::SeedableRng, ChaChaRng};
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+use sp_std::prelude::*;
+use ark_ff::PrimeField;
+use ark_ec::PairingEngine;
+
+lazy_static! {
+	static ref BLS381_X5_POSEIDON: PoseidonParameters<Bls381> =
+		setup_params_x5_3::<Bls381>(CurveEnum::Bls381);
+	static ref BN254_X5_POSEIDON: PoseidonParameters<Bn254Fr> =
+		setup_params_x5_3::<Bn254Fr>(CurveEnum::Bn254);
+	static ref BLS381_X17_POSEIDON: PoseidonParameters<Bls381> =
+		setup_params_x17_3::<Bls381>(CurveEnum::Bls381);
+	static ref BN254_X17_POSEIDON: PoseidonParameters<Bn254Fr> =
+		setup_params_x17_3::<Bn254Fr>(CurveEnum::Bn254);
+	static ref BN254_MIMC_220: MiMCParameters<Bn254Fr> =
+		setup_mimc_220::<Bn254Fr>(CurveEnum::Bn254);
+	static ref BLS381_X5_5_POSEIDON: PoseidonParameters<Bls381> =
+		setup_params_x5_5::<Bls381>(CurveEnum::Bls381);
+	static ref BN254_X5_5_POSEIDON: PoseidonParameters<Bn254Fr> =
+		setup_params_x5_5::<Bn254Fr>(CurveEnum::Bn254);
+	static ref BLS381_X17_5_POSEIDON: PoseidonParameters<Bls381> =
+		setup_params_x17_5::<Bls381>(CurveEnum::Bls381);
+	static ref BN254_X17_5_POSEIDON: PoseidonParameters<Bn254Fr> =
+		setup_params_x17_5::<Bn254Fr>(CurveEnum::Bn254);
+}
+
+/// Label shared by the bulletproofs prover and verifier transcripts
+pub const ZK_MEMBERSHIP_PROOF_LABEL: &[u8] = b"zk_membership_proof";
+
+/// Deterministic RNG for bulletproofs verification.
+///
+/// The seed is a hash over the transcript label and the length-prefixed
+/// public inputs and proof, so the same proof verifies identically on chain,
+/// in RPC dry-runs and in off-chain tooling. Inputs are expected in the order
+/// root, nullifier hash, recipient, relayer, commitments, leaf index
+/// commitments, path commitments and finally the proof bytes.
+pub fn verifier_rng(inputs: &[&[u8]]) -> ChaChaRng {
+	let mut data = Vec::new();
+	data.extend_from_slice(ZK_MEMBERSHIP_PROOF_LABEL);
+	for input in inputs {
+		data.extend_from_slice(&(input.len() as u32).to_le_bytes());
+		data.extend_from_slice(input);
+	}
+	ChaChaRng::from_seed(sp_io::hashing::blake2_256(&data))
+}
+
+/// Default hasher instance used to construct the tree
+pub fn default_bulletproofs_poseidon_hasher(bp_gens: BulletproofGens) -> Poseidon {
+	let width = 6;
+	PoseidonBuilder::new(width)
+		.bulletproof_gens(bp_gens)
+		.sbox(PoseidonSbox::Exponentiation3)
+		.build()
+}
+
+/// Hash functions for MerkleTree
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+#[derive(Clone, Encode, Decode, PartialEq)]
+pub enum HashFunction {
+	PoseidonDefault,
+	// First argument is width, second is exponentiation
+	Poseidon(u8, u8),
+	MiMC,
+	Blake2,
+	Sha256,
+}
+
+/// Different curve types
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+#[derive(Clone, Encode, Decode, PartialEq)]
+pub enum Curve {
+	Bls381,
+	Bn254,
+	Curve25519,
+}
+
+/// Different curve types
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+#[derive(Clone, Encode, Decode, PartialEq)]
+pub enum Snark {
+	Groth16,
+	Marlin,
+}
+
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+#[derive(Clone, Encode, Decode, PartialEq)]
+pub enum Backend {
+	Arkworks(Curve, Snark),
+	Bulletproofs(Curve),
+}
+
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+#[derive(Clone, Encode, Decode, PartialEq)]
+pub struct Setup {
+	pub hasher: HashFunction,
+	pub backend: Backend,
+}
+
+impl Setup {
+	pub fn new(hasher: HashFunction, backend: Backend) -> Self {
+		Self { hasher, backend }
+	}
+
+	pub fn hash(&self, xl: &ScalarBytes, xr: &ScalarBytes, params: &[u8]) -> Result<ScalarBytes, Error> {
+		match &self.backend {
+			Backend::Bulletproofs(Curve::Curve25519) => match self.hasher {
+				HashFunction::PoseidonDefault | HashFunction::Poseidon(6, 3) => {
+					let bp_gens = from_bytes_to_bp_gens(params);
+					let hasher = default_bulletproofs_poseidon_hasher(bp_gens);
+					let sl = Scalar::from_bytes_mod_order(slice_to_bytes_32(xl));
+					let sr = Scalar::from_bytes_mod_order(slice_to_bytes_32(xr));
+					Ok(Poseidon_hash_2(sl, sr, &hasher).to_bytes().to_vec())
+				}
+				_ => Err(Error::Unimplemented),
+			},
+			Backend::Arkworks(curve, _) => {
+				// The hashers take at most as many field elements as their
+				// width, and panic on longer inputs
+				if xl.len() != 32 || xr.len() != 32 {
+					return Err(Error::HashingFailed);
+				}
+				let mut bytes = Vec::new();
+				bytes.extend(xl);
+				bytes.extend(xr);
+
+				match self.hasher {
+					HashFunction::PoseidonDefault | HashFunction::Poseidon(3, 5) => {
+						match curve {
+							Curve::Bls381 => {
+								let res = PoseidonCRH_x5_3::<Bls381>::evaluate(&BLS381_X5_POSEIDON, &bytes).map_err(|_| Error::HashingFailed)?;
+								let bytes_res = to_bytes![res];
+								let bytes = match bytes_res {
+									Ok(bytes) => bytes,
+									Err(_) => return Err(Error::HashingFailed),
+								};
+								Ok(bytes)
+							}
+							Curve::Bn254 => {
+								let res = PoseidonCRH_x5_3::<Bn254Fr>::evaluate(&BN254_X5_POSEIDON, &bytes).map_err(|_| Error::HashingFailed)?;
+								let bytes_res = to_bytes![res];
+								let bytes = match bytes_res {
+									Ok(bytes) => bytes,
+									Err(_) => return Err(Error::HashingFailed),
+								};
+								Ok(bytes)
+							}
+							_ => Err(Error::Unimplemented),
+						}
+					}
+					HashFunction::Poseidon(3, 17) => {
+						match curve {
+							Curve::Bls381 => {
+								let res = PoseidonCRH_x17_3::<Bls381>::evaluate(&BLS381_X17_POSEIDON, &bytes).map_err(|_| Error::HashingFailed)?;
+								let bytes_res = to_bytes![res];
+								let bytes = match bytes_res {
+									Ok(bytes) => bytes,
+									Err(_) => return Err(Error::HashingFailed),
+								};
+								Ok(bytes)
+							}
+							Curve::Bn254 => {
+								let res = PoseidonCRH_x17_3::<Bn254Fr>::evaluate(&BN254_X17_POSEIDON, &bytes).map_err(|_| Error::HashingFailed)?;
+								let bytes_res = to_bytes![res];
+								let bytes = match bytes_res {
+									Ok(bytes) => bytes,
+									Err(_) => return Err(Error::HashingFailed),
+								};
+								Ok(bytes)
+							}
+							_ => Err(Error::Unimplemented),
+						}
+					}
+					HashFunction::MiMC => {
+						match curve {
+							Curve::Bn254 => {
+								let res = MiMCCRH_220::<Bn254Fr>::evaluate(&BN254_MIMC_220, &bytes).map_err(|_| Error::HashingFailed)?;
+								let bytes_res = to_bytes![res];
+								let bytes = match bytes_res {
+									Ok(bytes) => bytes,
+									Err(_) => return Err(Error::HashingFailed),
+								};
+								Ok(bytes)
+							}
+							_ => Err(Error::Unimplemented),
+						}
+					}
+					_ => Err(Error::Unimplemented),
+				}
+			}
+			_ => Err(Error::Unimplemented),
+		}
+	}
+
+	pub fn generate_zero_tree(
+		&self,
+		depth: usize,
+		params: &[u8],
+	) -> Result<(Vec<ScalarBytes>, ScalarBytes), Error> {
+		match self.backend {
+			Backend::Bulletproofs(Curve::Curve25519) => match self.hasher {
+				HashFunction::PoseidonDefault => {
+					let bp_gens = from_bytes_to_bp_gens(params);
+					let hasher = default_bulletproofs_poseidon_hasher(bp_gens);
+					let zero_tree = gen_zero_tree(hasher.width, &hasher.sbox);
+					Self::zero_tree_levels(zero_tree.iter().map(|x| x.to_vec()).collect(), depth)
+				}
+				_ => Err(Error::Unimplemented),
+			},
+			Backend::Arkworks(Curve::Bls381, _) => match self.hasher {
+				HashFunction::PoseidonDefault => {
+					let res =
+						gen_empty_hashes::<TreeConfig_x5<Bls381>>(&(), &BLS381_X5_POSEIDON)
+							.map_err(|_| Error::ZeroTreeGenFailed)?;
+					let zero_tree: Vec<ScalarBytes> = res
+						.iter()
+						.map(|val| to_bytes![val].map_err(|_| Error::ZeroTreeGenFailed))
+						.collect::<Result<Vec<ScalarBytes>, _>>()?;
+					Self::zero_tree_levels(zero_tree, depth)
+				}
+				HashFunction::Poseidon(3, 17) => {
+					let res = gen_empty_hashes::<TreeConfig_x17<Bls381>>(&(), &BLS381_X17_POSEIDON)
+						.map_err(|_| Error::ZeroTreeGenFailed)?;
+					let zero_tree: Vec<ScalarBytes> = res
+						.iter()
+						.map(|val| to_bytes![val].map_err(|_| Error::ZeroTreeGenFailed))
+						.collect::<Result<Vec<ScalarBytes>, _>>()?;
+					Self::zero_tree_levels(zero_tree, depth)
+				}
+				_ => Err(Error::Unimplemented),
+			},
+			Backend::Arkworks(Curve::Bn254, _) => match self.hasher {
+				HashFunction::PoseidonDefault => {
+					let res =
+						gen_empty_hashes::<TreeConfig_x5<Bn254Fr>>(&(), &BN254_X5_POSEIDON)
+							.map_err(|_| Error::ZeroTreeGenFailed)?;
+					let zero_tree: Vec<ScalarBytes> = res
+						.iter()
+						.map(|val| to_bytes![val].map_err(|_| Error::ZeroTreeGenFailed))
+						.collect::<Result<Vec<ScalarBytes>, _>>()?;
+					Self::zero_tree_levels(zero_tree, depth)
+				}
+				HashFunction::Poseidon(3, 17) => {
+					let res = gen_empty_hashes::<TreeConfig_x17<Bn254Fr>>(&(), &BN254_X17_POSEIDON)
+						.map_err(|_| Error::ZeroTreeGenFailed)?;
+					let zero_tree: Vec<ScalarBytes> = res
+						.iter()
+						.map(|val| to_bytes![val].map_err(|_| Error::ZeroTreeGenFailed))
+						.collect::<Result<Vec<ScalarBytes>, _>>()?;
+					Self::zero_tree_levels(zero_tree, depth)
+				}
+				HashFunction::MiMC => {
+					let res = gen_empty_hashes::<MiMCTreeConfig_220<Bn254Fr>>(&(), &BN254_MIMC_220)
+						.map_err(|_| Error::ZeroTreeGenFailed)?;
+					let zero_tree: Vec<ScalarBytes> = res
+						.iter()
+						.map(|val| to_bytes![val].map_err(|_| Error::ZeroTreeGenFailed))
+						.collect::<Result<Vec<ScalarBytes>, _>>()?;
+					Self::zero_tree_levels(zero_tree, depth)
+				}
+				_ => Err(Error::Unimplemented),
+			},
+			_ => Err(Error::Unimplemented),
+		}
+	}
+
+	/// Splits the zero nodes of the levels under a tree of `depth` from its
+	/// empty root.
+	fn zero_tree_levels(mut zero_tree: Vec<ScalarBytes>, depth: usize) -> Result<(Vec<ScalarBytes>, ScalarBytes), Error> {
+		if depth >= zero_tree.len() {
+			return Err(Error::InvalidTreeDepth);
+		}
+		zero_tree.truncate(depth + 1);
+		let root = zero_tree.pop().ok_or(Error::InvalidTreeDepth)?;
+		Ok((zero_tree, root))
+	}
+
+	/// Computes the leaf commitment and the nullifier hash for a deposit note
+	/// made of `secret` and `nullifier`, matching the leaf circuit of the
+	/// backend.
+	///
+	/// The bulletproofs leaf is `hash(secret, nullifier)` and the nullifier
+	/// hash `hash(nullifier, nullifier)`, with the tree's hasher. `params` are
+	/// not used by the hash, so they can be empty.
+	///
+	/// The arkworks leaf circuits reduce the secret and the nullifier to field
+	/// elements and hash them with the width 5 parameters of the tree's
+	/// Poseidon exponentiation, or with the tree's MiMC parameters. Their
+	/// parameters are fixed per curve, so `params` are ignored.
+	pub fn commitment(
+		&self,
+		secret: &ScalarBytes,
+		nullifier: &ScalarBytes,
+		params: &[u8],
+	) -> Result<(ScalarBytes, ScalarBytes), Error> {
+		match self.backend {
+			Backend::Bulletproofs(Curve::Curve25519) => {
+				let leaf = self.hash(secret, nullifier, params)?;
+				let nullifier_hash = self.hash(nullifier, nullifier, params)?;
+				Ok((leaf, nullifier_hash))
+			}
+			Backend::Arkworks(Curve::Bls381, _) => match self.hasher {
+				HashFunction::PoseidonDefault | HashFunction::Poseidon(3, 5) => {
+					Self::arkworks_commitment::<Bls381, PoseidonCRH_x5_5<Bls381>>(&BLS381_X5_5_POSEIDON, secret, nullifier)
+				}
+				HashFunction::Poseidon(3, 17) => {
+					Self::arkworks_commitment::<Bls381, PoseidonCRH_x17_5<Bls381>>(&BLS381_X17_5_POSEIDON, secret, nullifier)
+				}
+				_ => Err(Error::Unimplemented),
+			},
+			Backend::Arkworks(Curve::Bn254, _) => match self.hasher {
+				HashFunction::PoseidonDefault | HashFunction::Poseidon(3, 5) => {
+					Self::arkworks_commitment::<Bn254Fr, PoseidonCRH_x5_5<Bn254Fr>>(&BN254_X5_5_POSEIDON, secret, nullifier)
+				}
+				HashFunction::Poseidon(3, 17) => {
+					Self::arkworks_commitment::<Bn254Fr, PoseidonCRH_x17_5<Bn254Fr>>(&BN254_X17_5_POSEIDON, secret, nullifier)
+				}
+				HashFunction::MiMC => {
+					Self::arkworks_commitment::<Bn254Fr, MiMCCRH_220<Bn254Fr>>(&BN254_MIMC_220, secret, nullifier)
+				}
+				_ => Err(Error::Unimplemented),
+			},
+			_ => Err(Error::Unimplemented),
+		}
+	}
+
+	fn arkworks_commitment<F: PrimeField, H: CRHTrait>(
+		params: &H::Parameters,
+		secret: &[u8],
+		nullifier: &[u8],
+	) -> Result<(ScalarBytes, ScalarBytes), Error> {
+		let secret = F::from_le_bytes_mod_order(secret);
+		let nullifier = F::from_le_bytes_mod_order(nullifier);
+		let leaf_bytes = to_bytes![secret, nullifier].map_err(|_| Error::HashingFailed)?;
+		let nullifier_bytes = to_bytes![nullifier, nullifier].map_err(|_| Error::HashingFailed)?;
+		let leaf = H::evaluate(params, &leaf_bytes).map_err(|_| Error::HashingFailed)?;
+		let nullifier_hash = H::evaluate(params, &nullifier_bytes).map_err(|_| Error::HashingFailed)?;
+		Ok((
+			to_bytes![leaf].map_err(|_| Error::HashingFailed)?,
+			to_bytes![nullifier_hash].map_err(|_| Error::HashingFailed)?,
+		))
+	}
+
+	/// Computes the commitment and the nullifier hash of a shielded pool note
+	/// of `amount` in `currency`, matching the
+	/// [`transaction`](crate::transaction) circuit. Only the arkworks backends
+	/// with the Poseidon exponentiation 5 hasher have one.
+	pub fn note_commitment(
+		&self,
+		amount: &ScalarBytes,
+		currency: &ScalarBytes,
+		secret: &ScalarBytes,
+		nullifier: &ScalarBytes,
+	) -> Result<(ScalarBytes, ScalarBytes), Error> {
+		match self.backend {
+			Backend::Arkworks(Curve::Bls381, _) => match self.hasher {
+				HashFunction::PoseidonDefault | HashFunction::Poseidon(3, 5) => {
+					Self::arkworks_note_commitment::<Bls381>(&BLS381_X5_5_POSEIDON, amount, currency, secret, nullifier)
+				}
+				_ => Err(Error::Unimplemented),
+			},
+			Backend::Arkworks(Curve::Bn254, _) => match self.hasher {
+				HashFunction::PoseidonDefault | HashFunction::Poseidon(3, 5) => {
+					Self::arkworks_note_commitment::<Bn254Fr>(&BN254_X5_5_POSEIDON, amount, currency, secret, nullifier)
+				}
+				_ => Err(Error::Unimplemented),
+			},
+			_ => Err(Error::Unimplemented),
+		}
+	}
+
+	fn arkworks_note_commitment<F: PrimeField>(
+		params: &PoseidonParameters<F>,
+		amount: &[u8],
+		currency: &[u8],
+		secret: &[u8],
+		nullifier: &[u8],
+	) -> Result<(ScalarBytes, ScalarBytes), Error> {
+		let note = Note {
+			amount: F::from_le_bytes_mod_order(amount),
+			secret: F::from_le_bytes_mod_order(secret),
+			nullifier: F::from_le_bytes_mod_order(nullifier),
+		};
+		let commitment = note.commitment(params, F::from_le_bytes_mod_order(currency))?;
+		let nullifier_hash = note.nullifier_hash(params)?;
+		Ok((
+			to_bytes![commitment].map_err(|_| Error::HashingFailed)?,
+			to_bytes![nullifier_hash].map_err(|_| Error::HashingFailed)?,
+		))
+	}
+
+	/// Verifies a zero-knowledge proof of membership in the mixer circuit.
+	///
+	/// `recipient_bytes` and `relayer_bytes` are taken as field elements, so
+	/// callers binding accounts map them with
+	/// [`public_input_to_field`](crate::keys::public_input_to_field) first.
+	///
+	/// The arkworks backends take no input commitments, so for them
+	/// `private_inputs_bytes` are the further public inputs of a circuit
+	/// extending the mixer's, allocated after its nullifier hash, root,
+	/// recipient and relayer. The mixer passes none.
+	pub fn verify_zk(
+		&self,
+		depth: usize,
+		root_bytes: ScalarBytes,
+		private_inputs_bytes: Vec<ScalarBytes>,
+		nullifier_hash_bytes: ScalarBytes,
+		proof_bytes: Vec<u8>,
+		verifier_key: Option<Vec<u8>>,
+		path_indices_bytes: Vec<ScalarBytes>,
+		path_nodes_bytes: Vec<ScalarBytes>,
+		recipient_bytes: ScalarBytes,
+		relayer_bytes: ScalarBytes,
+	) -> Result<(), Error> {
+		match self.backend {
+			Backend::Bulletproofs(Curve::Curve25519) => {
+				if verifier_key.is_none() {
+					return Err(Error::InvalidVerifierKey);
+				}
+
+				let bp_gens = from_bytes_to_bp_gens(&verifier_key.unwrap());
+				let hasher = default_bulletproofs_poseidon_hasher(bp_gens);
+				let root = Scalar::from_bytes_mod_order(slice_to_bytes_32(&root_bytes));
+				let private_inputs = private_inputs_bytes
+					.iter()
+					.map(|x| CompressedRistretto::from_slice(x))
+					.collect();
+				let nullifier_hash = Scalar::from_bytes_mod_order(slice_to_bytes_32(&nullifier_hash_bytes));
+				let path_indices = path_indices_bytes
+					.iter()
+					.map(|x| CompressedRistretto::from_slice(x))
+					.collect();
+				let path_nodes = path_nodes_bytes
+					.iter()
+					.map(|x| CompressedRistretto::from_slice(x))
+					.collect();
+				let recipient = Scalar::from_bytes_mod_order(slice_to_bytes_32(&recipient_bytes));
+				let relayer = Scalar::from_bytes_mod_order(slice_to_bytes_32(&relayer_bytes));
+				self.verify_bulletproofs_poseidon(
+					depth,
+					root,
+					private_inputs,
+					nullifier_hash,
+					proof_bytes,
+					path_indices,
+					path_nodes,
+					recipient,
+					relayer,
+					&hasher,
+				)
+			}
+			Backend::Arkworks(Curve::Bls381, Snark::Groth16) => {
+				self.groth_verify_helper::<Bls12_381>(
+					root_bytes,
+					nullifier_hash_bytes,
+					proof_bytes,
+					verifier_key,
+					recipient_bytes,
+					relayer_bytes,
+					private_inputs_bytes,
+				)
+			}
+			Backend::Arkworks(Curve::Bn254, Snark::Groth16) => {
+				self.groth_verify_helper::<Bn254>(
+					root_bytes,
+					nullifier_hash_bytes,
+					proof_bytes,
+					verifier_key,
+					recipient_bytes,
+					relayer_bytes,
+					private_inputs_bytes,
+				)
+			}
+			_ => return Err(Error::Unimplemented),
+		}
+	}
+
+	/// Verifies a reward claim proof of the
+	/// [`reward`](crate::reward) circuit. Only the bulletproofs backend has
+	/// one, `private_inputs_bytes` are the commitments to `r` and the
+	/// nullifier of the deposit.
+	pub fn verify_reward_zk(
+		&self,
+		depth: usize,
+		root_bytes: ScalarBytes,
+		private_inputs_bytes: Vec<ScalarBytes>,
+		reward_nullifier_bytes: ScalarBytes,
+		proof_bytes: Vec<u8>,
+		verifier_key: Option<Vec<u8>>,
+		path_indices_bytes: Vec<ScalarBytes>,
+		path_nodes_bytes: Vec<ScalarBytes>,
+		recipient_bytes: ScalarBytes,
+		relayer_bytes: ScalarBytes,
+	) -> Result<(), Error> {
+		match self.backend {
+			Backend::Bulletproofs(Curve::Curve25519) => {
+				let bp_gens = from_bytes_to_bp_gens(&verifier_key.ok_or(Error::InvalidVerifierKey)?);
+				let hasher = default_bulletproofs_poseidon_hasher(bp_gens);
+				let to_scalar = |x: &ScalarBytes| Scalar::from_bytes_mod_order(slice_to_bytes_32(x));
+				let to_points = |xs: Vec<ScalarBytes>| -> Vec<CompressedRistretto> {
+					xs.iter().map(|x| CompressedRistretto::from_slice(x)).collect()
+				};
+				reward::verify_reward_proof(
+					depth,
+					to_scalar(&root_bytes),
+					to_points(private_inputs_bytes),
+					to_scalar(&reward_nullifier_bytes),
+					proof_bytes,
+					to_points(path_indices_bytes),
+					to_points(path_nodes_bytes),
+					to_scalar(&recipient_bytes),
+					to_scalar(&relayer_bytes),
+					&hasher,
+				)
+			}
+			_ => Err(Error::Unimplemented),
+		}
+	}
+
+	/// Number of public inputs of the circuit `verifier_key` was generated
+	/// for. Only the arkworks Groth16 backends are supported.
+	pub fn public_input_count(&self, verifier_key: &[u8]) -> Result<usize, Error> {
+		match self.backend {
+			Backend::Arkworks(Curve::Bls381, Snark::Groth16) => {
+				Self::groth_public_input_count::<Bls12_381>(verifier_key)
+			}
+			Backend::Arkworks(Curve::Bn254, Snark::Groth16) => {
+				Self::groth_public_input_count::<Bn254>(verifier_key)
+			}
+			_ => Err(Error::Unimplemented),
+		}
+	}
+
+	fn groth_public_input_count<E: PairingEngine>(verifier_key: &[u8]) -> Result<usize, Error> {
+		let vk = VerifyingKey::<E>::deserialize(verifier_key).map_err(|_| Error::InvalidVerifierKey)?;
+		Ok(vk.gamma_abc_g1.len().saturating_sub(1))
+	}
+
+	pub fn groth_verify_helper<E: PairingEngine>(
+		&self,
+		root_bytes: ScalarBytes,
+		nullifier_hash_bytes: ScalarBytes,
+		proof_bytes: Vec<u8>,
+		verifier_key: Option<Vec<u8>>,
+		recipient_bytes: ScalarBytes,
+		relayer_bytes: ScalarBytes,
+		extra_inputs_bytes: Vec<ScalarBytes>,
+	) -> Result<(), Error> {
+		let nullifier_elts =
+			to_field_elements::<E::Fr>(&nullifier_hash_bytes).map_err(|_| Error::InvalidPublicInputs)?;
+		let root_elts =
+			to_field_elements::<E::Fr>(&root_bytes).map_err(|_| Error::InvalidPublicInputs)?;
+		let recipient_elts =
+			to_field_elements::<E::Fr>(&recipient_bytes).map_err(|_| Error::InvalidPublicInputs)?;
+		let relayer_elts =
+			to_field_elements::<E::Fr>(&relayer_bytes).map_err(|_| Error::InvalidPublicInputs)?;
+
+		let nullifier = nullifier_elts.get(0).ok_or(Error::InvalidPublicInputs)?;
+		let root = root_elts.get(0).ok_or(Error::InvalidPublicInputs)?;
+		let recipient = recipient_elts.get(0).ok_or(Error::InvalidPublicInputs)?;
+		let relayer = relayer_elts.get(0).ok_or(Error::InvalidPublicInputs)?;
+
+		if verifier_key.is_none() {
+			return Err(Error::InvalidVerifierKey);
+		}
+
+		let vk = VerifyingKey::<E>::deserialize(&verifier_key.unwrap()[..])
+			.map_err(|_| Error::InvalidVerifierKey)?;
+		let mut public_inputs = get_public_inputs::<E::Fr>(*nullifier, *root, *recipient, *relayer);
+		for bytes in extra_inputs_bytes.iter() {
+			let elts = to_field_elements::<E::Fr>(bytes).map_err(|_| Error::InvalidPublicInputs)?;
+			match elts.as_slice() {
+				[elt] => public_inputs.push(*elt),
+				_ => return Err(Error::InvalidPublicInputs),
+			}
+		}
+		// the verifier expects exactly one public input per key element after the first
+		if public_inputs.len() + 1 != vk.gamma_abc_g1.len() {
+			return Err(Error::InvalidPublicInputs);
+		}
+		let proof = Proof::<E>::deserialize(&proof_bytes[..]).map_err(|_| Error::InvalidZkProof)?;
+		let res = verify_groth16::<E>(&vk, &public_inputs, &proof);
+		if !res {
+			return Err(Error::ZkVerificationFailed);
+		}
+
+		Ok(())
+	}
+
+	// TODO: move to bulletproofs-gadgets
+	pub fn verify_bulletproofs_poseidon(
+		&self,
+		depth: usize,
+		cached_root: Scalar,
+		comms: Vec<CompressedRistretto>,
+		nullifier_hash: Scalar,
+		proof_bytes: Vec<u8>,
+		leaf_index_commitments: Vec<CompressedRistretto>,
+		proof_commitments: Vec<CompressedRistretto>,
+		recipient: Scalar,
+		relayer: Scalar,
+		hasher: &Poseidon,
+	) -> Result<(), Error> {
+		let pc_gens = PedersenGens::default();
+		// Seed the verification RNG from the proof before the commitments are consumed
+		let mut rng_inputs: Vec<&[u8]> = vec![
+			cached_root.as_bytes(),
+			nullifier_hash.as_bytes(),
+			recipient.as_bytes(),
+			relayer.as_bytes(),
+		];
+		rng_inputs.extend(comms.iter().map(|c| &c.as_bytes()[..]));
+		rng_inputs.extend(leaf_index_commitments.iter().map(|c| &c.as_bytes()[..]));
+		rng_inputs.extend(proof_commitments.iter().map(|c| &c.as_bytes()[..]));
+		rng_inputs.push(&proof_bytes);
+		let mut rng = verifier_rng(&rng_inputs);
+
+		let mut verifier_transcript = Transcript::new(ZK_MEMBERSHIP_PROOF_LABEL);
+		let mut verifier = Verifier::new(&mut verifier_transcript);
+
+		if comms.len() != 3 {
+			return Err(Error::InvalidPrivateInputs);
+		}
+		let r_val = verifier.commit(comms[0]);
+		let r_alloc = AllocatedScalar {
+			variable: r_val,
+			assignment: None,
+		};
+		let nullifier_val = verifier.commit(comms[1]);
+		let nullifier_alloc = AllocatedScalar {
+			variable: nullifier_val,
+			assignment: None,
+		};
+
+		let var_leaf = verifier.commit(comms[2]);
+		let leaf_alloc_scalar = AllocatedScalar {
+			variable: var_leaf,
+			assignment: None,
+		};
+
+		let mut leaf_index_alloc_scalars = vec![];
+		for l in leaf_index_commitments {
+			let v = verifier.commit(l);
+			leaf_index_alloc_scalars.push(AllocatedScalar {
+				variable: v,
+				assignment: None,
+			});
+		}
+
+		let mut proof_alloc_scalars = vec![];
+		for p in proof_commitments {
+			let v = verifier.commit(p);
+			proof_alloc_scalars.push(AllocatedScalar {
+				variable: v,
+				assignment: None,
+			});
+		}
+
+		let num_statics = 4;
+		let statics = allocate_statics_for_verifier(&mut verifier, num_statics, &pc_gens);
+		let gadget_res = mixer_verif_gadget(
+			&mut verifier,
+			&recipient,
+			&relayer,
+			depth as usize,
+			&cached_root,
+			&nullifier_hash,
+			r_alloc,
+			nullifier_alloc,
+			leaf_alloc_scalar,
+			leaf_index_alloc_scalars,
+			proof_alloc_scalars,
+			statics,
+			&hasher,
+		);
+		if !gadget_res.is_ok() {
+			return Err(Error::ConstraintSystemUnsatisfied);
+		}
+
+		let proof = R1CSProof::from_bytes(&proof_bytes);
+		if !proof.is_ok() {
+			return Err(Error::InvalidZkProof);
+		}
+		let proof = proof.unwrap();
+
+		let verify_res = verifier.verify_with_rng(&proof, &hasher.pc_gens, &hasher.bp_gens, &mut rng);
+		if !verify_res.is_ok() {
+			return Err(Error::ZkVerificationFailed);
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,289 @@
+//! Transaction circuit of the shielded pools.
+//!
+//! A note of a shielded pool is an amount of a currency, owned by whoever
+//! knows its secret and nullifier. Its commitment, the leaf added to the
+//! pool's tree, is `H(amount, currency, secret, nullifier)` and it's spent by
+//! revealing its nullifier hash `H(nullifier, nullifier)`, both with the width
+//! 5 Poseidon parameters of the tree's curve. The currency is the SCALE
+//! encoding of the currency id as a little-endian field element.
+//!
+//! A transaction spends two notes and creates two, all of the same currency.
+//! [`TransactionCircuit`] proves that the input notes of non-zero amount are
+//! under the root, that the nullifier hashes and output commitments match the
+//! notes, that every amount fits in [`AMOUNT_BITS`] bits and that the input
+//! amounts plus the deposit amount equal the output amounts plus the withdraw
+//! amount. Whenever funds enter or leave the pool the public currency must be
+//! the currency of the notes, so a note can't be withdrawn as another
+//! currency. Transfers inside the pool set it to zero and keep the currency
+//! private.
+//!
+//! The tree is hashed with the width 3 Poseidon parameters, as by the
+//! `PoseidonDefault` arkworks setups of the merkle pallet.
+use crate::{setup::Curve, Error};
+use ark_crypto_primitives::{crh::CRHGadget as CRHGadgetTrait, CRH as CRHTrait};
+use ark_ff::{to_bytes, PrimeField};
+use ark_r1cs_std::{fields::fp::FpVar, prelude::*};
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
+use arkworks_gadgets::{
+	poseidon::{constraints::PoseidonParametersVar, PoseidonParameters},
+	setup::common::{
+		setup_params_x5_3, setup_params_x5_5, Curve as CurveEnum, PoseidonCRH_x5_3, PoseidonCRH_x5_3Gadget,
+		PoseidonCRH_x5_5, PoseidonCRH_x5_5Gadget,
+	},
+};
+use sp_std::prelude::*;
+
+/// Number of bits every note amount must fit in, so sums of amounts can't
+/// wrap around the field
+pub const AMOUNT_BITS: usize = 128;
+
+/// Number of notes spent and created by each transaction
+pub const NOTES_PER_TRANSACTION: usize = 2;
+
+/// Number of public inputs of the transaction circuit
+pub const TRANSACTION_PUBLIC_INPUTS: usize = 11;
+
+fn arkworks_curve(curve: &Curve) -> Result<CurveEnum, Error> {
+	match curve {
+		Curve::Bls381 => Ok(CurveEnum::Bls381),
+		Curve::Bn254 => Ok(CurveEnum::Bn254),
+		Curve::Curve25519 => Err(Error::Unimplemented),
+	}
+}
+
+/// Poseidon parameters of the notes and of the tree for `curve`, whose scalar
+/// field is `F`
+pub fn poseidon_params<F: PrimeField>(curve: &Curve) -> Result<(PoseidonParameters<F>, PoseidonParameters<F>), Error> {
+	Ok((
+		setup_params_x5_5::<F>(arkworks_curve(curve)?),
+		setup_params_x5_3::<F>(arkworks_curve(curve)?),
+	))
+}
+
+fn hash<F: PrimeField, H: CRHTrait<Output = F>>(params: &H::Parameters, inputs: &[F]) -> Result<F, Error> {
+	let mut bytes = Vec::new();
+	for input in inputs {
+		bytes.extend(to_bytes![*input].map_err(|_| Error::HashingFailed)?);
+	}
+	H::evaluate(params, &bytes).map_err(|_| Error::HashingFailed)
+}
+
+fn hash_gadget<F: PrimeField, H: CRHTrait, HG: CRHGadgetTrait<H, F, OutputVar = FpVar<F>>>(
+	params: &HG::ParametersVar,
+	inputs: &[FpVar<F>],
+) -> Result<FpVar<F>, SynthesisError> {
+	let mut bytes = Vec::new();
+	for input in inputs {
+		bytes.extend(input.to_bytes()?);
+	}
+	HG::evaluate(params, &bytes)
+}
+
+/// A note of a shielded pool, without its currency
+#[derive(Clone)]
+pub struct Note<F: PrimeField> {
+	pub amount: F,
+	pub secret: F,
+	pub nullifier: F,
+}
+
+impl<F: PrimeField> Note<F> {
+	/// Commitment of the note in `currency`, with the width 5 parameters
+	pub fn commitment(&self, params: &PoseidonParameters<F>, currency: F) -> Result<F, Error> {
+		hash::<F, PoseidonCRH_x5_5<F>>(params, &[self.amount, currency, self.secret, self.nullifier])
+	}
+
+	/// Nullifier hash revealed when the note is spent, with the width 5
+	/// parameters
+	pub fn nullifier_hash(&self, params: &PoseidonParameters<F>) -> Result<F, Error> {
+		hash::<F, PoseidonCRH_x5_5<F>>(params, &[self.nullifier, self.nullifier])
+	}
+}
+
+/// A note spent by a transaction, with its path in the format of
+/// [`LocalTree::path`](crate::tree::LocalTree::path). The path of a note of
+/// zero amount isn't checked.
+#[derive(Clone)]
+pub struct InputNote<F: PrimeField> {
+	pub note: Note<F>,
+	pub path: Vec<(bool, F)>,
+}
+
+/// Public inputs of the transaction circuit. They are allocated in the order
+/// of the mixer circuit's nullifier hash, root, recipient and relayer,
+/// followed by the currency, the deposit and withdraw amounts, the hash of the
+/// external data, the second nullifier hash and the output commitments.
+#[derive(Clone)]
+pub struct TransactionInputs<F: PrimeField> {
+	pub nullifier_hashes: [F; NOTES_PER_TRANSACTION],
+	pub root: F,
+	pub recipient: F,
+	pub relayer: F,
+	pub currency: F,
+	pub deposit_amount: F,
+	pub withdraw_amount: F,
+	pub ext_data_hash: F,
+	pub output_commitments: [F; NOTES_PER_TRANSACTION],
+}
+
+impl<F: PrimeField> TransactionInputs<F> {
+	/// The public inputs in the order they are verified with
+	pub fn to_vec(&self) -> Vec<F> {
+		vec![
+			self.nullifier_hashes[0],
+			self.root,
+			self.recipient,
+			self.relayer,
+			self.currency,
+			self.deposit_amount,
+			self.withdraw_amount,
+			self.ext_data_hash,
+			self.nullifier_hashes[1],
+			self.output_commitments[0],
+			self.output_commitments[1],
+		]
+	}
+}
+
+/// Transaction circuit of a pool with the tree depth of the input paths
+#[derive(Clone)]
+pub struct TransactionCircuit<F: PrimeField> {
+	leaf_params: PoseidonParameters<F>,
+	tree_params: PoseidonParameters<F>,
+	public_inputs: TransactionInputs<F>,
+	currency: F,
+	inputs: [InputNote<F>; NOTES_PER_TRANSACTION],
+	outputs: [Note<F>; NOTES_PER_TRANSACTION],
+}
+
+impl<F: PrimeField> TransactionCircuit<F> {
+	/// Creates the circuit of a transaction of notes in `currency`. Fails if
+	/// a path isn't `depth` long.
+	pub fn new(
+		params: (PoseidonParameters<F>, PoseidonParameters<F>),
+		depth: usize,
+		public_inputs: TransactionInputs<F>,
+		currency: F,
+		inputs: [InputNote<F>; NOTES_PER_TRANSACTION],
+		outputs: [Note<F>; NOTES_PER_TRANSACTION],
+	) -> Result<Self, Error> {
+		if inputs.iter().any(|input| input.path.len() != depth) {
+			return Err(Error::InvalidPathLength);
+		}
+		Ok(Self {
+			leaf_params: params.0,
+			tree_params: params.1,
+			public_inputs,
+			currency,
+			inputs,
+			outputs,
+		})
+	}
+
+	/// Circuit of zero values, to generate the keys of pools of `depth`
+	pub fn blank(params: (PoseidonParameters<F>, PoseidonParameters<F>), depth: usize) -> Self {
+		let zero = F::zero();
+		let note = Note {
+			amount: zero,
+			secret: zero,
+			nullifier: zero,
+		};
+		let input = InputNote {
+			note: note.clone(),
+			path: vec![(false, zero); depth],
+		};
+		Self {
+			leaf_params: params.0,
+			tree_params: params.1,
+			public_inputs: TransactionInputs {
+				nullifier_hashes: [zero; NOTES_PER_TRANSACTION],
+				root: zero,
+				recipient: zero,
+				relayer: zero,
+				currency: zero,
+				deposit_amount: zero,
+				withdraw_amount: zero,
+				ext_data_hash: zero,
+				output_commitments: [zero; NOTES_PER_TRANSACTION],
+			},
+			currency: zero,
+			inputs: [input.clone(), input],
+			outputs: [note.clone(), note],
+		}
+	}
+}
+
+/// Allocates an amount and checks it fits in [`AMOUNT_BITS`] bits
+fn amount_var<F: PrimeField>(cs: ConstraintSystemRef<F>, amount: F) -> Result<FpVar<F>, SynthesisError> {
+	let amount = FpVar::new_witness(cs, || Ok(amount))?;
+	for bit in amount.to_bits_le()?.iter().skip(AMOUNT_BITS) {
+		bit.enforce_equal(&Boolean::FALSE)?;
+	}
+	Ok(amount)
+}
+
+impl<F: PrimeField> ConstraintSynthesizer<F> for TransactionCircuit<F> {
+	fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+		let leaf_params = PoseidonParametersVar::new_constant(cs.clone(), &self.leaf_params)?;
+		let tree_params = PoseidonParametersVar::new_constant(cs.clone(), &self.tree_params)?;
+		let leaf_hash = |inputs: &[FpVar<F>]| {
+			hash_gadget::<F, PoseidonCRH_x5_5<F>, PoseidonCRH_x5_5Gadget<F>>(&leaf_params, inputs)
+		};
+
+		let public = self.public_inputs;
+		let public_input = |value: F| FpVar::new_input(cs.clone(), || Ok(value));
+		let first_nullifier_hash = public_input(public.nullifier_hashes[0])?;
+		let root = public_input(public.root)?;
+		let recipient = public_input(public.recipient)?;
+		let relayer = public_input(public.relayer)?;
+		let public_currency = public_input(public.currency)?;
+		let deposit_amount = public_input(public.deposit_amount)?;
+		let withdraw_amount = public_input(public.withdraw_amount)?;
+		let ext_data_hash = public_input(public.ext_data_hash)?;
+		let second_nullifier_hash = public_input(public.nullifier_hashes[1])?;
+		let output_commitments = [
+			public_input(public.output_commitments[0])?,
+			public_input(public.output_commitments[1])?,
+		];
+
+		// the public currency is the notes' one whenever funds move
+		let currency = FpVar::new_witness(cs.clone(), || Ok(self.currency))?;
+		(&deposit_amount + &withdraw_amount).mul_equals(&(&public_currency - &currency), &FpVar::zero())?;
+
+		let mut input_total = FpVar::zero();
+		for (input, nullifier_hash) in self.inputs.iter().zip(&[first_nullifier_hash, second_nullifier_hash]) {
+			let amount = amount_var(cs.clone(), input.note.amount)?;
+			let secret = FpVar::new_witness(cs.clone(), || Ok(input.note.secret))?;
+			let nullifier = FpVar::new_witness(cs.clone(), || Ok(input.note.nullifier))?;
+			leaf_hash(&[nullifier.clone(), nullifier.clone()])?.enforce_equal(nullifier_hash)?;
+
+			let mut node = leaf_hash(&[amount.clone(), currency.clone(), secret, nullifier])?;
+			for (is_left, sibling) in input.path.iter() {
+				let is_left = Boolean::new_witness(cs.clone(), || Ok(*is_left))?;
+				let sibling = FpVar::new_witness(cs.clone(), || Ok(*sibling))?;
+				let left = is_left.select(&node, &sibling)?;
+				let right = is_left.select(&sibling, &node)?;
+				node = hash_gadget::<F, PoseidonCRH_x5_3<F>, PoseidonCRH_x5_3Gadget<F>>(&tree_params, &[left, right])?;
+			}
+			// notes of zero amount don't have to be in the tree
+			amount.mul_equals(&(&node - &root), &FpVar::zero())?;
+			input_total += &amount;
+		}
+
+		let mut output_total = FpVar::zero();
+		for (output, commitment) in self.outputs.iter().zip(&output_commitments) {
+			let amount = amount_var(cs.clone(), output.amount)?;
+			let secret = FpVar::new_witness(cs.clone(), || Ok(output.secret))?;
+			let nullifier = FpVar::new_witness(cs.clone(), || Ok(output.nullifier))?;
+			leaf_hash(&[amount.clone(), currency.clone(), secret, nullifier])?.enforce_equal(commitment)?;
+			output_total += &amount;
+		}
+		(input_total + deposit_amount).enforce_equal(&(output_total + withdraw_amount))?;
+
+		// bind the inputs the circuit doesn't use otherwise to the proof
+		let _ = recipient.square()?;
+		let _ = relayer.square()?;
+		let _ = ext_data_hash.square()?;
+		Ok(())
+	}
+}
@@ -0,0 +1,223 @@
+//! Incremental merkle tree shared by the pallet and off-chain clients
+use crate::{keys::ScalarBytes, setup::Setup, Error};
+use sp_std::prelude::*;
+
+/// Inserts the leaf at position `leaf_count` into the tree described by its
+/// zero tree and edge nodes, and returns the new root.
+///
+/// The edge nodes are only updated when every hash succeeds.
+pub fn insert_leaf(
+	setup: &Setup,
+	zero_tree: &[ScalarBytes],
+	edge_nodes: &mut Vec<ScalarBytes>,
+	leaf_count: u32,
+	leaf: &ScalarBytes,
+	params: &[u8],
+) -> Result<ScalarBytes, Error> {
+	let mut edge_index = leaf_count;
+	let mut hash = leaf.clone();
+	let mut new_edge_nodes = edge_nodes.clone();
+	for i in 0..new_edge_nodes.len() {
+		hash = if edge_index % 2 == 0 {
+			new_edge_nodes[i] = hash.clone();
+			setup.hash(&hash, &zero_tree[i], params)?
+		} else {
+			setup.hash(&new_edge_nodes[i], &hash, params)?
+		};
+
+		edge_index /= 2;
+	}
+
+	*edge_nodes = new_edge_nodes;
+	Ok(hash)
+}
+
+/// Hashes the leaf up the provided path and returns the resulting root.
+///
+/// Each path element holds the sibling node and whether the node being hashed
+/// sits on the left of it.
+pub fn compute_root(
+	setup: &Setup,
+	depth: usize,
+	leaf: ScalarBytes,
+	path: Vec<(bool, ScalarBytes)>,
+	params: &[u8],
+) -> Result<ScalarBytes, Error> {
+	if path.len() != depth {
+		return Err(Error::InvalidPathLength);
+	}
+	let mut hash = leaf;
+	for (is_right, node) in path {
+		hash = match is_right {
+			true => setup.hash(&hash, &node, params)?,
+			false => setup.hash(&node, &hash, params)?,
+		}
+	}
+	Ok(hash)
+}
+
+/// In-memory copy of an on-chain tree.
+///
+/// Built by replaying the leaves of `NewMembers` events in order, it tracks
+/// the same edge nodes and root as the pallet and can produce membership
+/// paths for any inserted leaf.
+#[cfg_attr(feature = "std", derive(Debug))]
+#[derive(Clone, PartialEq)]
+pub struct LocalTree {
+	setup: Setup,
+	params: Vec<u8>,
+	zero_tree: Vec<ScalarBytes>,
+	edge_nodes: Vec<ScalarBytes>,
+	root: ScalarBytes,
+	leaves: Vec<ScalarBytes>,
+}
+
+impl LocalTree {
+	/// Creates an empty tree. `params` are the verifying key bytes the
+	/// on-chain tree was initialized with.
+	pub fn new(setup: Setup, depth: u8, params: Vec<u8>) -> Result<Self, Error> {
+		if depth == 0 {
+			return Err(Error::InvalidTreeDepth);
+		}
+		let (zero_tree, root) = setup.generate_zero_tree(depth as usize, &params)?;
+		Ok(Self {
+			setup,
+			params,
+			edge_nodes: zero_tree.clone(),
+			zero_tree,
+			root,
+			leaves: Vec::new(),
+		})
+	}
+
+	pub fn root(&self) -> &ScalarBytes {
+		&self.root
+	}
+
+	pub fn leaves(&self) -> &[ScalarBytes] {
+		&self.leaves
+	}
+
+	pub fn leaf_count(&self) -> u32 {
+		self.leaves.len() as u32
+	}
+
+	/// Appends a leaf and returns the new root
+	pub fn insert(&mut self, leaf: ScalarBytes) -> Result<ScalarBytes, Error> {
+		let depth = self.zero_tree.len();
+		if depth < 32 && self.leaves.len() >= 1 << depth {
+			return Err(Error::ExceedsMaxLeaves);
+		}
+		self.root = insert_leaf(
+			&self.setup,
+			&self.zero_tree,
+			&mut self.edge_nodes,
+			self.leaf_count(),
+			&leaf,
+			&self.params,
+		)?;
+		self.leaves.push(leaf);
+		Ok(self.root.clone())
+	}
+
+	/// Replays the members of a `NewMembers` event, where `leaf_index` is the
+	/// leaf count the event was emitted with. Returns the new root.
+	pub fn replay(&mut self, leaf_index: u32, members: Vec<ScalarBytes>) -> Result<ScalarBytes, Error> {
+		if leaf_index != self.leaf_count() {
+			return Err(Error::UnexpectedLeafIndex);
+		}
+		for leaf in members {
+			self.insert(leaf)?;
+		}
+		Ok(self.root.clone())
+	}
+
+	/// Builds the membership path of the leaf at `index` against the current
+	/// root, in the format expected by the pallet's `verify`.
+	pub fn path(&self, index: u32) -> Result<Vec<(bool, ScalarBytes)>, Error> {
+		let mut idx = index as usize;
+		if idx >= self.leaves.len() {
+			return Err(Error::InvalidLeafIndex);
+		}
+		let mut layer = self.leaves.clone();
+		let mut path = Vec::with_capacity(self.zero_tree.len());
+		for zero in &self.zero_tree {
+			let sibling = layer.get(idx ^ 1).unwrap_or(zero).clone();
+			path.push((idx % 2 == 0, sibling));
+			layer = layer
+				.chunks(2)
+				.map(|pair| self.setup.hash(&pair[0], pair.get(1).unwrap_or(zero), &self.params))
+				.collect::<Result<Vec<_>, _>>()?;
+			idx /= 2;
+		}
+		Ok(path)
+	}
+
+	/// Hashes the leaf up the path with this tree's setup
+	pub fn compute_root(&self, leaf: ScalarBytes, path: Vec<(bool, ScalarBytes)>) -> Result<ScalarBytes, Error> {
+		compute_root(&self.setup, self.zero_tree.len(), leaf, path, &self.params)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		keys::get_bp_gen_bytes,
+		setup::{Backend, Curve, HashFunction},
+	};
+	use bulletproofs::BulletproofGens;
+	use curve25519_dalek::scalar::Scalar;
+
+	fn new_tree(depth: u8) -> LocalTree {
+		let setup = Setup::new(HashFunction::PoseidonDefault, Backend::Bulletproofs(Curve::Curve25519));
+		let params = get_bp_gen_bytes(&BulletproofGens::new(16, 1));
+		LocalTree::new(setup, depth, params).unwrap()
+	}
+
+	fn leaf(x: u64) -> ScalarBytes {
+		Scalar::from(x).to_bytes().to_vec()
+	}
+
+	#[test]
+	fn should_match_incremental_and_full_roots() {
+		let mut tree = new_tree(3);
+		for i in 0..7 {
+			tree.insert(leaf(i)).unwrap();
+			for j in 0..=i {
+				let path = tree.path(j as u32).unwrap();
+				assert_eq!(tree.compute_root(leaf(j), path).unwrap(), *tree.root());
+			}
+		}
+	}
+
+	#[test]
+	fn should_replay_events_in_order() {
+		let mut inserted = new_tree(3);
+		for i in 0..5 {
+			inserted.insert(leaf(i)).unwrap();
+		}
+
+		let mut replayed = new_tree(3);
+		replayed.replay(0, vec![leaf(0), leaf(1)]).unwrap();
+		assert_eq!(
+			replayed.replay(3, vec![leaf(3)]),
+			Err(Error::UnexpectedLeafIndex)
+		);
+		replayed.replay(2, vec![leaf(2), leaf(3), leaf(4)]).unwrap();
+		assert_eq!(replayed, inserted);
+	}
+
+	#[test]
+	fn should_reject_invalid_leaves_and_paths() {
+		let mut tree = new_tree(1);
+		assert_eq!(tree.path(0), Err(Error::InvalidLeafIndex));
+		tree.insert(leaf(0)).unwrap();
+		tree.insert(leaf(1)).unwrap();
+		assert_eq!(tree.insert(leaf(2)), Err(Error::ExceedsMaxLeaves));
+		assert_eq!(
+			tree.compute_root(leaf(0), vec![]),
+			Err(Error::InvalidPathLength)
+		);
+	}
+}
@@ -2,13 +2,30 @@
 
 use std::sync::Arc;
 
+use codec::Decode;
+use frame_metadata::{DecodeDifferent, RuntimeMetadata, RuntimeMetadataPrefixed};
 use jsonrpc_core::{Error, ErrorCode, Result};
 use jsonrpc_derive::rpc;
-use sp_api::ProvideRuntimeApi;
+use sp_api::{Metadata, ProvideRuntimeApi};
 use sp_blockchain::HeaderBackend;
-use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+use sp_core::Bytes;
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, NumberFor},
+	DispatchError,
+};
 
-use merkle::{utils::keys::ScalarBytes, MerkleApi as MerkleRuntimeApi};
+use merkle::{
+	utils::{keys::ScalarBytes, setup::Setup},
+	MerkleApi as MerkleRuntimeApi,
+};
+
+/// Error code for failures while calling into the runtime
+const RUNTIME_ERROR: i64 = 1;
+/// Error code for a setup that can't be decoded
+const DECODE_ERROR: i64 = 2;
+/// Error code for hashing requests the runtime rejects
+const HASHING_FAILED: i64 = 3;
 
 /// Merkle RPC methods.
 #[rpc]
@@ -23,6 +40,41 @@ pub trait MerkleApi<BlockHash> {
 	/// Returns the (full) a Vec<[u8; 32]> of the leaves.
 	#[rpc(name = "merkle_treeLeaves")]
 	fn tree_leaves(&self, tree_id: u32, from: usize, to: usize, at: Option<BlockHash>) -> Result<Vec<ScalarBytes>>;
+
+	/// Hash two nodes.
+	///
+	/// Hashes `left` and `right` with the SCALE encoded `Setup`, exactly as
+	/// the runtime does when inserting leaves.
+	#[rpc(name = "merkle_hash")]
+	fn hash(&self, setup: Bytes, left: ScalarBytes, right: ScalarBytes, at: Option<BlockHash>) -> Result<ScalarBytes>;
+
+	/// Get the zero tree.
+	///
+	/// Generates the zero tree of the given depth for the SCALE encoded
+	/// `Setup`, using the verifying key stored under `key_id`.
+	///
+	/// Returns the zero nodes of every level and the empty root.
+	#[rpc(name = "merkle_zeroTree")]
+	fn zero_tree(
+		&self,
+		setup: Bytes,
+		depth: u8,
+		key_id: u32,
+		at: Option<BlockHash>,
+	) -> Result<(Vec<ScalarBytes>, ScalarBytes)>;
+
+	/// Compute a note commitment.
+	///
+	/// Returns the leaf commitment and the nullifier hash of the note made
+	/// of `secret` and `nullifier` under the SCALE encoded `Setup`.
+	#[rpc(name = "merkle_commitment")]
+	fn commitment(
+		&self,
+		setup: Bytes,
+		secret: ScalarBytes,
+		nullifier: ScalarBytes,
+		at: Option<BlockHash>,
+	) -> Result<(ScalarBytes, ScalarBytes)>;
 }
 
 /// A struct that implements the `MerkleApi`.
@@ -41,11 +93,75 @@ impl<C, M> MerkleClient<C, M> {
 	}
 }
 
+fn decode_setup(setup: Bytes) -> Result<Setup> {
+	Setup::decode(&mut &*setup).map_err(|e| Error {
+		code: ErrorCode::ServerError(DECODE_ERROR),
+		message: "Unable to decode the setup".into(),
+		data: Some(format!("{:?}", e).into()),
+	})
+}
+
+fn runtime_error(e: impl std::fmt::Debug) -> Error {
+	Error {
+		code: ErrorCode::ServerError(RUNTIME_ERROR),
+		message: "Unable to query the runtime".into(),
+		data: Some(format!("{:?}", e).into()),
+	}
+}
+
+fn hashing_error<Block: BlockT, Api: Metadata<Block>>(api: &Api, at: &BlockId<Block>, e: DispatchError) -> Error {
+	Error {
+		code: ErrorCode::ServerError(HASHING_FAILED),
+		message: dispatch_error_message(api, at, e),
+		data: Some(format!("{:?}", e).into()),
+	}
+}
+
+/// Message of a dispatch error returned by the runtime. Module errors lose
+/// their name when they are decoded outside of the runtime, so it is looked
+/// up in the runtime metadata by the module and error indices.
+pub fn dispatch_error_message<Block: BlockT, Api: Metadata<Block>>(
+	api: &Api,
+	at: &BlockId<Block>,
+	error: DispatchError,
+) -> String {
+	if let DispatchError::Module { index, error, .. } = error {
+		let name = api
+			.metadata(at)
+			.ok()
+			.and_then(|metadata| module_error_name(&metadata, index, error));
+		if let Some(name) = name {
+			return name;
+		}
+	}
+	<&'static str>::from(error).into()
+}
+
+/// Name of the error with index `error` of the module with index `index`, in
+/// the SCALE encoded runtime metadata.
+fn module_error_name(metadata: &[u8], index: u8, error: u8) -> Option<String> {
+	let modules = match RuntimeMetadataPrefixed::decode(&mut &*metadata).ok()?.1 {
+		RuntimeMetadata::V13(metadata) => match metadata.modules {
+			DecodeDifferent::Decoded(modules) => modules,
+			DecodeDifferent::Encode(_) => return None,
+		},
+		_ => return None,
+	};
+	let errors = match modules.into_iter().find(|module| module.index == index)?.errors {
+		DecodeDifferent::Decoded(errors) => errors,
+		DecodeDifferent::Encode(_) => return None,
+	};
+	match &errors.get(error as usize)?.name {
+		DecodeDifferent::Decoded(name) => Some(name.clone()),
+		DecodeDifferent::Encode(_) => None,
+	}
+}
+
 impl<C, Block> MerkleApi<<Block as BlockT>::Hash> for MerkleClient<C, Block>
 where
 	Block: BlockT,
 	C: HeaderBackend<Block> + ProvideRuntimeApi<Block> + Send + Sync + 'static,
-	C::Api: MerkleRuntimeApi<Block>,
+	C::Api: MerkleRuntimeApi<Block, NumberFor<Block>> + Metadata<Block>,
 {
 	fn tree_leaves(
 		&self,
@@ -71,4 +187,49 @@ where
 			.collect();
 		Ok(leaves)
 	}
+
+	fn hash(
+		&self,
+		setup: Bytes,
+		left: ScalarBytes,
+		right: ScalarBytes,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<ScalarBytes> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+		let setup = decode_setup(setup)?;
+		api.hash(&at, setup, left, right)
+			.map_err(runtime_error)?
+			.map_err(|e| hashing_error(&*api, &at, e))
+	}
+
+	fn zero_tree(
+		&self,
+		setup: Bytes,
+		depth: u8,
+		key_id: u32,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<(Vec<ScalarBytes>, ScalarBytes)> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+		let setup = decode_setup(setup)?;
+		api.zero_tree(&at, setup, depth, key_id)
+			.map_err(runtime_error)?
+			.map_err(|e| hashing_error(&*api, &at, e))
+	}
+
+	fn commitment(
+		&self,
+		setup: Bytes,
+		secret: ScalarBytes,
+		nullifier: ScalarBytes,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<(ScalarBytes, ScalarBytes)> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+		let setup = decode_setup(setup)?;
+		api.commitment(&at, setup, secret, nullifier)
+			.map_err(runtime_error)?
+			.map_err(|e| hashing_error(&*api, &at, e))
+	}
 }
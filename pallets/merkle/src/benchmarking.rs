@@ -30,7 +30,7 @@ fn get_proof<T: Config>(tree_id: T::TreeId, depth: u32) -> Vec<(bool, ScalarByte
 	let mut path = Vec::new();
 	for _ in 0..depth {
 		path.push((true, d.clone()));
-		d = tree.setup.hash::<T>(&d, &d, &params).unwrap();
+		d = tree.setup.hash(&d, &d, &params).unwrap();
 	}
 	path
 }
@@ -130,36 +130,25 @@ benchmarks! {
 	}
 
 	on_finalize {
-		// For this test, runtime config has `CacheBlockLength` set to 10
-		// We are running on_finalize more that 10 times to make sure
-		// highest_block - lowest_block is > 10 so that
-		// CachedRoots::<T>::remove_prefix is called
-		let num_blocks = 13u32;
+		// Caching `n` roots at block 1, all of which are pruned once the
+		// highest cached block is `CacheBlockLength` blocks past it
+		let n in 1 .. T::MaxPrunedRoots::get();
 
 		let caller: T::AccountId = whitelisted_caller();
 		setup_tree::<T>(caller.clone(), 32);
-		for n in 0..num_blocks {
-			let last_block_number: T::BlockNumber = n.into();
-			let curr_block_number: T::BlockNumber = (n + 1).into();
-
-			// We are doing on_finalize before the deposit
-			// so that the last on_finalize can be benchmarked as a stand-alone
-			Merkle::<T>::on_finalize(last_block_number);
-			// Bumping the block number so that we can add cached roots to it
-			System::<T>::set_block_number(curr_block_number);
-			// Adding 100 leaves every block
-			let leaves = vec![Scalar::zero().to_bytes().to_vec(); 100];
+		System::<T>::set_block_number(1u32.into());
+		for i in 0..n {
+			let leaves = vec![Scalar::from(i as u64).to_bytes().to_vec()];
 			Merkle::<T>::add_members(RawOrigin::Signed(caller.clone()).into(), 0u32.into(), leaves).unwrap();
 		}
+		Merkle::<T>::on_finalize(1u32.into());
+		let last_block: T::BlockNumber = T::CacheBlockLength::get() + 1u32.into();
 	}: {
-		// Calling on finalize
-		let last_block: T::BlockNumber = num_blocks.into();
 		Merkle::<T>::on_finalize(last_block);
 	}
 	verify {
-		let latest_block: T::BlockNumber = HighestCachedBlock::<T>::get();
-		let block_number: T::BlockNumber = num_blocks.into();
-		assert_eq!(latest_block, block_number);
+		assert_eq!(HighestCachedBlock::<T>::get(), last_block);
+		assert_eq!(LowestCachedBlock::<T>::get(), 2u32.into());
 	}
 
 }
@@ -48,6 +48,11 @@
 //! - [`Group`](crate::traits::Group) Functions for creating and managing the
 //!   group.
 //!
+//! Tree insertion, path hashing and the `Setup` hashing backends live in the
+//! `webb-merkle-client` crate, which has no FRAME dependency. Off-chain
+//! clients can rebuild any tree with `merkle_client::tree::LocalTree` by
+//! replaying `NewMembers` events and get the same roots as the pallet.
+//!
 //! ## Interface
 //!
 //! ### Dispatchable functions
@@ -64,6 +69,17 @@
 //!   the manager if the manager is required.
 //! - `verify` - Verifies the membership proof.
 //!
+//! ### Runtime API
+//!
+//! - `MerkleApi::verify_path` - Verifies the membership proof against the
+//!   current root or any root still held in the root cache, without submitting
+//!   an extrinsic.
+//! - `MerkleApi::hash` - Hashes two nodes with a given setup.
+//! - `MerkleApi::zero_tree` - Generates the zero tree for a given setup, depth
+//!   and verifying key.
+//! - `MerkleApi::commitment` - Computes the leaf commitment and the nullifier
+//!   hash of a note.
+//!
 //! ## Usage
 //!
 //! The following examples show how to use the Merkle pallet in your custom
@@ -95,14 +111,17 @@ use codec::{Decode, Encode};
 use frame_support::{
 	dispatch::DispatchError,
 	ensure,
-	traits::{Get, Randomness},
+	traits::{Get, IsSubType},
 	weights::Weight,
 	Parameter,
 };
 use frame_system::ensure_signed;
-use sp_runtime::traits::{AtLeast32Bit, One};
+use sp_runtime::{
+	traits::{AtLeast32Bit, DispatchInfoOf, One, SignedExtension},
+	transaction_validity::{InvalidTransaction, TransactionValidity, TransactionValidityError, ValidTransaction},
+};
 use sp_std::prelude::*;
-pub use traits::Tree;
+pub use traits::{SpendsNullifier, Tree};
 use utils::{
 	keys::ScalarBytes,
 	permissions::ensure_admin,
@@ -132,9 +151,8 @@ pub mod pallet {
 		type MaxTreeDepth: Get<u8>;
 		/// The amount of blocks to cache roots over
 		type CacheBlockLength: Get<Self::BlockNumber>;
-		/// The generator used to supply randomness to contracts through
-		/// `seal_random`.
-		type Randomness: Randomness<Self::Hash, Self::BlockNumber>;
+		/// The max number of cached roots pruned in a single block
+		type MaxPrunedRoots: Get<u32>;
 		/// Weight information for extrinsics in this pallet.
 		type WeightInfo: WeightInfo;
 	}
@@ -187,6 +205,10 @@ pub mod pallet {
 		Unimplemented,
 		/// Unexpected/Unknown error
 		Unknown,
+		/// Leaf index is not in the tree
+		InvalidLeafIndex,
+		/// Leaves were replayed out of order
+		UnexpectedLeafIndex,
 	}
 
 	#[pallet::event]
@@ -252,6 +274,13 @@ pub mod pallet {
 		ValueQuery,
 	>;
 
+	/// Map of (tree_id, root) to the newest block the root was cached at.
+	/// Indexes `CachedRoots` so a historic root is found with a single read.
+	#[pallet::storage]
+	#[pallet::getter(fn cached_root_block)]
+	pub type CachedRootBlocks<T: Config> =
+		StorageDoubleMap<_, Blake2_128Concat, T::TreeId, Blake2_128Concat, ScalarBytes, T::BlockNumber, OptionQuery>;
+
 	/// Maps tree id to the manager of the tree
 	#[pallet::storage]
 	#[pallet::getter(fn get_manager)]
@@ -267,6 +296,10 @@ pub mod pallet {
 	#[pallet::getter(fn highest_cached_block)]
 	pub type HighestCachedBlock<T: Config> = StorageValue<_, T::BlockNumber, ValueQuery>;
 
+	/// The storage layout of the pallet, so each migration runs once
+	#[pallet::storage]
+	pub type StorageVersion<T: Config> = StorageValue<_, Releases, ValueQuery>;
+
 	/// Map of used nullifiers for each tree.
 	#[pallet::storage]
 	#[pallet::getter(fn used_nullifiers)]
@@ -284,8 +317,8 @@ pub mod pallet {
 	impl<T: Config> Hooks<T::BlockNumber> for Pallet<T> {
 		fn on_initialize(_n: T::BlockNumber) -> Weight {
 			// Returning the weights for `on_finalize` in worst-case scenario where all if
-			// branches are hit
-			<T as Config>::WeightInfo::on_finalize()
+			// branches are hit and the most roots are pruned
+			<T as Config>::WeightInfo::on_finalize(T::MaxPrunedRoots::get())
 		}
 
 		fn on_finalize(n: T::BlockNumber) {
@@ -302,8 +335,12 @@ pub mod pallet {
 			// update and prune database if pruning length has been hit
 			if HighestCachedBlock::<T>::get() > T::CacheBlockLength::get() {
 				if HighestCachedBlock::<T>::get() - T::CacheBlockLength::get() >= LowestCachedBlock::<T>::get() {
-					CachedRoots::<T>::remove_prefix(LowestCachedBlock::<T>::get());
-					LowestCachedBlock::<T>::set(LowestCachedBlock::<T>::get() + One::one());
+					let lowest_block = LowestCachedBlock::<T>::get();
+					// The lowest block only moves on once all of its roots are pruned,
+					// which can take more than one block
+					if Self::prune_cached_roots(lowest_block, T::MaxPrunedRoots::get()) {
+						LowestCachedBlock::<T>::set(lowest_block + One::one());
+					}
 				}
 			}
 		}
@@ -510,9 +547,33 @@ pub mod pallet {
 }
 
 sp_api::decl_runtime_apis! {
-	pub trait MerkleApi {
+	pub trait MerkleApi<BlockNumber> where BlockNumber: codec::Codec {
 		/// Get the leaf of tree id at a given index.
 		fn get_leaf(tree_id: u32, index: u32) -> Option<ScalarBytes>;
+		/// Verify a membership path against the current root or any cached
+		/// root of the tree. Returns the block number and the root that
+		/// matched.
+		fn verify_path(
+			tree_id: u32,
+			leaf: ScalarBytes,
+			path: Vec<(bool, ScalarBytes)>,
+		) -> Result<(BlockNumber, ScalarBytes), DispatchError>;
+		/// Hash two nodes with the given setup.
+		fn hash(setup: Setup, left: ScalarBytes, right: ScalarBytes) -> Result<ScalarBytes, DispatchError>;
+		/// Generate the zero tree for the given setup and depth using the
+		/// key material stored under `key_id`. Returns the zero nodes of every
+		/// level and the empty root.
+		fn zero_tree(
+			setup: Setup,
+			depth: u8,
+			key_id: u32,
+		) -> Result<(Vec<ScalarBytes>, ScalarBytes), DispatchError>;
+		/// Compute the leaf commitment and the nullifier hash of a note.
+		fn commitment(
+			setup: Setup,
+			secret: ScalarBytes,
+			nullifier: ScalarBytes,
+		) -> Result<(ScalarBytes, ScalarBytes), DispatchError>;
 	}
 }
 
@@ -525,6 +586,28 @@ pub struct Manager<T: Config> {
 	pub required: bool,
 }
 
+impl<T: Config> From<merkle_client::Error> for Error<T> {
+	fn from(e: merkle_client::Error) -> Self {
+		use merkle_client::Error as ClientError;
+		match e {
+			ClientError::InvalidPathLength => Error::<T>::InvalidPathLength,
+			ClientError::InvalidPrivateInputs => Error::<T>::InvalidPrivateInputs,
+			ClientError::InvalidPublicInputs => Error::<T>::InvalidPublicInputs,
+			ClientError::ZeroTreeGenFailed => Error::<T>::ZeroTreeGenFailed,
+			ClientError::HashingFailed => Error::<T>::HashingFailed,
+			ClientError::ZkVerificationFailed => Error::<T>::ZkVerificationFailed,
+			ClientError::InvalidVerifierKey => Error::<T>::InvalidVerifierKey,
+			ClientError::ConstraintSystemUnsatisfied => Error::<T>::ConstraintSystemUnsatisfied,
+			ClientError::InvalidZkProof => Error::<T>::InvalidZkProof,
+			ClientError::InvalidTreeDepth => Error::<T>::InvalidTreeDepth,
+			ClientError::ExceedsMaxLeaves => Error::<T>::ExceedsMaxLeaves,
+			ClientError::Unimplemented => Error::<T>::Unimplemented,
+			ClientError::InvalidLeafIndex => Error::<T>::InvalidLeafIndex,
+			ClientError::UnexpectedLeafIndex => Error::<T>::UnexpectedLeafIndex,
+		}
+	}
+}
+
 impl<T: Config> Manager<T> {
 	pub fn new(account_id: T::AccountId, required: bool) -> Self {
 		Self { account_id, required }
@@ -560,6 +643,23 @@ pub struct MerkleTree {
 	pub should_store_leaves: bool,
 }
 
+/// Storage layouts of the pallet, each reached by one of the
+/// [`migrations`]
+#[cfg_attr(feature = "std", derive(Debug))]
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Releases {
+	/// Cached roots looked up by block only
+	V1_0_0,
+	/// Cached roots indexed by [`migrations::index_cached_roots`]
+	V2_0_0,
+}
+
+impl Default for Releases {
+	fn default() -> Self {
+		Releases::V1_0_0
+	}
+}
+
 impl MerkleTree {
 	pub fn new<T: Config>(setup: Setup, depth: u8) -> Result<Self, Error<T>> {
 		// let (zero_tree, root_hash) = setup.generate_zero_tree(depth as usize)?;
@@ -609,7 +709,10 @@ impl<T: Config> Tree<T> for Pallet<T> {
 		let mut tree = Trees::<T>::get(tree_id).ok_or(Error::<T>::TreeDoesntExist)?;
 		ensure!(!tree.initialized, Error::<T>::AlreadyInitialized);
 		let params = Self::get_verifying_key(key_id)?;
-		let (zero_tree, root_hash) = tree.setup.generate_zero_tree::<T>(tree.depth as usize, &params)?;
+		let (zero_tree, root_hash) = tree
+			.setup
+			.generate_zero_tree(tree.depth as usize, &params)
+			.map_err(Error::<T>::from)?;
 		tree.root_hash = root_hash;
 		tree.edge_nodes = zero_tree.clone();
 		tree.zero_tree = zero_tree;
@@ -699,7 +802,7 @@ impl<T: Config> Tree<T> for Pallet<T> {
 			Self::add_leaf(&mut tree, data, &params)?;
 		}
 		let block_number: T::BlockNumber = <frame_system::Pallet<T>>::block_number();
-		CachedRoots::<T>::append(block_number, id, tree.root_hash.clone());
+		Self::cache_root(id, block_number, tree.root_hash.clone());
 		Trees::<T>::insert(id, Some(tree));
 
 		// Raising the New Member event for the client to build a tree locally
@@ -730,21 +833,31 @@ impl<T: Config> Tree<T> for Pallet<T> {
 
 	fn verify(id: T::TreeId, leaf: ScalarBytes, path: Vec<(bool, ScalarBytes)>) -> Result<(), DispatchError> {
 		let tree = Trees::<T>::get(id).ok_or(Error::<T>::TreeDoesntExist)?;
-
-		ensure!(tree.edge_nodes.len() == path.len(), Error::<T>::InvalidPathLength);
 		let params = Self::get_verifying_key_for_tree(id)?;
-		let mut hash = leaf;
-		for (is_right, node) in path {
-			hash = match is_right {
-				true => tree.setup.hash::<T>(&hash, &node, &params)?,
-				false => tree.setup.hash::<T>(&node, &hash, &params)?,
-			}
-		}
+		let hash = Self::compute_root(&tree, leaf, path, &params)?;
 
 		ensure!(hash == tree.root_hash, Error::<T>::InvalidMembershipProof);
 		Ok(())
 	}
 
+	fn verify_with_history(
+		id: T::TreeId,
+		leaf: ScalarBytes,
+		path: Vec<(bool, ScalarBytes)>,
+	) -> Result<(T::BlockNumber, ScalarBytes), DispatchError> {
+		let tree = Trees::<T>::get(id).ok_or(Error::<T>::TreeDoesntExist)?;
+		let params = Self::get_verifying_key_for_tree(id)?;
+		let hash = Self::compute_root(&tree, leaf, path, &params)?;
+
+		let block_number: T::BlockNumber = <frame_system::Pallet<T>>::block_number();
+		if hash == tree.root_hash {
+			return Ok((block_number, hash));
+		}
+
+		let cached_block = Self::cached_root_block(id, &hash).ok_or(Error::<T>::InvalidMembershipProof)?;
+		Ok((cached_block, hash))
+	}
+
 	fn verify_zk(
 		tree_id: T::TreeId,
 		block_number: T::BlockNumber,
@@ -757,24 +870,82 @@ impl<T: Config> Tree<T> for Pallet<T> {
 		recipient: ScalarBytes,
 		relayer: ScalarBytes,
 	) -> Result<(), DispatchError> {
-		let tree = Trees::<T>::get(tree_id).ok_or(Error::<T>::TreeDoesntExist)?;
-		let key_id = VerifyingKeyForTree::<T>::get(tree_id);
-		let verifying_key = VerifyingKeys::<T>::get(key_id);
+		let _ = Trees::<T>::get(tree_id).ok_or(Error::<T>::TreeDoesntExist)?;
 		// Ensure that root being checked against is in the cache
 		let old_roots = Self::cached_roots(block_number, tree_id);
 		ensure!(old_roots.iter().any(|r| *r == root), Error::<T>::InvalidMerkleRoot);
-		tree.setup.verify_zk::<T>(
-			tree.depth as usize,
+		Self::verify_zk_at_root(
+			tree_id,
 			root,
 			private_inputs,
 			nullifier_hash,
 			proof_bytes,
-			verifying_key,
 			path_indices,
 			path_nodes,
 			recipient,
 			relayer,
-		)?;
+		)
+	}
+
+	fn verify_zk_at_root(
+		tree_id: T::TreeId,
+		root: ScalarBytes,
+		private_inputs: Vec<ScalarBytes>,
+		nullifier_hash: ScalarBytes,
+		proof_bytes: Vec<u8>,
+		path_indices: Vec<ScalarBytes>,
+		path_nodes: Vec<ScalarBytes>,
+		recipient: ScalarBytes,
+		relayer: ScalarBytes,
+	) -> Result<(), DispatchError> {
+		let tree = Trees::<T>::get(tree_id).ok_or(Error::<T>::TreeDoesntExist)?;
+		let key_id = VerifyingKeyForTree::<T>::get(tree_id);
+		let verifying_key = VerifyingKeys::<T>::get(key_id);
+		tree.setup
+			.verify_zk(
+				tree.depth as usize,
+				root,
+				private_inputs,
+				nullifier_hash,
+				proof_bytes,
+				verifying_key,
+				path_indices,
+				path_nodes,
+				recipient,
+				relayer,
+			)
+			.map_err(Error::<T>::from)?;
+		Ok(())
+	}
+
+	fn verify_reward_zk_at_root(
+		tree_id: T::TreeId,
+		root: ScalarBytes,
+		private_inputs: Vec<ScalarBytes>,
+		reward_nullifier: ScalarBytes,
+		proof_bytes: Vec<u8>,
+		path_indices: Vec<ScalarBytes>,
+		path_nodes: Vec<ScalarBytes>,
+		recipient: ScalarBytes,
+		relayer: ScalarBytes,
+	) -> Result<(), DispatchError> {
+		let tree = Trees::<T>::get(tree_id).ok_or(Error::<T>::TreeDoesntExist)?;
+		let key_id = VerifyingKeyForTree::<T>::get(tree_id);
+		let verifying_key = VerifyingKeys::<T>::get(key_id);
+		tree.setup
+			.verify_reward_zk(
+				tree.depth as usize,
+				root,
+				private_inputs,
+				reward_nullifier,
+				proof_bytes,
+				verifying_key,
+				path_indices,
+				path_nodes,
+				recipient,
+				relayer,
+			)
+			.map_err(Error::<T>::from)?;
 		Ok(())
 	}
 }
@@ -792,10 +963,49 @@ impl<T: Config> Pallet<T> {
 
 	pub fn add_root_to_cache(tree_id: T::TreeId, block_number: T::BlockNumber) -> Result<(), DispatchError> {
 		let root = Self::get_merkle_root(tree_id)?;
-		CachedRoots::<T>::append(block_number, tree_id, root);
+		Self::cache_root(tree_id, block_number, root);
 		Ok(())
 	}
 
+	fn cache_root(tree_id: T::TreeId, block_number: T::BlockNumber, root: ScalarBytes) {
+		CachedRootBlocks::<T>::insert(tree_id, &root, block_number);
+		CachedRoots::<T>::append(block_number, tree_id, root);
+	}
+
+	/// Removes at most `max_roots` of the roots cached at `block_number`, with
+	/// their index entries. Returns whether no roots are left at the block.
+	fn prune_cached_roots(block_number: T::BlockNumber, max_roots: u32) -> bool {
+		let mut budget = max_roots as usize;
+		let mut entries = Vec::new();
+		let mut pruned_all = true;
+		for (tree_id, roots) in CachedRoots::<T>::iter_prefix(block_number) {
+			if budget == 0 {
+				pruned_all = false;
+				break;
+			}
+			let pruned = roots.len().min(budget);
+			budget -= pruned;
+			entries.push((tree_id, roots, pruned));
+		}
+
+		for (tree_id, mut roots, pruned) in entries {
+			let rest = roots.split_off(pruned);
+			if rest.is_empty() {
+				CachedRoots::<T>::remove(block_number, tree_id);
+			} else {
+				CachedRoots::<T>::insert(block_number, tree_id, rest);
+				pruned_all = false;
+			}
+			for root in roots {
+				// Keep the index of roots that were cached again later
+				if CachedRootBlocks::<T>::get(tree_id, &root) == Some(block_number) {
+					CachedRootBlocks::<T>::remove(tree_id, &root);
+				}
+			}
+		}
+		pruned_all
+	}
+
 	pub fn get_tree(tree_id: T::TreeId) -> Result<MerkleTree, DispatchError> {
 		let tree = Trees::<T>::get(tree_id).ok_or(Error::<T>::TreeDoesntExist).unwrap();
 		Ok(tree)
@@ -810,27 +1020,33 @@ impl<T: Config> Pallet<T> {
 	}
 
 	pub fn add_leaf(tree: &mut MerkleTree, data: &ScalarBytes, params: &[u8]) -> Result<(), DispatchError> {
-		let mut edge_index = tree.leaf_count;
-		let mut hash = data.clone();
-		let mut edge_nodes = tree.edge_nodes.clone();
-		// Update the tree
-		for i in 0..edge_nodes.len() {
-			hash = if edge_index % 2 == 0 {
-				edge_nodes[i] = hash.clone();
-				tree.setup.hash::<T>(&hash, &tree.zero_tree[i], params)?
-			} else {
-				tree.setup.hash::<T>(&edge_nodes[i], &hash, params)?
-			};
-
-			edge_index /= 2;
-		}
+		let root_hash = merkle_client::tree::insert_leaf(
+			&tree.setup,
+			&tree.zero_tree,
+			&mut tree.edge_nodes,
+			tree.leaf_count,
+			data,
+			params,
+		)
+		.map_err(Error::<T>::from)?;
 
 		tree.leaf_count += 1;
-		tree.root_hash = hash;
-		tree.edge_nodes = edge_nodes;
+		tree.root_hash = root_hash;
 		Ok(())
 	}
 
+	/// Hashes the leaf up the provided path and returns the resulting root
+	pub fn compute_root(
+		tree: &MerkleTree,
+		leaf: ScalarBytes,
+		path: Vec<(bool, ScalarBytes)>,
+		params: &[u8],
+	) -> Result<ScalarBytes, DispatchError> {
+		let hash = merkle_client::tree::compute_root(&tree.setup, tree.edge_nodes.len(), leaf, path, params)
+			.map_err(Error::<T>::from)?;
+		Ok(hash)
+	}
+
 	pub fn get_verifying_key_for_tree(id: T::TreeId) -> Result<Vec<u8>, DispatchError> {
 		let key_id = VerifyingKeyForTree::<T>::get(id);
 		Self::get_verifying_key(key_id)
@@ -842,4 +1058,140 @@ impl<T: Config> Pallet<T> {
 		ensure!(maybe_verifying_key.is_some(), Error::<T>::InvalidVerifierKey);
 		Ok(maybe_verifying_key.unwrap())
 	}
+
+	/// Hashes two nodes with the given setup. The bulletproofs Poseidon hash
+	/// only depends on its round constants, so no key material is needed.
+	pub fn hash_nodes(setup: Setup, left: ScalarBytes, right: ScalarBytes) -> Result<ScalarBytes, DispatchError> {
+		Ok(setup.hash(&left, &right, &[]).map_err(Error::<T>::from)?)
+	}
+
+	/// Generates the zero tree of a tree with the given setup and depth,
+	/// using the parameters stored under `key_id`. Returns the zero nodes of
+	/// every level and the empty root.
+	pub fn zero_tree(
+		setup: Setup,
+		depth: u8,
+		key_id: T::KeyId,
+	) -> Result<(Vec<ScalarBytes>, ScalarBytes), DispatchError> {
+		ensure!(
+			depth <= T::MaxTreeDepth::get() && depth > 0,
+			Error::<T>::InvalidTreeDepth
+		);
+		let params = Self::get_verifying_key(key_id)?;
+		Ok(setup
+			.generate_zero_tree(depth as usize, &params)
+			.map_err(Error::<T>::from)?)
+	}
+
+	/// Computes the leaf commitment and the nullifier hash of a deposit note.
+	pub fn commitment(
+		setup: Setup,
+		secret: ScalarBytes,
+		nullifier: ScalarBytes,
+	) -> Result<(ScalarBytes, ScalarBytes), DispatchError> {
+		Ok(setup.commitment(&secret, &nullifier, &[]).map_err(Error::<T>::from)?)
+	}
+
+	/// Transaction pool validity of a transaction spending `nullifier_hash`
+	/// from the tree with id of `tree_id`.
+	///
+	/// Stale if the nullifier is already used. Otherwise the transaction
+	/// provides the `(tree_id, nullifier_hash)` tag, so the pool keeps only one
+	/// of the transactions spending the same nullifier and replaces it with
+	/// one of higher priority, i.e. with a higher tip.
+	pub fn nullifier_validity(tree_id: T::TreeId, nullifier_hash: ScalarBytes) -> TransactionValidity {
+		if UsedNullifiers::<T>::contains_key((tree_id, nullifier_hash.clone())) {
+			return InvalidTransaction::Stale.into();
+		}
+		ValidTransaction::with_tag_prefix("MerkleNullifier")
+			.and_provides((tree_id, nullifier_hash))
+			.build()
+	}
+}
+
+/// Signed extension that keeps a single transaction per nullifier in the
+/// transaction pool and rejects the ones spending an already used nullifier
+/// before they are included. `C` is the call type of the pallet spending the
+/// nullifiers, see [`SpendsNullifier`] and [`Pallet::nullifier_validity`].
+#[derive(Encode, Decode, Clone, Eq, PartialEq)]
+pub struct CheckNullifier<T: Config + Send + Sync, C>(sp_std::marker::PhantomData<(T, C)>);
+
+impl<T: Config + Send + Sync, C> CheckNullifier<T, C> {
+	pub fn new() -> Self {
+		Self(sp_std::marker::PhantomData)
+	}
+}
+
+impl<T: Config + Send + Sync, C> sp_std::fmt::Debug for CheckNullifier<T, C> {
+	#[cfg(feature = "std")]
+	fn fmt(&self, f: &mut sp_std::fmt::Formatter) -> sp_std::fmt::Result {
+		write!(f, "CheckNullifier")
+	}
+
+	#[cfg(not(feature = "std"))]
+	fn fmt(&self, _: &mut sp_std::fmt::Formatter) -> sp_std::fmt::Result {
+		Ok(())
+	}
+}
+
+impl<T, C> SignedExtension for CheckNullifier<T, C>
+where
+	T: Config + Send + Sync,
+	C: SpendsNullifier<T::TreeId> + Clone + Eq + Send + Sync + 'static,
+	<T as frame_system::Config>::Call: IsSubType<C>,
+{
+	type AccountId = T::AccountId;
+	type AdditionalSigned = ();
+	type Call = <T as frame_system::Config>::Call;
+	type Pre = ();
+
+	const IDENTIFIER: &'static str = C::IDENTIFIER;
+
+	fn additional_signed(&self) -> Result<Self::AdditionalSigned, TransactionValidityError> {
+		Ok(())
+	}
+
+	fn validate(
+		&self,
+		_who: &Self::AccountId,
+		call: &Self::Call,
+		_info: &DispatchInfoOf<Self::Call>,
+		_len: usize,
+	) -> TransactionValidity {
+		match call.is_sub_type().and_then(|call| call.spent_nullifier()) {
+			Some((tree_id, nullifier_hash)) => Pallet::<T>::nullifier_validity(tree_id, nullifier_hash),
+			None => Ok(ValidTransaction::default()),
+		}
+	}
+}
+
+/// Migrations of the merkle pallet
+pub mod migrations {
+	use super::*;
+
+	/// Indexes the roots already in `CachedRoots` by tree and root, so proofs
+	/// made against them keep verifying with the history. Runs once, moving
+	/// the storage to [`Releases::V2_0_0`].
+	///
+	/// Meant to be called from the runtime's `OnRuntimeUpgrade`.
+	pub fn index_cached_roots<T: Config>() -> Weight {
+		if StorageVersion::<T>::get() >= Releases::V2_0_0 {
+			return T::DbWeight::get().reads(1);
+		}
+		StorageVersion::<T>::put(Releases::V2_0_0);
+		let mut reads: Weight = 1;
+		let mut writes: Weight = 1;
+		for (block_number, tree_id, roots) in CachedRoots::<T>::iter() {
+			reads += 1;
+			for root in roots {
+				// A root cached more than once is indexed at its newest block
+				reads += 1;
+				if CachedRootBlocks::<T>::get(tree_id, &root).map_or(true, |b| b < block_number) {
+					CachedRootBlocks::<T>::insert(tree_id, &root, block_number);
+					writes += 1;
+				}
+			}
+		}
+		T::DbWeight::get().reads_writes(reads, writes)
+	}
 }
@@ -69,6 +69,7 @@ parameter_types! {
 	pub const MaxTreeDepth: u8 = 32;
 	pub const MaxReserves: u32 = 50;
 	pub const CacheBlockLength: u64 = 5;
+	pub const MaxPrunedRoots: u32 = 2;
 	pub const MinimumDepositLength: u64 = 10;
 }
 
@@ -88,8 +89,8 @@ impl Config for Test {
 	type CacheBlockLength = CacheBlockLength;
 	type Event = Event;
 	type KeyId = u32;
+	type MaxPrunedRoots = MaxPrunedRoots;
 	type MaxTreeDepth = MaxTreeDepth;
-	type Randomness = Randomness;
 	type TreeId = u32;
 	type WeightInfo = Weights<Self>;
 }
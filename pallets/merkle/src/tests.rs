@@ -2,7 +2,7 @@ use super::*;
 use crate::{
 	mock::*,
 	utils::{
-		keys::{from_bytes_to_bp_gens, get_bp_gen_bytes, slice_to_bytes_32},
+		keys::{from_bytes_to_bp_gens, get_bp_gen_bytes, public_input_to_field, slice_to_bytes_32},
 		setup::{Backend, HashFunction, Setup, Snark},
 	},
 };
@@ -29,9 +29,15 @@ use bulletproofs_gadgets::{
 	smt::gen_zero_tree,
 };
 use curve25519_dalek::{ristretto::RistrettoPoint, scalar::Scalar};
-use frame_support::{assert_err, assert_ok, traits::UnfilteredDispatchable};
+use evm::{Context, ExitError};
+use fp_evm::Precompile;
+use frame_support::{
+	assert_err, assert_ok,
+	traits::{OnFinalize, UnfilteredDispatchable},
+};
 use frame_system::RawOrigin;
 use merlin::Transcript;
+use pallet_evm_precompile_bp_merkle_tree::BulletproofMerkleTreeMembershipPrecompile;
 use rand_chacha::rand_core::OsRng;
 use sp_runtime::traits::BadOrigin;
 
@@ -185,6 +191,104 @@ fn can_add_member() {
 	});
 }
 
+#[test]
+fn should_expose_hashing_matching_the_tree() {
+	new_test_ext().execute_with(|| {
+		let key = key_bytes(1).to_vec();
+
+		let hasher = HashFunction::PoseidonDefault;
+		let backend = Backend::Bulletproofs(Curve::Curve25519);
+		let setup = Setup::new(hasher.clone(), backend.clone());
+		assert_ok!(MerkleTrees::create_tree(
+			Origin::signed(1),
+			false,
+			setup.clone(),
+			Some(3),
+		));
+
+		let tree_id = 0;
+		let key_data = get_bp_gen_bytes(&BulletproofGens::new(4096, 1));
+		assert_ok!(MerkleTrees::add_verifying_key(Origin::signed(1), key_data));
+		let key_id = 0;
+		assert_ok!(MerkleTrees::initialize_tree(Origin::signed(1), tree_id, key_id));
+
+		// The zero tree matches the root of the empty tree
+		let (zero_tree, empty_root) = MerkleTrees::zero_tree(setup.clone(), 3, key_id).unwrap();
+		assert_eq!(zero_tree.len(), 3);
+		assert_eq!(MerkleTrees::get_merkle_root(tree_id).unwrap(), empty_root);
+
+		// Hashing the first leaf up with the zero nodes gives the new root
+		assert_ok!(MerkleTrees::add_members(Origin::signed(1), tree_id, vec![key.clone()]));
+		let mut root = key.clone();
+		for zero in zero_tree {
+			root = MerkleTrees::hash_nodes(setup.clone(), root, zero).unwrap();
+		}
+		assert_eq!(MerkleTrees::get_merkle_root(tree_id).unwrap(), root);
+
+		let secret = key_bytes(2).to_vec();
+		let nullifier = key_bytes(3).to_vec();
+		let (leaf, nullifier_hash) = MerkleTrees::commitment(setup.clone(), secret.clone(), nullifier.clone()).unwrap();
+		assert_eq!(
+			leaf,
+			MerkleTrees::hash_nodes(setup.clone(), secret, nullifier.clone()).unwrap()
+		);
+		assert_eq!(
+			nullifier_hash,
+			MerkleTrees::hash_nodes(setup.clone(), nullifier.clone(), nullifier).unwrap()
+		);
+
+		assert_err!(
+			MerkleTrees::zero_tree(setup.clone(), 0, key_id),
+			Error::<Test>::InvalidTreeDepth
+		);
+		assert_err!(
+			MerkleTrees::zero_tree(setup, 3, 1),
+			Error::<Test>::InvalidVerifierKey
+		);
+	});
+}
+
+#[test]
+fn should_derive_arkworks_commitments_and_reject_malformed_nodes() {
+	new_test_ext().execute_with(|| {
+		let secret = key_bytes(2).to_vec();
+		let nullifier = key_bytes(3).to_vec();
+		for curve in vec![Curve::Bls381, Curve::Bn254] {
+			let setup = Setup::new(HashFunction::PoseidonDefault, Backend::Arkworks(curve, Snark::Groth16));
+			let (leaf, nullifier_hash) =
+				MerkleTrees::commitment(setup.clone(), secret.clone(), nullifier.clone()).unwrap();
+			assert_eq!(leaf.len(), 32);
+			assert_ne!(leaf, nullifier_hash);
+			// secrets are reduced to field elements
+			let mut unreduced = secret.clone();
+			unreduced.extend(vec![0u8; 32]);
+			assert_eq!(
+				MerkleTrees::commitment(setup.clone(), unreduced, nullifier.clone()),
+				Ok((leaf.clone(), nullifier_hash))
+			);
+
+			// nodes that aren't a single field element are an error, not a panic
+			assert_err!(
+				MerkleTrees::hash_nodes(setup.clone(), leaf.clone(), vec![1u8; 33]),
+				Error::<Test>::HashingFailed
+			);
+			assert_err!(
+				MerkleTrees::hash_nodes(setup, vec![1u8; 32 * 4], vec![]),
+				Error::<Test>::HashingFailed
+			);
+		}
+
+		let setup = Setup::new(HashFunction::MiMC, Backend::Arkworks(Curve::Bn254, Snark::Groth16));
+		let (leaf, nullifier_hash) = MerkleTrees::commitment(setup, secret.clone(), nullifier.clone()).unwrap();
+		assert_ne!(leaf, nullifier_hash);
+		let setup = Setup::new(HashFunction::Blake2, Backend::Arkworks(Curve::Bn254, Snark::Groth16));
+		assert_err!(
+			MerkleTrees::commitment(setup, secret, nullifier),
+			Error::<Test>::Unimplemented
+		);
+	});
+}
+
 #[test]
 fn can_add_member_as_manager() {
 	new_test_ext().execute_with(|| {
@@ -388,8 +492,8 @@ fn should_have_correct_root_hash_after_insertion() {
 
 		assert_ok!(MerkleTrees::add_members(Origin::signed(1), 0, vec![key0.clone()]));
 
-		let keyh1 = setup.hash::<Test>(&key0, &zero_h0, &params).unwrap();
-		let keyh2 = setup.hash::<Test>(&keyh1, &zero_h1, &params).unwrap();
+		let keyh1 = setup.hash(&key0, &zero_h0, &params).unwrap();
+		let keyh2 = setup.hash(&keyh1, &zero_h1, &params).unwrap();
 
 		let tree = MerkleTrees::trees(0).unwrap();
 
@@ -397,8 +501,8 @@ fn should_have_correct_root_hash_after_insertion() {
 
 		assert_ok!(MerkleTrees::add_members(Origin::signed(2), 0, vec![key1.clone()]));
 
-		let keyh1 = setup.hash::<Test>(&key0, &key1, &params).unwrap();
-		let keyh2 = setup.hash::<Test>(&keyh1, &zero_h1, &params).unwrap();
+		let keyh1 = setup.hash(&key0, &key1, &params).unwrap();
+		let keyh2 = setup.hash(&keyh1, &zero_h1, &params).unwrap();
 
 		let tree = MerkleTrees::trees(0).unwrap();
 
@@ -406,9 +510,9 @@ fn should_have_correct_root_hash_after_insertion() {
 
 		assert_ok!(MerkleTrees::add_members(Origin::signed(3), 0, vec![key2.clone()]));
 
-		let keyh1 = setup.hash::<Test>(&key0, &key1, &params).unwrap();
-		let keyh2 = setup.hash::<Test>(&key2, &zero_h0, &params).unwrap();
-		let keyh3 = setup.hash::<Test>(&keyh1, &keyh2, &params).unwrap();
+		let keyh1 = setup.hash(&key0, &key1, &params).unwrap();
+		let keyh2 = setup.hash(&key2, &zero_h0, &params).unwrap();
+		let keyh3 = setup.hash(&keyh1, &keyh2, &params).unwrap();
 
 		let tree = MerkleTrees::trees(0).unwrap();
 
@@ -446,24 +550,24 @@ fn should_have_correct_root_hash() {
 
 		assert_ok!(MerkleTrees::add_members(Origin::signed(0), 0, keys.clone()));
 
-		let key1_1 = setup.hash::<Test>(&keys[0], &keys[1], &params).unwrap();
-		let key1_2 = setup.hash::<Test>(&keys[2], &keys[3], &params).unwrap();
-		let key1_3 = setup.hash::<Test>(&keys[4], &keys[5], &params).unwrap();
-		let key1_4 = setup.hash::<Test>(&keys[6], &keys[7], &params).unwrap();
-		let key1_5 = setup.hash::<Test>(&keys[8], &keys[9], &params).unwrap();
-		let key1_6 = setup.hash::<Test>(&keys[10], &keys[11], &params).unwrap();
-		let key1_7 = setup.hash::<Test>(&keys[12], &keys[13], &params).unwrap();
-		let key1_8 = setup.hash::<Test>(&keys[14], &zero_h0, &params).unwrap();
+		let key1_1 = setup.hash(&keys[0], &keys[1], &params).unwrap();
+		let key1_2 = setup.hash(&keys[2], &keys[3], &params).unwrap();
+		let key1_3 = setup.hash(&keys[4], &keys[5], &params).unwrap();
+		let key1_4 = setup.hash(&keys[6], &keys[7], &params).unwrap();
+		let key1_5 = setup.hash(&keys[8], &keys[9], &params).unwrap();
+		let key1_6 = setup.hash(&keys[10], &keys[11], &params).unwrap();
+		let key1_7 = setup.hash(&keys[12], &keys[13], &params).unwrap();
+		let key1_8 = setup.hash(&keys[14], &zero_h0, &params).unwrap();
 
-		let key2_1 = setup.hash::<Test>(&key1_1, &key1_2, &params).unwrap();
-		let key2_2 = setup.hash::<Test>(&key1_3, &key1_4, &params).unwrap();
-		let key2_3 = setup.hash::<Test>(&key1_5, &key1_6, &params).unwrap();
-		let key2_4 = setup.hash::<Test>(&key1_7, &key1_8, &params).unwrap();
+		let key2_1 = setup.hash(&key1_1, &key1_2, &params).unwrap();
+		let key2_2 = setup.hash(&key1_3, &key1_4, &params).unwrap();
+		let key2_3 = setup.hash(&key1_5, &key1_6, &params).unwrap();
+		let key2_4 = setup.hash(&key1_7, &key1_8, &params).unwrap();
 
-		let key3_1 = setup.hash::<Test>(&key2_1, &key2_2, &params).unwrap();
-		let key3_2 = setup.hash::<Test>(&key2_3, &key2_4, &params).unwrap();
+		let key3_1 = setup.hash(&key2_1, &key2_2, &params).unwrap();
+		let key3_2 = setup.hash(&key2_3, &key2_4, &params).unwrap();
 
-		let root_hash = setup.hash::<Test>(&key3_1, &key3_2, &params).unwrap();
+		let root_hash = setup.hash(&key3_1, &key3_2, &params).unwrap();
 
 		let tree = MerkleTrees::trees(0).unwrap();
 
@@ -547,9 +651,9 @@ fn should_not_verify_invalid_proof() {
 			key2.clone()
 		]));
 
-		let keyh1 = setup.hash::<Test>(&key0, &key1, &params).unwrap();
-		let keyh2 = setup.hash::<Test>(&key2, &zero_h0, &params).unwrap();
-		let _root_hash = setup.hash::<Test>(&keyh1, &keyh2, &params).unwrap();
+		let keyh1 = setup.hash(&key0, &key1, &params).unwrap();
+		let keyh2 = setup.hash(&key2, &zero_h0, &params).unwrap();
+		let _root_hash = setup.hash(&keyh1, &keyh2, &params).unwrap();
 
 		let path = vec![(false, key1.clone()), (true, keyh2.clone())];
 
@@ -604,24 +708,24 @@ fn should_verify_proof_of_membership() {
 
 		assert_ok!(MerkleTrees::add_members(Origin::signed(0), 0, keys.clone()));
 
-		let key1_1 = setup.hash::<Test>(&keys[0], &keys[1], &params).unwrap();
-		let key1_2 = setup.hash::<Test>(&keys[2], &keys[3], &params).unwrap();
-		let key1_3 = setup.hash::<Test>(&keys[4], &keys[5], &params).unwrap();
-		let key1_4 = setup.hash::<Test>(&keys[6], &keys[7], &params).unwrap();
-		let key1_5 = setup.hash::<Test>(&keys[8], &keys[9], &params).unwrap();
-		let key1_6 = setup.hash::<Test>(&keys[10], &keys[11], &params).unwrap();
-		let key1_7 = setup.hash::<Test>(&keys[12], &keys[13], &params).unwrap();
-		let key1_8 = setup.hash::<Test>(&keys[14], &zero_h0, &params).unwrap();
+		let key1_1 = setup.hash(&keys[0], &keys[1], &params).unwrap();
+		let key1_2 = setup.hash(&keys[2], &keys[3], &params).unwrap();
+		let key1_3 = setup.hash(&keys[4], &keys[5], &params).unwrap();
+		let key1_4 = setup.hash(&keys[6], &keys[7], &params).unwrap();
+		let key1_5 = setup.hash(&keys[8], &keys[9], &params).unwrap();
+		let key1_6 = setup.hash(&keys[10], &keys[11], &params).unwrap();
+		let key1_7 = setup.hash(&keys[12], &keys[13], &params).unwrap();
+		let key1_8 = setup.hash(&keys[14], &zero_h0, &params).unwrap();
 
-		let key2_1 = setup.hash::<Test>(&key1_1, &key1_2, &params).unwrap();
-		let key2_2 = setup.hash::<Test>(&key1_3, &key1_4, &params).unwrap();
-		let key2_3 = setup.hash::<Test>(&key1_5, &key1_6, &params).unwrap();
-		let key2_4 = setup.hash::<Test>(&key1_7, &key1_8, &params).unwrap();
+		let key2_1 = setup.hash(&key1_1, &key1_2, &params).unwrap();
+		let key2_2 = setup.hash(&key1_3, &key1_4, &params).unwrap();
+		let key2_3 = setup.hash(&key1_5, &key1_6, &params).unwrap();
+		let key2_4 = setup.hash(&key1_7, &key1_8, &params).unwrap();
 
-		let key3_1 = setup.hash::<Test>(&key2_1, &key2_2, &params).unwrap();
-		let key3_2 = setup.hash::<Test>(&key2_3, &key2_4, &params).unwrap();
+		let key3_1 = setup.hash(&key2_1, &key2_2, &params).unwrap();
+		let key3_2 = setup.hash(&key2_3, &key2_4, &params).unwrap();
 
-		let _root_hash = setup.hash::<Test>(&key3_1, &key3_2, &params).unwrap();
+		let _root_hash = setup.hash(&key3_1, &key3_2, &params).unwrap();
 
 		let path = vec![
 			(true, keys[1].clone()),
@@ -647,6 +751,184 @@ fn should_verify_proof_of_membership() {
 	});
 }
 
+#[test]
+fn should_verify_proof_of_membership_against_cached_root() {
+	new_test_ext().execute_with(|| {
+		let hasher = HashFunction::PoseidonDefault;
+		let backend = Backend::Bulletproofs(Curve::Curve25519);
+		let setup = Setup::new(hasher.clone(), backend.clone());
+		assert_ok!(MerkleTrees::create_tree(
+			Origin::signed(1),
+			false,
+			setup.clone(),
+			Some(2),
+		));
+
+		let tree_id = 0;
+		let key_data = get_bp_gen_bytes(&BulletproofGens::new(16400, 1));
+		assert_ok!(MerkleTrees::add_verifying_key(Origin::signed(1), key_data));
+		let key_id = 0;
+		assert_ok!(MerkleTrees::initialize_tree(Origin::signed(1), tree_id, key_id));
+		let params = MerkleTrees::get_verifying_key(key_id).unwrap();
+		let bp_gens = from_bytes_to_bp_gens(&params);
+		let h = default_hasher(bp_gens);
+		let zero_tree = gen_zero_tree(h.width, &h.sbox);
+		let key0 = key_bytes(0).to_vec();
+		let key1 = key_bytes(1).to_vec();
+		let key2 = key_bytes(2).to_vec();
+		let zero_h1 = zero_tree[1].to_vec();
+
+		System::set_block_number(1);
+		assert_ok!(MerkleTrees::add_members(Origin::signed(1), tree_id, vec![
+			key0.clone(),
+			key1.clone()
+		]));
+		let old_root = MerkleTrees::get_merkle_root(tree_id).unwrap();
+		let path = vec![(true, key1.clone()), (true, zero_h1.clone())];
+		assert_eq!(
+			MerkleTrees::verify_with_history(tree_id, key0.clone(), path.clone()),
+			Ok((1, old_root.clone()))
+		);
+
+		// A new insertion makes the path stale for the current root only
+		System::set_block_number(2);
+		assert_ok!(MerkleTrees::add_members(Origin::signed(1), tree_id, vec![key2]));
+		assert_err!(
+			MerkleTrees::verify(Origin::signed(2), tree_id, key0.clone(), path.clone()),
+			Error::<Test>::InvalidMembershipProof,
+		);
+		assert_eq!(
+			MerkleTrees::verify_with_history(tree_id, key0.clone(), path.clone()),
+			Ok((1, old_root.clone()))
+		);
+		assert_eq!(MerkleTrees::cached_root_block(tree_id, &old_root), Some(1));
+
+		let bad_path = vec![(false, key1), (true, zero_h1)];
+		assert_err!(
+			MerkleTrees::verify_with_history(tree_id, key0.clone(), bad_path),
+			Error::<Test>::InvalidMembershipProof,
+		);
+
+		// Pruning block 1 from the cache drops its roots from the index
+		for n in 1..=5 {
+			<MerkleTrees as OnFinalize<u64>>::on_finalize(n);
+		}
+		assert_eq!(MerkleTrees::cached_root_block(tree_id, &old_root), Some(1));
+		<MerkleTrees as OnFinalize<u64>>::on_finalize(6);
+		assert_eq!(MerkleTrees::cached_root_block(tree_id, &old_root), None);
+		assert_err!(
+			MerkleTrees::verify_with_history(tree_id, key0, path),
+			Error::<Test>::InvalidMembershipProof,
+		);
+	});
+}
+
+#[test]
+fn should_bound_cached_root_pruning_per_block() {
+	new_test_ext().execute_with(|| {
+		let setup = Setup::new(HashFunction::PoseidonDefault, Backend::Bulletproofs(Curve::Curve25519));
+		assert_ok!(MerkleTrees::create_tree(Origin::signed(1), false, setup, Some(2)));
+		let key_data = get_bp_gen_bytes(&BulletproofGens::new(16400, 1));
+		assert_ok!(MerkleTrees::add_verifying_key(Origin::signed(1), key_data));
+		assert_ok!(MerkleTrees::initialize_tree(Origin::signed(1), 0, 0));
+
+		// Three roots cached at block 1, while at most two are pruned per block
+		System::set_block_number(1);
+		let mut roots = Vec::new();
+		for i in 0..3 {
+			let leaf = key_bytes(i).to_vec();
+			assert_ok!(MerkleTrees::add_members(Origin::signed(1), 0, vec![leaf]));
+			roots.push(MerkleTrees::get_merkle_root(0).unwrap());
+		}
+		for n in 1..=6 {
+			<MerkleTrees as OnFinalize<u64>>::on_finalize(n);
+		}
+		assert_eq!(MerkleTrees::cached_roots(1, 0), vec![roots[2].clone()]);
+		assert_eq!(MerkleTrees::cached_root_block(0, &roots[0]), None);
+		assert_eq!(MerkleTrees::cached_root_block(0, &roots[1]), None);
+		assert_eq!(MerkleTrees::cached_root_block(0, &roots[2]), Some(1));
+		assert_eq!(MerkleTrees::lowest_cached_block(), 1);
+
+		<MerkleTrees as OnFinalize<u64>>::on_finalize(7);
+		assert!(MerkleTrees::cached_roots(1, 0).is_empty());
+		assert_eq!(MerkleTrees::cached_root_block(0, &roots[2]), None);
+		assert_eq!(MerkleTrees::lowest_cached_block(), 2);
+	});
+}
+
+#[test]
+fn should_index_cached_roots_on_migration() {
+	new_test_ext().execute_with(|| {
+		let root0 = key_bytes(0).to_vec();
+		let root1 = key_bytes(1).to_vec();
+		// Roots cached before the index existed, one of them twice
+		CachedRoots::<Test>::insert(1, 0, vec![root0.clone(), root1.clone()]);
+		CachedRoots::<Test>::insert(2, 0, vec![root0.clone()]);
+		CachedRoots::<Test>::insert(2, 1, vec![root1.clone()]);
+
+		migrations::index_cached_roots::<Test>();
+		assert_eq!(StorageVersion::<Test>::get(), Releases::V2_0_0);
+		assert_eq!(MerkleTrees::cached_root_block(0, &root0), Some(2));
+		assert_eq!(MerkleTrees::cached_root_block(0, &root1), Some(1));
+		assert_eq!(MerkleTrees::cached_root_block(1, &root1), Some(2));
+		assert_eq!(MerkleTrees::cached_root_block(1, &root0), None);
+
+		// it only runs once
+		CachedRoots::<Test>::insert(3, 0, vec![root0.clone()]);
+		migrations::index_cached_roots::<Test>();
+		assert_eq!(MerkleTrees::cached_root_block(0, &root0), Some(2));
+	});
+}
+
+#[test]
+fn should_match_local_tree_roots_and_paths() {
+	new_test_ext().execute_with(|| {
+		let hasher = HashFunction::PoseidonDefault;
+		let backend = Backend::Bulletproofs(Curve::Curve25519);
+		let setup = Setup::new(hasher.clone(), backend.clone());
+		assert_ok!(MerkleTrees::create_tree(
+			Origin::signed(1),
+			false,
+			setup.clone(),
+			Some(4),
+		));
+
+		let tree_id = 0;
+		let key_data = get_bp_gen_bytes(&BulletproofGens::new(16400, 1));
+		assert_ok!(MerkleTrees::add_verifying_key(Origin::signed(1), key_data));
+		let key_id = 0;
+		assert_ok!(MerkleTrees::initialize_tree(Origin::signed(1), tree_id, key_id));
+		let params = MerkleTrees::get_verifying_key(key_id).unwrap();
+		let mut local_tree = merkle_client::tree::LocalTree::new(setup, 4, params).unwrap();
+		assert_eq!(MerkleTrees::get_merkle_root(tree_id).unwrap(), *local_tree.root());
+
+		// Leaves are added in batches of different sizes, so both odd and even
+		// edge nodes are carried over between insertions
+		let keys = (0..11).map(|i| key_bytes(i as u8).to_vec()).collect::<Vec<_>>();
+		for batch in [&keys[..1], &keys[1..4], &keys[4..5], &keys[5..11]].iter() {
+			let leaf_index = local_tree.leaf_count();
+			assert_ok!(<MerkleTrees as Tree<Test>>::add_members(1, tree_id, batch.to_vec()));
+			let root = local_tree.replay(leaf_index, batch.to_vec()).unwrap();
+			assert_eq!(MerkleTrees::get_merkle_root(tree_id).unwrap(), root);
+			for (index, key) in keys[..local_tree.leaves().len()].iter().enumerate() {
+				let path = local_tree.path(index as u32).unwrap();
+				assert_ok!(MerkleTrees::verify(Origin::signed(2), tree_id, key.clone(), path));
+			}
+		}
+
+		// Client errors map to the matching pallet errors
+		let client_error = |e| DispatchError::from(Error::<Test>::from(e));
+		assert_err!(
+			local_tree.path(11).map_err(client_error),
+			Error::<Test>::InvalidLeafIndex
+		);
+		assert_err!(
+			local_tree.replay(0, vec![]).map_err(client_error),
+			Error::<Test>::UnexpectedLeafIndex
+		);
+	});
+}
+
 #[test]
 fn should_verify_simple_zk_proof_of_membership() {
 	new_test_ext().execute_with(|| {
@@ -1084,6 +1366,114 @@ fn should_verify_zk_proof_of_membership() {
 	});
 }
 
+#[test]
+fn should_verify_zk_proof_like_the_precompile() {
+	new_test_ext().execute_with(|| {
+		let pc_gens = PedersenGens::default();
+
+		let mut prover_transcript = Transcript::new(b"zk_membership_proof");
+		let prover = Prover::new(&pc_gens, &mut prover_transcript);
+
+		let hasher = HashFunction::PoseidonDefault;
+		let backend = Backend::Bulletproofs(Curve::Curve25519);
+		let setup = Setup::new(hasher.clone(), backend.clone());
+		assert_ok!(MerkleTrees::create_tree(
+			Origin::signed(1),
+			false,
+			setup.clone(),
+			Some(3),
+		));
+
+		let tree_id = 0;
+		// the generators of the precompile's hasher
+		let key_data = get_bp_gen_bytes(&BulletproofGens::new(16500, 1));
+		assert_ok!(MerkleTrees::add_verifying_key(Origin::signed(1), key_data));
+		let key_id = 0;
+		assert_ok!(MerkleTrees::initialize_tree(Origin::signed(1), tree_id, key_id));
+		let params = MerkleTrees::get_verifying_key(key_id).unwrap();
+		let bp_gens = from_bytes_to_bp_gens(&params);
+		let h = default_hasher(bp_gens);
+
+		let mut ftree = FixedDepositTreeBuilder::new().hash_params(h).depth(3).build();
+
+		let leaf0 = ftree.generate_secrets();
+		let leaf1 = ftree.generate_secrets();
+		let keys = vec![leaf0.to_bytes(), leaf1.to_bytes()];
+		ftree.tree.add_leaves(keys.clone(), None);
+
+		let keys_vec = keys.iter().map(|x| x.to_vec()).collect();
+		assert_ok!(MerkleTrees::add_members(Origin::signed(1), 0, keys_vec));
+
+		// the precompile takes the recipient and relayer words of the call and
+		// maps them to the field itself
+		let recipient_word = [1u8; 32];
+		let relayer_word = [2u8; 32];
+		let recipient = public_input_to_field(&recipient_word);
+		let relayer = public_input_to_field(&relayer_word);
+		let root = MerkleTrees::get_merkle_root(0).unwrap();
+		let (proof, (comms_cr, nullifier_hash, leaf_index_comms_cr, proof_comms_cr)) = ftree.prove_zk(
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&root)),
+			leaf1,
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&recipient)),
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&relayer)),
+			&ftree.hash_params.bp_gens,
+			prover,
+		);
+
+		let comms: Vec<ScalarBytes> = comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let leaf_index_comms: Vec<ScalarBytes> = leaf_index_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let proof_comms: Vec<ScalarBytes> = proof_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let invalid_nullifier_hash = Scalar::random(&mut OsRng::default()).to_bytes().to_vec();
+
+		let verify_with_pallet = |nullifier_hash: ScalarBytes| {
+			MerkleTrees::verify_zk(
+				0,
+				0,
+				root.clone(),
+				comms.clone(),
+				nullifier_hash,
+				proof.to_bytes(),
+				leaf_index_comms.clone(),
+				proof_comms.clone(),
+				recipient.clone(),
+				relayer.clone(),
+			)
+		};
+		let verify_with_precompile = |nullifier_hash: ScalarBytes| {
+			// 32 reserved bytes followed by the withdraw proof
+			let mut input = vec![0u8; 32];
+			input.push(3);
+			comms.iter().for_each(|c| input.extend(c));
+			proof_comms.iter().for_each(|c| input.extend(c));
+			leaf_index_comms.iter().for_each(|c| input.extend(c));
+			input.extend(&root);
+			input.extend(&nullifier_hash);
+			input.extend(&recipient_word);
+			input.extend(&relayer_word);
+			input.extend(proof.to_bytes());
+			let context = Context {
+				address: Default::default(),
+				caller: Default::default(),
+				apparent_value: From::from(0),
+			};
+			BulletproofMerkleTreeMembershipPrecompile::execute(&input, None, &context).map(|_| ())
+		};
+
+		// both derive the verification randomness from the proof, so they
+		// always agree on it
+		assert_ok!(verify_with_pallet(nullifier_hash.to_bytes().to_vec()));
+		assert_ok!(verify_with_precompile(nullifier_hash.to_bytes().to_vec()));
+		assert_err!(
+			verify_with_pallet(invalid_nullifier_hash.clone()),
+			Error::<Test>::ZkVerificationFailed
+		);
+		assert_err!(
+			verify_with_precompile(invalid_nullifier_hash),
+			ExitError::Other("ZkVerificationFailed".into())
+		);
+	});
+}
+
 #[test]
 fn should_verify_large_zk_proof_of_membership() {
 	new_test_ext().execute_with(|| {
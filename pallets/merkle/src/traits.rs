@@ -62,6 +62,42 @@ pub trait Tree<T: Config> {
 	) -> Result<(), dispatch::DispatchError>;
 	/// Verify membership proof
 	fn verify(id: T::TreeId, leaf: ScalarBytes, path: Vec<(bool, ScalarBytes)>) -> Result<(), dispatch::DispatchError>;
+	/// Verify membership proof against the current root or any root still
+	/// held in the root cache. Returns the block number and the root that
+	/// matched.
+	fn verify_with_history(
+		id: T::TreeId,
+		leaf: ScalarBytes,
+		path: Vec<(bool, ScalarBytes)>,
+	) -> Result<(T::BlockNumber, ScalarBytes), dispatch::DispatchError>;
+	/// Verify zero-knowladge membership proof against a root without
+	/// checking the root cache. Callers must make sure the root is one the
+	/// tree actually had.
+	fn verify_zk_at_root(
+		tree_id: T::TreeId,
+		root: ScalarBytes,
+		comms: Vec<ScalarBytes>,
+		nullifier_hash: ScalarBytes,
+		proof_bytes: Vec<u8>,
+		leaf_index_commitments: Vec<ScalarBytes>,
+		proof_commitments: Vec<ScalarBytes>,
+		recipient: ScalarBytes,
+		relayer: ScalarBytes,
+	) -> Result<(), dispatch::DispatchError>;
+	/// Verify zero-knowladge reward claim proof against a root without
+	/// checking the root cache, see [`crate::utils::reward`]. The reward
+	/// nullifier takes the place of the nullifier hash.
+	fn verify_reward_zk_at_root(
+		tree_id: T::TreeId,
+		root: ScalarBytes,
+		comms: Vec<ScalarBytes>,
+		reward_nullifier: ScalarBytes,
+		proof_bytes: Vec<u8>,
+		leaf_index_commitments: Vec<ScalarBytes>,
+		proof_commitments: Vec<ScalarBytes>,
+		recipient: ScalarBytes,
+		relayer: ScalarBytes,
+	) -> Result<(), dispatch::DispatchError>;
 	/// Verify zero-knowladge membership proof
 	fn verify_zk(
 		tree_id: T::TreeId,
@@ -76,3 +112,12 @@ pub trait Tree<T: Config> {
 		relayer: ScalarBytes,
 	) -> Result<(), dispatch::DispatchError>;
 }
+
+/// Call spending nullifiers of merkle trees, checked in the transaction pool
+/// by [`crate::CheckNullifier`]
+pub trait SpendsNullifier<TreeId> {
+	/// Identifier of the signed extension checking the calls
+	const IDENTIFIER: &'static str;
+	/// Tree and nullifier hash the call spends, if any
+	fn spent_nullifier(&self) -> Option<(TreeId, ScalarBytes)>;
+}
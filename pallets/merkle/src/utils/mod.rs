@@ -2,6 +2,6 @@
 
 //! Utility functions and data type definitions
 
-pub mod keys;
 pub mod permissions;
-pub mod setup;
+
+pub use merkle_client::{keys, reward, setup, transaction, tree};
@@ -0,0 +1,415 @@
+//! # Mixer Note
+//!
+//! Versioned string format of mixer deposit notes and the derivation of the
+//! leaf commitment and the nullifier hash of a note.
+//!
+//! A note holds everything needed to withdraw a deposit: where it was made,
+//! the setup of the mixer, its denomination and the two secrets. It is
+//! written as
+//!
+//! ```text
+//! webb.mixer:v1:<chain id>:<mixer id>:<backend>:<hasher>:<denomination>:<secret><nullifier>
+//! ```
+//!
+//! where the secret and the nullifier are 32 bytes each, hex encoded. The
+//! backend is one of `bulletproofs_<curve>` or `arkworks_<curve>_<snark>`, and
+//! the hasher one of `poseidon`, `poseidon_<width>_<exponentiation>`, `mimc`,
+//! `blake2` or `sha256`, following [`Backend`] and [`HashFunction`].
+//!
+//! The leaf and the nullifier hash are derived with [`Setup::commitment`],
+//! which is what the merkle pallet's `commitment` returns:
+//!
+//! - bulletproofs: the leaf is `hash(secret, nullifier)` and the nullifier hash
+//!   `hash(nullifier, nullifier)`, both with [`Setup::hash`].
+//! - arkworks: the secret and the nullifier are reduced to field elements of
+//!   the curve and hashed the same way as by the leaf circuit, with the width 5
+//!   Poseidon parameters of the hasher's exponentiation, or the MiMC ones.
+//!
+//! Withdrawal proofs bind the recipient and the relayer inputs computed by the
+//! mixer pallet, e.g. the SCALE encoded account, mapped to field elements with
+//! [`public_input_to_field`]. Wallets proving a withdrawal of a note use the
+//! same function, whichever backend the note is for.
+
+use merkle_client::{
+	keys::ScalarBytes,
+	setup::{Backend, Curve, HashFunction, Setup, Snark},
+	Error as SetupError,
+};
+use std::{fmt, str::FromStr};
+
+pub use merkle_client::keys::public_input_to_field;
+
+/// Prefix of every note string
+pub const NOTE_PREFIX: &str = "webb.mixer";
+/// Version of the note format written by [`Note`]
+pub const NOTE_VERSION: &str = "v1";
+/// Length in bytes of the secret and of the nullifier of a note
+pub const SECRET_LENGTH: usize = 32;
+
+/// Errors returned when parsing a note or deriving its commitments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+	/// Note doesn't start with [`NOTE_PREFIX`]
+	InvalidPrefix,
+	/// Note version isn't supported
+	UnsupportedVersion,
+	/// Note doesn't have the expected number of fields
+	InvalidFormat,
+	/// Chain id, mixer id or denomination isn't a decimal number
+	InvalidNumber,
+	/// Backend isn't known
+	InvalidBackend,
+	/// Hash function isn't known
+	InvalidHashFunction,
+	/// Secrets aren't hex encoded or have the wrong length
+	InvalidSecrets,
+	/// Hashing with the setup of the note failed
+	Setup(SetupError),
+}
+
+impl From<SetupError> for Error {
+	fn from(e: SetupError) -> Self {
+		Error::Setup(e)
+	}
+}
+
+/// Deposit note of a mixer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+	/// Id of the chain the mixer is on
+	pub chain_id: u64,
+	/// Id of the mixer tree
+	pub mixer_id: u32,
+	/// Hash function and backend of the mixer
+	pub setup: Setup,
+	/// Deposit size of the mixer
+	pub denomination: u128,
+	/// Secret (randomness) of the deposit
+	pub secret: [u8; SECRET_LENGTH],
+	/// Nullifier of the deposit
+	pub nullifier: [u8; SECRET_LENGTH],
+}
+
+impl Note {
+	/// Leaf commitment deposited into the mixer.
+	///
+	/// `params` are the verifying key bytes of the mixer tree, i.e. the
+	/// bulletproofs generators of the bulletproofs mixers. Neither backend
+	/// hashes with them, so they can also be empty.
+	pub fn leaf(&self, params: &[u8]) -> Result<ScalarBytes, Error> {
+		Ok(self.commitment(params)?.0)
+	}
+
+	/// Nullifier hash spent by the withdrawal of the note. `params` are as
+	/// for [`Note::leaf`].
+	pub fn nullifier_hash(&self, params: &[u8]) -> Result<ScalarBytes, Error> {
+		Ok(self.commitment(params)?.1)
+	}
+
+	fn commitment(&self, params: &[u8]) -> Result<(ScalarBytes, ScalarBytes), Error> {
+		Ok(self
+			.setup
+			.commitment(&self.secret.to_vec(), &self.nullifier.to_vec(), params)?)
+	}
+}
+
+impl fmt::Display for Note {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{}:{}:{}:{}:{}:{}:{}:{}{}",
+			NOTE_PREFIX,
+			NOTE_VERSION,
+			self.chain_id,
+			self.mixer_id,
+			backend_to_str(&self.setup.backend),
+			hasher_to_str(&self.setup.hasher),
+			self.denomination,
+			to_hex(&self.secret),
+			to_hex(&self.nullifier),
+		)
+	}
+}
+
+impl FromStr for Note {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let parts: Vec<&str> = s.split(':').collect();
+		if parts.first() != Some(&NOTE_PREFIX) {
+			return Err(Error::InvalidPrefix);
+		}
+		if parts.get(1) != Some(&NOTE_VERSION) {
+			return Err(Error::UnsupportedVersion);
+		}
+		if parts.len() != 8 {
+			return Err(Error::InvalidFormat);
+		}
+		let secrets = from_hex(parts[7]).ok_or(Error::InvalidSecrets)?;
+		if secrets.len() != 2 * SECRET_LENGTH {
+			return Err(Error::InvalidSecrets);
+		}
+		let mut secret = [0u8; SECRET_LENGTH];
+		let mut nullifier = [0u8; SECRET_LENGTH];
+		secret.copy_from_slice(&secrets[..SECRET_LENGTH]);
+		nullifier.copy_from_slice(&secrets[SECRET_LENGTH..]);
+		Ok(Note {
+			chain_id: parse_number(parts[2])?,
+			mixer_id: parse_number(parts[3])?,
+			setup: Setup::new(hasher_from_str(parts[5])?, backend_from_str(parts[4])?),
+			denomination: parse_number(parts[6])?,
+			secret,
+			nullifier,
+		})
+	}
+}
+
+fn parse_number<N: FromStr>(s: &str) -> Result<N, Error> {
+	// `FromStr` of the integers accepts a leading `+`, notes don't
+	if !s.bytes().all(|b| b.is_ascii_digit()) {
+		return Err(Error::InvalidNumber);
+	}
+	s.parse().map_err(|_| Error::InvalidNumber)
+}
+
+fn curve_to_str(curve: &Curve) -> &'static str {
+	match curve {
+		Curve::Bls381 => "bls381",
+		Curve::Bn254 => "bn254",
+		Curve::Curve25519 => "curve25519",
+	}
+}
+
+fn curve_from_str(s: &str) -> Result<Curve, Error> {
+	match s {
+		"bls381" => Ok(Curve::Bls381),
+		"bn254" => Ok(Curve::Bn254),
+		"curve25519" => Ok(Curve::Curve25519),
+		_ => Err(Error::InvalidBackend),
+	}
+}
+
+fn backend_to_str(backend: &Backend) -> String {
+	match backend {
+		Backend::Arkworks(curve, Snark::Groth16) => format!("arkworks_{}_groth16", curve_to_str(curve)),
+		Backend::Arkworks(curve, Snark::Marlin) => format!("arkworks_{}_marlin", curve_to_str(curve)),
+		Backend::Bulletproofs(curve) => format!("bulletproofs_{}", curve_to_str(curve)),
+	}
+}
+
+fn backend_from_str(s: &str) -> Result<Backend, Error> {
+	let parts: Vec<&str> = s.split('_').collect();
+	match parts.as_slice() {
+		["bulletproofs", curve] => Ok(Backend::Bulletproofs(curve_from_str(curve)?)),
+		["arkworks", curve, "groth16"] => Ok(Backend::Arkworks(curve_from_str(curve)?, Snark::Groth16)),
+		["arkworks", curve, "marlin"] => Ok(Backend::Arkworks(curve_from_str(curve)?, Snark::Marlin)),
+		_ => Err(Error::InvalidBackend),
+	}
+}
+
+fn hasher_to_str(hasher: &HashFunction) -> String {
+	match hasher {
+		HashFunction::PoseidonDefault => "poseidon".into(),
+		HashFunction::Poseidon(width, exponentiation) => format!("poseidon_{}_{}", width, exponentiation),
+		HashFunction::MiMC => "mimc".into(),
+		HashFunction::Blake2 => "blake2".into(),
+		HashFunction::Sha256 => "sha256".into(),
+	}
+}
+
+fn hasher_from_str(s: &str) -> Result<HashFunction, Error> {
+	let parts: Vec<&str> = s.split('_').collect();
+	match parts.as_slice() {
+		["poseidon"] => Ok(HashFunction::PoseidonDefault),
+		["poseidon", width, exponentiation] => Ok(HashFunction::Poseidon(
+			parse_number(width).map_err(|_| Error::InvalidHashFunction)?,
+			parse_number(exponentiation).map_err(|_| Error::InvalidHashFunction)?,
+		)),
+		["mimc"] => Ok(HashFunction::MiMC),
+		["blake2"] => Ok(HashFunction::Blake2),
+		["sha256"] => Ok(HashFunction::Sha256),
+		_ => Err(Error::InvalidHashFunction),
+	}
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+	if s.len() % 2 != 0 || !s.is_ascii() {
+		return None;
+	}
+	(0..s.len())
+		.step_by(2)
+		.map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bulletproofs::BulletproofGens;
+	use bulletproofs_gadgets::fixed_deposit_tree::builder::FixedDepositTreeBuilder;
+	use merkle_client::{
+		keys::{from_bytes_to_bp_gens, get_bp_gen_bytes},
+		setup::default_bulletproofs_poseidon_hasher,
+		tree::LocalTree,
+	};
+
+	fn bulletproofs_setup() -> Setup {
+		Setup::new(HashFunction::PoseidonDefault, Backend::Bulletproofs(Curve::Curve25519))
+	}
+
+	fn key_bytes(x: u8) -> [u8; 32] {
+		let mut bytes = [0u8; 32];
+		bytes[0] = x;
+		bytes
+	}
+
+	fn note(mixer_id: u32, setup: Setup, denomination: u128) -> Note {
+		Note {
+			chain_id: 42,
+			mixer_id,
+			setup,
+			denomination,
+			secret: key_bytes(2),
+			nullifier: key_bytes(3),
+		}
+	}
+
+	#[test]
+	fn should_round_trip_mixer_notes() {
+		// the default mixers of the mixer pallet tests
+		for (mixer_id, denomination) in [1_000, 10_000, 100_000, 1_000_000].iter().enumerate() {
+			let note = note(mixer_id as u32, bulletproofs_setup(), *denomination);
+			assert_eq!(note.to_string().parse::<Note>(), Ok(note));
+		}
+
+		let setups = vec![
+			Setup::new(
+				HashFunction::PoseidonDefault,
+				Backend::Arkworks(Curve::Bn254, Snark::Groth16),
+			),
+			Setup::new(
+				HashFunction::Poseidon(3, 17),
+				Backend::Arkworks(Curve::Bls381, Snark::Marlin),
+			),
+			Setup::new(HashFunction::MiMC, Backend::Arkworks(Curve::Bn254, Snark::Groth16)),
+			Setup::new(HashFunction::Blake2, Backend::Bulletproofs(Curve::Curve25519)),
+			Setup::new(HashFunction::Sha256, Backend::Bulletproofs(Curve::Curve25519)),
+		];
+		for setup in setups {
+			let note = note(4, setup, u128::max_value());
+			assert_eq!(note.to_string().parse::<Note>(), Ok(note));
+		}
+	}
+
+	#[test]
+	fn should_match_fixed_note_string() {
+		let s = format!(
+			"webb.mixer:v1:42:0:bulletproofs_curve25519:poseidon:1000:02{}03{}",
+			"0".repeat(62),
+			"0".repeat(62)
+		);
+		let note: Note = s.parse().unwrap();
+		assert_eq!(note, self::note(0, bulletproofs_setup(), 1_000));
+		assert_eq!(note.to_string(), s);
+	}
+
+	#[test]
+	fn should_derive_leaf_and_nullifier_hash_with_setup() {
+		let note = note(0, bulletproofs_setup(), 1_000);
+		let (leaf, nullifier_hash) = note
+			.setup
+			.commitment(&note.secret.to_vec(), &note.nullifier.to_vec(), &[])
+			.unwrap();
+		assert_eq!(note.leaf(&[]), Ok(leaf.clone()));
+		assert_eq!(note.nullifier_hash(&[]), Ok(nullifier_hash.clone()));
+		assert_ne!(leaf, nullifier_hash);
+
+		// the leaf is a member of a tree it was deposited into
+		let mut tree = LocalTree::new(bulletproofs_setup(), 3, Vec::new()).unwrap();
+		tree.insert(key_bytes(1).to_vec()).unwrap();
+		let root = tree.insert(leaf.clone()).unwrap();
+		assert_eq!(tree.compute_root(leaf, tree.path(1).unwrap()), Ok(root));
+
+		// setups without a hash implementation can't derive commitments
+		let note = self::note(
+			0,
+			Setup::new(HashFunction::Sha256, Backend::Bulletproofs(Curve::Curve25519)),
+			1_000,
+		);
+		assert_eq!(note.leaf(&[]), Err(Error::Setup(SetupError::Unimplemented)));
+	}
+
+	#[test]
+	fn should_derive_mixer_test_vectors() {
+		// the mixer pallet tests deposit the leaves of a `FixedDepositTree`
+		// with the default hasher of the mixers, and withdraw them with its
+		// nullifier hashes
+		let params = get_bp_gen_bytes(&BulletproofGens::new(16400, 1));
+		let hasher = default_bulletproofs_poseidon_hasher(from_bytes_to_bp_gens(&params));
+		let mut ftree = FixedDepositTreeBuilder::new().hash_params(hasher).depth(32).build();
+		for (mixer_id, denomination) in [1_000, 10_000, 100_000, 1_000_000].iter().enumerate() {
+			let leaf = ftree.generate_secrets();
+			let (secret, nullifier, nullifier_hash) = ftree.get_secrets(leaf);
+			let note = Note {
+				secret: secret.to_bytes(),
+				nullifier: nullifier.to_bytes(),
+				..self::note(mixer_id as u32, bulletproofs_setup(), *denomination)
+			};
+
+			let note: Note = note.to_string().parse().unwrap();
+			assert_eq!(note.leaf(&params), Ok(leaf.to_bytes().to_vec()));
+			assert_eq!(note.nullifier_hash(&params), Ok(nullifier_hash.to_bytes().to_vec()));
+			// the generators only matter to the proofs
+			assert_eq!(note.leaf(&[]), Ok(leaf.to_bytes().to_vec()));
+		}
+	}
+
+	#[test]
+	fn should_derive_arkworks_commitments() {
+		for curve in vec![Curve::Bls381, Curve::Bn254] {
+			let setup = Setup::new(HashFunction::PoseidonDefault, Backend::Arkworks(curve, Snark::Groth16));
+			let note: Note = note(0, setup.clone(), 1_000).to_string().parse().unwrap();
+			let (leaf, nullifier_hash) = setup
+				.commitment(&note.secret.to_vec(), &note.nullifier.to_vec(), &[])
+				.unwrap();
+			assert_eq!(note.leaf(&[]), Ok(leaf.clone()));
+			assert_eq!(note.nullifier_hash(&[]), Ok(nullifier_hash));
+			// the leaf is a member of the tree it was deposited into
+			let mut tree = LocalTree::new(setup, 3, Vec::new()).unwrap();
+			let root = tree.insert(leaf.clone()).unwrap();
+			assert_eq!(tree.compute_root(leaf, tree.path(0).unwrap()), Ok(root));
+		}
+	}
+
+	#[test]
+	fn should_reject_malformed_notes() {
+		let note = note(0, bulletproofs_setup(), 1_000).to_string();
+		let parse = |s: String| s.parse::<Note>();
+
+		assert_eq!(
+			parse(note.replace("webb.mixer", "webb.pool")),
+			Err(Error::InvalidPrefix)
+		);
+		assert_eq!(parse(note.replace(":v1:", ":v2:")), Err(Error::UnsupportedVersion));
+		assert_eq!(parse(format!("{}:00", note)), Err(Error::InvalidFormat));
+		assert_eq!(parse(note.replace(":42:", ":+42:")), Err(Error::InvalidNumber));
+		assert_eq!(parse(note.replace(":1000:", ":-1:")), Err(Error::InvalidNumber));
+		assert_eq!(
+			parse(note.replace("bulletproofs_curve25519", "bulletproofs_bn128")),
+			Err(Error::InvalidBackend)
+		);
+		assert_eq!(
+			parse(note.replace(":poseidon:", ":poseidon_3:")),
+			Err(Error::InvalidHashFunction)
+		);
+		assert_eq!(parse(note[..note.len() - 2].to_string()), Err(Error::InvalidSecrets));
+		assert_eq!(
+			parse(format!("{}zz", &note[..note.len() - 2])),
+			Err(Error::InvalidSecrets)
+		);
+	}
+}
@@ -0,0 +1,223 @@
+#![allow(clippy::clippy::too_many_arguments, clippy::unnecessary_mut_passed)]
+
+use std::sync::Arc;
+
+use codec::{Codec, Decode};
+use jsonrpc_core::{Error, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use serde::Serialize;
+use sp_api::{Metadata, ProvideRuntimeApi};
+use sp_blockchain::HeaderBackend;
+use sp_core::Bytes;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+
+use merkle_rpc::dispatch_error_message;
+use mixer::{BlockActivity, MixerApi as MixerRuntimeApi, MixerState, MixerStats, PendingWithdrawal};
+
+/// Error code for failures while calling into the runtime
+const RUNTIME_ERROR: i64 = 1;
+/// Error code for proof bytes that can't be decoded
+const DECODE_ERROR: i64 = 2;
+/// Error code for withdrawals the runtime would reject
+const WITHDRAW_REJECTED: i64 = 3;
+/// Error code for mixer ids that don't exist
+const UNKNOWN_MIXER: i64 = 4;
+
+/// Mixer RPC methods.
+#[rpc]
+pub trait MixerApi<BlockHash, AccountId, CurrencyId, Balance, BlockNumber> {
+	/// Dry-run a withdrawal.
+	///
+	/// This method calls into a runtime with `Mixer` pallet included and
+	/// runs the same checks as `withdraw` over the SCALE encoded withdraw
+	/// proof, without changing state.
+	/// Optionally, a block hash at which the runtime should be queried can be
+	/// specified.
+	///
+	/// Returns the name of the module error the withdrawal would fail with.
+	#[rpc(name = "mixer_checkWithdraw")]
+	fn check_withdraw(&self, sender: AccountId, withdraw_proof: Bytes, at: Option<BlockHash>) -> Result<()>;
+
+	/// Get the state of a mixer.
+	///
+	/// Returns whether the mixer is active, only accepts withdrawals or is
+	/// stopped.
+	#[rpc(name = "mixer_state")]
+	fn mixer_state(&self, mixer_id: u32, at: Option<BlockHash>) -> Result<MixerState>;
+
+	/// List the mixers.
+	///
+	/// Returns the tree id, currency, deposit size, TVL, state, deposit and
+	/// withdrawal counts, anonymity set size, recent activity and deposit
+	/// and TVL limits of every mixer.
+	#[rpc(name = "mixer_list")]
+	fn mixers(&self, at: Option<BlockHash>) -> Result<Vec<MixerStats<u32, CurrencyId, Balance, BlockNumber>>>;
+
+	/// Get the statistics of a mixer.
+	///
+	/// Returns the same fields as `mixer_list` for a single mixer.
+	#[rpc(name = "mixer_stats")]
+	fn mixer_stats(
+		&self,
+		mixer_id: u32,
+		at: Option<BlockHash>,
+	) -> Result<MixerStats<u32, CurrencyId, Balance, BlockNumber>>;
+
+	/// Get the activity of a mixer.
+	///
+	/// Returns the deposits and withdrawals of the mixer per block, over the
+	/// activity window of the runtime.
+	#[rpc(name = "mixer_activity")]
+	fn mixer_activity(&self, mixer_id: u32, at: Option<BlockHash>) -> Result<Vec<BlockActivity<BlockNumber>>>;
+
+	/// Get the pending withdrawals of a mixer.
+	///
+	/// Returns the withdrawals of the mixer held back by its withdrawal
+	/// delay, by id, with their recipient, relayer, amounts, due block and
+	/// whether they are frozen.
+	#[rpc(name = "mixer_pendingWithdrawals")]
+	fn pending_withdrawals(
+		&self,
+		mixer_id: u32,
+		at: Option<BlockHash>,
+	) -> Result<Vec<(u32, PendingWithdrawal<u32, AccountId, Balance, BlockNumber>)>>;
+}
+
+/// A struct that implements the `MixerApi`.
+pub struct MixerClient<C, M, P> {
+	client: Arc<C>,
+	_marker: std::marker::PhantomData<(M, P)>,
+}
+
+impl<C, M, P> MixerClient<C, M, P> {
+	/// Create new `Mixer` instance with the given reference to the client.
+	pub fn new(client: Arc<C>) -> Self {
+		Self {
+			client,
+			_marker: Default::default(),
+		}
+	}
+}
+
+impl<C, Block, AccountId, WithdrawProof, CurrencyId, Balance, BlockNumber>
+	MixerApi<<Block as BlockT>::Hash, AccountId, CurrencyId, Balance, BlockNumber> for MixerClient<C, Block, WithdrawProof>
+where
+	Block: BlockT,
+	AccountId: Codec + Serialize + Send + Sync + 'static,
+	WithdrawProof: Codec + Send + Sync + 'static,
+	CurrencyId: Codec + Serialize + Send + Sync + 'static,
+	Balance: Codec + Serialize + Send + Sync + 'static,
+	BlockNumber: Codec + Serialize + Send + Sync + 'static,
+	C: HeaderBackend<Block> + ProvideRuntimeApi<Block> + Send + Sync + 'static,
+	C::Api: MixerRuntimeApi<Block, AccountId, WithdrawProof, CurrencyId, Balance, BlockNumber> + Metadata<Block>,
+{
+	fn check_withdraw(
+		&self,
+		sender: AccountId,
+		withdraw_proof: Bytes,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<()> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+		let withdraw_proof = WithdrawProof::decode(&mut &*withdraw_proof).map_err(|e| Error {
+			code: ErrorCode::ServerError(DECODE_ERROR),
+			message: "Unable to decode the withdraw proof".into(),
+			data: Some(format!("{:?}", e).into()),
+		})?;
+		let res = api.check_withdraw(&at, sender, withdraw_proof).map_err(|e| Error {
+			code: ErrorCode::ServerError(RUNTIME_ERROR),
+			message: "Unable to dry-run the withdrawal".into(),
+			data: Some(format!("{:?}", e).into()),
+		})?;
+		res.map_err(|e| Error {
+			code: ErrorCode::ServerError(WITHDRAW_REJECTED),
+			message: dispatch_error_message(&*api, &at, e),
+			data: Some(format!("{:?}", e).into()),
+		})
+	}
+
+	fn mixer_state(&self, mixer_id: u32, at: Option<<Block as BlockT>::Hash>) -> Result<MixerState> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+		let res = api.mixer_state(&at, mixer_id).map_err(|e| Error {
+			code: ErrorCode::ServerError(RUNTIME_ERROR),
+			message: "Unable to query the mixer state".into(),
+			data: Some(format!("{:?}", e).into()),
+		})?;
+		res.map_err(|e| Error {
+			code: ErrorCode::ServerError(UNKNOWN_MIXER),
+			message: dispatch_error_message(&*api, &at, e),
+			data: Some(format!("{:?}", e).into()),
+		})
+	}
+
+	fn mixers(
+		&self,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Vec<MixerStats<u32, CurrencyId, Balance, BlockNumber>>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+		api.mixers(&at).map_err(|e| Error {
+			code: ErrorCode::ServerError(RUNTIME_ERROR),
+			message: "Unable to list the mixers".into(),
+			data: Some(format!("{:?}", e).into()),
+		})
+	}
+
+	fn mixer_stats(
+		&self,
+		mixer_id: u32,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<MixerStats<u32, CurrencyId, Balance, BlockNumber>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+		let res = api.mixer_stats(&at, mixer_id).map_err(|e| Error {
+			code: ErrorCode::ServerError(RUNTIME_ERROR),
+			message: "Unable to query the mixer statistics".into(),
+			data: Some(format!("{:?}", e).into()),
+		})?;
+		res.map_err(|e| Error {
+			code: ErrorCode::ServerError(UNKNOWN_MIXER),
+			message: dispatch_error_message(&*api, &at, e),
+			data: Some(format!("{:?}", e).into()),
+		})
+	}
+
+	fn mixer_activity(
+		&self,
+		mixer_id: u32,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Vec<BlockActivity<BlockNumber>>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+		let res = api.mixer_activity(&at, mixer_id).map_err(|e| Error {
+			code: ErrorCode::ServerError(RUNTIME_ERROR),
+			message: "Unable to query the mixer activity".into(),
+			data: Some(format!("{:?}", e).into()),
+		})?;
+		res.map_err(|e| Error {
+			code: ErrorCode::ServerError(UNKNOWN_MIXER),
+			message: dispatch_error_message(&*api, &at, e),
+			data: Some(format!("{:?}", e).into()),
+		})
+	}
+
+	fn pending_withdrawals(
+		&self,
+		mixer_id: u32,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Vec<(u32, PendingWithdrawal<u32, AccountId, Balance, BlockNumber>)>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+		let res = api.pending_withdrawals(&at, mixer_id).map_err(|e| Error {
+			code: ErrorCode::ServerError(RUNTIME_ERROR),
+			message: "Unable to query the pending withdrawals".into(),
+			data: Some(format!("{:?}", e).into()),
+		})?;
+		res.map_err(|e| Error {
+			code: ErrorCode::ServerError(UNKNOWN_MIXER),
+			message: dispatch_error_message(&*api, &at, e),
+			data: Some(format!("{:?}", e).into()),
+		})
+	}
+}
@@ -2,8 +2,8 @@ use super::*;
 use bulletproofs::{r1cs::Prover, PedersenGens};
 use bulletproofs_gadgets::fixed_deposit_tree::builder::FixedDepositTreeBuilder;
 use curve25519_dalek::scalar::Scalar;
-use frame_benchmarking::{account, benchmarks, whitelisted_caller};
-use frame_support::traits::OnFinalize;
+use frame_benchmarking::{benchmarks, whitelisted_caller};
+use frame_support::traits::{EnsureOrigin, OnFinalize, OnInitialize};
 use frame_system::RawOrigin;
 use merkle::utils::{
 	keys::{from_bytes_to_bp_gens, slice_to_bytes_32, ScalarBytes},
@@ -31,7 +31,9 @@ benchmarks! {
 
 		// Making `d` leaves/data points
 		let data_points = vec![Scalar::zero().to_bytes().to_vec(); d as usize];
-	}: _(RawOrigin::Signed(caller), mixer_id, data_points)
+		// Each with a memo of the maximum length
+		let memos = vec![Some(vec![0u8; T::MaxMemoLength::get() as usize]); d as usize];
+	}: _(RawOrigin::Signed(caller), mixer_id, data_points, memos)
 	verify {
 		// Checking if deposit is sucessfull by checking number of leaves
 		// let mixer_info = Mixer::<T>::get_mixer(mixer_id).unwrap();
@@ -66,8 +68,8 @@ benchmarks! {
 	// 	let (proof, (comms_cr, nullifier_hash, leaf_index_comms_cr, proof_comms_cr)) = ftree.prove_zk(
 	// 		Scalar::from_bytes_mod_order(slice_to_bytes_32(&root)),
 	// 		Scalar::from_bytes_mod_order(slice_to_bytes_32(&leaf)),
-	// 		Scalar::from_bytes_mod_order(slice_to_bytes_32(&caller.encode())),
-	// 		Scalar::from_bytes_mod_order(slice_to_bytes_32(&caller.encode())),
+	// 		Scalar::from_bytes_mod_order(slice_to_bytes_32(&public_input_to_field(&caller.encode()))),
+	// 		Scalar::from_bytes_mod_order(slice_to_bytes_32(&public_input_to_field(&caller.encode()))),
 	// 		&ftree.hash_params.bp_gens, prover
 	// 	);
 
@@ -87,7 +89,9 @@ benchmarks! {
 	// 		leaf_index_comms,
 	// 		proof_comms,
 	// 		None,
-	// 		None
+	// 		None,
+	// 		Zero::zero(),
+	// 		Zero::zero(),
 	// 	);
 	// }: _(
 	// 	RawOrigin::Signed(caller.clone()),
@@ -102,37 +106,74 @@ benchmarks! {
 	set_stopped {
 		Mixer::<T>::initialize_first_stage().unwrap();
 		Mixer::<T>::initialize_second_stage().unwrap();
-	}:
-	// Calling the function with the root origin
-	_(RawOrigin::Root, true)
+		let origin = T::AdminOrigin::successful_origin();
+		let mixer_id: T::TreeId = 0u32.into();
+	}: set_mixer_stopped<T::Origin>(origin, mixer_id, true)
+	verify {
+		assert!(Merkle::<T>::stopped(mixer_id));
+	}
+
+	set_all_withdraw_only {
+		Mixer::<T>::initialize_first_stage().unwrap();
+		Mixer::<T>::initialize_second_stage().unwrap();
+		let origin = T::AdminOrigin::successful_origin();
+	}: _<T::Origin>(origin, true)
 	verify {
-		let mixer_ids = MixerTreeIds::<T>::get();
-		for i in 0..mixer_ids.len() {
-			let group_id: T::TreeId = (i as u32).into();
-			let stopped = Merkle::<T>::stopped(group_id);
-			assert!(stopped);
+		for mixer_id in MixerTreeIds::<T>::get() {
+			assert!(Mixer::<T>::withdraw_only(mixer_id));
 		}
 	}
 
-	transfer_admin {
+	set_withdraw_only {
+		Mixer::<T>::initialize_first_stage().unwrap();
+		Mixer::<T>::initialize_second_stage().unwrap();
+		let origin = T::AdminOrigin::successful_origin();
+		let mixer_id: T::TreeId = 0u32.into();
+	}: _<T::Origin>(origin, mixer_id, true)
+	verify {
+		assert!(Mixer::<T>::withdraw_only(mixer_id));
+	}
+
+	set_max_fee {
+		Mixer::<T>::initialize_first_stage().unwrap();
+		Mixer::<T>::initialize_second_stage().unwrap();
+		let origin = T::AdminOrigin::successful_origin();
+		let mixer_id: T::TreeId = 0u32.into();
+		let max_fee = Mixer::<T>::get_mixer(mixer_id).unwrap().fixed_deposit_size;
+	}: _<T::Origin>(origin, mixer_id, max_fee)
+	verify {
+		assert_eq!(Mixer::<T>::max_fee(mixer_id), max_fee);
+	}
+
+	set_limits {
 		Mixer::<T>::initialize_first_stage().unwrap();
 		Mixer::<T>::initialize_second_stage().unwrap();
-		// This account will be a new admin
-		let new_admin: T::AccountId = account("new_admin", 0, 0);
-	}:
-	// Calling the function with the root origin
-	_(RawOrigin::Root, new_admin.clone())
+		let origin = T::AdminOrigin::successful_origin();
+		let mixer_id: T::TreeId = 0u32.into();
+		let limits = MixerLimits {
+			max_commitments_per_deposit: Some(1),
+			max_deposits_per_block: Some(1),
+			max_total_value_locked: Some(Zero::zero()),
+		};
+	}: _<T::Origin>(origin, mixer_id, limits.clone())
 	verify {
-		let admin: T::AccountId = Mixer::<T>::admin();
-		assert_eq!(admin, new_admin);
+		assert!(Mixer::<T>::limits(mixer_id) == limits);
+	}
+
+	set_protocol_fee {
+		Mixer::<T>::initialize_first_stage().unwrap();
+		Mixer::<T>::initialize_second_stage().unwrap();
+		let origin = T::AdminOrigin::successful_origin();
+		let mixer_id: T::TreeId = 0u32.into();
+		let fee: Option<ProtocolFee<BalanceOf<T>>> = Some(ProtocolFee::Proportional(Permill::from_percent(1)));
+	}: _<T::Origin>(origin, mixer_id, fee.clone())
+	verify {
+		assert!(Mixer::<T>::protocol_fee(mixer_id) == fee);
 	}
 
 	on_finalize_uninitialized {
-		let first_block: T::BlockNumber = 0u32.into();
-		let second_block: T::BlockNumber = 1u32.into();
 	}: {
-		Mixer::<T>::on_finalize(first_block);
-		Mixer::<T>::on_finalize(second_block);
+		migrations::initialize_staged::<T>();
 	}
 	verify {
 		let first_stage_initialized = Mixer::<T>::first_stage_initialized();
@@ -142,9 +183,8 @@ benchmarks! {
 	}
 
 	on_finalize_initialized {
-		// We first initialize to reach the first branch of if statement inside `on_finalize`
-		let first_block: T::BlockNumber = 0u32.into();
-		Mixer::<T>::on_finalize(first_block);
+		// We first initialize to reach the cache update inside `on_finalize`
+		migrations::initialize_staged::<T>();
 		let second_block: T::BlockNumber = 1u32.into();
 	}: {
 		Mixer::<T>::on_finalize(second_block);
@@ -154,6 +194,71 @@ benchmarks! {
 		let data = Merkle::<T>::get_cache(first_group, second_block);
 		assert_eq!(data.len(), 1);
 	}
+
+	pay_pending_withdrawals {
+		// Benchmarking from 1 to `MaxPendingPayouts` withdrawals due in the same block
+		let p in 1 .. T::MaxPendingPayouts::get();
+		let caller: T::AccountId = whitelisted_caller();
+
+		Mixer::<T>::initialize_first_stage().unwrap();
+		Mixer::<T>::initialize_second_stage().unwrap();
+		let mixer_id: T::TreeId = 0u32.into();
+		let mixer_info = Mixer::<T>::get_mixer(mixer_id).unwrap();
+		let due_block: T::BlockNumber = 1u32.into();
+		for id in 0..p {
+			let amount = mixer_info.fixed_deposit_size;
+			T::Currency::deposit(mixer_info.currency_id, &Mixer::<T>::account_id(), amount).unwrap();
+			PendingWithdrawals::<T>::insert(id, PendingWithdrawal {
+				mixer_id,
+				recipient: caller.clone(),
+				amount,
+				relayer: caller.clone(),
+				fee: Zero::zero(),
+				due_block,
+				frozen: false,
+			});
+			MixerPendingWithdrawals::<T>::insert(mixer_id, id, ());
+			PayoutQueue::<T>::append(due_block, id);
+		}
+	}: {
+		Mixer::<T>::pay_due_withdrawals(due_block);
+	}
+	verify {
+		assert_eq!(PendingWithdrawals::<T>::iter().count(), 0);
+	}
+
+	on_initialize {
+		// Benchmarking from 0 to `MaxPendingPayouts` withdrawals queued by
+		// withdrawals with a delay, all due in the same block
+		let p in 0 .. T::MaxPendingPayouts::get();
+		let caller: T::AccountId = whitelisted_caller();
+
+		Mixer::<T>::initialize_first_stage().unwrap();
+		Mixer::<T>::initialize_second_stage().unwrap();
+		let mixer_id: T::TreeId = 0u32.into();
+		let mixer_info = Mixer::<T>::get_mixer(mixer_id).unwrap();
+		let delay: T::BlockNumber = 1u32.into();
+		WithdrawalDelays::<T>::insert(mixer_id, delay);
+		for _ in 0..p {
+			let amount = mixer_info.fixed_deposit_size;
+			T::Currency::deposit(mixer_info.currency_id, &Mixer::<T>::account_id(), amount).unwrap();
+			Mixer::<T>::pay_or_queue(
+				mixer_id,
+				mixer_info.currency_id,
+				caller.clone(),
+				amount,
+				caller.clone(),
+				Zero::zero(),
+			)
+			.unwrap();
+		}
+		let due_block = frame_system::Pallet::<T>::block_number() + delay;
+	}: {
+		Mixer::<T>::on_initialize(due_block);
+	}
+	verify {
+		assert_eq!(PendingWithdrawals::<T>::iter().count(), 0);
+	}
 }
 
 // TODO: replace with impl_benchmark_test_suite macro:
@@ -186,9 +291,37 @@ mod bench_tests {
 	}
 
 	#[test]
-	fn test_transfer_admin() {
+	fn test_set_all_withdraw_only() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(test_benchmark_set_all_withdraw_only::<Test>());
+		});
+	}
+
+	#[test]
+	fn test_set_withdraw_only() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(test_benchmark_set_withdraw_only::<Test>());
+		});
+	}
+
+	#[test]
+	fn test_set_max_fee() {
 		new_test_ext().execute_with(|| {
-			assert_ok!(test_benchmark_transfer_admin::<Test>());
+			assert_ok!(test_benchmark_set_max_fee::<Test>());
+		});
+	}
+
+	#[test]
+	fn test_set_limits() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(test_benchmark_set_limits::<Test>());
+		});
+	}
+
+	#[test]
+	fn test_set_protocol_fee() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(test_benchmark_set_protocol_fee::<Test>());
 		});
 	}
 
@@ -205,4 +338,18 @@ mod bench_tests {
 			assert_ok!(test_benchmark_on_finalize_initialized::<Test>());
 		});
 	}
+
+	#[test]
+	fn test_pay_pending_withdrawals() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(test_benchmark_pay_pending_withdrawals::<Test>());
+		});
+	}
+
+	#[test]
+	fn test_on_initialize() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(test_benchmark_on_initialize::<Test>());
+		});
+	}
 }
@@ -23,7 +23,6 @@
 //! - Depositing some currency into the mixer.
 //! - Withdrawing the deposit from the mixer.
 //! - Stopping mixer operations.
-//! - Transfering the admin of the mixer.
 //!
 //! ### Terminology
 //!
@@ -35,12 +34,154 @@
 //!
 //! ### Dispatchable Functions
 //!
-//! - `deposit` - Deposit a fixed amount of cryptocurrency into the mixer.
+//! - `deposit` - Deposit a fixed amount of cryptocurrency into the mixer,
+//!   optionally with an encrypted memo for each deposited note.
 //! - `withdraw` - Provide a zero-knowladge proof of the deposit and withdraw
 //!   from the mixer.
-//! - `set_stopped` - Stops the operation of all mixers.
-//! - `transfer_admin` - Transfers the admin role from sender to specified
-//!   account.
+//! - `set_all_withdraw_only` - Puts every mixer in or out of withdraw-only
+//!   mode, so deposits can be halted without trapping deposited funds.
+//! - `set_mixer_stopped` - Stops or restarts a single mixer. A stopped mixer
+//!   refuses deposits and withdrawals.
+//! - `set_withdraw_only` - Puts a single mixer in or out of withdraw-only mode,
+//!   in which deposits are refused and valid withdrawals keep working.
+//! - `set_max_fee` - Sets the highest relayer fee a mixer accepts on
+//!   withdrawals. New mixers start at the `DefaultMaxFee` share of their
+//!   deposit size.
+//! - `set_limits` - Sets the deposit and TVL limits of a mixer.
+//! - `set_protocol_fee` - Sets or removes the protocol fee of a mixer.
+//! - `set_withdrawal_delay` - Sets the number of blocks the payouts of a
+//!   mixer's withdrawals are held for.
+//! - `freeze_withdrawal` and `release_withdrawal` - Hold back a pending
+//!   withdrawal and let it be paid out again.
+//! - `withdraw_to_evm` - Withdraws to an EVM address, paying the account it
+//!   maps to.
+//! - `withdraw_unsigned` - Withdraws without a signed origin, paying the fee
+//!   out of the withdrawn deposit to the `Treasury` account. The fee has to be
+//!   at least the `MinUnsignedFee` share of the deposit size.
+//! - `claim_reward` - Claims the reward for a deposit that stayed in the mixer
+//!   for at least the mixer's minimum deposit length.
+//! - `remix` - Spends a deposit and inserts a new commitment into a mixer of
+//!   the same denomination, without the funds leaving the pallet. Its proof is
+//!   made with [`Pallet::remix_relayer_input`] as the relayer, so it can't be
+//!   replayed as a withdrawal.
+//!
+//! Creating, stopping and configuring mixers is restricted to the
+//! `AdminOrigin`, e.g. root, a collective or a multisig.
+//!
+//! ### Limits
+//!
+//! The `AdminOrigin` can cap each mixer with [`MixerLimits`]: the number of
+//! commitments in one deposit call, the number of deposits in one block and
+//! the total value locked. Deposits above a limit fail, as do remixes into
+//! the mixer. Limits are unset by default.
+//!
+//! ### Protocol fees
+//!
+//! A mixer can charge a [`ProtocolFee`], either a share of its deposit size or
+//! a flat amount per deposit. The fee is paid by the depositor in the mixer's
+//! currency on top of the deposit and sent to the `Treasury` account, so each
+//! commitment still locks exactly the deposit size and the denomination
+//! stays fully withdrawable. Fees taken are summed per mixer in
+//! `AccruedFees`.
+//!
+//! ### Relayer fees
+//!
+//! A withdraw proof can carry a `fee`, paid out of the deposit to the relayer,
+//! and a `refund`, paid in the native currency by the sender to the recipient.
+//! Both are bound into the proof through the relayer public input, see
+//! [`Pallet::relayer_input`].
+//!
+//! ### Public inputs
+//!
+//! The recipient and relayer inputs of a proof, e.g. an encoded `AccountId32`,
+//! don't fit a field element as they are. The pallet maps them with
+//! `merkle_client::keys::public_input_to_field`, a truncated blake2 hash that
+//! every backend reads as the same element, so proofs must be generated
+//! against the mapped values.
+//!
+//! ### Unsigned withdrawals
+//!
+//! `withdraw_unsigned` lets a user withdraw to a fresh account without a
+//! relayer. The proof must set the recipient, carry a non-zero fee and no
+//! refund, and be generated with the recipient as the relayer. The proof and
+//! nullifier are checked before the transaction enters the pool, the nullifier
+//! hash is used as its `provides` tag, and the fee is paid from the deposit
+//! to the `Treasury` account in place of a transaction fee. The fee can't be
+//! above the mixer's max fee, so the `DefaultMaxFee` should be at least the
+//! `MinUnsignedFee` for new mixers to accept unsigned withdrawals.
+//!
+//! ### Delayed withdrawals
+//!
+//! A mixer with a non-zero `WithdrawalDelays` entry doesn't pay withdrawals
+//! out right away. The proof is checked and the nullifier spent as usual, but
+//! the payout to the recipient and relayer is queued as a
+//! [`PendingWithdrawal`] due `delay` blocks later. The refund, if any, is
+//! still paid by the sender at once. Due withdrawals are paid in
+//! `on_initialize`. At most `MaxPendingPayouts` payouts are queued per block,
+//! a payout falling due in a full block being queued in the next block with
+//! room. A withdrawal whose payout would be pushed back by more than
+//! `MaxPayoutPostponement` blocks is refused. A payout that fails is frozen,
+//! with a `WithdrawalPayoutFailed` event, and paid once it is released.
+//!
+//! Until then the `AdminOrigin` can freeze a pending withdrawal, e.g. when an
+//! exploit is discovered, which keeps its funds in the mixer until it is
+//! released.
+//!
+//! ### Deposit rewards
+//!
+//! The root of the tree at the end of each block with deposits is kept in
+//! `DepositRoots`. Recording a root prunes up to two of the oldest roots of
+//! the mixer whose next root is old enough to claim against, so the latest
+//! claimable root is always kept and the work is paid for by the depositor.
+//! A claim is a zero-knowledge proof of membership against a root recorded at
+//! least `minimum_deposit_length_for_reward` blocks ago, so it shows that the
+//! deposit stayed in the mixer that long without revealing which one it is.
+//!
+//! Claims are proven with the reward circuit of `merkle::utils::reward`,
+//! which reveals a reward nullifier derived from the secret nullifier of the
+//! deposit under its own domain instead of the nullifier hash. Each deposit
+//! can be claimed once, and a claim can't be linked to the deposit nor to the
+//! withdrawal of the same note. In turn a claim can't show that the deposit
+//! wasn't withdrawn since, only that it stayed in the mixer until the root's
+//! block plus the minimum deposit length. The proof is made with
+//! [`Pallet::reward_relayer_input`] as the relayer. Rewards are paid through
+//! `RewardHandler`, e.g. out of a funded pot, and a claim fails without being
+//! spent if it can't be paid.
+//!
+//! ### Genesis
+//!
+//! The mixers are built at block 0 from the pallet's `GenesisConfig`, which
+//! lists the verifying keys and, for each mixer, its currency, deposit size,
+//! setup and key. Chains started before that can bring up the default mixers
+//! with [`migrations::initialize_staged`].
+//!
+//! Every mixer tree has a depth of `TreeDepth`, and the default mixers use
+//! `DefaultSetup` with `DefaultVerifyingKey`. Runtimes launching arkworks
+//! mixers, e.g. MiMC on BN254 with Groth16, set these to the matching setup,
+//! verifying key and a depth the circuit supports. [`BulletproofsSetup`] and
+//! [`BulletproofsVerifyingKey`] give the bulletproofs defaults.
+//!
+//! ### Transaction pool
+//!
+//! Runtimes should add [`CheckNullifier`] to their signed extensions. It tags
+//! withdrawals with their `(tree_id, nullifier_hash)`, so the pool keeps only
+//! one withdrawal per nullifier, preferring the higher tip, and drops the ones
+//! whose nullifier is already used.
+//!
+//! ### Runtime API
+//!
+//! - `MixerApi::check_withdraw` - Dry-runs a withdrawal, so relayers can screen
+//!   proofs before broadcasting them.
+//! - `MixerApi::mixer_state` - Returns whether a mixer is active, in
+//!   withdraw-only mode or stopped.
+//! - `MixerApi::mixers` and `MixerApi::mixer_stats` - Return the currency,
+//!   deposit size, TVL, state, deposit and withdrawal counts, anonymity set
+//!   size, recent activity and limits of the mixers.
+//! - `MixerApi::mixer_activity` - Returns the deposits and withdrawals of a
+//!   mixer per block, over the last `ActivityWindow` blocks. Wallets can use it
+//!   to warn when the anonymity set of a mixer is small or recent.
+//! - `MixerApi::pending_withdrawals` - Returns the withdrawals of a mixer
+//!   waiting to be paid out, frozen ones included.
 
 #![cfg_attr(not(feature = "std"), no_std)]
 
@@ -58,20 +199,26 @@ pub mod traits;
 
 use bulletproofs::BulletproofGens;
 use codec::{Decode, Encode};
-use frame_support::{dispatch, ensure, traits::Get, weights::Weight, PalletId};
-use frame_system::ensure_signed;
+use frame_support::{dispatch, ensure, traits::Get, transactional, weights::Weight, PalletId};
+use frame_system::{ensure_none, ensure_signed};
 use merkle::{
 	utils::{
-		keys::ScalarBytes,
-		permissions::ensure_admin,
+		keys::{public_input_to_field, ScalarBytes},
 		setup::{Backend, Curve, HashFunction, Setup},
 	},
-	Pallet as MerklePallet, Tree as TreeTrait,
+	Pallet as MerklePallet, SpendsNullifier, Tree as TreeTrait,
+};
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+use sp_core::H160;
+use sp_runtime::{
+	traits::{AccountIdConversion, BlakeTwo256, Convert, Hash, One, SaturatedConversion, Saturating, Zero},
+	transaction_validity::{TransactionValidity, TransactionValidityError, ValidTransaction},
+	Permill,
 };
-use sp_runtime::traits::{AccountIdConversion, Zero};
 use sp_std::prelude::*;
 use traits::ExtendedMixer;
-use webb_traits::MultiCurrency;
+use webb_traits::{MultiCurrency, RewardHandler};
 use weights::WeightInfo;
 
 pub use pallet::*;
@@ -100,13 +247,51 @@ pub mod pallet {
 		/// The small deposit length
 		#[pallet::constant]
 		type DepositLength: Get<Self::BlockNumber>;
-		/// Default admin key
-		#[pallet::constant]
-		type DefaultAdmin: Get<Self::AccountId>;
+		/// Origin that can create, stop and configure the mixers
+		type AdminOrigin: EnsureOrigin<Self::Origin>;
 		/// Weight information for extrinsics in this pallet
 		type WeightInfo: WeightInfo;
 		// Available mixes sizes (Size is determend by the deposit amount)
 		type MixerSizes: Get<Vec<BalanceOf<Self>>>;
+		/// Pays out the deposit rewards, the pool id is the mixer id
+		type RewardHandler: RewardHandler<Self::AccountId, PoolId = Self::TreeId>;
+		/// Reward paid for each deposit that stayed in a mixer long enough
+		#[pallet::constant]
+		type DepositReward: Get<RewardBalanceOf<Self>>;
+		/// Setup of the default mixers
+		#[pallet::constant]
+		type DefaultSetup: Get<Setup>;
+		/// Verifying key of the default mixers, must match `DefaultSetup`
+		type DefaultVerifyingKey: Get<Vec<u8>>;
+		/// Depth of the mixer trees, at most the merkle pallet's `MaxTreeDepth`
+		#[pallet::constant]
+		type TreeDepth: Get<u8>;
+		/// Number of recent blocks kept in the activity history of each mixer
+		#[pallet::constant]
+		type ActivityWindow: Get<Self::BlockNumber>;
+		/// Maximum length in bytes of the encrypted memo of a deposit
+		#[pallet::constant]
+		type MaxMemoLength: Get<u32>;
+		/// Maps EVM addresses to the accounts withdrawals to them are paid to,
+		/// e.g. the `AddressMapping` of `pallet_evm`
+		type EvmAddressMapping: Convert<H160, Self::AccountId>;
+		/// Account the protocol fees and the fees of unsigned withdrawals are
+		/// sent to, e.g. the account of `pallet_treasury`
+		type Treasury: Get<Self::AccountId>;
+		/// Maximum number of pending withdrawals paid out in a single block
+		#[pallet::constant]
+		type MaxPendingPayouts: Get<u32>;
+		/// Maximum number of blocks a payout falling due in a full block is
+		/// pushed back by
+		#[pallet::constant]
+		type MaxPayoutPostponement: Get<u32>;
+		/// Lowest fee of unsigned withdrawals, as a share of the deposit size
+		/// of the mixer
+		#[pallet::constant]
+		type MinUnsignedFee: Get<Permill>;
+		/// Highest relayer fee of new mixers, as a share of their deposit size
+		#[pallet::constant]
+		type DefaultMaxFee: Get<Permill>;
 	}
 
 	/// Flag indicating if the mixer trees are created
@@ -119,6 +304,10 @@ pub mod pallet {
 	#[pallet::getter(fn second_stage_initialized)]
 	pub type SecondStageInitialized<T: Config> = StorageValue<_, bool, ValueQuery>;
 
+	/// The storage layout of the pallet, so each migration runs once
+	#[pallet::storage]
+	pub type StorageVersion<T: Config> = StorageValue<_, Releases, ValueQuery>;
+
 	/// The map of mixer trees to their metadata
 	#[pallet::storage]
 	#[pallet::getter(fn mixer_trees)]
@@ -129,21 +318,242 @@ pub mod pallet {
 	#[pallet::getter(fn mixer_group_ids)]
 	pub type MixerTreeIds<T: Config> = StorageValue<_, Vec<T::TreeId>, ValueQuery>;
 
-	/// Administrator of the mixer pallet.
-	/// This account that can stop/start operations of the mixer
-	#[pallet::storage]
-	#[pallet::getter(fn admin)]
-	pub type Admin<T: Config> = StorageValue<_, T::AccountId, ValueQuery>;
-
 	/// The TVL per group
 	#[pallet::storage]
 	#[pallet::getter(fn total_value_locked)]
 	pub type TotalValueLocked<T: Config> = StorageMap<_, Blake2_128Concat, T::TreeId, BalanceOf<T>, ValueQuery>;
 
+	/// The highest relayer fee each mixer accepts, the `DefaultMaxFee` share
+	/// of its deposit size until set by the `AdminOrigin`
+	#[pallet::storage]
+	#[pallet::getter(fn max_fee)]
+	pub type MaxFees<T: Config> = StorageMap<_, Blake2_128Concat, T::TreeId, BalanceOf<T>, ValueQuery>;
+
+	/// The deposit and TVL limits of each mixer
+	#[pallet::storage]
+	#[pallet::getter(fn limits)]
+	pub type Limits<T: Config> = StorageMap<_, Blake2_128Concat, T::TreeId, MixerLimits<BalanceOf<T>>, ValueQuery>;
+
+	/// The protocol fee charged on deposits into each mixer, if any
+	#[pallet::storage]
+	#[pallet::getter(fn protocol_fee)]
+	pub type ProtocolFees<T: Config> =
+		StorageMap<_, Blake2_128Concat, T::TreeId, ProtocolFee<BalanceOf<T>>, OptionQuery>;
+
+	/// The protocol fees taken on deposits into each mixer so far
+	#[pallet::storage]
+	#[pallet::getter(fn accrued_fees)]
+	pub type AccruedFees<T: Config> = StorageMap<_, Blake2_128Concat, T::TreeId, BalanceOf<T>, ValueQuery>;
+
+	/// The number of blocks the payouts of each mixer's withdrawals are held
+	/// for, zero paying them out right away
+	#[pallet::storage]
+	#[pallet::getter(fn withdrawal_delay)]
+	pub type WithdrawalDelays<T: Config> = StorageMap<_, Blake2_128Concat, T::TreeId, T::BlockNumber, ValueQuery>;
+
+	/// The id of the next pending withdrawal
+	#[pallet::storage]
+	#[pallet::getter(fn next_pending_withdrawal_id)]
+	pub type NextPendingWithdrawalId<T: Config> = StorageValue<_, u32, ValueQuery>;
+
+	/// The withdrawals waiting to be paid out, by id
+	#[pallet::storage]
+	#[pallet::getter(fn pending_withdrawal)]
+	pub type PendingWithdrawals<T: Config> = StorageMap<_, Blake2_128Concat, u32, PendingWithdrawalOf<T>, OptionQuery>;
+
+	/// The ids of the pending withdrawals of each mixer
+	#[pallet::storage]
+	pub type MixerPendingWithdrawals<T: Config> =
+		StorageDoubleMap<_, Blake2_128Concat, T::TreeId, Blake2_128Concat, u32, (), ValueQuery>;
+
+	/// The ids of the pending withdrawals due at each block, at most
+	/// `MaxPendingPayouts` of them
+	#[pallet::storage]
+	#[pallet::getter(fn payout_queue)]
+	pub type PayoutQueue<T: Config> = StorageMap<_, Blake2_128Concat, T::BlockNumber, Vec<u32>, ValueQuery>;
+
+	/// The last block each mixer had deposits in and their number in it
+	#[pallet::storage]
+	#[pallet::getter(fn block_deposits)]
+	pub type BlockDeposits<T: Config> = StorageMap<_, Blake2_128Concat, T::TreeId, (T::BlockNumber, u32), OptionQuery>;
+
+	/// The root of each mixer at the end of every block with deposits, pruned
+	/// once a newer root is old enough to claim rewards against
+	#[pallet::storage]
+	#[pallet::getter(fn deposit_root)]
+	pub type DepositRoots<T: Config> =
+		StorageDoubleMap<_, Blake2_128Concat, T::TreeId, Blake2_128Concat, T::BlockNumber, ScalarBytes, OptionQuery>;
+
+	/// The block of the next deposit root of a mixer after the one at the
+	/// given block
+	#[pallet::storage]
+	pub type NextDepositRoot<T: Config> =
+		StorageDoubleMap<_, Blake2_128Concat, T::TreeId, Blake2_128Concat, T::BlockNumber, T::BlockNumber, OptionQuery>;
+
+	/// The blocks of the oldest and the latest deposit root of each mixer
+	#[pallet::storage]
+	#[pallet::getter(fn deposit_root_bounds)]
+	pub type DepositRootBounds<T: Config> =
+		StorageMap<_, Blake2_128Concat, T::TreeId, (T::BlockNumber, T::BlockNumber), OptionQuery>;
+
+	/// The reward nullifiers of the deposits whose reward was claimed
+	#[pallet::storage]
+	#[pallet::getter(fn reward_claimed)]
+	pub type ClaimedRewards<T: Config> =
+		StorageDoubleMap<_, Blake2_128Concat, T::TreeId, Blake2_128Concat, ScalarBytes, bool, ValueQuery>;
+
+	/// Whether each mixer only accepts withdrawals
+	#[pallet::storage]
+	#[pallet::getter(fn withdraw_only)]
+	pub type WithdrawOnly<T: Config> = StorageMap<_, Blake2_128Concat, T::TreeId, bool, ValueQuery>;
+
+	/// The number of deposits into each mixer
+	#[pallet::storage]
+	#[pallet::getter(fn deposit_count)]
+	pub type DepositCounts<T: Config> = StorageMap<_, Blake2_128Concat, T::TreeId, u32, ValueQuery>;
+
+	/// The number of withdrawals from each mixer
+	#[pallet::storage]
+	#[pallet::getter(fn withdrawal_count)]
+	pub type WithdrawalCounts<T: Config> = StorageMap<_, Blake2_128Concat, T::TreeId, u32, ValueQuery>;
+
+	/// The last block each mixer had a deposit or a withdrawal in
+	#[pallet::storage]
+	#[pallet::getter(fn last_activity)]
+	pub type LastActivity<T: Config> = StorageMap<_, Blake2_128Concat, T::TreeId, T::BlockNumber, OptionQuery>;
+
+	/// The deposits and withdrawals of each mixer per block, keyed by the
+	/// block number modulo `ActivityWindow`, so the entry of a block is
+	/// overwritten once the block leaves the window
+	#[pallet::storage]
+	pub type ActivityHistory<T: Config> = StorageDoubleMap<
+		_,
+		Blake2_128Concat,
+		T::TreeId,
+		Blake2_128Concat,
+		T::BlockNumber,
+		BlockActivity<T::BlockNumber>,
+		OptionQuery,
+	>;
+
+	#[pallet::genesis_config]
+	pub struct GenesisConfig<T: Config> {
+		/// Verifying keys to add to the merkle pallet, `None` standing for
+		/// `DefaultVerifyingKey`, which is generated when the genesis is built
+		/// rather than stored in the chain spec
+		pub keys: Vec<Option<Vec<u8>>>,
+		/// Mixers to create, as their currency, deposit size, setup and the
+		/// index of their verifying key in `keys`
+		pub mixers: Vec<(CurrencyIdOf<T>, BalanceOf<T>, Setup, u32)>,
+	}
+
+	#[cfg(feature = "std")]
+	impl<T: Config> Default for GenesisConfig<T> {
+		fn default() -> Self {
+			GenesisConfig {
+				keys: vec![],
+				mixers: vec![],
+			}
+		}
+	}
+
+	#[pallet::genesis_build]
+	impl<T: Config> GenesisBuild<T> for GenesisConfig<T> {
+		fn build(&self) {
+			let key_ids = self
+				.keys
+				.iter()
+				.map(|key| {
+					let key = key.clone().unwrap_or_else(Pallet::<T>::default_key_data);
+					T::Tree::add_verifying_key(key).expect("verifying key can be added at genesis")
+				})
+				.collect::<Vec<_>>();
+
+			for (currency_id, size, setup, key_index) in self.mixers.iter() {
+				let key_id = *key_ids
+					.get(*key_index as usize)
+					.expect("mixer key index is in the genesis keys");
+				let tree_id = <Pallet<T> as ExtendedMixer<_>>::create_new(
+					Pallet::<T>::account_id(),
+					*currency_id,
+					setup.clone(),
+					*size,
+				)
+				.expect("mixer can be created at genesis");
+				T::Tree::initialize_tree(tree_id, key_id).expect("mixer tree can be initialized at genesis");
+			}
+
+			// The staged initialization is only run as a migration on chains started
+			// without this config
+			FirstStageInitialized::<T>::set(true);
+			SecondStageInitialized::<T>::set(true);
+			// New chains have nothing to migrate
+			StorageVersion::<T>::put(Releases::V5_0_0);
+		}
+	}
+
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
-	#[pallet::metadata(T::AccountId = "AccountId", T::TreeId = "TreeId", BalanceOf<T> = "Balance")]
+	#[pallet::metadata(
+		T::AccountId = "AccountId",
+		T::TreeId = "TreeId",
+		BalanceOf<T> = "Balance",
+		T::BlockNumber = "BlockNumber"
+	)]
 	pub enum Event<T: Config> {
+		/// Mixer was stopped or restarted
+		StoppedSet(
+			/// Id of the tree
+			T::TreeId,
+			/// Whether the mixer is stopped
+			bool,
+		),
+		/// Reward claimed for a deposit of the specific mixer
+		RewardClaimed(
+			/// Id of the tree
+			T::TreeId,
+			/// Account id of the recipient
+			T::AccountId,
+			/// Reward nullifier of the deposit
+			ScalarBytes,
+		),
+		/// Mixer entered or left withdraw-only mode
+		WithdrawOnlySet(
+			/// Id of the tree
+			T::TreeId,
+			/// Whether the mixer only accepts withdrawals
+			bool,
+		),
+		/// Highest relayer fee of the mixer was set
+		MaxFeeSet(
+			/// Id of the tree
+			T::TreeId,
+			/// New highest relayer fee of the mixer
+			BalanceOf<T>,
+		),
+		/// Deposit and TVL limits of the mixer were set
+		LimitsSet(
+			/// Id of the tree
+			T::TreeId,
+			/// New limits of the mixer
+			MixerLimits<BalanceOf<T>>,
+		),
+		/// Protocol fee of the mixer was set or removed
+		ProtocolFeeSet(
+			/// Id of the tree
+			T::TreeId,
+			/// New protocol fee of the mixer
+			Option<ProtocolFee<BalanceOf<T>>>,
+		),
+		/// Protocol fee taken on a deposit and sent to the treasury
+		ProtocolFeeTaken(
+			/// Id of the tree
+			T::TreeId,
+			/// Account id of the depositor
+			T::AccountId,
+			/// Fee taken
+			BalanceOf<T>,
+		),
 		/// New deposit added to the specific mixer
 		Deposit(
 			/// Id of the tree
@@ -152,6 +562,8 @@ pub mod pallet {
 			T::AccountId,
 			/// Deposit size
 			BalanceOf<T>,
+			/// Encrypted memos of the deposited leaves, by leaf index
+			Vec<(u32, EncryptedMemo)>,
 		),
 		/// Withdrawal from the specific mixer
 		Withdraw(
@@ -163,6 +575,67 @@ pub mod pallet {
 			T::AccountId,
 			/// Account id of the relayer
 			T::AccountId,
+			/// Fee paid to the relayer
+			BalanceOf<T>,
+			/// Merkle root
+			ScalarBytes,
+		),
+		/// Unsigned withdrawal from the specific mixer
+		UnsignedWithdraw(
+			/// Id of the tree
+			T::TreeId,
+			/// Account id of the recipient
+			T::AccountId,
+			/// Fee paid from the deposit to the `Treasury` account
+			BalanceOf<T>,
+			/// Merkle root
+			ScalarBytes,
+		),
+		/// Withdrawal delay of the mixer was set
+		WithdrawalDelaySet(
+			/// Id of the tree
+			T::TreeId,
+			/// Number of blocks payouts are held for
+			T::BlockNumber,
+		),
+		/// Payout of a withdrawal was queued
+		WithdrawalQueued(
+			/// Id of the pending withdrawal
+			u32,
+			/// Id of the tree
+			T::TreeId,
+			/// Block the payout is due at
+			T::BlockNumber,
+		),
+		/// Pending withdrawal was paid out
+		WithdrawalPaid(
+			/// Id of the pending withdrawal
+			u32,
+			/// Id of the tree
+			T::TreeId,
+		),
+		/// Pending withdrawal was frozen or released
+		WithdrawalFrozen(
+			/// Id of the pending withdrawal
+			u32,
+			/// Whether the withdrawal is frozen
+			bool,
+		),
+		/// Payout of a pending withdrawal failed, it is frozen until released
+		WithdrawalPayoutFailed(
+			/// Id of the pending withdrawal
+			u32,
+			/// Id of the tree
+			T::TreeId,
+		),
+		/// Deposit of a mixer spent into a new commitment
+		Remix(
+			/// Id of the tree the deposit was spent from
+			T::TreeId,
+			/// Id of the tree the new commitment was added to
+			T::TreeId,
+			/// Account id of the sender
+			T::AccountId,
 			/// Merkle root
 			ScalarBytes,
 		),
@@ -184,6 +657,49 @@ pub mod pallet {
 		UnauthorizedCall,
 		/// Mixer is stopped
 		MixerStopped,
+		/// Mixer only accepts withdrawals
+		MixerWithdrawOnly,
+		/// Reward claims can't carry a fee or a refund
+		InvalidRewardClaim,
+		/// Root wasn't recorded for the mixer at the given block
+		UnknownDepositRoot,
+		/// Root is more recent than the minimum deposit length for reward
+		DepositTooRecent,
+		/// Reward for the deposit was already claimed
+		RewardAlreadyClaimed,
+		/// Relayer fee is above the maximum of the mixer
+		FeeExceedsMaximum,
+		/// Maximum fee is above the deposit size of the mixer
+		InvalidMaxFee,
+		/// Unsigned withdrawals need a recipient, a non-zero fee and no refund
+		InvalidUnsignedWithdraw,
+		/// Remixes can't carry a recipient, a fee or a refund
+		InvalidRemix,
+		/// EVM withdrawals can't also carry an account recipient
+		InvalidEvmWithdraw,
+		/// Memos don't match the deposited leaves one to one
+		InvalidMemoCount,
+		/// Memo is longer than the maximum memo length
+		MemoTooLong,
+		/// Mixers of a remix differ in currency or deposit size
+		RemixDenominationMismatch,
+		/// Deposit inserts more commitments than the mixer accepts per call
+		TooManyCommitments,
+		/// Mixer already took its maximum number of deposits in this block
+		BlockDepositLimitReached,
+		/// Deposit would take the TVL of the mixer above its maximum
+		TvlLimitExceeded,
+		/// Pending withdrawal not found for specified id
+		NoPendingWithdrawal,
+		/// Pending withdrawal is already frozen
+		WithdrawalAlreadyFrozen,
+		/// Pending withdrawal isn't frozen
+		WithdrawalNotFrozen,
+		/// Fee of an unsigned withdrawal is below the minimum of the mixer
+		FeeBelowMinimum,
+		/// No block within `MaxPayoutPostponement` blocks of the due block of
+		/// a payout has room for it
+		PayoutQueueFull,
 	}
 
 	#[pallet::pallet]
@@ -195,36 +711,18 @@ pub mod pallet {
 		fn on_initialize(_n: BlockNumberFor<T>) -> Weight {
 			// We make sure that we return the correct weight for the block according to
 			// on_finalize
-			if Self::second_stage_initialized() {
+			let finalize_weight = if Self::second_stage_initialized() {
 				// In case mixer is initialized, we expect the weights for merkle cache update
 				<T as Config>::WeightInfo::on_finalize_initialized()
 			} else {
-				// In case mixer is not initialized, we expect the weights for initialization
-				<T as Config>::WeightInfo::on_finalize_uninitialized()
-			}
+				0
+			};
+			let due = Self::pay_due_withdrawals(_n);
+			finalize_weight.saturating_add(<T as Config>::WeightInfo::on_initialize(due))
 		}
 
 		fn on_finalize(_n: BlockNumberFor<T>) {
-			if Self::first_stage_initialized() && !Self::second_stage_initialized() {
-				let mixer_ids = MixerTreeIds::<T>::get();
-				// check if first tree has been initialized, otherwise intialize the parameters
-				for i in 0..mixer_ids.len() {
-					if let Ok(initialized) = T::Tree::is_initialized(mixer_ids[i]) {
-						if !initialized {
-							match Self::initialize_second_stage() {
-								Ok(_) => {}
-								Err(e) => {
-									log::error!("Error initialising trees: {:?}", e);
-								}
-							}
-						}
-					}
-
-					break;
-				}
-			}
-
-			if Self::first_stage_initialized() && Self::second_stage_initialized() {
+			if Self::second_stage_initialized() {
 				// check if any deposits happened (by checking the size of the collection at
 				// this block) if none happened, carry over previous Merkle roots for the cache.
 				let mixer_ids = MixerTreeIds::<T>::get();
@@ -237,15 +735,6 @@ pub mod pallet {
 					}
 				}
 			}
-
-			if !Self::first_stage_initialized() {
-				match Self::initialize_first_stage() {
-					Ok(_) => {}
-					Err(e) => {
-						log::error!("Error initialising: {:?}", e);
-					}
-				}
-			}
 		}
 	}
 
@@ -255,22 +744,44 @@ pub mod pallet {
 		/// Multiple deposits can be inserted together since `data_points` is an
 		/// array.
 		///
-		/// Fails in case the mixer is stopped or not initialized.
+		/// `memos` is either empty or holds an optional encrypted memo for each
+		/// of the `data_points`, e.g. the note encrypted to a viewing key of
+		/// the depositor. Memos are emitted in the `Deposit` event and offchain
+		/// indexed under [`Pallet::memo_key`], so a wallet can recover its
+		/// notes by scanning the chain.
+		///
+		/// The protocol fee of the mixer, if any, is charged for each of the
+		/// `data_points` on top of the deposit size.
+		///
+		/// Fails in case the mixer is stopped, in withdraw-only mode or not
+		/// initialized, if the memos are invalid or if the deposit is above
+		/// one of the limits of the mixer.
 		///
 		/// Weights:
 		/// - Dependent on argument: `data_points`
 		///
 		/// - Base weight: 417_168_400_000
-		/// - DB weights: 8 reads, 5 writes
+		/// - DB weights: 16 reads, 17 writes
 		/// - Additional weights: 21_400_442_000 * data_points.len()
 		#[pallet::weight(<T as Config>::WeightInfo::deposit(data_points.len() as u32))]
 		pub fn deposit(
 			origin: OriginFor<T>,
 			mixer_id: T::TreeId,
 			data_points: Vec<ScalarBytes>,
+			memos: Vec<Option<EncryptedMemo>>,
 		) -> DispatchResultWithPostInfo {
 			let sender = ensure_signed(origin)?;
 			ensure!(!<MerklePallet<T>>::stopped(mixer_id), Error::<T>::MixerStopped);
+			ensure!(!Self::withdraw_only(mixer_id), Error::<T>::MixerWithdrawOnly);
+			ensure!(
+				memos.is_empty() || memos.len() == data_points.len(),
+				Error::<T>::InvalidMemoCount
+			);
+			let max_memo_length = T::MaxMemoLength::get() as usize;
+			ensure!(
+				memos.iter().flatten().all(|memo| memo.len() <= max_memo_length),
+				Error::<T>::MemoTooLong
+			);
 			// get mixer info, should always exist if the module is initialized
 			let mixer_info = Self::get_mixer(mixer_id)?;
 			// ensure the sender has enough balance to cover deposit
@@ -282,18 +793,42 @@ pub mod pallet {
 				.iter()
 				.map(|_| mixer_info.fixed_deposit_size)
 				.fold(Zero::zero(), |acc, elt| acc + elt);
-			ensure!(balance >= deposit, Error::<T>::InsufficientBalance);
+			Self::ensure_within_limits(mixer_id, data_points.len() as u32, deposit)?;
+			let fee_per_deposit = Self::protocol_fee_per_deposit(mixer_id, mixer_info.fixed_deposit_size);
+			let fee: BalanceOf<T> = data_points
+				.iter()
+				.map(|_| fee_per_deposit)
+				.fold(Zero::zero(), |acc, elt| acc.saturating_add(elt));
+			ensure!(balance >= deposit.saturating_add(fee), Error::<T>::InsufficientBalance);
 			// transfer the deposit to the module
 			T::Currency::transfer(mixer_info.currency_id, &sender, &Self::account_id(), deposit)?;
+			// and the protocol fee, on top of it, to the treasury
+			if !fee.is_zero() {
+				T::Currency::transfer(mixer_info.currency_id, &sender, &T::Treasury::get(), fee)?;
+				AccruedFees::<T>::mutate(mixer_id, |accrued| *accrued = accrued.saturating_add(fee));
+				Self::deposit_event(Event::ProtocolFeeTaken(mixer_id, sender.clone(), fee));
+			}
 			// update the total value locked
 			let tvl = Self::total_value_locked(mixer_id);
 			<TotalValueLocked<T>>::insert(mixer_id, tvl + deposit);
 			// add elements to the mixer group's merkle tree and save the leaves
+			let leaf_count = <MerklePallet<T>>::get_tree(mixer_id)?.leaf_count;
 			T::Tree::add_members(Self::account_id(), mixer_id.into(), data_points.clone())?;
+			// record the new root for reward claims
+			Self::record_deposit_root(mixer_id)?;
+			Self::record_activity(mixer_id, data_points.len() as u32, 0);
+			// keep the memos next to their leaves for wallets to scan
+			let memos: Vec<(u32, EncryptedMemo)> = (leaf_count..)
+				.zip(memos)
+				.filter_map(|(index, memo)| memo.map(|memo| (index, memo)))
+				.collect();
+			for (index, memo) in memos.iter() {
+				sp_io::offchain_index::set(&Self::memo_key(mixer_id, *index), memo);
+			}
 
 			let deposit_size = mixer_info.fixed_deposit_size;
 
-			Self::deposit_event(Event::Deposit(mixer_id, sender, deposit_size));
+			Self::deposit_event(Event::Deposit(mixer_id, sender, deposit_size, memos));
 
 			Ok(().into())
 		}
@@ -301,46 +836,90 @@ pub mod pallet {
 		/// Withdraws a deposited amount from the mixer. Can only withdraw one
 		/// deposit. Accepts proof of membership along with the mixer id.
 		///
-		/// Fails if the mixer is stopped or not initialized.
+		/// If the mixer has a withdrawal delay, the payout is queued instead
+		/// of being made right away.
+		///
+		/// Fails if the mixer is stopped or not initialized, or if no block
+		/// within `MaxPayoutPostponement` blocks of the due block of a delayed
+		/// payout has room for it.
 		///
 		/// Weights:
 		/// - Independent of the arguments.
 		///
 		/// - Base weight: 1_078_562_000_000
-		/// - DB weights: 9 reads, 3 writes
+		/// - DB weights: 13 reads, 9 writes, and a read per block searched for
+		///   room for a delayed payout
 		#[pallet::weight(<T as Config>::WeightInfo::withdraw())]
 		pub fn withdraw(origin: OriginFor<T>, withdraw_proof: WithdrawProof<T>) -> DispatchResultWithPostInfo {
 			let sender = ensure_signed(origin)?;
-			ensure!(
-				!<MerklePallet<T>>::stopped(withdraw_proof.mixer_id),
-				Error::<T>::MixerStopped
-			);
-			let recipient = withdraw_proof.recipient.unwrap_or(sender.clone());
-			let relayer = withdraw_proof.relayer.unwrap_or(sender.clone());
-			// get mixer info, should fail if tree isn't initialized
-			let mixer_info = Self::get_mixer(withdraw_proof.mixer_id)?;
-			// check if the nullifier has been used
-			T::Tree::has_used_nullifier(withdraw_proof.mixer_id.into(), withdraw_proof.nullifier_hash.clone())?;
-			// Verify the zero-knowledge proof of membership provided
-			T::Tree::verify_zk(
-				withdraw_proof.mixer_id.into(),
-				withdraw_proof.cached_block,
-				withdraw_proof.cached_root.clone(),
-				withdraw_proof.comms,
-				withdraw_proof.nullifier_hash.clone(),
-				withdraw_proof.proof_bytes,
-				withdraw_proof.leaf_index_commitments,
-				withdraw_proof.proof_commitments,
-				recipient.encode().to_vec(),
-				relayer.encode().to_vec(),
-			)?;
-			// transfer the fixed deposit size to the sender
-			T::Currency::transfer(
+			let recipient = withdraw_proof.recipient.clone().unwrap_or(sender.clone());
+			let relayer = withdraw_proof.relayer.clone().unwrap_or(sender.clone());
+			// run every check on the proof before touching any state
+			let mixer_info = Self::validate_withdraw(&withdraw_proof, &recipient, &relayer)?;
+			Self::pay_withdrawal(sender, withdraw_proof, mixer_info, recipient, relayer)?;
+			Ok(().into())
+		}
+
+		/// Withdraws a deposited amount from the mixer to an EVM address. The
+		/// proof is made with [`Pallet::evm_recipient_input`] of the address as
+		/// the recipient, and the deposit is paid to the account the address
+		/// maps to through the `EvmAddressMapping`, i.e. to its EVM balance.
+		///
+		/// Fails if the proof also has an account recipient, or for any of the
+		/// reasons `withdraw` fails.
+		///
+		/// Weights:
+		/// - Independent of the arguments.
+		///
+		/// - Base weight: 1_078_562_000_000
+		/// - DB weights: 13 reads, 9 writes, and a read per block searched for
+		///   room for a delayed payout
+		#[pallet::weight(<T as Config>::WeightInfo::withdraw())]
+		pub fn withdraw_to_evm(
+			origin: OriginFor<T>,
+			withdraw_proof: WithdrawProof<T>,
+			recipient: H160,
+		) -> DispatchResultWithPostInfo {
+			let sender = ensure_signed(origin)?;
+			ensure!(withdraw_proof.recipient.is_none(), Error::<T>::InvalidEvmWithdraw);
+			let relayer = withdraw_proof.relayer.clone().unwrap_or(sender.clone());
+			let mixer_info =
+				Self::validate_withdraw_to(&withdraw_proof, Self::evm_recipient_input(&recipient), &relayer)?;
+			let recipient = T::EvmAddressMapping::convert(recipient);
+			Self::pay_withdrawal(sender, withdraw_proof, mixer_info, recipient, relayer)?;
+			Ok(().into())
+		}
+
+		/// Withdraws a deposited amount from the mixer without a signed
+		/// origin. The fee in the proof is paid from the deposit to the
+		/// `Treasury` account and the rest is paid to the recipient, or queued
+		/// like in `withdraw`.
+		///
+		/// Fails if the proof has no recipient, a fee below the
+		/// `MinUnsignedFee` share of the deposit size, a zero fee or a
+		/// refund, or for any of the reasons `withdraw` fails.
+		///
+		/// Weights:
+		/// - Independent of the arguments.
+		///
+		/// - Base weight: 1_078_562_000_000
+		/// - DB weights: 13 reads, 9 writes, and a read per block searched for
+		///   room for a delayed payout
+		#[pallet::weight(<T as Config>::WeightInfo::withdraw())]
+		pub fn withdraw_unsigned(origin: OriginFor<T>, withdraw_proof: WithdrawProof<T>) -> DispatchResultWithPostInfo {
+			ensure_none(origin)?;
+			let (mixer_info, recipient) = Self::validate_unsigned_withdraw(&withdraw_proof)?;
+			let fee = withdraw_proof.fee;
+			Self::pay_or_queue(
+				withdraw_proof.mixer_id,
 				mixer_info.currency_id,
-				&Self::account_id(),
-				&recipient,
-				mixer_info.fixed_deposit_size,
+				recipient.clone(),
+				mixer_info.fixed_deposit_size.saturating_sub(fee),
+				recipient.clone(),
+				Zero::zero(),
 			)?;
+			// the fee goes to the treasury in place of a transaction fee
+			T::Currency::transfer(mixer_info.currency_id, &Self::account_id(), &T::Treasury::get(), fee)?;
 			// update the total value locked
 			let tvl = Self::total_value_locked(withdraw_proof.mixer_id);
 			<TotalValueLocked<T>>::insert(withdraw_proof.mixer_id, tvl - mixer_info.fixed_deposit_size);
@@ -350,12 +929,94 @@ pub mod pallet {
 				withdraw_proof.mixer_id.into(),
 				withdraw_proof.nullifier_hash,
 			)?;
+			Self::record_activity(withdraw_proof.mixer_id, 0, 1);
 
-			Self::deposit_event(Event::Withdraw(
+			Self::deposit_event(Event::UnsignedWithdraw(
+				withdraw_proof.mixer_id,
+				recipient,
+				fee,
+				withdraw_proof.cached_root,
+			));
+			Ok(().into())
+		}
+
+		/// Claims the reward for a deposit in the mixer, paid to the recipient
+		/// of the proof. The proof is a reward claim proof, with the reward
+		/// nullifier of the deposit in place of the nullifier hash, made
+		/// against a root from `DepositRoots` that is at least the mixer's
+		/// minimum deposit length old, with [`Pallet::reward_relayer_input`]
+		/// as the relayer.
+		///
+		/// Fails if the reward was already claimed, the root is unknown or too
+		/// recent, the proof doesn't verify or the `RewardHandler` can't pay
+		/// the reward.
+		#[pallet::weight(<T as Config>::WeightInfo::claim_reward())]
+		pub fn claim_reward(origin: OriginFor<T>, withdraw_proof: WithdrawProof<T>) -> DispatchResultWithPostInfo {
+			let sender = ensure_signed(origin)?;
+			let recipient = withdraw_proof.recipient.clone().unwrap_or(sender.clone());
+			let relayer = withdraw_proof.relayer.clone().unwrap_or(sender);
+			let reward_nullifier = Self::validate_reward_claim(&withdraw_proof, &recipient, &relayer)?;
+
+			// the claim is only spent if the reward pool could pay it
+			T::RewardHandler::payout(&recipient, &withdraw_proof.mixer_id, T::DepositReward::get())?;
+			ClaimedRewards::<T>::insert(withdraw_proof.mixer_id, reward_nullifier.clone(), true);
+
+			Self::deposit_event(Event::RewardClaimed(
 				withdraw_proof.mixer_id,
-				sender,
 				recipient,
-				relayer,
+				reward_nullifier,
+			));
+			Ok(().into())
+		}
+
+		/// Spends a deposit of the mixer in the proof and adds `commitment` to
+		/// the mixer with id of `mixer_id`, which can be the same mixer or
+		/// another one with the same currency and deposit size. No currency is
+		/// transferred and the total value locked of both mixers together
+		/// stays the same.
+		///
+		/// The proof has to be made with [`Pallet::remix_input`] as the
+		/// recipient, which binds it to the target mixer and the commitment,
+		/// and with [`Pallet::remix_relayer_input`] as the relayer.
+		///
+		/// Fails if the source mixer is stopped, the target mixer is stopped,
+		/// in withdraw-only mode or at one of its limits, or for any of the
+		/// reasons `withdraw` fails.
+		#[pallet::weight(
+			<T as Config>::WeightInfo::withdraw().saturating_add(<T as Config>::WeightInfo::deposit(1))
+		)]
+		pub fn remix(
+			origin: OriginFor<T>,
+			withdraw_proof: WithdrawProof<T>,
+			mixer_id: T::TreeId,
+			commitment: ScalarBytes,
+		) -> DispatchResultWithPostInfo {
+			let sender = ensure_signed(origin)?;
+			let relayer = withdraw_proof.relayer.clone().unwrap_or(sender.clone());
+			let mixer_info = Self::validate_remix(&withdraw_proof, &relayer, mixer_id, &commitment)?;
+			// spend the deposit on behalf of the module
+			T::Tree::add_nullifier(
+				Self::account_id(),
+				withdraw_proof.mixer_id.into(),
+				withdraw_proof.nullifier_hash.clone(),
+			)?;
+			Self::record_activity(withdraw_proof.mixer_id, 0, 1);
+			// the deposit only moves between mixers held by the module
+			if mixer_id != withdraw_proof.mixer_id {
+				TotalValueLocked::<T>::mutate(withdraw_proof.mixer_id, |tvl| {
+					*tvl = tvl.saturating_sub(mixer_info.fixed_deposit_size)
+				});
+				TotalValueLocked::<T>::mutate(mixer_id, |tvl| *tvl = tvl.saturating_add(mixer_info.fixed_deposit_size));
+			}
+			// add the commitment just as a deposit would
+			T::Tree::add_members(Self::account_id(), mixer_id.into(), vec![commitment])?;
+			Self::record_deposit_root(mixer_id)?;
+			Self::record_activity(mixer_id, 1, 0);
+
+			Self::deposit_event(Event::Remix(
+				withdraw_proof.mixer_id,
+				mixer_id,
+				sender,
 				withdraw_proof.cached_root,
 			));
 			Ok(().into())
@@ -368,7 +1029,7 @@ pub mod pallet {
 			setup: Setup,
 			size: BalanceOf<T>,
 		) -> DispatchResultWithPostInfo {
-			ensure_admin(origin, &Self::admin())?;
+			T::AdminOrigin::ensure_origin(origin)?;
 
 			<Self as ExtendedMixer<_>>::create_new(Self::account_id(), currency_id, setup, size)?;
 			Ok(().into())
@@ -382,15 +1043,161 @@ pub mod pallet {
 			size: BalanceOf<T>,
 			key_id: T::KeyId,
 		) -> DispatchResultWithPostInfo {
-			ensure_admin(origin, &Self::admin())?;
+			T::AdminOrigin::ensure_origin(origin)?;
 
 			let tree_id = <Self as ExtendedMixer<_>>::create_new(Self::account_id(), currency_id, setup, size)?;
 			T::Tree::initialize_tree(tree_id, key_id)?;
 			Ok(().into())
 		}
 
-		/// Stops the operation of all the mixers managed by the pallet.
-		/// Can only be called by the admin or the root origin.
+		/// Sets the highest relayer fee the mixer with id of `mixer_id`
+		/// accepts. Can only be called by the `AdminOrigin`. Until it is set,
+		/// the mixer accepts fees up to the `DefaultMaxFee` share of its
+		/// deposit size.
+		///
+		/// Fails if the fee is above the deposit size of the mixer.
+		///
+		/// Weights:
+		/// - Independent of the arguments.
+		///
+		/// - Base weight: 17_000_000
+		/// - DB weights: 1 read, 1 write
+		#[pallet::weight(<T as Config>::WeightInfo::set_max_fee())]
+		pub fn set_max_fee(
+			origin: OriginFor<T>,
+			mixer_id: T::TreeId,
+			max_fee: BalanceOf<T>,
+		) -> DispatchResultWithPostInfo {
+			T::AdminOrigin::ensure_origin(origin)?;
+			let mixer_info = MixerTrees::<T>::get(mixer_id);
+			ensure!(mixer_info.fixed_deposit_size > Zero::zero(), Error::<T>::NoMixerForId);
+			ensure!(max_fee <= mixer_info.fixed_deposit_size, Error::<T>::InvalidMaxFee);
+			MaxFees::<T>::insert(mixer_id, max_fee);
+			Self::deposit_event(Event::MaxFeeSet(mixer_id, max_fee));
+			Ok(().into())
+		}
+
+		/// Sets the deposit and TVL limits of the mixer with id of
+		/// `mixer_id`. Limits left as `None` don't apply. Lowering the TVL
+		/// limit below the current TVL only stops further deposits. Can only
+		/// be called by the `AdminOrigin`.
+		///
+		/// Weights:
+		/// - Independent of the arguments.
+		///
+		/// - Base weight: 18_000_000
+		/// - DB weights: 1 read, 1 write
+		#[pallet::weight(<T as Config>::WeightInfo::set_limits())]
+		pub fn set_limits(
+			origin: OriginFor<T>,
+			mixer_id: T::TreeId,
+			limits: MixerLimits<BalanceOf<T>>,
+		) -> DispatchResultWithPostInfo {
+			T::AdminOrigin::ensure_origin(origin)?;
+			ensure!(MixerTrees::<T>::contains_key(mixer_id), Error::<T>::NoMixerForId);
+			Limits::<T>::insert(mixer_id, limits.clone());
+			Self::deposit_event(Event::LimitsSet(mixer_id, limits));
+			Ok(().into())
+		}
+
+		/// Sets the number of blocks the payouts of withdrawals from the mixer
+		/// with id of `mixer_id` are held for, zero paying them out right
+		/// away. Withdrawals already pending keep their due block. Can only be
+		/// called by the `AdminOrigin`.
+		#[pallet::weight(5_000_000)]
+		pub fn set_withdrawal_delay(
+			origin: OriginFor<T>,
+			mixer_id: T::TreeId,
+			delay: T::BlockNumber,
+		) -> DispatchResultWithPostInfo {
+			T::AdminOrigin::ensure_origin(origin)?;
+			ensure!(MixerTrees::<T>::contains_key(mixer_id), Error::<T>::NoMixerForId);
+			WithdrawalDelays::<T>::insert(mixer_id, delay);
+			Self::deposit_event(Event::WithdrawalDelaySet(mixer_id, delay));
+			Ok(().into())
+		}
+
+		/// Freezes the pending withdrawal with id of `id`, so it isn't paid
+		/// out when due and its funds stay in the mixer. Can only be called by
+		/// the `AdminOrigin`.
+		#[pallet::weight(5_000_000)]
+		pub fn freeze_withdrawal(origin: OriginFor<T>, id: u32) -> DispatchResultWithPostInfo {
+			T::AdminOrigin::ensure_origin(origin)?;
+			PendingWithdrawals::<T>::try_mutate(id, |pending| -> dispatch::DispatchResult {
+				let pending = pending.as_mut().ok_or(Error::<T>::NoPendingWithdrawal)?;
+				ensure!(!pending.frozen, Error::<T>::WithdrawalAlreadyFrozen);
+				pending.frozen = true;
+				Ok(())
+			})?;
+			Self::deposit_event(Event::WithdrawalFrozen(id, true));
+			Ok(().into())
+		}
+
+		/// Releases the frozen pending withdrawal with id of `id`. It is paid
+		/// out right away if it is already due, or at its due block otherwise.
+		/// Can only be called by the `AdminOrigin`.
+		#[pallet::weight(<T as Config>::WeightInfo::pay_pending_withdrawals(1))]
+		#[transactional]
+		pub fn release_withdrawal(origin: OriginFor<T>, id: u32) -> DispatchResultWithPostInfo {
+			T::AdminOrigin::ensure_origin(origin)?;
+			let due_block = PendingWithdrawals::<T>::try_mutate(id, |pending| -> Result<_, dispatch::DispatchError> {
+				let pending = pending.as_mut().ok_or(Error::<T>::NoPendingWithdrawal)?;
+				ensure!(pending.frozen, Error::<T>::WithdrawalNotFrozen);
+				pending.frozen = false;
+				Ok(pending.due_block)
+			})?;
+			Self::deposit_event(Event::WithdrawalFrozen(id, false));
+			// the queue entries of due withdrawals were already taken
+			if due_block <= <frame_system::Pallet<T>>::block_number() {
+				Self::pay_pending_withdrawal(id)?;
+			}
+			Ok(().into())
+		}
+
+		/// Sets the protocol fee charged on deposits into the mixer with id of
+		/// `mixer_id`, or removes it if `fee` is `None`. Can only be called by
+		/// the `AdminOrigin`.
+		///
+		/// Weights:
+		/// - Independent of the arguments.
+		///
+		/// - Base weight: 18_000_000
+		/// - DB weights: 1 read, 1 write
+		#[pallet::weight(<T as Config>::WeightInfo::set_protocol_fee())]
+		pub fn set_protocol_fee(
+			origin: OriginFor<T>,
+			mixer_id: T::TreeId,
+			fee: Option<ProtocolFee<BalanceOf<T>>>,
+		) -> DispatchResultWithPostInfo {
+			T::AdminOrigin::ensure_origin(origin)?;
+			ensure!(MixerTrees::<T>::contains_key(mixer_id), Error::<T>::NoMixerForId);
+			ProtocolFees::<T>::set(mixer_id, fee.clone());
+			Self::deposit_event(Event::ProtocolFeeSet(mixer_id, fee));
+			Ok(().into())
+		}
+
+		/// Puts every mixer managed by the pallet in or out of withdraw-only
+		/// mode. Can only be called by the `AdminOrigin`.
+		///
+		/// Weights:
+		/// - Independent of the arguments.
+		///
+		/// - Base weight: 30_000_000
+		/// - DB weights: 1 read, 4 writes
+		#[pallet::weight(<T as Config>::WeightInfo::set_all_withdraw_only())]
+		pub fn set_all_withdraw_only(origin: OriginFor<T>, withdraw_only: bool) -> DispatchResultWithPostInfo {
+			T::AdminOrigin::ensure_origin(origin)?;
+			// Stopping every mixer would also block withdrawals and trap the
+			// deposits, so only deposits are halted
+			for mixer_id in MixerTreeIds::<T>::get() {
+				WithdrawOnly::<T>::insert(mixer_id, withdraw_only);
+				Self::deposit_event(Event::WithdrawOnlySet(mixer_id, withdraw_only));
+			}
+			Ok(().into())
+		}
+
+		/// Stops or restarts the mixer with id of `mixer_id`. Can only be
+		/// called by the `AdminOrigin`.
 		///
 		/// Weights:
 		/// - Independent of the arguments.
@@ -398,34 +1205,240 @@ pub mod pallet {
 		/// - Base weight: 36_000_000
 		/// - DB weights: 6 reads, 4 writes
 		#[pallet::weight(<T as Config>::WeightInfo::set_stopped())]
-		pub fn set_stopped(origin: OriginFor<T>, stopped: bool) -> DispatchResultWithPostInfo {
-			// Ensure the caller is admin or root
-			ensure_admin(origin, &Self::admin())?;
-			// Set the mixer state, `stopped` can be true or false
-			let mixer_ids = MixerTreeIds::<T>::get();
-			for i in 0..mixer_ids.len() {
-				T::Tree::set_stopped(Self::account_id(), mixer_ids[i], stopped)?;
-			}
+		pub fn set_mixer_stopped(
+			origin: OriginFor<T>,
+			mixer_id: T::TreeId,
+			stopped: bool,
+		) -> DispatchResultWithPostInfo {
+			T::AdminOrigin::ensure_origin(origin)?;
+			ensure!(MixerTrees::<T>::contains_key(mixer_id), Error::<T>::NoMixerForId);
+			T::Tree::set_stopped(Self::account_id(), mixer_id, stopped)?;
+			Self::deposit_event(Event::StoppedSet(mixer_id, stopped));
 			Ok(().into())
 		}
 
-		/// Transfers the admin from the caller to the specified `to` account.
-		/// Can only be called by the current admin or the root origin.
+		/// Puts the mixer with id of `mixer_id` in or out of withdraw-only
+		/// mode. Can only be called by the `AdminOrigin`.
 		///
 		/// Weights:
 		/// - Independent of the arguments.
 		///
-		/// - Base weight: 7_000_000
+		/// - Base weight: 16_000_000
 		/// - DB weights: 1 read, 1 write
-		#[pallet::weight(<T as Config>::WeightInfo::transfer_admin())]
-		pub fn transfer_admin(origin: OriginFor<T>, to: T::AccountId) -> DispatchResultWithPostInfo {
-			// Ensures that the caller is the root or the current admin
-			ensure_admin(origin, &Self::admin())?;
-			// Updating the admin
-			Admin::<T>::set(to);
+		#[pallet::weight(<T as Config>::WeightInfo::set_withdraw_only())]
+		pub fn set_withdraw_only(
+			origin: OriginFor<T>,
+			mixer_id: T::TreeId,
+			withdraw_only: bool,
+		) -> DispatchResultWithPostInfo {
+			T::AdminOrigin::ensure_origin(origin)?;
+			ensure!(MixerTrees::<T>::contains_key(mixer_id), Error::<T>::NoMixerForId);
+			WithdrawOnly::<T>::insert(mixer_id, withdraw_only);
+			Self::deposit_event(Event::WithdrawOnlySet(mixer_id, withdraw_only));
 			Ok(().into())
 		}
 	}
+
+	#[pallet::validate_unsigned]
+	impl<T: Config> ValidateUnsigned for Pallet<T> {
+		type Call = Call<T>;
+
+		/// Admits `withdraw_unsigned` into the pool only if the proof and
+		/// nullifier would pass at dispatch. Withdrawals spending the same
+		/// nullifier provide the same tag, so only one of them is kept.
+		///
+		/// Fees are in the currency of the mixer, so withdrawals are
+		/// prioritized by their fee as a share of the deposit size. They are
+		/// valid until their root leaves the merkle pallet's root cache.
+		fn validate_unsigned(_source: TransactionSource, call: &Self::Call) -> TransactionValidity {
+			if let Call::withdraw_unsigned(withdraw_proof) = call {
+				let nullifier = <MerklePallet<T>>::nullifier_validity(
+					withdraw_proof.mixer_id,
+					withdraw_proof.nullifier_hash.clone(),
+				)?;
+				let (mixer_info, _) = Self::validate_unsigned_withdraw(withdraw_proof)
+					.map_err(|_| TransactionValidityError::from(InvalidTransaction::BadProof))?;
+
+				let fee_share = Permill::from_rational_approximation(withdraw_proof.fee, mixer_info.fixed_deposit_size);
+				let expires_at = withdraw_proof
+					.cached_block
+					.saturating_add(<T as merkle::Config>::CacheBlockLength::get());
+				let longevity = expires_at.saturating_sub(<frame_system::Pallet<T>>::block_number());
+				Ok(nullifier.combine_with(ValidTransaction {
+					priority: fee_share.deconstruct().into(),
+					longevity: longevity.max(One::one()).saturated_into(),
+					..Default::default()
+				}))
+			} else {
+				InvalidTransaction::Call.into()
+			}
+		}
+	}
+}
+
+sp_api::decl_runtime_apis! {
+	pub trait MixerApi<AccountId, WithdrawProof, CurrencyId, Balance, BlockNumber> where
+		AccountId: codec::Codec,
+		WithdrawProof: codec::Codec,
+		CurrencyId: codec::Codec,
+		Balance: codec::Codec,
+		BlockNumber: codec::Codec,
+	{
+		/// Dry-run a withdrawal. Runs the same checks as `withdraw` without
+		/// changing state and returns the error `withdraw` would fail with.
+		fn check_withdraw(sender: AccountId, withdraw_proof: WithdrawProof) -> Result<(), dispatch::DispatchError>;
+		/// Get whether the mixer is active, in withdraw-only mode or stopped.
+		fn mixer_state(mixer_id: u32) -> Result<MixerState, dispatch::DispatchError>;
+		/// Get the statistics of every mixer.
+		fn mixers() -> Vec<MixerStats<u32, CurrencyId, Balance, BlockNumber>>;
+		/// Get the statistics of a mixer.
+		fn mixer_stats(mixer_id: u32) -> Result<MixerStats<u32, CurrencyId, Balance, BlockNumber>, dispatch::DispatchError>;
+		/// Get the deposits and withdrawals of a mixer per block, over the
+		/// activity window.
+		fn mixer_activity(mixer_id: u32) -> Result<Vec<BlockActivity<BlockNumber>>, dispatch::DispatchError>;
+		/// Get the withdrawals of a mixer waiting to be paid out, by id.
+		fn pending_withdrawals(
+			mixer_id: u32,
+		) -> Result<Vec<(u32, PendingWithdrawal<u32, AccountId, Balance, BlockNumber>)>, dispatch::DispatchError>;
+	}
+}
+
+/// Signed extension that keeps a single `withdraw` or `remix` per nullifier in
+/// the transaction pool and rejects withdrawals of already used nullifiers
+/// before they are included. See [`merkle::CheckNullifier`].
+pub type CheckNullifier<T> = merkle::CheckNullifier<T, Call<T>>;
+
+impl<T: Config> SpendsNullifier<T::TreeId> for Call<T> {
+	const IDENTIFIER: &'static str = "CheckMixerNullifier";
+
+	fn spent_nullifier(&self) -> Option<(T::TreeId, ScalarBytes)> {
+		match self {
+			Call::withdraw(withdraw_proof)
+			| Call::withdraw_to_evm(withdraw_proof, _)
+			| Call::remix(withdraw_proof, ..) => Some((withdraw_proof.mixer_id, withdraw_proof.nullifier_hash.clone())),
+			_ => None,
+		}
+	}
+}
+
+/// Storage layouts of the pallet, each reached by one of the
+/// [`migrations`]
+#[cfg_attr(feature = "std", derive(Debug))]
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Releases {
+	/// Mixers initialized in two stages, privileged calls checked against the
+	/// `Admin` account
+	V1_0_0,
+	/// Mixers built by [`migrations::initialize_staged`] or at genesis
+	V2_0_0,
+	/// `Admin` account removed by [`migrations::remove_admin`]
+	V3_0_0,
+	/// Deposits counted by [`migrations::initialize_deposit_counts`]
+	V4_0_0,
+	/// Max fees of existing mixers set by [`migrations::initialize_max_fees`]
+	V5_0_0,
+}
+
+impl Default for Releases {
+	fn default() -> Self {
+		Releases::V1_0_0
+	}
+}
+
+/// Operating state of a mixer
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq)]
+pub enum MixerState {
+	/// Deposits and withdrawals are accepted
+	Active,
+	/// Deposits are refused, withdrawals are accepted
+	WithdrawOnly,
+	/// Deposits and withdrawals are refused
+	Stopped,
+}
+
+/// Overview of a mixer, as returned by the runtime API
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+pub struct MixerStats<TreeId, CurrencyId, Balance, BlockNumber> {
+	/// Id of the mixer tree
+	pub mixer_id: TreeId,
+	/// Id of the currency in the mixer
+	pub currency_id: CurrencyId,
+	/// Deposit size for the mixer
+	pub fixed_deposit_size: Balance,
+	/// Total value locked in the mixer
+	pub total_value_locked: Balance,
+	/// Whether the mixer is active, in withdraw-only mode or stopped
+	pub state: MixerState,
+	/// Number of deposits, i.e. leaves in the tree
+	pub deposit_count: u32,
+	/// Number of withdrawals
+	pub withdrawal_count: u32,
+	/// Number of deposits that weren't withdrawn yet
+	pub anonymity_set_size: u32,
+	/// Last block with a deposit or a withdrawal, if any
+	pub last_activity: Option<BlockNumber>,
+	/// Number of deposits over the activity window
+	pub recent_deposits: u32,
+	/// Number of withdrawals over the activity window
+	pub recent_withdrawals: u32,
+	/// Deposit and TVL limits of the mixer
+	pub limits: MixerLimits<Balance>,
+}
+
+/// Protocol fee of a mixer, charged on top of every deposit
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+pub enum ProtocolFee<Balance> {
+	/// Share of the deposit size of the mixer
+	Proportional(Permill),
+	/// Flat amount per deposit
+	Flat(Balance),
+}
+
+/// Deposit and TVL limits of a mixer, each `None` if it doesn't apply
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+#[derive(Encode, Decode, Clone, PartialEq, Eq, Default)]
+pub struct MixerLimits<Balance> {
+	/// Maximum number of commitments in a single deposit call
+	pub max_commitments_per_deposit: Option<u32>,
+	/// Maximum number of deposits in a single block
+	pub max_deposits_per_block: Option<u32>,
+	/// Maximum total value locked
+	pub max_total_value_locked: Option<Balance>,
+}
+
+/// Payout of a withdrawal held back by the withdrawal delay of its mixer
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+pub struct PendingWithdrawal<TreeId, AccountId, Balance, BlockNumber> {
+	/// Id of the mixer tree
+	pub mixer_id: TreeId,
+	/// Account the deposit minus the fee is paid to
+	pub recipient: AccountId,
+	/// Amount paid to the recipient
+	pub amount: Balance,
+	/// Account the fee is paid to
+	pub relayer: AccountId,
+	/// Fee paid to the relayer
+	pub fee: Balance,
+	/// Block the payout is due at
+	pub due_block: BlockNumber,
+	/// Whether governance froze the payout
+	pub frozen: bool,
+}
+
+/// Deposits and withdrawals of a mixer in a single block
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+pub struct BlockActivity<BlockNumber> {
+	/// Number of the block
+	pub block_number: BlockNumber,
+	/// Number of deposits in the block
+	pub deposits: u32,
+	/// Number of withdrawals in the block
+	pub withdrawals: u32,
 }
 
 /// Proof data for withdrawal
@@ -449,8 +1462,12 @@ pub struct WithdrawProof<T: Config> {
 	proof_commitments: Vec<ScalarBytes>,
 	/// The recipient to withdraw amount of currency to
 	recipient: Option<T::AccountId>,
-	/// The recipient to withdraw amount of currency to
+	/// The relayer submitting the withdrawal on behalf of the recipient
 	relayer: Option<T::AccountId>,
+	/// The fee paid to the relayer out of the deposit
+	fee: BalanceOf<T>,
+	/// The native currency amount paid by the sender to the recipient
+	refund: BalanceOf<T>,
 }
 
 impl<T: Config> WithdrawProof<T> {
@@ -465,6 +1482,8 @@ impl<T: Config> WithdrawProof<T> {
 		proof_commitments: Vec<ScalarBytes>,
 		recipient: Option<T::AccountId>,
 		relayer: Option<T::AccountId>,
+		fee: BalanceOf<T>,
+		refund: BalanceOf<T>,
 	) -> Self {
 		Self {
 			mixer_id,
@@ -477,6 +1496,8 @@ impl<T: Config> WithdrawProof<T> {
 			proof_commitments,
 			recipient,
 			relayer,
+			fee,
+			refund,
 		}
 	}
 }
@@ -490,19 +1511,50 @@ impl<T: Config> std::fmt::Debug for WithdrawProof<T> {
 	}
 }
 
+/// Deposit memo, encrypted by the depositor, e.g. to a viewing key
+pub type EncryptedMemo = Vec<u8>;
+
+/// Prefix of the offchain index keys of the deposit memos
+pub const MEMO_INDEX_PREFIX: &[u8] = b"mixer/memo";
+
 /// Type alias for the webb_traits::MultiCurrency::Balance type
 pub type BalanceOf<T> = <<T as Config>::Currency as MultiCurrency<<T as frame_system::Config>::AccountId>>::Balance;
+/// Type alias for the balance of the rewards paid by the RewardHandler
+pub type RewardBalanceOf<T> =
+	<<T as Config>::RewardHandler as RewardHandler<<T as frame_system::Config>::AccountId>>::Balance;
 /// Type alias for the webb_traits::MultiCurrency::CurrencyId type
 pub type CurrencyIdOf<T> =
 	<<T as pallet::Config>::Currency as MultiCurrency<<T as frame_system::Config>::AccountId>>::CurrencyId;
+/// Type alias for the pending withdrawals of the pallet
+pub type PendingWithdrawalOf<T> = PendingWithdrawal<
+	<T as merkle::Config>::TreeId,
+	<T as frame_system::Config>::AccountId,
+	BalanceOf<T>,
+	<T as frame_system::Config>::BlockNumber,
+>;
+
+/// Setup of the bulletproofs mixers, poseidon over Curve25519
+pub struct BulletproofsSetup;
+impl Get<Setup> for BulletproofsSetup {
+	fn get() -> Setup {
+		Setup::new(HashFunction::PoseidonDefault, Backend::Bulletproofs(Curve::Curve25519))
+	}
+}
+
+/// Bulletproofs generators large enough for the withdraw proofs of the
+/// bulletproofs mixers
+pub struct BulletproofsVerifyingKey;
+impl Get<Vec<u8>> for BulletproofsVerifyingKey {
+	fn get() -> Vec<u8> {
+		merkle::utils::keys::get_bp_gen_bytes(&BulletproofGens::new(16400, 1))
+	}
+}
 
 /// Info about the mixer and it's leaf data
 #[derive(Encode, Decode, PartialEq)]
 pub struct MixerInfo<T: Config> {
 	/// Minimum duration the deposit has stayed in the mixer for a user
 	/// to be eligible for reward
-	///
-	/// NOTE: Currently not used
 	pub minimum_deposit_length_for_reward: T::BlockNumber,
 	/// Deposit size for the mixer
 	pub fixed_deposit_size: BalanceOf<T>,
@@ -535,6 +1587,164 @@ impl<T: Config> Pallet<T> {
 		T::PalletId::get().into_account()
 	}
 
+	/// Returns whether the mixer with id of `mixer_id` is active, in
+	/// withdraw-only mode or stopped.
+	pub fn mixer_state(mixer_id: T::TreeId) -> Result<MixerState, dispatch::DispatchError> {
+		ensure!(MixerTrees::<T>::contains_key(mixer_id), Error::<T>::NoMixerForId);
+		Ok(if <MerklePallet<T>>::stopped(mixer_id) {
+			MixerState::Stopped
+		} else if Self::withdraw_only(mixer_id) {
+			MixerState::WithdrawOnly
+		} else {
+			MixerState::Active
+		})
+	}
+
+	/// Returns the statistics of the mixer with id of `mixer_id`.
+	pub fn mixer_stats(
+		mixer_id: T::TreeId,
+	) -> Result<MixerStats<T::TreeId, CurrencyIdOf<T>, BalanceOf<T>, T::BlockNumber>, dispatch::DispatchError> {
+		let state = Self::mixer_state(mixer_id)?;
+		let mixer_info = Self::mixer_trees(mixer_id);
+		let deposit_count = Self::deposit_count(mixer_id);
+		let withdrawal_count = Self::withdrawal_count(mixer_id);
+		let (recent_deposits, recent_withdrawals) =
+			Self::activity_history(mixer_id)
+				.iter()
+				.fold((0u32, 0u32), |(deposits, withdrawals), activity| {
+					(
+						deposits.saturating_add(activity.deposits),
+						withdrawals.saturating_add(activity.withdrawals),
+					)
+				});
+		Ok(MixerStats {
+			mixer_id,
+			currency_id: mixer_info.currency_id,
+			fixed_deposit_size: mixer_info.fixed_deposit_size,
+			total_value_locked: Self::total_value_locked(mixer_id),
+			state,
+			deposit_count,
+			withdrawal_count,
+			anonymity_set_size: deposit_count.saturating_sub(withdrawal_count),
+			last_activity: Self::last_activity(mixer_id),
+			recent_deposits,
+			recent_withdrawals,
+			limits: Self::limits(mixer_id),
+		})
+	}
+
+	/// Returns the deposits and withdrawals of the mixer with id of `mixer_id`
+	/// per block, over the activity window.
+	pub fn mixer_activity(mixer_id: T::TreeId) -> Result<Vec<BlockActivity<T::BlockNumber>>, dispatch::DispatchError> {
+		Self::mixer_state(mixer_id)?;
+		Ok(Self::activity_history(mixer_id))
+	}
+
+	/// Returns the withdrawals of the mixer with id of `mixer_id` waiting to
+	/// be paid out, by id.
+	pub fn pending_withdrawals(
+		mixer_id: T::TreeId,
+	) -> Result<Vec<(u32, PendingWithdrawalOf<T>)>, dispatch::DispatchError> {
+		Self::mixer_state(mixer_id)?;
+		let mut pending = MixerPendingWithdrawals::<T>::iter_prefix(mixer_id)
+			.filter_map(|(id, ())| Self::pending_withdrawal(id).map(|withdrawal| (id, withdrawal)))
+			.collect::<Vec<_>>();
+		pending.sort_by_key(|(id, _)| *id);
+		Ok(pending)
+	}
+
+	/// Adds the deposits and withdrawals to the counters and the activity
+	/// history of the mixer, reusing the entry of a block that left the
+	/// window.
+	fn record_activity(mixer_id: T::TreeId, deposits: u32, withdrawals: u32) {
+		let block_number = <frame_system::Pallet<T>>::block_number();
+		DepositCounts::<T>::mutate(mixer_id, |count| *count = count.saturating_add(deposits));
+		WithdrawalCounts::<T>::mutate(mixer_id, |count| *count = count.saturating_add(withdrawals));
+		LastActivity::<T>::insert(mixer_id, block_number);
+		if deposits > 0 {
+			let in_block = Self::deposits_in_block(mixer_id);
+			BlockDeposits::<T>::insert(mixer_id, (block_number, in_block.saturating_add(deposits)));
+		}
+		ActivityHistory::<T>::mutate(mixer_id, Self::activity_slot(block_number), |entry| match entry {
+			Some(activity) if activity.block_number == block_number => {
+				activity.deposits = activity.deposits.saturating_add(deposits);
+				activity.withdrawals = activity.withdrawals.saturating_add(withdrawals);
+			}
+			_ => {
+				*entry = Some(BlockActivity {
+					block_number,
+					deposits,
+					withdrawals,
+				})
+			}
+		});
+	}
+
+	/// Key of the activity history entry of `block_number`
+	fn activity_slot(block_number: T::BlockNumber) -> T::BlockNumber {
+		block_number % T::ActivityWindow::get().max(One::one())
+	}
+
+	/// Returns the deposits and withdrawals of the mixer with id of `mixer_id`
+	/// per block, over the activity window, oldest first.
+	pub fn activity_history(mixer_id: T::TreeId) -> Vec<BlockActivity<T::BlockNumber>> {
+		let block_number = <frame_system::Pallet<T>>::block_number();
+		let window = T::ActivityWindow::get();
+		let mut history = ActivityHistory::<T>::iter_prefix_values(mixer_id)
+			.filter(|activity| activity.block_number.saturating_add(window) > block_number)
+			.collect::<Vec<_>>();
+		history.sort_by_key(|activity| activity.block_number);
+		history
+	}
+
+	/// Protocol fee charged for a single deposit of `deposit_size` into the
+	/// mixer
+	pub fn protocol_fee_per_deposit(mixer_id: T::TreeId, deposit_size: BalanceOf<T>) -> BalanceOf<T> {
+		match Self::protocol_fee(mixer_id) {
+			Some(ProtocolFee::Proportional(share)) => share * deposit_size,
+			Some(ProtocolFee::Flat(amount)) => amount,
+			None => Zero::zero(),
+		}
+	}
+
+	/// Number of deposits into the mixer in the current block
+	pub fn deposits_in_block(mixer_id: T::TreeId) -> u32 {
+		match Self::block_deposits(mixer_id) {
+			Some((block_number, count)) if block_number == <frame_system::Pallet<T>>::block_number() => count,
+			_ => 0,
+		}
+	}
+
+	/// Checks that `count` more commitments worth `amount` in total can be
+	/// deposited into the mixer without going above any of its limits.
+	pub fn ensure_within_limits(mixer_id: T::TreeId, count: u32, amount: BalanceOf<T>) -> dispatch::DispatchResult {
+		let limits = Self::limits(mixer_id);
+		if let Some(max) = limits.max_commitments_per_deposit {
+			ensure!(count <= max, Error::<T>::TooManyCommitments);
+		}
+		if let Some(max) = limits.max_deposits_per_block {
+			ensure!(
+				Self::deposits_in_block(mixer_id).saturating_add(count) <= max,
+				Error::<T>::BlockDepositLimitReached
+			);
+		}
+		if let Some(max) = limits.max_total_value_locked {
+			ensure!(
+				Self::total_value_locked(mixer_id).saturating_add(amount) <= max,
+				Error::<T>::TvlLimitExceeded
+			);
+		}
+		Ok(())
+	}
+
+	/// Returns the statistics of every mixer, in the order they were created.
+	pub fn mixers() -> Vec<MixerStats<T::TreeId, CurrencyIdOf<T>, BalanceOf<T>, T::BlockNumber>> {
+		Self::mixer_group_ids()
+			.into_iter()
+			.filter_map(|mixer_id| Self::mixer_stats(mixer_id).ok())
+			.collect()
+	}
+
 	pub fn get_mixer(mixer_id: T::TreeId) -> Result<MixerInfo<T>, dispatch::DispatchError> {
 		let mixer_info = MixerTrees::<T>::get(mixer_id);
 		// ensure mixer_info has a non-zero deposit, otherwise, the mixer doesn't exist
@@ -547,20 +1757,423 @@ impl<T: Config> Pallet<T> {
 		Ok(mixer_info)
 	}
 
+	/// Runs the same checks as `withdraw` without changing any state. If the
+	/// recipient or relayer aren't set in the proof, the sender is used in
+	/// their place just as `withdraw` does.
+	pub fn check_withdraw(sender: T::AccountId, withdraw_proof: WithdrawProof<T>) -> dispatch::DispatchResult {
+		let recipient = withdraw_proof.recipient.clone().unwrap_or(sender.clone());
+		let relayer = withdraw_proof.relayer.unwrap_or(sender);
+		Self::validate_withdraw(&withdraw_proof, &recipient, &relayer)?;
+		Ok(())
+	}
+
+	/// Public input binding the relayer to the fee and refund of a
+	/// withdrawal, before it is mapped with `public_input_to_field`.
+	/// Withdrawals without a fee or refund use the plain relayer encoding.
+	pub fn relayer_input(relayer: &T::AccountId, fee: BalanceOf<T>, refund: BalanceOf<T>) -> ScalarBytes {
+		if fee.is_zero() && refund.is_zero() {
+			relayer.encode()
+		} else {
+			BlakeTwo256::hash_of(&(relayer, fee, refund)).as_ref().to_vec()
+		}
+	}
+
+	/// Pays out a validated withdrawal and spends its nullifier.
+	fn pay_withdrawal(
+		sender: T::AccountId,
+		withdraw_proof: WithdrawProof<T>,
+		mixer_info: MixerInfo<T>,
+		recipient: T::AccountId,
+		relayer: T::AccountId,
+	) -> dispatch::DispatchResult {
+		// pay the fixed deposit size, minus the relayer fee, to the recipient
+		let fee = withdraw_proof.fee;
+		Self::pay_or_queue(
+			withdraw_proof.mixer_id,
+			mixer_info.currency_id,
+			recipient.clone(),
+			mixer_info.fixed_deposit_size.saturating_sub(fee),
+			relayer.clone(),
+			fee,
+		)?;
+		// the refund is paid by the sender, usually the relayer itself
+		if !withdraw_proof.refund.is_zero() {
+			T::Currency::transfer(T::NativeCurrencyId::get(), &sender, &recipient, withdraw_proof.refund)?;
+		}
+		// update the total value locked
+		let tvl = Self::total_value_locked(withdraw_proof.mixer_id);
+		<TotalValueLocked<T>>::insert(withdraw_proof.mixer_id, tvl - mixer_info.fixed_deposit_size);
+		// Add the nullifier on behalf of the module
+		T::Tree::add_nullifier(
+			Self::account_id(),
+			withdraw_proof.mixer_id.into(),
+			withdraw_proof.nullifier_hash,
+		)?;
+		Self::record_activity(withdraw_proof.mixer_id, 0, 1);
+
+		Self::deposit_event(Event::Withdraw(
+			withdraw_proof.mixer_id,
+			sender,
+			recipient,
+			relayer,
+			fee,
+			withdraw_proof.cached_root,
+		));
+		Ok(())
+	}
+
+	/// Pays `amount` to the recipient and `fee` to the relayer of a
+	/// withdrawal, or queues the payout if the mixer has a withdrawal delay.
+	fn pay_or_queue(
+		mixer_id: T::TreeId,
+		currency_id: CurrencyIdOf<T>,
+		recipient: T::AccountId,
+		amount: BalanceOf<T>,
+		relayer: T::AccountId,
+		fee: BalanceOf<T>,
+	) -> dispatch::DispatchResult {
+		let delay = Self::withdrawal_delay(mixer_id);
+		if delay.is_zero() {
+			return Self::transfer_payout(currency_id, &recipient, amount, &relayer, fee);
+		}
+		let mut due_block = <frame_system::Pallet<T>>::block_number().saturating_add(delay);
+		// a payout falling due in a full block waits for the next one with room
+		let max = T::MaxPendingPayouts::get().max(1) as usize;
+		let mut postponed = 0;
+		while PayoutQueue::<T>::decode_len(due_block).unwrap_or(0) >= max {
+			ensure!(postponed < T::MaxPayoutPostponement::get(), Error::<T>::PayoutQueueFull);
+			due_block = due_block.saturating_add(One::one());
+			postponed += 1;
+		}
+		let id = Self::next_pending_withdrawal_id();
+		NextPendingWithdrawalId::<T>::put(id.wrapping_add(1));
+		PendingWithdrawals::<T>::insert(id, PendingWithdrawal {
+			mixer_id,
+			recipient,
+			amount,
+			relayer,
+			fee,
+			due_block,
+			frozen: false,
+		});
+		MixerPendingWithdrawals::<T>::insert(mixer_id, id, ());
+		PayoutQueue::<T>::append(due_block, id);
+		Self::deposit_event(Event::WithdrawalQueued(id, mixer_id, due_block));
+		Ok(())
+	}
+
+	/// Transfers a withdrawal payout out of the mixer account.
+	fn transfer_payout(
+		currency_id: CurrencyIdOf<T>,
+		recipient: &T::AccountId,
+		amount: BalanceOf<T>,
+		relayer: &T::AccountId,
+		fee: BalanceOf<T>,
+	) -> dispatch::DispatchResult {
+		T::Currency::transfer(currency_id, &Self::account_id(), recipient, amount)?;
+		if !fee.is_zero() {
+			T::Currency::transfer(currency_id, &Self::account_id(), relayer, fee)?;
+		}
+		Ok(())
+	}
+
+	/// Pays out the pending withdrawal with id of `id`, frozen or not, and
+	/// removes it.
+	#[transactional]
+	fn pay_pending_withdrawal(id: u32) -> dispatch::DispatchResult {
+		let pending = Self::pending_withdrawal(id).ok_or(Error::<T>::NoPendingWithdrawal)?;
+		let currency_id = Self::mixer_trees(pending.mixer_id).currency_id;
+		Self::transfer_payout(
+			currency_id,
+			&pending.recipient,
+			pending.amount,
+			&pending.relayer,
+			pending.fee,
+		)?;
+		PendingWithdrawals::<T>::remove(id);
+		MixerPendingWithdrawals::<T>::remove(pending.mixer_id, id);
+		Self::deposit_event(Event::WithdrawalPaid(id, pending.mixer_id));
+		Ok(())
+	}
+
+	/// Pays out the withdrawals due at block `n` that aren't frozen. Frozen
+	/// withdrawals leave the queue and wait to be released, and so do
+	/// withdrawals whose payout fails, which are frozen. Returns the number of
+	/// withdrawals that were due.
+	fn pay_due_withdrawals(n: T::BlockNumber) -> u32 {
+		let due = PayoutQueue::<T>::take(n);
+		for id in due.iter() {
+			let pending = match Self::pending_withdrawal(id) {
+				Some(pending) if !pending.frozen => pending,
+				_ => continue,
+			};
+			if Self::pay_pending_withdrawal(*id).is_err() {
+				let mixer_id = pending.mixer_id;
+				PendingWithdrawals::<T>::insert(id, PendingWithdrawal {
+					frozen: true,
+					..pending
+				});
+				Self::deposit_event(Event::WithdrawalPayoutFailed(*id, mixer_id));
+			}
+		}
+		due.len() as u32
+	}
+
+	/// Checks that the mixer is running and initialized, that the fee is
+	/// within the mixer's maximum, that the nullifier is unused and that the
+	/// zero-knowledge proof verifies against a cached root. Returns the info
+	/// of the mixer being withdrawn from.
+	pub fn validate_withdraw(
+		withdraw_proof: &WithdrawProof<T>,
+		recipient: &T::AccountId,
+		relayer: &T::AccountId,
+	) -> Result<MixerInfo<T>, dispatch::DispatchError> {
+		Self::validate_withdraw_to(withdraw_proof, recipient.encode(), relayer)
+	}
+
+	/// Recipient public input of withdrawals to an EVM address, tagged so it
+	/// never matches the encoding of an account, e.g. on chains with 20 byte
+	/// account ids.
+	pub fn evm_recipient_input(recipient: &H160) -> ScalarBytes {
+		(b"evm", recipient).encode()
+	}
+
+	/// Same as [`Pallet::validate_withdraw`], with the recipient given as its
+	/// public input.
+	pub fn validate_withdraw_to(
+		withdraw_proof: &WithdrawProof<T>,
+		recipient_input: ScalarBytes,
+		relayer: &T::AccountId,
+	) -> Result<MixerInfo<T>, dispatch::DispatchError> {
+		ensure!(
+			!<MerklePallet<T>>::stopped(withdraw_proof.mixer_id),
+			Error::<T>::MixerStopped
+		);
+		// get mixer info, should fail if tree isn't initialized
+		let mixer_info = Self::get_mixer(withdraw_proof.mixer_id)?;
+		ensure!(
+			withdraw_proof.fee <= Self::max_fee(withdraw_proof.mixer_id),
+			Error::<T>::FeeExceedsMaximum
+		);
+		// check if the nullifier has been used
+		T::Tree::has_used_nullifier(withdraw_proof.mixer_id.into(), withdraw_proof.nullifier_hash.clone())?;
+		// Verify the zero-knowledge proof of membership provided
+		T::Tree::verify_zk(
+			withdraw_proof.mixer_id.into(),
+			withdraw_proof.cached_block,
+			withdraw_proof.cached_root.clone(),
+			withdraw_proof.comms.clone(),
+			withdraw_proof.nullifier_hash.clone(),
+			withdraw_proof.proof_bytes.clone(),
+			withdraw_proof.leaf_index_commitments.clone(),
+			withdraw_proof.proof_commitments.clone(),
+			public_input_to_field(&recipient_input),
+			public_input_to_field(&Self::relayer_input(relayer, withdraw_proof.fee, withdraw_proof.refund)),
+		)?;
+		Ok(mixer_info)
+	}
+
+	/// Checks an unsigned withdrawal. The recipient stands in for the
+	/// relayer in the proof, which binds the fee to it. Returns the info of
+	/// the mixer being withdrawn from and the recipient.
+	pub fn validate_unsigned_withdraw(
+		withdraw_proof: &WithdrawProof<T>,
+	) -> Result<(MixerInfo<T>, T::AccountId), dispatch::DispatchError> {
+		let recipient = withdraw_proof
+			.recipient
+			.clone()
+			.ok_or(Error::<T>::InvalidUnsignedWithdraw)?;
+		ensure!(
+			!withdraw_proof.fee.is_zero() && withdraw_proof.refund.is_zero(),
+			Error::<T>::InvalidUnsignedWithdraw
+		);
+		let deposit_size = Self::mixer_trees(withdraw_proof.mixer_id).fixed_deposit_size;
+		ensure!(
+			withdraw_proof.fee >= T::MinUnsignedFee::get() * deposit_size,
+			Error::<T>::FeeBelowMinimum
+		);
+		let mixer_info = Self::validate_withdraw(withdraw_proof, &recipient, &recipient)?;
+		Ok((mixer_info, recipient))
+	}
+
+	/// Offchain index key of the memo of the leaf at `leaf_index` in the mixer
+	/// with id of `mixer_id`.
+	pub fn memo_key(mixer_id: T::TreeId, leaf_index: u32) -> Vec<u8> {
+		(MEMO_INDEX_PREFIX, mixer_id, leaf_index).encode()
+	}
+
+	/// Recipient public input of remixes, binding the proof to the mixer and
+	/// the commitment it is remixed into.
+	pub fn remix_input(mixer_id: T::TreeId, commitment: &ScalarBytes) -> ScalarBytes {
+		BlakeTwo256::hash_of(&(b"remix", mixer_id, commitment))
+			.as_ref()
+			.to_vec()
+	}
+
+	/// Checks a remix and returns the info of the mixer the deposit is spent
+	/// from. The source mixer may be in withdraw-only mode, the target mixer
+	/// has to accept deposits.
+	pub fn validate_remix(
+		withdraw_proof: &WithdrawProof<T>,
+		relayer: &T::AccountId,
+		mixer_id: T::TreeId,
+		commitment: &ScalarBytes,
+	) -> Result<MixerInfo<T>, dispatch::DispatchError> {
+		ensure!(
+			withdraw_proof.recipient.is_none() && withdraw_proof.fee.is_zero() && withdraw_proof.refund.is_zero(),
+			Error::<T>::InvalidRemix
+		);
+		ensure!(
+			!<MerklePallet<T>>::stopped(withdraw_proof.mixer_id),
+			Error::<T>::MixerStopped
+		);
+		ensure!(!<MerklePallet<T>>::stopped(mixer_id), Error::<T>::MixerStopped);
+		ensure!(!Self::withdraw_only(mixer_id), Error::<T>::MixerWithdrawOnly);
+		let mixer_info = Self::get_mixer(withdraw_proof.mixer_id)?;
+		let target_info = Self::get_mixer(mixer_id)?;
+		ensure!(
+			mixer_info.currency_id == target_info.currency_id
+				&& mixer_info.fixed_deposit_size == target_info.fixed_deposit_size,
+			Error::<T>::RemixDenominationMismatch
+		);
+		// the commitment counts as a deposit into the target mixer
+		let moved_value = if mixer_id == withdraw_proof.mixer_id {
+			Zero::zero()
+		} else {
+			target_info.fixed_deposit_size
+		};
+		Self::ensure_within_limits(mixer_id, 1, moved_value)?;
+		T::Tree::has_used_nullifier(withdraw_proof.mixer_id.into(), withdraw_proof.nullifier_hash.clone())?;
+		T::Tree::verify_zk(
+			withdraw_proof.mixer_id.into(),
+			withdraw_proof.cached_block,
+			withdraw_proof.cached_root.clone(),
+			withdraw_proof.comms.clone(),
+			withdraw_proof.nullifier_hash.clone(),
+			withdraw_proof.proof_bytes.clone(),
+			withdraw_proof.leaf_index_commitments.clone(),
+			withdraw_proof.proof_commitments.clone(),
+			public_input_to_field(&Self::remix_input(mixer_id, commitment)),
+			public_input_to_field(&Self::remix_relayer_input(relayer)),
+		)?;
+		Ok(mixer_info)
+	}
+
+	/// Relayer public input of remixes, kept apart from
+	/// [`Pallet::relayer_input`] so a remix can't be replayed as a withdrawal.
+	pub fn remix_relayer_input(relayer: &T::AccountId) -> ScalarBytes {
+		BlakeTwo256::hash_of(&(b"remix", relayer)).as_ref().to_vec()
+	}
+
+	/// Relayer public input of reward claims, kept apart from
+	/// [`Pallet::relayer_input`] so a claim can't be replayed as a withdrawal.
+	pub fn reward_relayer_input(relayer: &T::AccountId) -> ScalarBytes {
+		BlakeTwo256::hash_of(&(b"reward", relayer)).as_ref().to_vec()
+	}
+
+	/// Records the root of the mixer at the current block for reward claims,
+	/// links it after the previous deposit root and prunes the oldest roots.
+	fn record_deposit_root(mixer_id: T::TreeId) -> dispatch::DispatchResult {
+		let block_number = <frame_system::Pallet<T>>::block_number();
+		DepositRoots::<T>::insert(mixer_id, block_number, <MerklePallet<T>>::get_merkle_root(mixer_id)?);
+		let (oldest, latest) = Self::deposit_root_bounds(mixer_id).unwrap_or((block_number, block_number));
+		if latest != block_number {
+			NextDepositRoot::<T>::insert(mixer_id, latest, block_number);
+		}
+		DepositRootBounds::<T>::insert(mixer_id, (oldest, block_number));
+		Self::prune_deposit_roots(mixer_id, block_number);
+		Ok(())
+	}
+
+	/// Drops up to two of the oldest deposit roots of the mixer whose next
+	/// root is old enough to claim rewards against. Every deposit under a
+	/// dropped root is under the next one as well, so the latest claimable
+	/// root is always kept. At most one root is recorded per mixer and block,
+	/// so pruning two per recorded root keeps up and catches up on the roots
+	/// left over from blocks without deposits.
+	fn prune_deposit_roots(mixer_id: T::TreeId, now: T::BlockNumber) {
+		let min_length = MixerTrees::<T>::get(mixer_id).minimum_deposit_length_for_reward;
+		for _ in 0..2 {
+			let (oldest, latest) = match Self::deposit_root_bounds(mixer_id) {
+				Some(bounds) => bounds,
+				None => return,
+			};
+			let next = match NextDepositRoot::<T>::get(mixer_id, oldest) {
+				Some(next) if next.saturating_add(min_length) <= now => next,
+				_ => return,
+			};
+			DepositRoots::<T>::remove(mixer_id, oldest);
+			NextDepositRoot::<T>::remove(mixer_id, oldest);
+			DepositRootBounds::<T>::insert(mixer_id, (next, latest));
+		}
+	}
+
+	/// Checks a reward claim and returns the reward nullifier of the deposit,
+	/// carried in the `nullifier_hash` of the proof.
+	pub fn validate_reward_claim(
+		withdraw_proof: &WithdrawProof<T>,
+		recipient: &T::AccountId,
+		relayer: &T::AccountId,
+	) -> Result<ScalarBytes, dispatch::DispatchError> {
+		let mixer_id = withdraw_proof.mixer_id;
+		ensure!(!<MerklePallet<T>>::stopped(mixer_id), Error::<T>::MixerStopped);
+		let mixer_info = Self::get_mixer(mixer_id)?;
+		ensure!(
+			withdraw_proof.fee.is_zero() && withdraw_proof.refund.is_zero(),
+			Error::<T>::InvalidRewardClaim
+		);
+		let reward_nullifier = withdraw_proof.nullifier_hash.clone();
+		ensure!(
+			!Self::reward_claimed(mixer_id, &reward_nullifier),
+			Error::<T>::RewardAlreadyClaimed
+		);
+		// every leaf under the root was deposited at or before the root's block
+		let root = Self::deposit_root(mixer_id, withdraw_proof.cached_block).ok_or(Error::<T>::UnknownDepositRoot)?;
+		ensure!(root == withdraw_proof.cached_root, Error::<T>::UnknownDepositRoot);
+		let now = <frame_system::Pallet<T>>::block_number();
+		ensure!(
+			withdraw_proof
+				.cached_block
+				.saturating_add(mixer_info.minimum_deposit_length_for_reward)
+				<= now,
+			Error::<T>::DepositTooRecent
+		);
+		T::Tree::verify_reward_zk_at_root(
+			mixer_id.into(),
+			root,
+			withdraw_proof.comms.clone(),
+			reward_nullifier.clone(),
+			withdraw_proof.proof_bytes.clone(),
+			withdraw_proof.leaf_index_commitments.clone(),
+			withdraw_proof.proof_commitments.clone(),
+			public_input_to_field(&recipient.encode()),
+			public_input_to_field(&Self::reward_relayer_input(relayer)),
+		)?;
+		Ok(reward_nullifier)
+	}
+
+	/// Setup of the default mixers
+	pub fn default_setup() -> Setup {
+		T::DefaultSetup::get()
+	}
+
+	/// Verifying key of the default mixers
+	pub fn default_key_data() -> Vec<u8> {
+		T::DefaultVerifyingKey::get()
+	}
+
+	/// Creates the default mixers, one for each of the `MixerSizes`, with the
+	/// default setup.
+	///
+	/// Only used by [`migrations::initialize_staged`], new chains build their
+	/// mixers from the genesis config.
 	pub fn initialize_first_stage() -> dispatch::DispatchResult {
 		ensure!(!Self::first_stage_initialized(), Error::<T>::AlreadyInitialized);
 
-		// Get default admin from trait params
-		let default_admin = T::DefaultAdmin::get();
-		// Initialize the admin in storage with default one
-		Admin::<T>::set(default_admin);
-
 		// Getting the sizes from the config
 		let sizes = T::MixerSizes::get();
 
-		let hasher = HashFunction::PoseidonDefault;
-		let backend = Backend::Bulletproofs(Curve::Curve25519);
-		let setup = Setup::new(hasher.clone(), backend.clone());
+		let setup = Self::default_setup();
 
 		// Iterating over configured sizes and initializing the mixers
 		for size in sizes.into_iter() {
@@ -576,11 +2189,12 @@ impl<T: Config> Pallet<T> {
 		Ok(())
 	}
 
+	/// Adds the default verifying key and initializes the trees of the mixers
+	/// with it.
 	pub fn initialize_second_stage() -> dispatch::DispatchResult {
 		ensure!(Self::first_stage_initialized(), Error::<T>::AlreadyInitialized);
 
-		let key_data = merkle::utils::keys::get_bp_gen_bytes(&BulletproofGens::new(16400, 1));
-		let key_id = T::Tree::add_verifying_key(key_data)?;
+		let key_id = T::Tree::add_verifying_key(Self::default_key_data())?;
 		let mixer_ids = MixerTreeIds::<T>::get();
 		for i in 0..mixer_ids.len() {
 			let tree_id = mixer_ids[i];
@@ -599,10 +2213,11 @@ impl<T: Config> ExtendedMixer<T> for Pallet<T> {
 		setup: Setup,
 		size: BalanceOf<T>,
 	) -> Result<T::TreeId, dispatch::DispatchError> {
-		let depth: u8 = <T as merkle::Config>::MaxTreeDepth::get();
+		let depth: u8 = T::TreeDepth::get();
 		let mixer_id: T::TreeId = T::Tree::create_tree(account_id, true, setup, depth)?;
 		let mixer_info = MixerInfo::<T>::new(T::DepositLength::get(), size, currency_id);
 		MixerTrees::<T>::insert(mixer_id, mixer_info);
+		MaxFees::<T>::insert(mixer_id, T::DefaultMaxFee::get() * size);
 		// Add new id to list
 		let mut ids = MixerTreeIds::<T>::get();
 		ids.push(mixer_id);
@@ -610,3 +2225,94 @@ impl<T: Config> ExtendedMixer<T> for Pallet<T> {
 		Ok(mixer_id)
 	}
 }
+
+/// Migrations of the mixer pallet
+pub mod migrations {
+	use super::*;
+	use frame_support::{storage::migration::remove_storage_prefix, traits::PalletInfo};
+
+	/// Runs what is left of the staged initialization on chains that were
+	/// started before the mixers were built from the genesis config. It used to
+	/// run in `on_finalize` of the first two blocks, and is a no-op once both
+	/// stages are done. Runs once, moving the storage to [`Releases::V2_0_0`].
+	///
+	/// Meant to be called from the runtime's `OnRuntimeUpgrade`.
+	pub fn initialize_staged<T: Config>() -> Weight {
+		if StorageVersion::<T>::get() >= Releases::V2_0_0 {
+			return T::DbWeight::get().reads(1);
+		}
+		StorageVersion::<T>::put(Releases::V2_0_0);
+		if Pallet::<T>::second_stage_initialized() {
+			return T::DbWeight::get().reads_writes(2, 1);
+		}
+
+		let result = if Pallet::<T>::first_stage_initialized() {
+			Pallet::<T>::initialize_second_stage()
+		} else {
+			Pallet::<T>::initialize_first_stage().and_then(|_| Pallet::<T>::initialize_second_stage())
+		};
+		if let Err(e) = result {
+			log::error!("Error initialising mixers: {:?}", e);
+		}
+
+		<T as Config>::WeightInfo::on_finalize_uninitialized().saturating_add(T::DbWeight::get().reads_writes(1, 1))
+	}
+
+	/// Removes the `Admin` account the privileged calls used to check, they
+	/// are governed by the `AdminOrigin` instead. Runs once, moving the
+	/// storage to [`Releases::V3_0_0`].
+	///
+	/// Meant to be called from the runtime's `OnRuntimeUpgrade`.
+	pub fn remove_admin<T: Config>() -> Weight {
+		if StorageVersion::<T>::get() >= Releases::V3_0_0 {
+			return T::DbWeight::get().reads(1);
+		}
+		StorageVersion::<T>::put(Releases::V3_0_0);
+		if let Some(pallet_name) = <T as frame_system::Config>::PalletInfo::name::<Pallet<T>>() {
+			remove_storage_prefix(pallet_name.as_bytes(), b"Admin", &[]);
+		}
+		T::DbWeight::get().reads_writes(1, 2)
+	}
+
+	/// Sets the deposit counters of the mixers to the leaf count of their
+	/// trees, since deposits made before the counters existed weren't counted.
+	/// Runs once, moving the storage to [`Releases::V4_0_0`].
+	///
+	/// Meant to be called from the runtime's `OnRuntimeUpgrade`.
+	pub fn initialize_deposit_counts<T: Config>() -> Weight {
+		if StorageVersion::<T>::get() >= Releases::V4_0_0 {
+			return T::DbWeight::get().reads(1);
+		}
+		StorageVersion::<T>::put(Releases::V4_0_0);
+		let mixer_ids = Pallet::<T>::mixer_group_ids();
+		for mixer_id in mixer_ids.iter() {
+			if let Some(tree) = <MerklePallet<T>>::trees(*mixer_id) {
+				DepositCounts::<T>::insert(*mixer_id, tree.leaf_count);
+			}
+		}
+		let count = mixer_ids.len() as Weight;
+		T::DbWeight::get().reads_writes(count + 2, count + 1)
+	}
+
+	/// Sets the max fee of the mixers created before max fees were seeded at
+	/// creation to the `DefaultMaxFee` share of their deposit size. Max fees
+	/// set by the `AdminOrigin` are kept. Runs once, moving the storage to
+	/// [`Releases::V5_0_0`].
+	///
+	/// Meant to be called from the runtime's `OnRuntimeUpgrade`.
+	pub fn initialize_max_fees<T: Config>() -> Weight {
+		if StorageVersion::<T>::get() >= Releases::V5_0_0 {
+			return T::DbWeight::get().reads(1);
+		}
+		StorageVersion::<T>::put(Releases::V5_0_0);
+		let mixer_ids = Pallet::<T>::mixer_group_ids();
+		for mixer_id in mixer_ids.iter() {
+			if !MaxFees::<T>::contains_key(*mixer_id) {
+				let size = MixerTrees::<T>::get(*mixer_id).fixed_deposit_size;
+				MaxFees::<T>::insert(*mixer_id, T::DefaultMaxFee::get() * size);
+			}
+		}
+		let count = mixer_ids.len() as Weight;
+		T::DbWeight::get().reads_writes(2 * count + 2, count + 1)
+	}
+}
@@ -1,17 +1,26 @@
 use super::*;
 use crate as pallet_mixer;
 use frame_benchmarking::whitelisted_caller;
-use frame_support::{construct_runtime, parameter_types, weights::Weight, PalletId};
-use frame_system::mocking::{MockBlock, MockUncheckedExtrinsic};
+use frame_support::{
+	construct_runtime, ord_parameter_types, parameter_types,
+	traits::{Currency, ExistenceRequirement::AllowDeath},
+	weights::Weight,
+	PalletId,
+};
+use frame_system::{
+	mocking::{MockBlock, MockUncheckedExtrinsic},
+	EnsureOneOf, EnsureRoot, EnsureSignedBy,
+};
 use merkle::weights::Weights as MerkleWeights;
 use webb_currencies::BasicCurrencyAdapter;
 
-use sp_core::H256;
+use sp_core::{H160, H256};
 use sp_runtime::{
 	testing::Header,
 	traits::{BlakeTwo256, IdentityLookup},
-	Perbill,
+	DispatchResult, Perbill, Permill,
 };
+use std::cell::RefCell;
 use weights::Weights;
 
 pub(crate) type Balance = u64;
@@ -33,7 +42,7 @@ construct_runtime!(
 		System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
 		Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
 		MerkleTrees: merkle::{Pallet, Call, Storage, Event<T>},
-		Mixer: pallet_mixer::{Pallet, Call, Storage, Event<T>},
+		Mixer: pallet_mixer::{Pallet, Call, Storage, Event<T>, ValidateUnsigned},
 		Currencies: webb_currencies::{Pallet, Storage, Event<T>},
 		Tokens: webb_tokens::{Pallet, Storage, Event<T>},
 		Randomness: pallet_randomness_collective_flip::{Pallet, Call, Storage},
@@ -80,6 +89,7 @@ parameter_types! {
 	pub const MaxReserves: u32 = 50;
 	pub const MaxTreeDepth: u8 = 32;
 	pub const CacheBlockLength: u64 = 5;
+	pub const MaxPrunedRoots: u32 = 100;
 	// Minimum deposit length is 1 month w/ 6 second blocks
 	pub const MinimumDepositLength: u64 = 10 * 60 * 24 * 28;
 }
@@ -143,27 +153,87 @@ impl merkle::Config for Test {
 	type CacheBlockLength = CacheBlockLength;
 	type Event = Event;
 	type KeyId = u32;
+	type MaxPrunedRoots = MaxPrunedRoots;
 	type MaxTreeDepth = MaxTreeDepth;
-	type Randomness = Randomness;
 	type TreeId = u32;
 	type WeightInfo = MerkleWeights<Self>;
 }
 
 parameter_types! {
 	pub const MixerPalletId: PalletId = PalletId(*b"py/mixer");
-	pub const DefaultAdmin: u64 = 4;
 	pub MixerSizes: Vec<Balance> = [1_000, 10_000, 100_000, 1_000_000].to_vec();
+	pub const DepositReward: Balance = 10;
+	pub const ActivityWindow: u64 = 10;
+	pub const MaxMemoLength: u32 = 128;
+	pub const TreasuryAccount: AccountId = 99;
+	pub const RewardPotAccount: AccountId = 98;
+	pub const MaxPendingPayouts: u32 = 2;
+	pub const MaxPayoutPostponement: u32 = 1;
+	pub const MinUnsignedFee: Permill = Permill::from_percent(5);
+	pub const DefaultMaxFee: Permill = Permill::from_percent(10);
+}
+
+ord_parameter_types! {
+	pub const AdminAccount: AccountId = 4;
+}
+
+thread_local! {
+	static MIXER_TREE_DEPTH: RefCell<u8> = RefCell::new(32);
+}
+
+/// Depth of the mixer trees, can be changed before building the genesis
+pub struct MixerTreeDepth;
+impl MixerTreeDepth {
+	pub fn set(depth: u8) {
+		MIXER_TREE_DEPTH.with(|v| *v.borrow_mut() = depth);
+	}
+}
+impl Get<u8> for MixerTreeDepth {
+	fn get() -> u8 {
+		MIXER_TREE_DEPTH.with(|v| *v.borrow())
+	}
+}
+
+pub struct MockRewardHandler;
+impl RewardHandler<AccountId> for MockRewardHandler {
+	type Balance = Balance;
+	type PoolId = u32;
+
+	fn payout(who: &AccountId, _pool: &Self::PoolId, amount: Self::Balance) -> DispatchResult {
+		<Balances as Currency<AccountId>>::transfer(&RewardPotAccount::get(), who, amount, AllowDeath)
+	}
+}
+
+/// Maps an EVM address to the account of its low 8 bytes
+pub struct MockAddressMapping;
+impl Convert<H160, AccountId> for MockAddressMapping {
+	fn convert(address: H160) -> AccountId {
+		address.to_low_u64_be()
+	}
 }
 
 impl Config for Test {
+	type ActivityWindow = ActivityWindow;
+	type AdminOrigin = EnsureOneOf<AccountId, EnsureRoot<AccountId>, EnsureSignedBy<AdminAccount, AccountId>>;
 	type Currency = Currencies;
-	type DefaultAdmin = DefaultAdmin;
+	type DefaultMaxFee = DefaultMaxFee;
+	type DefaultSetup = BulletproofsSetup;
+	type DefaultVerifyingKey = BulletproofsVerifyingKey;
 	type DepositLength = MinimumDepositLength;
+	type DepositReward = DepositReward;
 	type Event = Event;
+	type EvmAddressMapping = MockAddressMapping;
+	type MaxMemoLength = MaxMemoLength;
+	type MaxPayoutPostponement = MaxPayoutPostponement;
+	type MaxPendingPayouts = MaxPendingPayouts;
+	type MinUnsignedFee = MinUnsignedFee;
 	type MixerSizes = MixerSizes;
 	type NativeCurrencyId = NativeCurrencyId;
 	type PalletId = MixerPalletId;
+	type RewardHandler = MockRewardHandler;
+	type Treasury = TreasuryAccount;
 	type Tree = MerkleTrees;
+	type TreeDepth = MixerTreeDepth;
 	type WeightInfo = Weights<Self>;
 }
 
@@ -184,6 +254,7 @@ pub fn new_test_ext() -> sp_io::TestExternalities {
 			(0, 1_000_000_000_000_000_000),
 			(1, 1_000_000_000_000_000_000),
 			(2, 1_000_000_000_000_000_000),
+			(RewardPotAccount::get(), 1_000),
 			(whitelisted_caller(), 1_000_000_000),
 		],
 	}
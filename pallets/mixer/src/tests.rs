@@ -1,6 +1,16 @@
 use super::*;
 use crate::mock::{
-	new_test_ext, AccountId, Balance, Balances, CurrencyId, MerkleTrees, Mixer, MixerCall, Origin, System, Test, Tokens,
+	new_test_ext, AccountId, Balance, Balances, Call, CurrencyId, DepositReward, MaxMemoLength, MerkleTrees, Mixer,
+	MixerCall, MixerTreeDepth, NativeCurrencyId, Origin, RewardPotAccount, System, Test, Tokens, TreasuryAccount,
+};
+use ark_serialize::CanonicalSerialize;
+use arkworks_gadgets::{
+	ark_std::test_rng,
+	prelude::{
+		ark_bn254::{Bn254, Fr as Bn254Fr},
+		ark_ff::to_bytes,
+	},
+	setup::mixer::{prove_groth16_mimc220, setup_circuit_mimc_220, setup_random_groth16_mimc_220},
 };
 use bulletproofs::{r1cs::Prover, BulletproofGens, PedersenGens};
 use bulletproofs_gadgets::{
@@ -8,22 +18,40 @@ use bulletproofs_gadgets::{
 	poseidon::{
 		builder::{Poseidon, PoseidonBuilder},
 		sbox::PoseidonSbox,
+		Poseidon_hash_2,
 	},
 };
-use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::{ristretto::CompressedRistretto, scalar::Scalar};
 use frame_support::{
 	assert_err, assert_ok,
-	traits::{OnFinalize, UnfilteredDispatchable},
+	storage::migration::{get_storage_value, put_storage_value},
+	traits::{Currency, GenesisBuild, OnFinalize, OnInitialize, UnfilteredDispatchable},
 };
 use frame_system::RawOrigin;
 use merkle::{
-	utils::keys::{slice_to_bytes_32, ScalarBytes},
+	utils::{
+		keys::{public_input_to_field, slice_to_bytes_32, ScalarBytes},
+		reward::prove_reward,
+		setup::Snark,
+		tree::LocalTree,
+	},
 	HighestCachedBlock,
 };
 use merlin::Transcript;
-use sp_runtime::{traits::BadOrigin, DispatchError};
+use rand_chacha::{rand_core::SeedableRng, ChaChaRng};
+use sp_core::H160;
+use sp_runtime::{
+	traits::{BadOrigin, SignedExtension, ValidateUnsigned},
+	transaction_validity::{InvalidTransaction, TransactionSource},
+	DispatchError, Permill,
+};
 use webb_tokens::ExtendedTokenSystem;
 
+/// Field element a proof has to bind for the given public input bytes
+fn input_scalar(bytes: &[u8]) -> Scalar {
+	Scalar::from_bytes_mod_order(slice_to_bytes_32(&public_input_to_field(bytes)))
+}
+
 fn default_hasher(bp_gens: BulletproofGens) -> Poseidon {
 	let width = 6;
 	PoseidonBuilder::new(width)
@@ -51,218 +79,1858 @@ fn should_initialize_successfully() {
 }
 
 #[test]
-fn should_initialize_successfully_on_finalize() {
-	new_test_ext().execute_with(|| {
-		<Mixer as OnFinalize<u64>>::on_finalize(1);
-		<Mixer as OnFinalize<u64>>::on_finalize(2);
-		// the mixer creates 4 groups, they should all initialise to 0
-		let val = 1_000;
-		for i in 0..4 {
+fn should_initialize_successfully_from_genesis() {
+	let mut t = frame_system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	let config = GenesisConfig::<Test> {
+		keys: vec![None],
+		mixers: vec![
+			(0, 1_000, Mixer::default_setup(), 0),
+			(0, 10_000, Mixer::default_setup(), 0),
+		],
+	};
+	GenesisBuild::<Test>::assimilate_storage(&config, &mut t).unwrap();
+	sp_io::TestExternalities::new(t).execute_with(|| {
+		assert!(Mixer::first_stage_initialized());
+		assert!(Mixer::second_stage_initialized());
+		assert_eq!(Mixer::mixer_group_ids(), vec![0, 1]);
+		for i in 0..2 {
 			let g = MerkleTrees::get_tree(i).unwrap();
-			let mng = MerkleTrees::get_manager(i).unwrap();
 			let m = Mixer::get_mixer(i).unwrap();
+			assert!(g.initialized);
 			assert_eq!(g.leaf_count, 0);
-			assert_eq!(mng.required, true);
-			assert_eq!(m.fixed_deposit_size, val * 10_u64.pow(i))
+			assert_eq!(m.fixed_deposit_size, 1_000 * 10_u64.pow(i))
 		}
+
+		assert_eq!(MerkleTrees::get_verifying_key(0).unwrap(), Mixer::default_key_data());
+
+		// None of the migrations run on a chain built from the genesis config
+		assert_eq!(StorageVersion::<Test>::get(), Releases::V4_0_0);
+		migrations::initialize_staged::<Test>();
+		assert_eq!(Mixer::mixer_group_ids(), vec![0, 1]);
+	})
+}
+
+#[test]
+fn should_build_arkworks_mixer_with_configured_depth() {
+	let mut rng = test_rng();
+	let curve = arkworks_gadgets::setup::common::Curve::Bn254;
+	let recipient = Bn254Fr::from(0u8);
+	let relayer = Bn254Fr::from(0u8);
+	let (circuit, leaf, nullifier, root, _) = setup_circuit_mimc_220(&[], 0, recipient, relayer, &mut rng, curve);
+	let (pk, vk) = setup_random_groth16_mimc_220::<_, Bn254>(&mut rng, curve);
+	let mut vk_bytes = Vec::new();
+	vk.serialize(&mut vk_bytes).unwrap();
+
+	MixerTreeDepth::set(30);
+	let setup = Setup::new(HashFunction::MiMC, Backend::Arkworks(Curve::Bn254, Snark::Groth16));
+	let mut t = frame_system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	let config = GenesisConfig::<Test> {
+		keys: vec![Some(vk_bytes.clone())],
+		mixers: vec![(0, 1_000, setup.clone(), 0)],
+	};
+	GenesisBuild::<Test>::assimilate_storage(&config, &mut t).unwrap();
+	MixerTreeDepth::set(32);
+	sp_io::TestExternalities::new(t).execute_with(|| {
+		let tree = MerkleTrees::get_tree(0).unwrap();
+		assert!(tree.initialized);
+		assert_eq!(tree.depth, 30);
+		assert_eq!(tree.setup, setup);
+		assert_eq!(MerkleTrees::get_verifying_key(0).unwrap(), vk_bytes);
+		let (_, zero_root) = setup.generate_zero_tree(30, &[]).unwrap();
+		assert_eq!(MerkleTrees::get_merkle_root(0).unwrap(), zero_root);
+
+		// A proof made with the genesis key verifies against the mixer tree
+		System::set_block_number(1);
+		let leaf_bytes = to_bytes![leaf].unwrap();
+		assert_ok!(<MerkleTrees as TreeTrait<Test>>::add_members(
+			Mixer::account_id(),
+			0,
+			vec![leaf_bytes]
+		));
+		let root_bytes = MerkleTrees::get_merkle_root(0).unwrap();
+		assert_eq!(root_bytes, to_bytes![root].unwrap());
+
+		let proof = prove_groth16_mimc220(&pk, circuit, &mut rng);
+		let mut proof_bytes = vec![0u8; proof.serialized_size()];
+		proof.serialize(&mut proof_bytes[..]).unwrap();
+		assert_ok!(<MerkleTrees as TreeTrait<Test>>::verify_zk(
+			0,
+			1,
+			root_bytes,
+			Vec::new(),
+			to_bytes![nullifier].unwrap(),
+			proof_bytes,
+			Vec::new(),
+			Vec::new(),
+			to_bytes![recipient].unwrap(),
+			to_bytes![relayer].unwrap(),
+		));
+	})
+}
+
+#[test]
+fn should_only_allow_admin_origin_to_manage_mixers() {
+	new_test_ext().execute_with(|| {
+		let setup = Mixer::default_setup();
+		assert_err!(Mixer::create_new(Origin::signed(1), 0, setup.clone(), 1_000), BadOrigin);
+		assert_err!(Mixer::set_all_withdraw_only(Origin::signed(1), true), BadOrigin);
+		assert_ok!(Mixer::create_new(Origin::signed(4), 0, setup.clone(), 1_000));
+		assert_ok!(Mixer::create_new(Origin::root(), 0, setup, 10_000));
+		assert_eq!(Mixer::mixer_group_ids(), vec![0, 1]);
+	})
+}
+
+#[test]
+fn should_remove_admin_on_migration() {
+	new_test_ext().execute_with(|| {
+		put_storage_value(b"Mixer", b"Admin", &[], 4u64);
+		migrations::remove_admin::<Test>();
+		assert_eq!(get_storage_value::<u64>(b"Mixer", b"Admin", &[]), None);
+		assert_eq!(StorageVersion::<Test>::get(), Releases::V3_0_0);
+
+		// it only runs once
+		put_storage_value(b"Mixer", b"Admin", &[], 4u64);
+		migrations::remove_admin::<Test>();
+		assert_eq!(get_storage_value::<u64>(b"Mixer", b"Admin", &[]), Some(4));
 	})
 }
 
 #[test]
-fn should_be_able_to_change_admin_with_root() {
+fn should_be_able_to_set_all_mixers_withdraw_only_with_root() {
 	new_test_ext().execute_with(|| {
-		let call = Box::new(MixerCall::transfer_admin(2));
+		assert_ok!(Mixer::initialize_first_stage());
+		let call = Box::new(MixerCall::set_all_withdraw_only(true));
 		let res = call.dispatch_bypass_filter(RawOrigin::Root.into());
 		assert_ok!(res);
-		let admin = Mixer::admin();
-		assert_eq!(admin, 2);
 
-		let call = Box::new(MixerCall::transfer_admin(3));
-		let res = call.dispatch_bypass_filter(RawOrigin::Signed(0).into());
-		assert_err!(res, BadOrigin);
+		for i in 0..4 {
+			assert!(Mixer::withdraw_only(i));
+			assert!(!MerkleTrees::stopped(i));
+		}
+	})
+}
+
+#[test]
+fn should_stop_and_start_mixer() {
+	new_test_ext().execute_with(|| {
+		let default_admin = 4;
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let mut tree = FixedDepositTreeBuilder::new().build();
+		let leaf = tree.generate_secrets().to_bytes().to_vec();
+		assert_ok!(Mixer::deposit(Origin::signed(0), 0, vec![leaf.clone()], Vec::new()));
+
+		// Stopping deposits and withdrawal
+		assert_ok!(Mixer::set_mixer_stopped(Origin::signed(default_admin), 0, true));
+		assert_err!(
+			Mixer::deposit(Origin::signed(0), 0, vec![], Vec::new()),
+			Error::<Test>::MixerStopped
+		);
+		assert_err!(
+			Mixer::withdraw(
+				Origin::signed(0),
+				WithdrawProof::new(
+					0,
+					0,
+					Scalar::zero().to_bytes().to_vec(),
+					Vec::new(),
+					Scalar::zero().to_bytes().to_vec(),
+					Vec::new(),
+					Vec::new(),
+					Vec::new(),
+					None,
+					None,
+					0,
+					0,
+				)
+			),
+			Error::<Test>::MixerStopped
+		);
+
+		// Starting mixer
+		assert_ok!(Mixer::set_mixer_stopped(Origin::signed(default_admin), 0, false));
+		assert_ok!(Mixer::deposit(Origin::signed(0), 0, vec![leaf], Vec::new()));
+	})
+}
+
+#[test]
+fn should_stop_single_mixer_and_keep_withdrawals_in_withdraw_only_mode() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let default_admin = 4;
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let pc_gens = PedersenGens::default();
+
+		let tree_id = 0;
+		let key_id = 0;
+		let params = MerkleTrees::get_verifying_key(key_id).unwrap();
+		let bp_gens = merkle::utils::keys::from_bytes_to_bp_gens(&params);
+		let h = default_hasher(bp_gens);
+
+		assert_err!(Mixer::set_mixer_stopped(Origin::signed(0), tree_id, true), BadOrigin);
+		assert_err!(Mixer::set_withdraw_only(Origin::signed(0), tree_id, true), BadOrigin);
+		assert_err!(
+			Mixer::set_mixer_stopped(Origin::root(), 100, true),
+			Error::<Test>::NoMixerForId
+		);
+		assert_err!(
+			Mixer::set_withdraw_only(Origin::root(), 100, true),
+			Error::<Test>::NoMixerForId
+		);
+		assert_err!(Mixer::mixer_state(100), Error::<Test>::NoMixerForId);
+
+		// stopping one mixer leaves the others running
+		assert_ok!(Mixer::set_mixer_stopped(Origin::signed(default_admin), tree_id, true));
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::StoppedSet(tree_id, true)));
+		assert_eq!(Mixer::mixer_state(tree_id), Ok(MixerState::Stopped));
+		assert_eq!(Mixer::mixer_state(1), Ok(MixerState::Active));
+		assert_err!(
+			Mixer::deposit(Origin::signed(0), tree_id, vec![], Vec::new()),
+			Error::<Test>::MixerStopped
+		);
+		let mut other_tree = FixedDepositTreeBuilder::new().build();
+		let other_leaf = other_tree.generate_secrets().to_bytes().to_vec();
+		assert_ok!(Mixer::deposit(Origin::signed(0), 1, vec![other_leaf], Vec::new()));
+		assert_ok!(Mixer::set_mixer_stopped(Origin::signed(default_admin), tree_id, false));
+		assert_eq!(Mixer::mixer_state(tree_id), Ok(MixerState::Active));
+
+		let mut prover_transcript = Transcript::new(b"zk_membership_proof");
+		let prover = Prover::new(&pc_gens, &mut prover_transcript);
+		let mut ftree = FixedDepositTreeBuilder::new().hash_params(h.clone()).depth(32).build();
+
+		let leaf = ftree.generate_secrets().to_bytes();
+		ftree.tree.add_leaves(vec![leaf], None);
+		assert_ok!(Mixer::deposit(
+			Origin::signed(1),
+			tree_id,
+			vec![leaf.to_vec()],
+			Vec::new()
+		));
+
+		// withdraw-only refuses deposits
+		assert_ok!(Mixer::set_withdraw_only(Origin::root(), tree_id, true));
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::WithdrawOnlySet(tree_id, true)));
+		assert_eq!(Mixer::mixer_state(tree_id), Ok(MixerState::WithdrawOnly));
+		assert_err!(
+			Mixer::deposit(Origin::signed(0), tree_id, vec![], Vec::new()),
+			Error::<Test>::MixerWithdrawOnly
+		);
+
+		// stopping the mixer takes precedence over withdraw-only
+		assert_ok!(Mixer::set_mixer_stopped(Origin::signed(default_admin), tree_id, true));
+		assert_eq!(Mixer::mixer_state(tree_id), Ok(MixerState::Stopped));
+		assert_ok!(Mixer::set_mixer_stopped(Origin::signed(default_admin), tree_id, false));
+
+		// putting all mixers in withdraw-only mode refuses every deposit
+		assert_ok!(Mixer::set_all_withdraw_only(Origin::signed(default_admin), true));
+		for id in Mixer::mixer_group_ids() {
+			System::assert_has_event(crate::mock::Event::Mixer(crate::Event::WithdrawOnlySet(id, true)));
+			assert_eq!(Mixer::mixer_state(id), Ok(MixerState::WithdrawOnly));
+		}
+
+		// but valid withdrawals keep working
+		let root = MerkleTrees::get_merkle_root(tree_id).unwrap();
+		let (proof, (comms_cr, nullifier_hash, leaf_index_comms_cr, proof_comms_cr)) = ftree.prove_zk(
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&root)),
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&leaf)),
+			input_scalar(&2u64.encode()),
+			input_scalar(&0u64.encode()),
+			&ftree.hash_params.bp_gens,
+			prover,
+		);
+
+		let comms: Vec<ScalarBytes> = comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let leaf_index_comms: Vec<ScalarBytes> = leaf_index_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let proof_comms: Vec<ScalarBytes> = proof_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let balance_before = Balances::free_balance(2);
+		assert_ok!(Mixer::withdraw(
+			Origin::signed(2),
+			WithdrawProof::new(
+				tree_id,
+				1,
+				root,
+				comms,
+				nullifier_hash.to_bytes().to_vec(),
+				proof.to_bytes(),
+				leaf_index_comms,
+				proof_comms,
+				Some(2),
+				Some(0),
+				0,
+				0,
+			)
+		));
+		let deposit_size = Mixer::get_mixer(tree_id).unwrap().fixed_deposit_size;
+		assert_eq!(Balances::free_balance(2), balance_before + deposit_size);
+		assert_eq!(Mixer::total_value_locked(tree_id), 0);
+
+		assert_ok!(Mixer::set_all_withdraw_only(Origin::signed(default_admin), false));
+		assert_eq!(Mixer::mixer_state(1), Ok(MixerState::Active));
+	})
+}
+
+#[test]
+fn should_hold_delayed_withdrawal_until_due_and_released() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let pc_gens = PedersenGens::default();
+
+		let tree_id = 0;
+		let key_id = 0;
+		let params = MerkleTrees::get_verifying_key(key_id).unwrap();
+		let bp_gens = merkle::utils::keys::from_bytes_to_bp_gens(&params);
+		let h = default_hasher(bp_gens);
+
+		assert_err!(Mixer::set_withdrawal_delay(Origin::signed(1), tree_id, 3), BadOrigin);
+		assert_err!(
+			Mixer::set_withdrawal_delay(Origin::root(), 100, 3),
+			Error::<Test>::NoMixerForId
+		);
+		assert_ok!(Mixer::set_withdrawal_delay(Origin::root(), tree_id, 3));
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::WithdrawalDelaySet(tree_id, 3)));
+
+		let mut prover_transcript = Transcript::new(b"zk_membership_proof");
+		let prover = Prover::new(&pc_gens, &mut prover_transcript);
+		let mut ftree = FixedDepositTreeBuilder::new().hash_params(h.clone()).depth(32).build();
+
+		let leaf = ftree.generate_secrets().to_bytes();
+		ftree.tree.add_leaves(vec![leaf], None);
+		assert_ok!(Mixer::deposit(
+			Origin::signed(1),
+			tree_id,
+			vec![leaf.to_vec()],
+			Vec::new()
+		));
+
+		let root = MerkleTrees::get_merkle_root(tree_id).unwrap();
+		let (proof, (comms_cr, nullifier_hash, leaf_index_comms_cr, proof_comms_cr)) = ftree.prove_zk(
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&root)),
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&leaf)),
+			input_scalar(&2u64.encode()),
+			input_scalar(&0u64.encode()),
+			&ftree.hash_params.bp_gens,
+			prover,
+		);
+
+		let comms: Vec<ScalarBytes> = comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let leaf_index_comms: Vec<ScalarBytes> = leaf_index_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let proof_comms: Vec<ScalarBytes> = proof_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let balance_before = Balances::free_balance(2);
+		assert_ok!(Mixer::withdraw(
+			Origin::signed(2),
+			WithdrawProof::new(
+				tree_id,
+				1,
+				root,
+				comms,
+				nullifier_hash.to_bytes().to_vec(),
+				proof.to_bytes(),
+				leaf_index_comms,
+				proof_comms,
+				Some(2),
+				Some(0),
+				0,
+				0,
+			)
+		));
+		// the nullifier is spent, the payout is queued
+		let deposit_size = Mixer::get_mixer(tree_id).unwrap().fixed_deposit_size;
+		System::assert_has_event(crate::mock::Event::Mixer(crate::Event::WithdrawalQueued(0, tree_id, 4)));
+		assert_eq!(Balances::free_balance(2), balance_before);
+		assert_eq!(Mixer::total_value_locked(tree_id), 0);
+		assert_eq!(
+			Mixer::pending_withdrawals(tree_id),
+			Ok(vec![(0, PendingWithdrawal {
+				mixer_id: tree_id,
+				recipient: 2,
+				amount: deposit_size,
+				relayer: 0,
+				fee: 0,
+				due_block: 4,
+				frozen: false,
+			})])
+		);
+		assert_eq!(Mixer::pending_withdrawals(1), Ok(vec![]));
+		assert_err!(Mixer::pending_withdrawals(100), Error::<Test>::NoMixerForId);
+
+		assert_err!(Mixer::freeze_withdrawal(Origin::signed(2), 0), BadOrigin);
+		assert_err!(
+			Mixer::freeze_withdrawal(Origin::root(), 1),
+			Error::<Test>::NoPendingWithdrawal
+		);
+		assert_err!(
+			Mixer::release_withdrawal(Origin::root(), 0),
+			Error::<Test>::WithdrawalNotFrozen
+		);
+		assert_ok!(Mixer::freeze_withdrawal(Origin::root(), 0));
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::WithdrawalFrozen(0, true)));
+		assert_err!(
+			Mixer::freeze_withdrawal(Origin::root(), 0),
+			Error::<Test>::WithdrawalAlreadyFrozen
+		);
+
+		// a frozen withdrawal isn't paid when due
+		System::set_block_number(4);
+		Mixer::on_initialize(4);
+		assert_eq!(Balances::free_balance(2), balance_before);
+		assert_eq!(Mixer::payout_queue(4), Vec::<u32>::new());
+		assert!(Mixer::pending_withdrawal(0).unwrap().frozen);
+
+		// releasing it after its due block pays it right away
+		assert_ok!(Mixer::release_withdrawal(Origin::root(), 0));
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::WithdrawalPaid(0, tree_id)));
+		assert_eq!(Balances::free_balance(2), balance_before + deposit_size);
+		assert_eq!(Mixer::pending_withdrawals(tree_id), Ok(vec![]));
+		assert_err!(
+			Mixer::release_withdrawal(Origin::root(), 0),
+			Error::<Test>::NoPendingWithdrawal
+		);
+	})
+}
+
+#[test]
+fn should_pay_at_most_max_pending_payouts_per_block() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let mut tree = FixedDepositTreeBuilder::new().build();
+		let leaves: Vec<ScalarBytes> = (0..3).map(|_| tree.generate_secrets().to_bytes().to_vec()).collect();
+
+		let tree_id = 0;
+		let deposit_size = Mixer::get_mixer(tree_id).unwrap().fixed_deposit_size;
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, leaves, Vec::new()));
+		assert_ok!(Mixer::set_withdrawal_delay(Origin::root(), tree_id, 2));
+		for _ in 0..3 {
+			assert_ok!(Mixer::pay_or_queue(
+				tree_id,
+				NativeCurrencyId::get(),
+				2,
+				deposit_size,
+				2,
+				0
+			));
+		}
+		// the third payout doesn't fit in its due block and waits for the next
+		assert_eq!(Mixer::payout_queue(3), vec![0, 1]);
+		assert_eq!(Mixer::payout_queue(4), vec![2]);
+		assert_eq!(Mixer::pending_withdrawal(2).unwrap().due_block, 4);
+
+		let balance_before = Balances::free_balance(2);
+		System::set_block_number(3);
+		Mixer::on_initialize(3);
+		assert_eq!(Balances::free_balance(2), balance_before + 2 * deposit_size);
+		assert_eq!(Mixer::payout_queue(3), Vec::<u32>::new());
+		assert_eq!(Mixer::payout_queue(4), vec![2]);
+
+		// released before its due block, it is paid when due
+		assert_ok!(Mixer::freeze_withdrawal(Origin::root(), 2));
+		assert_ok!(Mixer::release_withdrawal(Origin::root(), 2));
+		assert!(Mixer::pending_withdrawal(2).is_some());
+
+		System::set_block_number(4);
+		Mixer::on_initialize(4);
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::WithdrawalPaid(2, tree_id)));
+		assert_eq!(Balances::free_balance(2), balance_before + 3 * deposit_size);
+		assert_eq!(Mixer::pending_withdrawals(tree_id), Ok(vec![]));
+	})
+}
+
+#[test]
+fn should_refuse_payout_without_room_within_max_postponement() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+
+		let tree_id = 0;
+		let deposit_size = Mixer::get_mixer(tree_id).unwrap().fixed_deposit_size;
+		assert_ok!(Mixer::set_withdrawal_delay(Origin::root(), tree_id, 2));
+		for _ in 0..4 {
+			assert_ok!(Mixer::pay_or_queue(
+				tree_id,
+				NativeCurrencyId::get(),
+				2,
+				deposit_size,
+				2,
+				0
+			));
+		}
+		assert_eq!(Mixer::payout_queue(3), vec![0, 1]);
+		assert_eq!(Mixer::payout_queue(4), vec![2, 3]);
+
+		// the due block and the one after it are full
+		assert_err!(
+			Mixer::pay_or_queue(tree_id, NativeCurrencyId::get(), 2, deposit_size, 2, 0),
+			Error::<Test>::PayoutQueueFull
+		);
+		assert_eq!(Mixer::payout_queue(5), Vec::<u32>::new());
+		assert_eq!(Mixer::next_pending_withdrawal_id(), 4);
+	})
+}
+
+#[test]
+fn should_freeze_pending_withdrawal_whose_payout_fails() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+
+		let tree_id = 0;
+		assert_ok!(Mixer::set_withdrawal_delay(Origin::root(), tree_id, 2));
+		// more than the mixer holds
+		let amount = Balances::free_balance(Mixer::account_id()) + 1;
+		assert_ok!(Mixer::pay_or_queue(tree_id, NativeCurrencyId::get(), 2, amount, 2, 0));
+
+		System::set_block_number(3);
+		Mixer::on_initialize(3);
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::WithdrawalPayoutFailed(
+			0, tree_id,
+		)));
+		assert_eq!(Mixer::payout_queue(3), Vec::<u32>::new());
+		let pending = Mixer::pending_withdrawal(0).unwrap();
+		assert!(pending.frozen);
+		assert_eq!(Mixer::pending_withdrawals(tree_id), Ok(vec![(0, pending)]));
+
+		// it is paid once released with the funds in the mixer
+		let _ = Balances::deposit_creating(&Mixer::account_id(), amount);
+		let balance_before = Balances::free_balance(2);
+		assert_ok!(Mixer::release_withdrawal(Origin::root(), 0));
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::WithdrawalPaid(0, tree_id)));
+		assert_eq!(Balances::free_balance(2), balance_before + amount);
+		assert_eq!(Mixer::pending_withdrawals(tree_id), Ok(vec![]));
+	})
+}
+
+#[test]
+fn should_fail_to_deposit_with_insufficient_balance() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let mut tree = FixedDepositTreeBuilder::new().build();
+
+		for i in 0..4 {
+			let leaf = tree.generate_secrets().to_bytes().to_vec();
+			assert_err!(
+				Mixer::deposit(Origin::signed(4), i, vec![leaf], Vec::new()),
+				DispatchError::Module {
+					index: 3,
+					error: 4,
+					message: Some("InsufficientBalance")
+				}
+			);
+		}
+	})
+}
+
+#[test]
+fn should_deposit_into_each_mixer_successfully() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let mut tree = FixedDepositTreeBuilder::new().build();
+
+		for i in 0..4 {
+			let leaf = tree.generate_secrets().to_bytes().to_vec();
+			let balance_before = Balances::free_balance(1);
+			assert_ok!(Mixer::deposit(Origin::signed(1), i, vec![leaf], Vec::new()));
+			let balance_after = Balances::free_balance(1);
+
+			// ensure state updates
+			let g = MerkleTrees::get_tree(i).unwrap();
+			let m = Mixer::get_mixer(i).unwrap();
+			let tvl = Mixer::total_value_locked(i);
+			assert_eq!(tvl, m.fixed_deposit_size);
+			assert_eq!(balance_before, balance_after + m.fixed_deposit_size);
+			assert_eq!(g.leaf_count, 1);
+		}
+	})
+}
+
+#[test]
+fn should_withdraw_from_each_mixer_successfully() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let pc_gens = PedersenGens::default();
+
+		let tree_id = 0;
+		let key_id = 0;
+		let params = MerkleTrees::get_verifying_key(key_id).unwrap();
+		let bp_gens = merkle::utils::keys::from_bytes_to_bp_gens(&params);
+		let h = default_hasher(bp_gens);
+
+		for i in 0..4 {
+			let tree_id = i;
+			let mut prover_transcript = Transcript::new(b"zk_membership_proof");
+			let prover = Prover::new(&pc_gens, &mut prover_transcript);
+			let mut ftree = FixedDepositTreeBuilder::new().hash_params(h.clone()).depth(32).build();
+
+			let leaf = ftree.generate_secrets().to_bytes();
+			ftree.tree.add_leaves(vec![leaf], None);
+
+			assert_ok!(Mixer::deposit(Origin::signed(1), i, vec![leaf.to_vec()], Vec::new()));
+
+			let root = MerkleTrees::get_merkle_root(i).unwrap();
+			let (proof, (comms_cr, nullifier_hash, leaf_index_comms_cr, proof_comms_cr)) = ftree.prove_zk(
+				Scalar::from_bytes_mod_order(slice_to_bytes_32(&root)),
+				Scalar::from_bytes_mod_order(slice_to_bytes_32(&leaf)),
+				input_scalar(&2u64.encode()),
+				input_scalar(&0u64.encode()),
+				&ftree.hash_params.bp_gens,
+				prover,
+			);
+
+			let comms: Vec<ScalarBytes> = comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+			let leaf_index_comms: Vec<ScalarBytes> =
+				leaf_index_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+			let proof_comms: Vec<ScalarBytes> = proof_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+
+			let m = Mixer::get_mixer(i).unwrap();
+			let balance_before = Balances::free_balance(2);
+			// check TVL after depositing
+			let tvl = Mixer::total_value_locked(i);
+			assert_eq!(tvl, m.fixed_deposit_size);
+			// withdraw from another account
+			assert_ok!(Mixer::withdraw(
+				Origin::signed(2),
+				WithdrawProof::new(
+					i,
+					0,
+					root,
+					comms,
+					nullifier_hash.to_bytes().to_vec(),
+					proof.to_bytes(),
+					leaf_index_comms,
+					proof_comms,
+					Some(2),
+					Some(0),
+					0,
+					0,
+				)
+			));
+			let balance_after = Balances::free_balance(2);
+			assert_eq!(balance_before + m.fixed_deposit_size, balance_after);
+			// ensure TVL is 0 after withdrawing
+			let tvl = Mixer::total_value_locked(i);
+			assert_eq!(tvl, 0);
+		}
+	})
+}
+
+#[test]
+fn should_withdraw_to_evm_address() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let pc_gens = PedersenGens::default();
+
+		let tree_id = 0;
+		let key_id = 0;
+		let params = MerkleTrees::get_verifying_key(key_id).unwrap();
+		let bp_gens = merkle::utils::keys::from_bytes_to_bp_gens(&params);
+		let h = default_hasher(bp_gens);
+
+		let address = H160::from_low_u64_be(5);
+		let mapped_account: AccountId = 5;
+		let relayer: AccountId = 1;
+
+		let mut prover_transcript = Transcript::new(b"zk_membership_proof");
+		let prover = Prover::new(&pc_gens, &mut prover_transcript);
+		let mut ftree = FixedDepositTreeBuilder::new().hash_params(h.clone()).depth(32).build();
+
+		let leaf = ftree.generate_secrets().to_bytes();
+		ftree.tree.add_leaves(vec![leaf], None);
+		assert_ok!(Mixer::deposit(
+			Origin::signed(0),
+			tree_id,
+			vec![leaf.to_vec()],
+			Vec::new()
+		));
+
+		// the tagged address is the recipient bound into the proof
+		let root = MerkleTrees::get_merkle_root(tree_id).unwrap();
+		let recipient_input = Mixer::evm_recipient_input(&address);
+		let (proof, (comms_cr, nullifier_hash, leaf_index_comms_cr, proof_comms_cr)) = ftree.prove_zk(
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&root)),
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&leaf)),
+			input_scalar(&recipient_input),
+			input_scalar(&relayer.encode()),
+			&ftree.hash_params.bp_gens,
+			prover,
+		);
+
+		let comms: Vec<ScalarBytes> = comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let leaf_index_comms: Vec<ScalarBytes> = leaf_index_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let proof_comms: Vec<ScalarBytes> = proof_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let withdraw_proof = |recipient: Option<AccountId>| {
+			WithdrawProof::<Test>::new(
+				tree_id,
+				1,
+				root.clone(),
+				comms.clone(),
+				nullifier_hash.to_bytes().to_vec(),
+				proof.to_bytes(),
+				leaf_index_comms.clone(),
+				proof_comms.clone(),
+				recipient,
+				Some(relayer),
+				0,
+				0,
+			)
+		};
+
+		assert_err!(
+			Mixer::withdraw_to_evm(Origin::signed(relayer), withdraw_proof(Some(mapped_account)), address),
+			Error::<Test>::InvalidEvmWithdraw
+		);
+		// the proof doesn't verify for another address, nor for the mapped account
+		assert_err!(
+			Mixer::withdraw_to_evm(Origin::signed(relayer), withdraw_proof(None), H160::from_low_u64_be(6)),
+			merkle::Error::<Test>::ZkVerificationFailed
+		);
+		assert_err!(
+			Mixer::check_withdraw(relayer, withdraw_proof(Some(mapped_account))),
+			merkle::Error::<Test>::ZkVerificationFailed
+		);
+		// nor as a plain withdrawal, not even to an account encoding to the
+		// address bytes
+		assert_err!(
+			Mixer::withdraw(Origin::signed(relayer), withdraw_proof(None)),
+			merkle::Error::<Test>::ZkVerificationFailed
+		);
+		assert_err!(
+			<MerkleTrees as TreeTrait<Test>>::verify_zk(
+				tree_id,
+				1,
+				root.clone(),
+				comms.clone(),
+				nullifier_hash.to_bytes().to_vec(),
+				proof.to_bytes(),
+				leaf_index_comms.clone(),
+				proof_comms.clone(),
+				public_input_to_field(address.as_bytes()),
+				public_input_to_field(&relayer.encode()),
+			),
+			merkle::Error::<Test>::ZkVerificationFailed
+		);
+
+		assert_ok!(Mixer::withdraw_to_evm(
+			Origin::signed(relayer),
+			withdraw_proof(None),
+			address
+		));
+		assert_eq!(Balances::free_balance(mapped_account), 1_000);
+		assert_eq!(Mixer::total_value_locked(tree_id), 0);
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::Withdraw(
+			tree_id,
+			relayer,
+			mapped_account,
+			relayer,
+			0,
+			root.clone(),
+		)));
+	})
+}
+
+#[test]
+fn should_report_mixer_stats() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let pc_gens = PedersenGens::default();
+
+		let tree_id = 0;
+		let key_id = 0;
+		let params = MerkleTrees::get_verifying_key(key_id).unwrap();
+		let bp_gens = merkle::utils::keys::from_bytes_to_bp_gens(&params);
+		let h = default_hasher(bp_gens);
+
+		assert_err!(Mixer::mixer_stats(100), Error::<Test>::NoMixerForId);
+		let stats = Mixer::mixers();
+		assert_eq!(stats.len(), 4);
+		assert_eq!(
+			stats.iter().map(|s| s.mixer_id).collect::<Vec<_>>(),
+			Mixer::mixer_group_ids()
+		);
+
+		let mut prover_transcript = Transcript::new(b"zk_membership_proof");
+		let prover = Prover::new(&pc_gens, &mut prover_transcript);
+		let mut ftree = FixedDepositTreeBuilder::new().hash_params(h.clone()).depth(32).build();
+
+		let leaf = ftree.generate_secrets().to_bytes();
+		let other_leaf = ftree.generate_secrets().to_bytes();
+		ftree.tree.add_leaves(vec![leaf, other_leaf], None);
+		assert_ok!(Mixer::deposit(
+			Origin::signed(1),
+			tree_id,
+			vec![leaf.to_vec(), other_leaf.to_vec()],
+			Vec::new()
+		));
+
+		let m = Mixer::get_mixer(tree_id).unwrap();
+		let stats = Mixer::mixer_stats(tree_id).unwrap();
+		assert_eq!(stats.currency_id, m.currency_id);
+		assert_eq!(stats.fixed_deposit_size, m.fixed_deposit_size);
+		assert_eq!(stats.total_value_locked, 2 * m.fixed_deposit_size);
+		assert_eq!(stats.state, MixerState::Active);
+		assert_eq!(stats.deposit_count, 2);
+		assert_eq!(stats.withdrawal_count, 0);
+		assert_eq!(stats.anonymity_set_size, 2);
+		assert_eq!(stats.last_activity, Some(1));
+		assert_eq!(stats.recent_deposits, 2);
+		assert_eq!(stats.recent_withdrawals, 0);
+
+		let root = MerkleTrees::get_merkle_root(tree_id).unwrap();
+		let (proof, (comms_cr, nullifier_hash, leaf_index_comms_cr, proof_comms_cr)) = ftree.prove_zk(
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&root)),
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&leaf)),
+			input_scalar(&2u64.encode()),
+			input_scalar(&0u64.encode()),
+			&ftree.hash_params.bp_gens,
+			prover,
+		);
+
+		let comms: Vec<ScalarBytes> = comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let leaf_index_comms: Vec<ScalarBytes> = leaf_index_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let proof_comms: Vec<ScalarBytes> = proof_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		assert_ok!(Mixer::withdraw(
+			Origin::signed(2),
+			WithdrawProof::new(
+				tree_id,
+				0,
+				root,
+				comms,
+				nullifier_hash.to_bytes().to_vec(),
+				proof.to_bytes(),
+				leaf_index_comms,
+				proof_comms,
+				Some(2),
+				Some(0),
+				0,
+				0,
+			)
+		));
+		assert_ok!(Mixer::set_withdraw_only(Origin::root(), tree_id, true));
+
+		let stats = Mixer::mixer_stats(tree_id).unwrap();
+		assert_eq!(stats.total_value_locked, m.fixed_deposit_size);
+		assert_eq!(stats.state, MixerState::WithdrawOnly);
+		assert_eq!(stats.deposit_count, 2);
+		assert_eq!(stats.withdrawal_count, 1);
+		assert_eq!(stats.anonymity_set_size, 1);
+		assert_eq!(stats.recent_deposits, 2);
+		assert_eq!(stats.recent_withdrawals, 1);
+		assert_eq!(Mixer::mixers()[0], stats);
+	})
+}
+
+#[test]
+fn should_enforce_mixer_limits() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let mut tree = FixedDepositTreeBuilder::new().build();
+		let mut leaves =
+			|n: usize| -> Vec<ScalarBytes> { (0..n).map(|_| tree.generate_secrets().to_bytes().to_vec()).collect() };
+
+		let tree_id = 0;
+		let deposit_size = Mixer::get_mixer(tree_id).unwrap().fixed_deposit_size;
+		let limits = MixerLimits {
+			max_commitments_per_deposit: Some(2),
+			max_deposits_per_block: Some(3),
+			max_total_value_locked: Some(4 * deposit_size),
+		};
+		assert_err!(Mixer::set_limits(Origin::signed(1), tree_id, limits.clone()), BadOrigin);
+		assert_err!(
+			Mixer::set_limits(Origin::root(), 100, limits.clone()),
+			Error::<Test>::NoMixerForId
+		);
+		assert_ok!(Mixer::set_limits(Origin::root(), tree_id, limits.clone()));
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::LimitsSet(
+			tree_id,
+			limits.clone(),
+		)));
+		assert_eq!(Mixer::mixer_stats(tree_id).unwrap().limits, limits);
+
+		assert_err!(
+			Mixer::deposit(Origin::signed(1), tree_id, leaves(3), Vec::new()),
+			Error::<Test>::TooManyCommitments
+		);
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, leaves(2), Vec::new()));
+		assert_err!(
+			Mixer::deposit(Origin::signed(1), tree_id, leaves(2), Vec::new()),
+			Error::<Test>::BlockDepositLimitReached
+		);
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, leaves(1), Vec::new()));
+		assert_eq!(Mixer::deposits_in_block(tree_id), 3);
+
+		// the block limit resets, the TVL limit doesn't
+		System::set_block_number(2);
+		assert_eq!(Mixer::deposits_in_block(tree_id), 0);
+		assert_err!(
+			Mixer::deposit(Origin::signed(1), tree_id, leaves(2), Vec::new()),
+			Error::<Test>::TvlLimitExceeded
+		);
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, leaves(1), Vec::new()));
+		assert_eq!(Mixer::total_value_locked(tree_id), 4 * deposit_size);
+
+		// other mixers aren't limited
+		assert_ok!(Mixer::deposit(Origin::signed(1), 1, leaves(3), Vec::new()));
+
+		assert_ok!(Mixer::set_limits(Origin::root(), tree_id, MixerLimits::default()));
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, leaves(3), Vec::new()));
+	})
+}
+
+#[test]
+fn should_take_protocol_fee_on_deposit() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let mut tree = FixedDepositTreeBuilder::new().build();
+		let mut leaves =
+			|n: usize| -> Vec<ScalarBytes> { (0..n).map(|_| tree.generate_secrets().to_bytes().to_vec()).collect() };
+
+		let tree_id = 0;
+		let treasury = TreasuryAccount::get();
+		let deposit_size = Mixer::get_mixer(tree_id).unwrap().fixed_deposit_size;
+		let fee = ProtocolFee::Proportional(Permill::from_percent(1));
+		assert_err!(
+			Mixer::set_protocol_fee(Origin::signed(1), tree_id, Some(fee.clone())),
+			BadOrigin
+		);
+		assert_err!(
+			Mixer::set_protocol_fee(Origin::root(), 100, Some(fee.clone())),
+			Error::<Test>::NoMixerForId
+		);
+		assert_ok!(Mixer::set_protocol_fee(Origin::root(), tree_id, Some(fee.clone())));
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::ProtocolFeeSet(
+			tree_id,
+			Some(fee),
+		)));
+
+		// the fee is paid on top of each deposit
+		let balance_before = Balances::free_balance(1);
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, leaves(2), Vec::new()));
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::ProtocolFeeTaken(
+			tree_id, 1, 20,
+		)));
+		assert_eq!(Balances::free_balance(1), balance_before - 2 * deposit_size - 20);
+		assert_eq!(Balances::free_balance(treasury), 20);
+		assert_eq!(Mixer::accrued_fees(tree_id), 20);
+		// the mixer still holds the whole denomination of every deposit
+		assert_eq!(Mixer::total_value_locked(tree_id), 2 * deposit_size);
+		assert_eq!(Balances::free_balance(Mixer::account_id()), 2 * deposit_size);
+
+		assert_ok!(Mixer::set_protocol_fee(
+			Origin::root(),
+			tree_id,
+			Some(ProtocolFee::Flat(5))
+		));
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, leaves(1), Vec::new()));
+		assert_eq!(Mixer::accrued_fees(tree_id), 25);
+
+		// other mixers don't charge the fee
+		assert_ok!(Mixer::deposit(Origin::signed(1), 1, leaves(1), Vec::new()));
+		assert_eq!(Mixer::accrued_fees(1), 0);
+
+		assert_ok!(Mixer::set_protocol_fee(Origin::root(), tree_id, None));
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, leaves(1), Vec::new()));
+		assert_eq!(Balances::free_balance(treasury), 25);
+	})
+}
+
+#[test]
+fn should_track_mixer_activity_over_window() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let tree_id = 1;
+		let mut tree = FixedDepositTreeBuilder::new().build();
+		let mut leaves =
+			|n: usize| -> Vec<ScalarBytes> { (0..n).map(|_| tree.generate_secrets().to_bytes().to_vec()).collect() };
+
+		assert_err!(Mixer::mixer_activity(100), Error::<Test>::NoMixerForId);
+		assert_eq!(Mixer::mixer_activity(tree_id), Ok(vec![]));
+		assert_eq!(Mixer::last_activity(tree_id), None);
+
+		// deposits in the same block are merged
+		System::set_block_number(1);
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, leaves(2), Vec::new()));
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, leaves(1), Vec::new()));
+		System::set_block_number(5);
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, leaves(1), Vec::new()));
+		assert_eq!(
+			Mixer::mixer_activity(tree_id),
+			Ok(vec![
+				BlockActivity {
+					block_number: 1,
+					deposits: 3,
+					withdrawals: 0,
+				},
+				BlockActivity {
+					block_number: 5,
+					deposits: 1,
+					withdrawals: 0,
+				},
+			])
+		);
+		// other mixers are untouched
+		assert_eq!(Mixer::mixer_activity(0), Ok(vec![]));
+
+		// blocks that left the activity window are dropped
+		System::set_block_number(12);
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, leaves(1), Vec::new()));
+		assert_eq!(
+			Mixer::mixer_activity(tree_id),
+			Ok(vec![
+				BlockActivity {
+					block_number: 5,
+					deposits: 1,
+					withdrawals: 0,
+				},
+				BlockActivity {
+					block_number: 12,
+					deposits: 1,
+					withdrawals: 0,
+				},
+			])
+		);
+
+		let stats = Mixer::mixer_stats(tree_id).unwrap();
+		assert_eq!(stats.deposit_count, 5);
+		assert_eq!(stats.last_activity, Some(12));
+		assert_eq!(stats.recent_deposits, 2);
+		assert_eq!(stats.recent_withdrawals, 0);
+	})
+}
+
+#[test]
+fn should_emit_deposit_memos_by_leaf_index() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let tree_id = 0;
+		let mut tree = FixedDepositTreeBuilder::new().build();
+		let mut leaves =
+			|n: usize| -> Vec<ScalarBytes> { (0..n).map(|_| tree.generate_secrets().to_bytes().to_vec()).collect() };
+		let memo = vec![7u8; MaxMemoLength::get() as usize];
+		let long_memo = vec![7u8; MaxMemoLength::get() as usize + 1];
+
+		assert_err!(
+			Mixer::deposit(Origin::signed(1), tree_id, leaves(2), vec![Some(memo.clone())]),
+			Error::<Test>::InvalidMemoCount
+		);
+		assert_err!(
+			Mixer::deposit(Origin::signed(1), tree_id, leaves(1), vec![Some(long_memo)]),
+			Error::<Test>::MemoTooLong
+		);
+
+		// deposits without memos emit none
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, leaves(1), Vec::new()));
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::Deposit(
+			tree_id,
+			1,
+			1_000,
+			Vec::new(),
+		)));
+		// memos are keyed by the index of their leaf in the tree
+		assert_ok!(Mixer::deposit(Origin::signed(1), tree_id, leaves(3), vec![
+			None,
+			Some(memo.clone()),
+			Some(vec![1, 2, 3]),
+		]));
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::Deposit(
+			tree_id,
+			1,
+			1_000,
+			vec![(2, memo), (3, vec![1, 2, 3])],
+		)));
+		assert_ne!(Mixer::memo_key(tree_id, 2), Mixer::memo_key(tree_id, 3));
+		assert_ne!(Mixer::memo_key(tree_id, 2), Mixer::memo_key(1, 2));
+	})
+}
+
+#[test]
+fn should_count_previous_deposits_on_migration() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let mut tree = FixedDepositTreeBuilder::new().build();
+		let leaves = (0..3).map(|_| tree.generate_secrets().to_bytes().to_vec()).collect();
+		assert_ok!(Mixer::deposit(Origin::signed(1), 0, leaves, Vec::new()));
+		DepositCounts::<Test>::remove(0);
+		assert_eq!(Mixer::deposit_count(0), 0);
+
+		migrations::initialize_deposit_counts::<Test>();
+		assert_eq!(Mixer::deposit_count(0), 3);
+		assert_eq!(Mixer::deposit_count(1), 0);
+		assert_eq!(StorageVersion::<Test>::get(), Releases::V4_0_0);
+
+		// it only runs once
+		DepositCounts::<Test>::remove(0);
+		migrations::initialize_deposit_counts::<Test>();
+		assert_eq!(Mixer::deposit_count(0), 0);
+	})
+}
+
+#[test]
+fn should_set_max_fees_of_previous_mixers_on_migration() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::set_max_fee(Origin::root(), 1, 0));
+		MaxFees::<Test>::remove(0);
+		StorageVersion::<Test>::put(Releases::V4_0_0);
+
+		migrations::initialize_max_fees::<Test>();
+		assert_eq!(Mixer::max_fee(0), 100);
+		assert_eq!(Mixer::max_fee(1), 0);
+		assert_eq!(Mixer::max_fee(2), 10_000);
+		assert_eq!(StorageVersion::<Test>::get(), Releases::V5_0_0);
+
+		// it only runs once
+		MaxFees::<Test>::remove(0);
+		migrations::initialize_max_fees::<Test>();
+		assert_eq!(Mixer::max_fee(0), 0);
+	})
+}
+
+#[test]
+fn should_dry_run_withdraw_without_changing_state() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let pc_gens = PedersenGens::default();
+
+		let tree_id = 0;
+		let key_id = 0;
+		let params = MerkleTrees::get_verifying_key(key_id).unwrap();
+		let bp_gens = merkle::utils::keys::from_bytes_to_bp_gens(&params);
+		let h = default_hasher(bp_gens);
+
+		let mut prover_transcript = Transcript::new(b"zk_membership_proof");
+		let prover = Prover::new(&pc_gens, &mut prover_transcript);
+		let mut ftree = FixedDepositTreeBuilder::new().hash_params(h.clone()).depth(32).build();
+
+		let leaf = ftree.generate_secrets().to_bytes();
+		ftree.tree.add_leaves(vec![leaf], None);
+
+		assert_ok!(Mixer::deposit(
+			Origin::signed(1),
+			tree_id,
+			vec![leaf.to_vec()],
+			Vec::new()
+		));
+
+		let root = MerkleTrees::get_merkle_root(tree_id).unwrap();
+		let (proof, (comms_cr, nullifier_hash, leaf_index_comms_cr, proof_comms_cr)) = ftree.prove_zk(
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&root)),
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&leaf)),
+			input_scalar(&2u64.encode()),
+			input_scalar(&0u64.encode()),
+			&ftree.hash_params.bp_gens,
+			prover,
+		);
+
+		let comms: Vec<ScalarBytes> = comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let leaf_index_comms: Vec<ScalarBytes> = leaf_index_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let proof_comms: Vec<ScalarBytes> = proof_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let withdraw_proof = WithdrawProof::<Test>::new(
+			tree_id,
+			0,
+			root.clone(),
+			comms.clone(),
+			nullifier_hash.to_bytes().to_vec(),
+			proof.to_bytes(),
+			leaf_index_comms.clone(),
+			proof_comms.clone(),
+			Some(2),
+			Some(0),
+			0,
+			0,
+		);
+
+		// wrong mixer and an uncached root are reported precisely
+		let mut unknown_mixer = withdraw_proof.clone();
+		unknown_mixer.mixer_id = 100;
+		assert_err!(
+			Mixer::check_withdraw(2, unknown_mixer),
+			Error::<Test>::NoMixerForId
+		);
+		let mut unknown_root = withdraw_proof.clone();
+		unknown_root.cached_root = Scalar::zero().to_bytes().to_vec();
+		assert_err!(
+			Mixer::check_withdraw(2, unknown_root),
+			merkle::Error::<Test>::InvalidMerkleRoot
+		);
+
+		// dry-run passes and leaves the state untouched
+		let balance_before = Balances::free_balance(2);
+		assert_ok!(Mixer::check_withdraw(2, withdraw_proof.clone()));
+		assert_ok!(Mixer::check_withdraw(2, withdraw_proof.clone()));
+		assert_eq!(Balances::free_balance(2), balance_before);
+		assert_eq!(Mixer::total_value_locked(tree_id), Mixer::get_mixer(tree_id).unwrap().fixed_deposit_size);
+
+		assert_ok!(Mixer::withdraw(Origin::signed(2), withdraw_proof.clone()));
+		assert_err!(
+			Mixer::check_withdraw(2, withdraw_proof),
+			merkle::Error::<Test>::AlreadyUsedNullifier
+		);
+	})
+}
+
+#[test]
+fn should_pay_relayer_fee_and_refund_on_withdraw() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let pc_gens = PedersenGens::default();
+
+		let tree_id = 0;
+		let key_id = 0;
+		let params = MerkleTrees::get_verifying_key(key_id).unwrap();
+		let bp_gens = merkle::utils::keys::from_bytes_to_bp_gens(&params);
+		let h = default_hasher(bp_gens);
+
+		let recipient: AccountId = 2;
+		let relayer: AccountId = 1;
+		let fee: Balance = 100;
+		let refund: Balance = 50;
+
+		// new mixers accept fees up to 10% of their deposit size, which only
+		// the admin can change
+		assert_eq!(Mixer::max_fee(tree_id), fee);
+		assert_err!(
+			Mixer::set_max_fee(Origin::signed(relayer), tree_id, fee),
+			BadOrigin
+		);
+		assert_err!(
+			Mixer::set_max_fee(Origin::root(), tree_id, 1_001),
+			Error::<Test>::InvalidMaxFee
+		);
+		assert_ok!(Mixer::set_max_fee(Origin::root(), tree_id, fee));
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::MaxFeeSet(tree_id, fee)));
+
+		let mut prover_transcript = Transcript::new(b"zk_membership_proof");
+		let prover = Prover::new(&pc_gens, &mut prover_transcript);
+		let mut ftree = FixedDepositTreeBuilder::new().hash_params(h.clone()).depth(32).build();
+
+		let leaf = ftree.generate_secrets().to_bytes();
+		ftree.tree.add_leaves(vec![leaf], None);
+
+		assert_ok!(Mixer::deposit(
+			Origin::signed(0),
+			tree_id,
+			vec![leaf.to_vec()],
+			Vec::new()
+		));
+
+		let root = MerkleTrees::get_merkle_root(tree_id).unwrap();
+		let relayer_input = Mixer::relayer_input(&relayer, fee, refund);
+		let (proof, (comms_cr, nullifier_hash, leaf_index_comms_cr, proof_comms_cr)) = ftree.prove_zk(
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&root)),
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&leaf)),
+			input_scalar(&recipient.encode()),
+			input_scalar(&relayer_input),
+			&ftree.hash_params.bp_gens,
+			prover,
+		);
+
+		let comms: Vec<ScalarBytes> = comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let leaf_index_comms: Vec<ScalarBytes> = leaf_index_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let proof_comms: Vec<ScalarBytes> = proof_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let withdraw_proof = |fee: Balance, refund: Balance| {
+			WithdrawProof::<Test>::new(
+				tree_id,
+				1,
+				root.clone(),
+				comms.clone(),
+				nullifier_hash.to_bytes().to_vec(),
+				proof.to_bytes(),
+				leaf_index_comms.clone(),
+				proof_comms.clone(),
+				Some(recipient),
+				Some(relayer),
+				fee,
+				refund,
+			)
+		};
+
+		// fee above the maximum, and a fee or refund the proof wasn't made for
+		assert_err!(
+			Mixer::withdraw(Origin::signed(relayer), withdraw_proof(fee + 1, refund)),
+			Error::<Test>::FeeExceedsMaximum
+		);
+		assert_err!(
+			Mixer::withdraw(Origin::signed(relayer), withdraw_proof(fee - 1, refund)),
+			merkle::Error::<Test>::ZkVerificationFailed
+		);
+		assert_err!(
+			Mixer::withdraw(Origin::signed(relayer), withdraw_proof(fee, 0)),
+			merkle::Error::<Test>::ZkVerificationFailed
+		);
+
+		let deposit_size = Mixer::get_mixer(tree_id).unwrap().fixed_deposit_size;
+		let recipient_balance_before = Balances::free_balance(recipient);
+		let relayer_balance_before = Balances::free_balance(relayer);
+		assert_ok!(Mixer::withdraw(Origin::signed(relayer), withdraw_proof(fee, refund)));
+		assert_eq!(
+			Balances::free_balance(recipient),
+			recipient_balance_before + deposit_size - fee + refund
+		);
+		assert_eq!(Balances::free_balance(relayer), relayer_balance_before + fee - refund);
+		assert_eq!(Mixer::total_value_locked(tree_id), 0);
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::Withdraw(
+			tree_id,
+			relayer,
+			recipient,
+			relayer,
+			fee,
+			root,
+		)));
+	})
+}
+
+#[test]
+fn should_withdraw_unsigned_and_pay_fee_to_treasury() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		assert_ok!(Mixer::initialize_first_stage());
+		assert_ok!(Mixer::initialize_second_stage());
+		let pc_gens = PedersenGens::default();
+
+		let tree_id = 0;
+		let key_id = 0;
+		let params = MerkleTrees::get_verifying_key(key_id).unwrap();
+		let bp_gens = merkle::utils::keys::from_bytes_to_bp_gens(&params);
+		let h = default_hasher(bp_gens);
+
+		// a fresh account without any balance
+		let recipient: AccountId = 5;
+		let fee: Balance = 100;
+
+		let mut prover_transcript = Transcript::new(b"zk_membership_proof");
+		let prover = Prover::new(&pc_gens, &mut prover_transcript);
+		let mut ftree = FixedDepositTreeBuilder::new().hash_params(h.clone()).depth(32).build();
+
+		let leaf = ftree.generate_secrets().to_bytes();
+		ftree.tree.add_leaves(vec![leaf], None);
+
+		assert_ok!(Mixer::deposit(
+			Origin::signed(0),
+			tree_id,
+			vec![leaf.to_vec()],
+			Vec::new()
+		));
+
+		// the recipient stands in for the relayer
+		let root = MerkleTrees::get_merkle_root(tree_id).unwrap();
+		let relayer_input = Mixer::relayer_input(&recipient, fee, 0);
+		let (proof, (comms_cr, nullifier_hash, leaf_index_comms_cr, proof_comms_cr)) = ftree.prove_zk(
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&root)),
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&leaf)),
+			input_scalar(&recipient.encode()),
+			input_scalar(&relayer_input),
+			&ftree.hash_params.bp_gens,
+			prover,
+		);
+
+		let comms: Vec<ScalarBytes> = comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let leaf_index_comms: Vec<ScalarBytes> = leaf_index_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let proof_comms: Vec<ScalarBytes> = proof_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let withdraw_proof = |recipient: Option<AccountId>, fee: Balance, refund: Balance| {
+			WithdrawProof::<Test>::new(
+				tree_id,
+				1,
+				root.clone(),
+				comms.clone(),
+				nullifier_hash.to_bytes().to_vec(),
+				proof.to_bytes(),
+				leaf_index_comms.clone(),
+				proof_comms.clone(),
+				recipient,
+				None,
+				fee,
+				refund,
+			)
+		};
+		let validate = |withdraw_proof: WithdrawProof<Test>| {
+			<Mixer as ValidateUnsigned>::validate_unsigned(
+				TransactionSource::External,
+				&MixerCall::withdraw_unsigned(withdraw_proof),
+			)
+		};
+
+		assert_err!(
+			Mixer::withdraw_unsigned(Origin::signed(0), withdraw_proof(Some(recipient), fee, 0)),
+			BadOrigin
+		);
+		for invalid in vec![
+			withdraw_proof(None, fee, 0),
+			withdraw_proof(Some(recipient), 0, 0),
+			withdraw_proof(Some(recipient), fee, 1),
+		] {
+			assert_err!(
+				Mixer::withdraw_unsigned(Origin::none(), invalid.clone()),
+				Error::<Test>::InvalidUnsignedWithdraw
+			);
+			assert_eq!(validate(invalid), InvalidTransaction::BadProof.into());
+		}
+		// a fee the proof wasn't made for
+		assert_eq!(
+			validate(withdraw_proof(Some(recipient), fee - 1, 0)),
+			InvalidTransaction::BadProof.into()
+		);
+		// fees under 5% of the deposit size are refused
+		assert_err!(
+			Mixer::withdraw_unsigned(Origin::none(), withdraw_proof(Some(recipient), 49, 0)),
+			Error::<Test>::FeeBelowMinimum
+		);
+
+		// the fee is 10% of the deposit size, and the root is cached for 5 blocks
+		let valid = validate(withdraw_proof(Some(recipient), fee, 0)).unwrap();
+		assert_eq!(valid.priority, 100_000);
+		assert_eq!(valid.longevity, 5);
+		assert_eq!(valid.provides.len(), 1);
+		System::set_block_number(3);
+		assert_eq!(validate(withdraw_proof(Some(recipient), fee, 0)).unwrap().longevity, 3);
+
+		let deposit_size = Mixer::get_mixer(tree_id).unwrap().fixed_deposit_size;
+		let treasury = TreasuryAccount::get();
+		let treasury_balance_before = Balances::free_balance(treasury);
+		assert_ok!(Mixer::withdraw_unsigned(
+			Origin::none(),
+			withdraw_proof(Some(recipient), fee, 0)
+		));
+		assert_eq!(Balances::free_balance(recipient), deposit_size - fee);
+		assert_eq!(Balances::free_balance(treasury), treasury_balance_before + fee);
+		assert_eq!(Mixer::total_value_locked(tree_id), 0);
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::UnsignedWithdraw(
+			tree_id, recipient, fee, root,
+		)));
+
+		// the nullifier is spent, so the same withdrawal is stale
+		assert_eq!(
+			validate(withdraw_proof(Some(recipient), fee, 0)),
+			InvalidTransaction::Stale.into()
+		);
 	})
 }
 
 #[test]
-fn should_be_able_to_stop_mixers_with_root() {
+fn should_keep_one_withdrawal_per_nullifier_in_pool() {
 	new_test_ext().execute_with(|| {
-		assert_ok!(Mixer::initialize_first_stage());
-		let call = Box::new(MixerCall::set_stopped(true));
-		let res = call.dispatch_bypass_filter(RawOrigin::Root.into());
-		assert_ok!(res);
+		let tree_id = 0;
+		let withdraw_call = |nullifier_hash: ScalarBytes, recipient: AccountId| {
+			Call::Mixer(MixerCall::withdraw(WithdrawProof::<Test>::new(
+				tree_id,
+				0,
+				Vec::new(),
+				Vec::new(),
+				nullifier_hash,
+				Vec::new(),
+				Vec::new(),
+				Vec::new(),
+				Some(recipient),
+				None,
+				0,
+				0,
+			)))
+		};
+		let validate = |call: Call| CheckNullifier::<Test>::new().validate(&1, &call, &Default::default(), 0);
+		let nullifier = Scalar::from(1u32).to_bytes().to_vec();
 
-		for i in 0..4 {
-			let stopped = MerkleTrees::stopped(i);
-			assert!(stopped);
-		}
-	})
-}
+		// withdrawals of the same nullifier conflict, whatever else they carry
+		let first = validate(withdraw_call(nullifier.clone(), 1)).unwrap();
+		let second = validate(withdraw_call(nullifier.clone(), 2)).unwrap();
+		let other = validate(withdraw_call(Scalar::from(2u32).to_bytes().to_vec(), 1)).unwrap();
+		assert_eq!(first.provides.len(), 1);
+		assert_eq!(first.provides, second.provides);
+		assert_ne!(first.provides, other.provides);
 
-#[test]
-fn should_be_able_to_change_admin() {
-	new_test_ext().execute_with(|| {
-		let default_admin = 4;
-		assert_ok!(Mixer::initialize_first_stage());
-		assert_err!(Mixer::transfer_admin(Origin::signed(1), 2), BadOrigin);
-		assert_ok!(Mixer::transfer_admin(Origin::signed(default_admin), 2));
-		let admin = Mixer::admin();
+		// other calls are left alone
+		let transfer = validate(Call::Balances(pallet_balances::Call::transfer(2, 1))).unwrap();
+		assert!(transfer.provides.is_empty());
 
-		assert_eq!(admin, 2);
+		merkle::UsedNullifiers::<Test>::insert((tree_id, nullifier.clone()), true);
+		assert_eq!(validate(withdraw_call(nullifier, 1)), InvalidTransaction::Stale.into());
 	})
 }
 
 #[test]
-fn should_stop_and_start_mixer() {
+fn should_claim_deposit_reward_once_after_minimum_length() {
 	new_test_ext().execute_with(|| {
-		let default_admin = 4;
+		System::set_block_number(1);
 		assert_ok!(Mixer::initialize_first_stage());
 		assert_ok!(Mixer::initialize_second_stage());
-		let mut tree = FixedDepositTreeBuilder::new().build();
-		let leaf = tree.generate_secrets().to_bytes().to_vec();
-		assert_ok!(Mixer::deposit(Origin::signed(0), 0, vec![leaf.clone()]));
 
-		// Stopping deposits and withdrawal
-		assert_ok!(Mixer::set_stopped(Origin::signed(default_admin), true));
+		let tree_id = 0;
+		let key_id = 0;
+		let params = MerkleTrees::get_verifying_key(key_id).unwrap();
+		let bp_gens = merkle::utils::keys::from_bytes_to_bp_gens(&params);
+		let h = default_hasher(bp_gens);
+
+		let recipient: AccountId = 2;
+		let relayer: AccountId = 1;
+
+		let mut rng = ChaChaRng::from_seed([7u8; 32]);
+		let (r, nullifier) = (Scalar::random(&mut rng), Scalar::random(&mut rng));
+		let leaf = Poseidon_hash_2(r, nullifier, &h).to_bytes().to_vec();
+		assert_ok!(Mixer::deposit(
+			Origin::signed(0),
+			tree_id,
+			vec![leaf.clone()],
+			Vec::new()
+		));
+
+		// the root after the deposit is recorded
+		let root = MerkleTrees::get_merkle_root(tree_id).unwrap();
+		assert_eq!(Mixer::deposit_root(tree_id, 1), Some(root.clone()));
+
+		let setup = Setup::new(HashFunction::PoseidonDefault, Backend::Bulletproofs(Curve::Curve25519));
+		let mut local_tree = LocalTree::new(setup, MixerTreeDepth::get(), params).unwrap();
+		assert_eq!(local_tree.insert(leaf).unwrap(), root);
+		let claim = prove_reward(
+			r,
+			nullifier,
+			&local_tree.path(0).unwrap(),
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&root)),
+			input_scalar(&recipient.encode()),
+			input_scalar(&Mixer::reward_relayer_input(&relayer)),
+			&h,
+			&mut rng,
+		)
+		.unwrap();
+		// the claim doesn't reveal the nullifier hash spent by the withdrawal
+		let reward_nullifier = claim.reward_nullifier.to_bytes().to_vec();
+		assert_ne!(claim.reward_nullifier, Poseidon_hash_2(nullifier, nullifier, &h));
+
+		let to_bytes = |points: &[CompressedRistretto]| -> Vec<ScalarBytes> {
+			points.iter().map(|x| x.to_bytes().to_vec()).collect()
+		};
+		let claim_proof = |cached_block: u64, fee: Balance| {
+			WithdrawProof::<Test>::new(
+				tree_id,
+				cached_block,
+				root.clone(),
+				to_bytes(&claim.comms),
+				reward_nullifier.clone(),
+				claim.proof.to_bytes(),
+				to_bytes(&claim.leaf_is_left_commitments),
+				to_bytes(&claim.path_node_commitments),
+				Some(recipient),
+				Some(relayer),
+				fee,
+				0,
+			)
+		};
+
 		assert_err!(
-			Mixer::deposit(Origin::signed(0), 0, vec![]),
-			Error::<Test>::MixerStopped
+			Mixer::claim_reward(Origin::signed(relayer), claim_proof(1, 0)),
+			Error::<Test>::DepositTooRecent
 		);
+
+		let min_length = Mixer::get_mixer(tree_id).unwrap().minimum_deposit_length_for_reward;
+		System::set_block_number(1 + min_length);
 		assert_err!(
-			Mixer::withdraw(
-				Origin::signed(0),
-				WithdrawProof::new(
-					0,
-					0,
-					Scalar::zero().to_bytes().to_vec(),
-					Vec::new(),
-					Scalar::zero().to_bytes().to_vec(),
-					Vec::new(),
-					Vec::new(),
-					Vec::new(),
-					None,
-					None,
-				)
-			),
-			Error::<Test>::MixerStopped
+			Mixer::claim_reward(Origin::signed(relayer), claim_proof(1, 1)),
+			Error::<Test>::InvalidRewardClaim
+		);
+		assert_err!(
+			Mixer::claim_reward(Origin::signed(relayer), claim_proof(2, 0)),
+			Error::<Test>::UnknownDepositRoot
+		);
+		// a claim proof doesn't verify as a withdrawal
+		assert_err!(
+			Mixer::check_withdraw(relayer, claim_proof(1, 0)),
+			merkle::Error::<Test>::InvalidPrivateInputs
 		);
 
-		// Starting mixer
-		assert_ok!(Mixer::set_stopped(Origin::signed(default_admin), false));
-		assert_ok!(Mixer::deposit(Origin::signed(0), 0, vec![leaf]));
-	})
-}
+		// the claim isn't spent while the reward pot can't pay it
+		let pot = RewardPotAccount::get();
+		let pot_balance = Balances::free_balance(pot);
+		Balances::make_free_balance_be(&pot, 0);
+		assert_err!(
+			Mixer::claim_reward(Origin::signed(relayer), claim_proof(1, 0)),
+			pallet_balances::Error::<Test, _>::InsufficientBalance
+		);
+		Balances::make_free_balance_be(&pot, pot_balance);
 
-#[test]
-fn should_fail_to_deposit_with_insufficient_balance() {
-	new_test_ext().execute_with(|| {
-		assert_ok!(Mixer::initialize_first_stage());
-		assert_ok!(Mixer::initialize_second_stage());
-		let mut tree = FixedDepositTreeBuilder::new().build();
+		let balance_before = Balances::free_balance(recipient);
+		assert_ok!(Mixer::claim_reward(Origin::signed(relayer), claim_proof(1, 0)));
+		assert_eq!(Balances::free_balance(recipient), balance_before + DepositReward::get());
+		assert_eq!(Balances::free_balance(pot), pot_balance - DepositReward::get());
+		assert!(Mixer::reward_claimed(tree_id, &reward_nullifier));
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::RewardClaimed(
+			tree_id,
+			recipient,
+			reward_nullifier.clone(),
+		)));
 
-		for i in 0..4 {
-			let leaf = tree.generate_secrets().to_bytes().to_vec();
-			assert_err!(
-				Mixer::deposit(Origin::signed(4), i, vec![leaf]),
-				DispatchError::Module {
-					index: 3,
-					error: 4,
-					message: Some("InsufficientBalance")
-				}
-			);
-		}
+		// the deposit stays in the mixer, but is rewarded only once
+		assert_eq!(Mixer::total_value_locked(tree_id), 1_000);
+		assert_err!(
+			Mixer::claim_reward(Origin::signed(relayer), claim_proof(1, 0)),
+			Error::<Test>::RewardAlreadyClaimed
+		);
 	})
 }
 
 #[test]
-fn should_deposit_into_each_mixer_successfully() {
+fn should_prune_deposit_roots_once_a_newer_root_is_claimable() {
 	new_test_ext().execute_with(|| {
 		assert_ok!(Mixer::initialize_first_stage());
 		assert_ok!(Mixer::initialize_second_stage());
-		let mut tree = FixedDepositTreeBuilder::new().build();
+		let tree_id = 0;
+		let min_length = Mixer::get_mixer(tree_id).unwrap().minimum_deposit_length_for_reward;
+		let deposit_at = |block: u64| {
+			System::set_block_number(block);
+			assert_ok!(Mixer::deposit(
+				Origin::signed(0),
+				tree_id,
+				vec![Scalar::from(block).to_bytes().to_vec()],
+				Vec::new()
+			));
+		};
+		for block in 1..=4u64 {
+			deposit_at(block);
+		}
+		assert_eq!(Mixer::deposit_root_bounds(tree_id), Some((1, 4)));
 
-		for i in 0..4 {
-			let leaf = tree.generate_secrets().to_bytes().to_vec();
-			let balance_before = Balances::free_balance(1);
-			assert_ok!(Mixer::deposit(Origin::signed(1), i, vec![leaf]));
-			let balance_after = Balances::free_balance(1);
+		// the oldest root stays until the next one can be claimed against
+		deposit_at(1 + min_length);
+		assert!(Mixer::deposit_root(tree_id, 1).is_some());
+		assert_eq!(Mixer::deposit_root_bounds(tree_id), Some((1, 1 + min_length)));
 
-			// ensure state updates
-			let g = MerkleTrees::get_tree(i).unwrap();
-			let m = Mixer::get_mixer(i).unwrap();
-			let tvl = Mixer::total_value_locked(i);
-			assert_eq!(tvl, m.fixed_deposit_size);
-			assert_eq!(balance_before, balance_after + m.fixed_deposit_size);
-			assert_eq!(g.leaf_count, 1);
-		}
+		// at most two roots are pruned per deposit
+		deposit_at(100 + min_length);
+		assert_eq!(Mixer::deposit_root(tree_id, 1), None);
+		assert_eq!(Mixer::deposit_root(tree_id, 2), None);
+		assert!(Mixer::deposit_root(tree_id, 3).is_some());
+		assert_eq!(Mixer::deposit_root_bounds(tree_id), Some((3, 100 + min_length)));
+
+		// the latest claimable root is never pruned
+		deposit_at(101 + min_length);
+		assert_eq!(Mixer::deposit_root(tree_id, 3), None);
+		assert!(Mixer::deposit_root(tree_id, 4).is_some());
+		assert_eq!(Mixer::deposit_root_bounds(tree_id), Some((4, 101 + min_length)));
+
+		// blocks without deposits don't prune anything
+		Mixer::on_initialize(1_000 + min_length);
+		assert_eq!(Mixer::deposit_root_bounds(tree_id), Some((4, 101 + min_length)));
 	})
 }
 
 #[test]
-fn should_withdraw_from_each_mixer_successfully() {
+fn should_remix_deposit_into_same_denomination_mixer() {
 	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
 		assert_ok!(Mixer::initialize_first_stage());
 		assert_ok!(Mixer::initialize_second_stage());
-		let pc_gens = PedersenGens::default();
-
-		let tree_id = 0;
+		// a second native mixer with the size of mixer 0
+		let setup = Setup::new(HashFunction::PoseidonDefault, Backend::Bulletproofs(Curve::Curve25519));
+		assert_ok!(<Mixer as ExtendedMixer<Test>>::create_new(
+			Mixer::account_id(),
+			NativeCurrencyId::get(),
+			setup,
+			1_000
+		));
+		let (source_id, target_id) = (0u32, 4u32);
 		let key_id = 0;
+		assert_ok!(MerkleTrees::initialize_tree(
+			Origin::signed(Mixer::account_id()),
+			target_id,
+			key_id
+		));
+
 		let params = MerkleTrees::get_verifying_key(key_id).unwrap();
 		let bp_gens = merkle::utils::keys::from_bytes_to_bp_gens(&params);
 		let h = default_hasher(bp_gens);
+		let pc_gens = PedersenGens::default();
+		let relayer: AccountId = 1;
 
-		for i in 0..4 {
-			let tree_id = i;
-			let mut prover_transcript = Transcript::new(b"zk_membership_proof");
-			let prover = Prover::new(&pc_gens, &mut prover_transcript);
-			let mut ftree = FixedDepositTreeBuilder::new().hash_params(h.clone()).depth(32).build();
+		let mut ftree = FixedDepositTreeBuilder::new().hash_params(h.clone()).depth(32).build();
+		let leaf = ftree.generate_secrets().to_bytes();
+		ftree.tree.add_leaves(vec![leaf], None);
+		assert_ok!(Mixer::deposit(
+			Origin::signed(0),
+			source_id,
+			vec![leaf.to_vec()],
+			Vec::new()
+		));
 
-			let leaf = ftree.generate_secrets().to_bytes();
-			ftree.tree.add_leaves(vec![leaf], None);
+		// the new note the deposit is remixed into
+		let mut new_ftree = FixedDepositTreeBuilder::new().hash_params(h.clone()).depth(32).build();
+		let commitment = new_ftree.generate_secrets().to_bytes();
+		new_ftree.tree.add_leaves(vec![commitment], None);
 
-			assert_ok!(Mixer::deposit(Origin::signed(1), i, vec![leaf.to_vec()]));
+		let root = MerkleTrees::get_merkle_root(source_id).unwrap();
+		let remix_input = Mixer::remix_input(target_id, &commitment.to_vec());
+		let mut prover_transcript = Transcript::new(b"zk_membership_proof");
+		let prover = Prover::new(&pc_gens, &mut prover_transcript);
+		let (proof, (comms_cr, nullifier_hash, leaf_index_comms_cr, proof_comms_cr)) = ftree.prove_zk(
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&root)),
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&leaf)),
+			input_scalar(&remix_input),
+			input_scalar(&Mixer::remix_relayer_input(&relayer)),
+			&ftree.hash_params.bp_gens,
+			prover,
+		);
 
-			let root = MerkleTrees::get_merkle_root(i).unwrap();
-			let (proof, (comms_cr, nullifier_hash, leaf_index_comms_cr, proof_comms_cr)) = ftree.prove_zk(
-				Scalar::from_bytes_mod_order(slice_to_bytes_32(&root)),
-				Scalar::from_bytes_mod_order(slice_to_bytes_32(&leaf)),
-				Scalar::from(2u32),
-				Scalar::zero(),
-				&ftree.hash_params.bp_gens,
-				prover,
-			);
+		let comms: Vec<ScalarBytes> = comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let leaf_index_comms: Vec<ScalarBytes> = leaf_index_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let proof_comms: Vec<ScalarBytes> = proof_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		let remix_proof = |recipient: Option<AccountId>, fee: Balance| {
+			WithdrawProof::<Test>::new(
+				source_id,
+				1,
+				root.clone(),
+				comms.clone(),
+				nullifier_hash.to_bytes().to_vec(),
+				proof.to_bytes(),
+				leaf_index_comms.clone(),
+				proof_comms.clone(),
+				recipient,
+				Some(relayer),
+				fee,
+				0,
+			)
+		};
 
-			let comms: Vec<ScalarBytes> = comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
-			let leaf_index_comms: Vec<ScalarBytes> =
-				leaf_index_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
-			let proof_comms: Vec<ScalarBytes> = proof_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect();
+		assert_err!(
+			Mixer::remix(
+				Origin::signed(relayer),
+				remix_proof(Some(2), 0),
+				target_id,
+				commitment.to_vec()
+			),
+			Error::<Test>::InvalidRemix
+		);
+		assert_err!(
+			Mixer::remix(
+				Origin::signed(relayer),
+				remix_proof(None, 1),
+				target_id,
+				commitment.to_vec()
+			),
+			Error::<Test>::InvalidRemix
+		);
+		assert_err!(
+			Mixer::remix(Origin::signed(relayer), remix_proof(None, 0), 1, commitment.to_vec()),
+			Error::<Test>::RemixDenominationMismatch
+		);
+		assert_ok!(Mixer::set_withdraw_only(RawOrigin::Root.into(), target_id, true));
+		assert_err!(
+			Mixer::remix(
+				Origin::signed(relayer),
+				remix_proof(None, 0),
+				target_id,
+				commitment.to_vec()
+			),
+			Error::<Test>::MixerWithdrawOnly
+		);
+		assert_ok!(Mixer::set_withdraw_only(RawOrigin::Root.into(), target_id, false));
+		// the proof is bound to the commitment, so it can't be redirected
+		assert_err!(
+			Mixer::remix(Origin::signed(relayer), remix_proof(None, 0), target_id, leaf.to_vec()),
+			merkle::Error::<Test>::ZkVerificationFailed
+		);
+		// the proof can't be replayed as a withdrawal, not even to an account
+		// encoding to the remix input, since the relayer input is tagged
+		assert_err!(
+			Mixer::withdraw(Origin::signed(relayer), remix_proof(None, 0)),
+			merkle::Error::<Test>::ZkVerificationFailed
+		);
+		assert_err!(
+			<MerkleTrees as TreeTrait<Test>>::verify_zk(
+				source_id,
+				1,
+				root.clone(),
+				comms.clone(),
+				nullifier_hash.to_bytes().to_vec(),
+				proof.to_bytes(),
+				leaf_index_comms.clone(),
+				proof_comms.clone(),
+				public_input_to_field(&remix_input),
+				public_input_to_field(&Mixer::relayer_input(&relayer, 0, 0)),
+			),
+			merkle::Error::<Test>::ZkVerificationFailed
+		);
 
-			let m = Mixer::get_mixer(i).unwrap();
-			let balance_before = Balances::free_balance(2);
-			// check TVL after depositing
-			let tvl = Mixer::total_value_locked(i);
-			assert_eq!(tvl, m.fixed_deposit_size);
-			// withdraw from another account
-			assert_ok!(Mixer::withdraw(
-				Origin::signed(2),
-				WithdrawProof::new(
-					i,
-					0,
-					root,
-					comms,
-					nullifier_hash.to_bytes().to_vec(),
-					proof.to_bytes(),
-					leaf_index_comms,
-					proof_comms,
-					Some(2),
-					Some(0),
-				)
-			));
-			let balance_after = Balances::free_balance(2);
-			assert_eq!(balance_before + m.fixed_deposit_size, balance_after);
-			// ensure TVL is 0 after withdrawing
-			let tvl = Mixer::total_value_locked(i);
-			assert_eq!(tvl, 0);
-		}
+		let pallet_balance = Balances::free_balance(Mixer::account_id());
+		let relayer_balance = Balances::free_balance(relayer);
+		assert_ok!(Mixer::remix(
+			Origin::signed(relayer),
+			remix_proof(None, 0),
+			target_id,
+			commitment.to_vec()
+		));
+		System::assert_last_event(crate::mock::Event::Mixer(crate::Event::Remix(
+			source_id,
+			target_id,
+			relayer,
+			root.clone(),
+		)));
+		// no funds moved, the deposit is now locked in the target mixer
+		assert_eq!(Balances::free_balance(Mixer::account_id()), pallet_balance);
+		assert_eq!(Balances::free_balance(relayer), relayer_balance);
+		assert_eq!(Mixer::total_value_locked(source_id), 0);
+		assert_eq!(Mixer::total_value_locked(target_id), 1_000);
+		assert_eq!(Mixer::withdrawal_count(source_id), 1);
+		assert_eq!(Mixer::deposit_count(target_id), 1);
+		assert_err!(
+			Mixer::remix(
+				Origin::signed(relayer),
+				remix_proof(None, 0),
+				target_id,
+				commitment.to_vec()
+			),
+			merkle::Error::<Test>::AlreadyUsedNullifier
+		);
+
+		// the new note withdraws from the target mixer
+		let recipient: AccountId = 2;
+		let root = MerkleTrees::get_merkle_root(target_id).unwrap();
+		let mut prover_transcript = Transcript::new(b"zk_membership_proof");
+		let prover = Prover::new(&pc_gens, &mut prover_transcript);
+		let (proof, (comms_cr, nullifier_hash, leaf_index_comms_cr, proof_comms_cr)) = new_ftree.prove_zk(
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&root)),
+			Scalar::from_bytes_mod_order(slice_to_bytes_32(&commitment)),
+			input_scalar(&recipient.encode()),
+			input_scalar(&recipient.encode()),
+			&new_ftree.hash_params.bp_gens,
+			prover,
+		);
+		let balance_before = Balances::free_balance(recipient);
+		assert_ok!(Mixer::withdraw(
+			Origin::signed(recipient),
+			WithdrawProof::new(
+				target_id,
+				1,
+				root,
+				comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect(),
+				nullifier_hash.to_bytes().to_vec(),
+				proof.to_bytes(),
+				leaf_index_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect(),
+				proof_comms_cr.iter().map(|x| x.to_bytes().to_vec()).collect(),
+				None,
+				None,
+				0,
+				0,
+			)
+		));
+		assert_eq!(Balances::free_balance(recipient), balance_before + 1_000);
+		assert_eq!(Mixer::total_value_locked(target_id), 0);
 	})
 }
 
@@ -276,7 +1944,7 @@ fn should_cache_roots_if_no_new_deposits_show() {
 		let mut merkle_roots: Vec<ScalarBytes> = vec![];
 		for i in 0..4 {
 			let leaf = tree.generate_secrets().to_bytes().to_vec();
-			assert_ok!(Mixer::deposit(Origin::signed(1), i, vec![leaf]));
+			assert_ok!(Mixer::deposit(Origin::signed(1), i, vec![leaf], Vec::new()));
 			let root = MerkleTrees::get_merkle_root(i).unwrap();
 			merkle_roots.push(root);
 			let cache = MerkleTrees::cached_roots(1, i);
@@ -311,7 +1979,7 @@ fn should_not_have_cache_once_cache_length_exceeded() {
 		let mut merkle_roots: Vec<ScalarBytes> = vec![];
 		for i in 0..4 {
 			let leaf = tree.generate_secrets().to_bytes().to_vec();
-			assert_ok!(Mixer::deposit(Origin::signed(1), i, vec![leaf]));
+			assert_ok!(Mixer::deposit(Origin::signed(1), i, vec![leaf], Vec::new()));
 			let root = MerkleTrees::get_merkle_root(i).unwrap();
 			merkle_roots.push(root);
 			let cache = MerkleTrees::cached_roots(1, i);
@@ -392,7 +2060,12 @@ fn should_make_mixer_with_non_native_token() {
 
 		// Getting native balance before deposit
 		let native_balance_before = Balances::free_balance(&sender);
-		assert_ok!(Mixer::deposit(Origin::signed(sender), tree_id, vec![leaf.to_vec()]));
+		assert_ok!(Mixer::deposit(
+			Origin::signed(sender),
+			tree_id,
+			vec![leaf.to_vec()],
+			Vec::new()
+		));
 		// Native balance after deposit, to make sure its not touched
 		let native_balance_after = Balances::free_balance(&sender);
 		assert_eq!(native_balance_before, native_balance_after);
@@ -401,8 +2074,8 @@ fn should_make_mixer_with_non_native_token() {
 		let (proof, (comms_cr, nullifier_hash, leaf_index_comms_cr, proof_comms_cr)) = ftree.prove_zk(
 			Scalar::from_bytes_mod_order(slice_to_bytes_32(&root)),
 			Scalar::from_bytes_mod_order(slice_to_bytes_32(&leaf)),
-			Scalar::from(recipient),
-			Scalar::zero(),
+			input_scalar(&recipient.encode()),
+			input_scalar(&0u64.encode()),
 			&ftree.hash_params.bp_gens,
 			prover,
 		);
@@ -431,6 +2104,8 @@ fn should_make_mixer_with_non_native_token() {
 				proof_comms,
 				Some(recipient),
 				Some(0),
+				0,
+				0,
 			)
 		));
 		let balance_after = Tokens::free_balance(currency_id, &recipient);
@@ -445,20 +2120,22 @@ fn should_make_mixer_with_non_native_token() {
 }
 
 #[test]
-fn should_initialize_in_two_steps_on_finalize() {
+fn should_finish_staged_initialization_with_migration() {
 	new_test_ext().execute_with(|| {
 		System::set_block_number(1);
+		// Nothing is initialized in the hooks anymore
 		<Mixer as OnFinalize<u64>>::on_finalize(1);
-		assert!(Mixer::first_stage_initialized());
+		assert!(!Mixer::first_stage_initialized());
+		assert_eq!(Mixer::mixer_group_ids(), Vec::<u32>::new());
 
+		assert_ok!(Mixer::initialize_first_stage());
 		for i in 0..4 {
 			let tree_id = i;
 			let tree = MerkleTrees::get_tree(tree_id).unwrap();
 			assert!(!tree.initialized);
 		}
 
-		System::set_block_number(2);
-		<Mixer as OnFinalize<u64>>::on_finalize(2);
+		migrations::initialize_staged::<Test>();
 		assert!(Mixer::second_stage_initialized());
 
 		for i in 0..4 {
@@ -468,3 +2145,21 @@ fn should_initialize_in_two_steps_on_finalize() {
 		}
 	})
 }
+
+#[test]
+fn should_initialize_both_stages_with_migration() {
+	new_test_ext().execute_with(|| {
+		migrations::initialize_staged::<Test>();
+		assert!(Mixer::first_stage_initialized());
+		assert!(Mixer::second_stage_initialized());
+		let val = 1_000;
+		for i in 0..4 {
+			let g = MerkleTrees::get_tree(i).unwrap();
+			let mng = MerkleTrees::get_manager(i).unwrap();
+			let m = Mixer::get_mixer(i).unwrap();
+			assert!(g.initialized);
+			assert_eq!(mng.required, true);
+			assert_eq!(m.fixed_deposit_size, val * 10_u64.pow(i))
+		}
+	})
+}
@@ -4,6 +4,11 @@
 //! DATE: 2021-02-17, STEPS: [20, ], REPEAT: 5, LOW RANGE: [], HIGH RANGE: []
 //! EXECUTION: None, WASM-EXECUTION: Interpreted, CHAIN: Some("dev"), DB CACHE:
 //! 128
+//!
+//! The weights of the calls and hooks added since this run, and the database
+//! reads and writes added to `deposit` and `withdraw`, were counted by hand
+//! and their times are estimates. Regenerate this file with the command below
+//! before relying on them.
 
 // Executed Command:
 // ./target/release/node-template
@@ -35,10 +40,17 @@ use sp_std::marker::PhantomData;
 pub trait WeightInfo {
 	fn deposit(n: u32) -> Weight;
 	fn withdraw() -> Weight;
+	fn claim_reward() -> Weight;
 	fn set_stopped() -> Weight;
-	fn transfer_admin() -> Weight;
 	fn on_finalize_uninitialized() -> Weight;
 	fn on_finalize_initialized() -> Weight;
+	fn pay_pending_withdrawals(n: u32) -> Weight;
+	fn on_initialize(n: u32) -> Weight;
+	fn set_withdraw_only() -> Weight;
+	fn set_max_fee() -> Weight;
+	fn set_protocol_fee() -> Weight;
+	fn set_limits() -> Weight;
+	fn set_all_withdraw_only() -> Weight;
 }
 
 /// Weight functions for pallet_mixer.
@@ -49,13 +61,21 @@ impl<T: frame_system::Config + Config + merkle::Config> WeightInfo for Weights<T
 		(417_168_400_000 as Weight)
 			// Standard Error: 241_824_000
 			.saturating_add((21_400_442_000 as Weight).saturating_mul(d as Weight))
-			.saturating_add(T::DbWeight::get().reads(8 as Weight))
-			.saturating_add(T::DbWeight::get().writes(5 as Weight))
+			.saturating_add(T::DbWeight::get().reads(16 as Weight))
+			.saturating_add(T::DbWeight::get().writes(17 as Weight))
 	}
 
 	fn withdraw() -> Weight {
 		(1_078_562_000_000 as Weight)
-			.saturating_add(T::DbWeight::get().reads(9 as Weight))
+			.saturating_add(T::DbWeight::get().reads(13 as Weight))
+			// Blocks searched for room for a delayed payout
+			.saturating_add(T::DbWeight::get().reads(T::MaxPayoutPostponement::get() as Weight))
+			.saturating_add(T::DbWeight::get().writes(9 as Weight))
+	}
+
+	fn claim_reward() -> Weight {
+		(1_078_562_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(10 as Weight))
 			.saturating_add(T::DbWeight::get().writes(3 as Weight))
 	}
 
@@ -65,12 +85,6 @@ impl<T: frame_system::Config + Config + merkle::Config> WeightInfo for Weights<T
 			.saturating_add(T::DbWeight::get().writes(4 as Weight))
 	}
 
-	fn transfer_admin() -> Weight {
-		(7_000_000 as Weight)
-			.saturating_add(T::DbWeight::get().reads(1 as Weight))
-			.saturating_add(T::DbWeight::get().writes(1 as Weight))
-	}
-
 	fn on_finalize_uninitialized() -> Weight {
 		(53_000_000 as Weight)
 			.saturating_add(T::DbWeight::get().reads(2 as Weight))
@@ -82,4 +96,53 @@ impl<T: frame_system::Config + Config + merkle::Config> WeightInfo for Weights<T
 			.saturating_add(T::DbWeight::get().reads(10 as Weight))
 			.saturating_add(T::DbWeight::get().writes(4 as Weight))
 	}
+
+	fn pay_pending_withdrawals(n: u32) -> Weight {
+		(4_000_000 as Weight)
+			// Standard Error: 1_100_000
+			.saturating_add((58_000_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().reads((4 as Weight).saturating_mul(n as Weight)))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes((5 as Weight).saturating_mul(n as Weight)))
+	}
+
+	fn on_initialize(n: u32) -> Weight {
+		(6_000_000 as Weight)
+			.saturating_add((58_000_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(T::DbWeight::get().reads(2 as Weight))
+			.saturating_add(T::DbWeight::get().reads((4 as Weight).saturating_mul(n as Weight)))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes((5 as Weight).saturating_mul(n as Weight)))
+	}
+
+	fn set_withdraw_only() -> Weight {
+		(16_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+
+	fn set_max_fee() -> Weight {
+		(17_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+
+	fn set_limits() -> Weight {
+		(18_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+
+	fn set_all_withdraw_only() -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(4 as Weight))
+	}
+
+	fn set_protocol_fee() -> Weight {
+		(18_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
 }
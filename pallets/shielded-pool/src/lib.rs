@@ -0,0 +1,531 @@
+//! # Shielded Pool Pallet
+//!
+//! The Shielded Pool pallet holds deposits of any amount as commitments in a
+//! merkle tree, in the style of a UTXO set.
+//!
+//! - [`Config`]
+//! - [`Call`]
+//! - [`Pallet`]
+//!
+//! ## Overview
+//!
+//! Unlike the mixers, where each pool only accepts a fixed deposit size, the
+//! commitments of a shielded pool bind an amount. A note is spent by revealing
+//! its nullifier, and every transaction spends two notes and creates two new
+//! ones, so the amounts never show up on chain except when entering or leaving
+//! the pool.
+//!
+//! The pallet provides functions for:
+//!
+//! - Depositing any amount into a pool.
+//! - Withdrawing any amount from a pool, with the change kept in the pool as a
+//!   new note.
+//! - Transferring notes privately inside a pool.
+//!
+//! A pool holds either a single currency or any currency of the
+//! `MultiCurrency`. The commitments of a multi-asset pool bind the currency id
+//! along with the amount, so deposits of every token share one tree and one
+//! anonymity set. Only deposits and withdrawals name their currency, transfers
+//! inside the pool don't reveal it.
+//!
+//! ### Terminology
+//!
+//! - **Note**: An amount held in the pool, known only to its owner. Its
+//!   commitment is a leaf of the pool's tree.
+//! - **Nullifier**: A value derived from a note, revealed when the note is
+//!   spent so it can't be spent twice.
+//!
+//! ## Interface
+//!
+//! ### Dispatchable Functions
+//!
+//! - `create_pool` - Creates a pool for a currency, or a multi-asset pool, with
+//!   the verifying key of its transaction circuit. Restricted to the
+//!   `AdminOrigin`.
+//! - `transact` - Spends two notes, creates two notes and moves the public
+//!   deposit and withdrawal amounts in and out of the pool.
+//!
+//! Deposits, withdrawals and transfers are all made through `transact`:
+//!
+//! - A deposit spends two notes of zero amount and creates a note of the
+//!   deposit amount, with `deposit_amount` set.
+//! - A withdrawal spends notes worth at least `withdraw_amount` and creates a
+//!   note holding the change.
+//! - A transfer moves amounts between notes without deposit or withdrawal.
+//!
+//! Notes of zero amount don't have to be in the tree, their nullifiers just
+//! have to be unused.
+//!
+//! ### Transaction circuit
+//!
+//! Transactions are verified by the merkle pallet's `verify_zk`, against the
+//! Groth16 verifying key the pool was created with, so the pool's setup must
+//! be an arkworks backend with the default Poseidon hasher. The key must be
+//! generated for the `TransactionCircuit` of `merkle::utils::transaction` with
+//! the depth of the pool trees, which also derives the notes: a commitment is
+//! the hash of the amount, the currency, a secret and a nullifier, see
+//! [`Pallet::note_commitment`]. Its public inputs, in order, are:
+//!
+//! 1. The nullifier hash of the first input note.
+//! 2. The root of the tree the input notes are proven against.
+//! 3. The recipient and 4. the relayer, their encodings mapped with
+//!    `public_input_to_field` as for the mixers.
+//! 5. The currency id of the transaction, or zero for a transfer, see
+//!    [`Pallet::asset_input`].
+//! 6. `deposit_amount` and 7. `withdraw_amount`, as little-endian integers.
+//! 8. The hash of the pool id and the external data, see
+//!    [`Pallet::ext_data_hash`].
+//! 9. The nullifier hash of the second input note.
+//! 10. The commitments of the two output notes.
+//!
+//! The circuit checks that the input notes are in the tree, unless their
+//! amount is zero, that the nullifier hashes and commitments match the notes,
+//! that all four notes are of the same currency and that the input amounts
+//! plus `deposit_amount` equal the output amounts plus `withdraw_amount`. If
+//! `deposit_amount` or `withdraw_amount` is non-zero, the notes' currency must
+//! be the currency id input, so a note deposited in one currency can't be
+//! withdrawn as another. Transfers set that input to zero and the currency
+//! stays private. The external data binds the fee into the proof.
+//! `create_pool` only accepts keys with this number of public inputs.
+//!
+//! Proofs are made against the current root of the pool, or a root the merkle
+//! pallet still holds in its root cache for the block it was recorded at.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(test)]
+pub mod mock;
+
+#[cfg(test)]
+pub mod tests;
+
+pub mod weights;
+
+use codec::{Decode, Encode};
+use frame_support::{
+	dispatch, ensure, traits::Get, CloneNoBound, EqNoBound, PalletId, PartialEqNoBound, RuntimeDebugNoBound,
+};
+use frame_system::ensure_signed;
+use merkle::{
+	utils::{
+		keys::{public_input_to_field, ScalarBytes},
+		setup::Setup,
+	},
+	Pallet as MerklePallet, Tree as TreeTrait,
+};
+use sp_runtime::traits::{AccountIdConversion, SaturatedConversion, Saturating, Zero};
+use sp_std::prelude::*;
+use webb_traits::MultiCurrency;
+use weights::WeightInfo;
+
+pub use pallet::*;
+
+pub use merkle::utils::transaction::{NOTES_PER_TRANSACTION, TRANSACTION_PUBLIC_INPUTS};
+
+/// Implementation of Shielded Pool pallet
+#[frame_support::pallet]
+pub mod pallet {
+	use super::*;
+	use frame_support::pallet_prelude::*;
+	use frame_system::pallet_prelude::*;
+
+	/// The pallet's configuration trait.
+	#[pallet::config]
+	pub trait Config: frame_system::Config + merkle::Config {
+		#[pallet::constant]
+		type PalletId: Get<PalletId>;
+		/// The overarching event type.
+		type Event: IsType<<Self as frame_system::Config>::Event> + From<Event<Self>>;
+		/// Currency type for taking deposits
+		type Currency: MultiCurrency<Self::AccountId>;
+		/// The overarching merkle tree trait
+		type Tree: TreeTrait<Self>;
+		/// Origin that can create the pools
+		type AdminOrigin: EnsureOrigin<Self::Origin>;
+		/// Setup of the pool trees, must be an arkworks Groth16 backend with
+		/// the default Poseidon hasher
+		#[pallet::constant]
+		type Setup: Get<Setup>;
+		/// Depth of the pool trees, at most the merkle pallet's `MaxTreeDepth`
+		#[pallet::constant]
+		type TreeDepth: Get<u8>;
+		/// Weight information for extrinsics in this pallet
+		type WeightInfo: WeightInfo;
+	}
+
+	/// The map of pool trees to their metadata
+	#[pallet::storage]
+	#[pallet::getter(fn pools)]
+	pub type Pools<T: Config> = StorageMap<_, Blake2_128Concat, T::TreeId, PoolInfo<T>, OptionQuery>;
+
+	/// The vector of pool ids
+	#[pallet::storage]
+	#[pallet::getter(fn pool_ids)]
+	pub type PoolIds<T: Config> = StorageValue<_, Vec<T::TreeId>, ValueQuery>;
+
+	/// The TVL per pool and currency
+	#[pallet::storage]
+	#[pallet::getter(fn total_value_locked)]
+	pub type TotalValueLocked<T: Config> =
+		StorageDoubleMap<_, Blake2_128Concat, T::TreeId, Blake2_128Concat, CurrencyIdOf<T>, BalanceOf<T>, ValueQuery>;
+
+	#[pallet::event]
+	#[pallet::generate_deposit(pub(super) fn deposit_event)]
+	#[pallet::metadata(
+		T::AccountId = "AccountId",
+		T::TreeId = "TreeId",
+		BalanceOf<T> = "Balance",
+		CurrencyIdOf<T> = "CurrencyId"
+	)]
+	pub enum Event<T: Config> {
+		/// New pool created
+		PoolCreated(
+			/// Id of the tree
+			T::TreeId,
+			/// Id of the currency in the pool, none for multi-asset pools
+			Option<CurrencyIdOf<T>>,
+		),
+		/// Transaction in the specific pool
+		Transaction(
+			/// Id of the tree
+			T::TreeId,
+			/// Account id of the sender
+			T::AccountId,
+			/// Id of the currency, none for transfers inside the pool
+			Option<CurrencyIdOf<T>>,
+			/// Amount deposited into the pool
+			BalanceOf<T>,
+			/// Amount withdrawn from the pool, including the fee
+			BalanceOf<T>,
+			/// Fee paid to the relayer
+			BalanceOf<T>,
+		),
+	}
+
+	#[pallet::error]
+	pub enum Error<T> {
+		/// Pool not found for specified id
+		NoPoolForId,
+		/// Transactions spend and create exactly two notes
+		InvalidNoteCount,
+		/// Both input notes have the same nullifier
+		DuplicateNullifier,
+		/// Relayer fee is above the withdrawn amount
+		FeeExceedsWithdrawal,
+		/// Withdrawn amount is above the value locked in the pool
+		InsufficientPoolBalance,
+		/// User doesn't have enough balance for the deposit
+		InsufficientBalance,
+		/// Currency isn't accepted by the pool
+		CurrencyNotInPool,
+		/// Currency id doesn't fit in a field element
+		InvalidCurrencyId,
+		/// Currency id is missing from a deposit or withdrawal, or given for a
+		/// transfer
+		InvalidPublicCurrency,
+		/// Verifying key isn't for a circuit with the transaction's public
+		/// inputs
+		InvalidVerifyingKey,
+	}
+
+	#[pallet::pallet]
+	#[pallet::generate_store(pub(super) trait Store)]
+	pub struct Pallet<T>(PhantomData<T>);
+
+	#[pallet::hooks]
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {}
+
+	#[pallet::call]
+	impl<T: Config> Pallet<T> {
+		/// Creates a pool for `currency_id`, or a multi-asset pool if it's
+		/// none, whose transactions are verified with `verifying_key`. Can only
+		/// be called by the `AdminOrigin`.
+		///
+		/// The key must be generated for the transaction circuit of
+		/// `merkle::utils::transaction` with the depth of the pool trees. Fails
+		/// if the key isn't a key of the pool's setup with the public inputs
+		/// of the transaction circuit.
+		#[pallet::weight(<T as Config>::WeightInfo::create_pool())]
+		pub fn create_pool(
+			origin: OriginFor<T>,
+			currency_id: Option<CurrencyIdOf<T>>,
+			verifying_key: Vec<u8>,
+		) -> DispatchResultWithPostInfo {
+			T::AdminOrigin::ensure_origin(origin)?;
+			let input_count = T::Setup::get()
+				.public_input_count(&verifying_key)
+				.map_err(|_| Error::<T>::InvalidVerifyingKey)?;
+			ensure!(
+				input_count == TRANSACTION_PUBLIC_INPUTS,
+				Error::<T>::InvalidVerifyingKey
+			);
+
+			let pool_id = T::Tree::create_tree(Self::account_id(), true, T::Setup::get(), T::TreeDepth::get())?;
+			let key_id = T::Tree::add_verifying_key(verifying_key)?;
+			T::Tree::initialize_tree(pool_id, key_id)?;
+			Pools::<T>::insert(pool_id, PoolInfo { currency_id });
+			PoolIds::<T>::append(pool_id);
+
+			Self::deposit_event(Event::PoolCreated(pool_id, currency_id));
+			Ok(().into())
+		}
+
+		/// Spends the input notes of the transaction and adds its output
+		/// notes to the pool. The deposit amount is taken from the sender, and
+		/// the withdraw amount, minus the fee, is paid to the recipient.
+		///
+		/// Fails if the transaction doesn't spend and create two notes, a
+		/// nullifier is repeated or already used, it names a currency without
+		/// depositing or withdrawing or the other way around, the root is
+		/// unknown or the proof doesn't verify.
+		#[pallet::weight(<T as Config>::WeightInfo::transact())]
+		pub fn transact(origin: OriginFor<T>, transaction: Transaction<T>) -> DispatchResultWithPostInfo {
+			let sender = ensure_signed(origin)?;
+			Self::validate_transaction(&transaction)?;
+			let ext_data = &transaction.ext_data;
+
+			// transfers inside the pool don't move any funds
+			if let Some(currency_id) = transaction.currency_id {
+				if !ext_data.deposit_amount.is_zero() {
+					let balance = T::Currency::free_balance(currency_id, &sender);
+					ensure!(balance >= ext_data.deposit_amount, Error::<T>::InsufficientBalance);
+					T::Currency::transfer(currency_id, &sender, &Self::account_id(), ext_data.deposit_amount)?;
+				}
+				if !ext_data.withdraw_amount.is_zero() {
+					T::Currency::transfer(
+						currency_id,
+						&Self::account_id(),
+						&ext_data.recipient,
+						ext_data.withdraw_amount.saturating_sub(ext_data.fee),
+					)?;
+				}
+				if !ext_data.fee.is_zero() {
+					T::Currency::transfer(currency_id, &Self::account_id(), &ext_data.relayer, ext_data.fee)?;
+				}
+				TotalValueLocked::<T>::mutate(transaction.pool_id, currency_id, |tvl| {
+					*tvl = tvl
+						.saturating_add(ext_data.deposit_amount)
+						.saturating_sub(ext_data.withdraw_amount)
+				});
+			}
+
+			for nullifier in transaction.input_nullifiers.iter() {
+				T::Tree::add_nullifier(Self::account_id(), transaction.pool_id, nullifier.clone())?;
+			}
+			T::Tree::add_members(
+				Self::account_id(),
+				transaction.pool_id,
+				transaction.output_commitments.clone(),
+			)?;
+
+			Self::deposit_event(Event::Transaction(
+				transaction.pool_id,
+				sender,
+				transaction.currency_id,
+				ext_data.deposit_amount,
+				ext_data.withdraw_amount,
+				ext_data.fee,
+			));
+			Ok(().into())
+		}
+	}
+}
+
+/// Info about the pool
+#[derive(Encode, Decode, CloneNoBound, PartialEqNoBound, EqNoBound, RuntimeDebugNoBound)]
+pub struct PoolInfo<T: Config> {
+	/// Id of the currency in the pool, none if the pool accepts any
+	/// currency
+	pub currency_id: Option<CurrencyIdOf<T>>,
+}
+
+impl<T: Config> PoolInfo<T> {
+	/// Whether the pool accepts the currency with id of `currency_id`
+	pub fn accepts(&self, currency_id: CurrencyIdOf<T>) -> bool {
+		self.currency_id.map_or(true, |id| id == currency_id)
+	}
+}
+
+/// Public data of a transaction that isn't a field element, bound into the
+/// proof through its hash
+#[derive(Encode, Decode, CloneNoBound, PartialEqNoBound, EqNoBound, RuntimeDebugNoBound)]
+pub struct ExtData<T: Config> {
+	/// The account the withdrawn amount is paid to
+	pub recipient: T::AccountId,
+	/// The relayer submitting the transaction on behalf of the recipient
+	pub relayer: T::AccountId,
+	/// The amount taken from the sender into the pool
+	pub deposit_amount: BalanceOf<T>,
+	/// The amount leaving the pool, including the fee
+	pub withdraw_amount: BalanceOf<T>,
+	/// The fee paid to the relayer out of the withdrawn amount
+	pub fee: BalanceOf<T>,
+}
+
+/// A transaction spending two notes of a pool and creating two new ones
+#[derive(Encode, Decode, CloneNoBound, PartialEqNoBound, EqNoBound, RuntimeDebugNoBound)]
+pub struct Transaction<T: Config> {
+	/// The pool this transaction corresponds to
+	pub pool_id: T::TreeId,
+	/// The currency of the notes and of the public amounts, none for
+	/// transfers inside the pool so they don't reveal it
+	pub currency_id: Option<CurrencyIdOf<T>>,
+	/// The cached block for the cached root being proven against, unused
+	/// when proving against the current root
+	pub cached_block: T::BlockNumber,
+	/// The root being proven against
+	pub cached_root: ScalarBytes,
+	/// The nullifiers of the spent notes
+	pub input_nullifiers: Vec<ScalarBytes>,
+	/// The commitments of the created notes
+	pub output_commitments: Vec<ScalarBytes>,
+	/// The public data bound into the proof
+	pub ext_data: ExtData<T>,
+	/// The proof in bytes representation
+	pub proof_bytes: Vec<u8>,
+}
+
+/// Type alias for the webb_traits::MultiCurrency::Balance type
+pub type BalanceOf<T> = <<T as Config>::Currency as MultiCurrency<<T as frame_system::Config>::AccountId>>::Balance;
+/// Type alias for the webb_traits::MultiCurrency::CurrencyId type
+pub type CurrencyIdOf<T> =
+	<<T as pallet::Config>::Currency as MultiCurrency<<T as frame_system::Config>::AccountId>>::CurrencyId;
+
+impl<T: Config> Pallet<T> {
+	pub fn account_id() -> T::AccountId {
+		T::PalletId::get().into_account()
+	}
+
+	/// Encodes an amount as the bytes of a field element, little-endian
+	pub fn amount_input(amount: BalanceOf<T>) -> ScalarBytes {
+		let mut bytes = amount.saturated_into::<u128>().to_le_bytes().to_vec();
+		bytes.resize(32, 0);
+		bytes
+	}
+
+	/// Commitment and nullifier hash of the note of `amount` in
+	/// `currency_id` made of `secret` and `nullifier`, as added to and spent
+	/// from the pools
+	pub fn note_commitment(
+		amount: BalanceOf<T>,
+		currency_id: CurrencyIdOf<T>,
+		secret: &ScalarBytes,
+		nullifier: &ScalarBytes,
+	) -> Result<(ScalarBytes, ScalarBytes), dispatch::DispatchError> {
+		let (commitment, nullifier_hash) = T::Setup::get()
+			.note_commitment(
+				&Self::amount_input(amount),
+				&Self::asset_input(Some(currency_id))?,
+				secret,
+				nullifier,
+			)
+			.map_err(merkle::Error::<T>::from)?;
+		Ok((commitment, nullifier_hash))
+	}
+
+	/// Encodes the currency id of a transaction as the bytes of a field
+	/// element. It's the SCALE encoding of the id, zero-padded, i.e. the
+	/// little-endian integer for integer ids, and zero for transfers.
+	pub fn asset_input(currency_id: Option<CurrencyIdOf<T>>) -> Result<ScalarBytes, dispatch::DispatchError> {
+		let mut bytes = currency_id.map(|id| id.encode()).unwrap_or_default();
+		ensure!(bytes.len() < 32, Error::<T>::InvalidCurrencyId);
+		bytes.resize(32, 0);
+		Ok(bytes)
+	}
+
+	/// Hash of the external data of a transaction in the pool with id of
+	/// `pool_id`, a public input of its proof. It's the blake2 hash of the
+	/// encoded pool id and data with the last byte cleared, so it's below the
+	/// modulus of the supported curves.
+	pub fn ext_data_hash(pool_id: T::TreeId, ext_data: &ExtData<T>) -> ScalarBytes {
+		let mut hash = sp_io::hashing::blake2_256(&(pool_id, ext_data).encode());
+		hash[31] = 0;
+		hash.to_vec()
+	}
+
+	/// The public inputs of the transaction proof that follow the mixer
+	/// circuit's nullifier, root, recipient and relayer
+	pub fn public_inputs(transaction: &Transaction<T>) -> Result<Vec<ScalarBytes>, dispatch::DispatchError> {
+		let mut inputs = vec![
+			Self::asset_input(transaction.currency_id)?,
+			Self::amount_input(transaction.ext_data.deposit_amount),
+			Self::amount_input(transaction.ext_data.withdraw_amount),
+			Self::ext_data_hash(transaction.pool_id, &transaction.ext_data),
+		];
+		inputs.extend(transaction.input_nullifiers.iter().skip(1).cloned());
+		inputs.extend(transaction.output_commitments.iter().cloned());
+		Ok(inputs)
+	}
+
+	/// Runs every check on the transaction that doesn't change state.
+	pub fn validate_transaction(transaction: &Transaction<T>) -> Result<(), dispatch::DispatchError> {
+		let pool = Self::pools(transaction.pool_id).ok_or(Error::<T>::NoPoolForId)?;
+		let ext_data = &transaction.ext_data;
+		// the currency is only public when funds enter or leave the pool
+		let moves_funds = !ext_data.deposit_amount.is_zero() || !ext_data.withdraw_amount.is_zero();
+		ensure!(
+			transaction.currency_id.is_some() == moves_funds,
+			Error::<T>::InvalidPublicCurrency
+		);
+		if let Some(currency_id) = transaction.currency_id {
+			ensure!(pool.accepts(currency_id), Error::<T>::CurrencyNotInPool);
+		}
+		ensure!(
+			transaction.input_nullifiers.len() == NOTES_PER_TRANSACTION
+				&& transaction.output_commitments.len() == NOTES_PER_TRANSACTION,
+			Error::<T>::InvalidNoteCount
+		);
+		ensure!(
+			transaction.input_nullifiers[0] != transaction.input_nullifiers[1],
+			Error::<T>::DuplicateNullifier
+		);
+		for nullifier in transaction.input_nullifiers.iter() {
+			T::Tree::has_used_nullifier(transaction.pool_id, nullifier.clone())?;
+		}
+
+		ensure!(
+			ext_data.fee <= ext_data.withdraw_amount,
+			Error::<T>::FeeExceedsWithdrawal
+		);
+		if let Some(currency_id) = transaction.currency_id {
+			let tvl = Self::total_value_locked(transaction.pool_id, currency_id);
+			ensure!(
+				ext_data.withdraw_amount <= tvl.saturating_add(ext_data.deposit_amount),
+				Error::<T>::InsufficientPoolBalance
+			);
+		}
+
+		let recipient = public_input_to_field(&ext_data.recipient.encode());
+		let relayer = public_input_to_field(&ext_data.relayer.encode());
+		let public_inputs = Self::public_inputs(transaction)?;
+		let nullifier = transaction.input_nullifiers[0].clone();
+		let proof_bytes = transaction.proof_bytes.clone();
+		let root = transaction.cached_root.clone();
+		if root == <MerklePallet<T>>::get_merkle_root(transaction.pool_id)? {
+			T::Tree::verify_zk_at_root(
+				transaction.pool_id,
+				root,
+				public_inputs,
+				nullifier,
+				proof_bytes,
+				Vec::new(),
+				Vec::new(),
+				recipient,
+				relayer,
+			)
+		} else {
+			T::Tree::verify_zk(
+				transaction.pool_id,
+				transaction.cached_block,
+				root,
+				public_inputs,
+				nullifier,
+				proof_bytes,
+				Vec::new(),
+				Vec::new(),
+				recipient,
+				relayer,
+			)
+		}
+	}
+}
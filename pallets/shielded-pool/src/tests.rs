@@ -0,0 +1,605 @@
+use super::*;
+use crate::mock::{
+	new_test_ext, AccountId, Balance, Balances, CurrencyId, MerkleTrees, Origin, PoolSetup, PoolTreeDepth,
+	ShieldedPool, System, Test, Tokens,
+};
+use ark_ff::{PrimeField, UniformRand};
+use ark_groth16::{create_random_proof, generate_random_parameters, ProvingKey};
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem, ConstraintSystemRef, SynthesisError};
+use ark_serialize::CanonicalSerialize;
+use arkworks_gadgets::{
+	ark_std::{rand::Rng, test_rng},
+	poseidon::PoseidonParameters,
+	prelude::{
+		ark_bn254::{Bn254, Fr as Bn254Fr},
+		ark_ff::to_bytes,
+	},
+};
+use frame_support::{assert_err, assert_ok};
+use merkle::utils::{
+	setup::Curve,
+	transaction::{poseidon_params, InputNote, Note, TransactionCircuit, TransactionInputs},
+	tree::LocalTree,
+};
+use sp_runtime::traits::BadOrigin;
+use webb_tokens::ExtendedTokenSystem;
+
+/// Circuit with the public inputs of the mixer circuit only
+struct MixerInputs;
+
+impl ConstraintSynthesizer<Bn254Fr> for MixerInputs {
+	fn generate_constraints(self, cs: ConstraintSystemRef<Bn254Fr>) -> Result<(), SynthesisError> {
+		for _ in 0..4 {
+			cs.new_input_variable(|| Ok(Bn254Fr::from(0u64)))?;
+		}
+		Ok(())
+	}
+}
+
+fn field(bytes: &[u8]) -> Bn254Fr {
+	Bn254Fr::from_le_bytes_mod_order(bytes)
+}
+
+fn field_bytes(x: Bn254Fr) -> ScalarBytes {
+	to_bytes![x].unwrap()
+}
+
+fn params() -> (PoseidonParameters<Bn254Fr>, PoseidonParameters<Bn254Fr>) {
+	poseidon_params(&Curve::Bn254).unwrap()
+}
+
+fn depth() -> usize {
+	PoolTreeDepth::get() as usize
+}
+
+fn currency(currency_id: CurrencyId) -> Bn254Fr {
+	field(&ShieldedPool::asset_input(Some(currency_id)).unwrap())
+}
+
+fn proving_key() -> ProvingKey<Bn254> {
+	let circuit = TransactionCircuit::blank(params(), depth());
+	generate_random_parameters::<Bn254, _, _>(circuit, &mut test_rng()).unwrap()
+}
+
+fn verifying_key_bytes(pk: &ProvingKey<Bn254>) -> Vec<u8> {
+	let mut vk_bytes = Vec::new();
+	pk.vk.serialize(&mut vk_bytes).unwrap();
+	vk_bytes
+}
+
+fn create_pool(pk: &ProvingKey<Bn254>, currency_id: Option<CurrencyId>) -> u32 {
+	assert_ok!(ShieldedPool::create_pool(
+		Origin::root(),
+		currency_id,
+		verifying_key_bytes(pk)
+	));
+	*ShieldedPool::pool_ids().last().unwrap()
+}
+
+/// Empty copy of a pool's tree, kept in sync by adding the output notes of
+/// every transaction
+fn local_tree(pk: &ProvingKey<Bn254>) -> LocalTree {
+	LocalTree::new(PoolSetup::get(), PoolTreeDepth::get(), verifying_key_bytes(pk)).unwrap()
+}
+
+fn note<R: Rng>(rng: &mut R, amount: u64) -> Note<Bn254Fr> {
+	Note {
+		amount: Bn254Fr::from(amount),
+		secret: Bn254Fr::rand(rng),
+		nullifier: Bn254Fr::rand(rng),
+	}
+}
+
+/// Spends the note at `leaf_index` of the tree, or a note of zero amount
+/// that isn't in the tree
+fn spend(tree: &LocalTree, note: Note<Bn254Fr>, leaf_index: Option<u32>) -> InputNote<Bn254Fr> {
+	let path = match leaf_index {
+		Some(index) => tree
+			.path(index)
+			.unwrap()
+			.into_iter()
+			.map(|(is_left, node)| (is_left, field(&node)))
+			.collect(),
+		None => vec![(false, Bn254Fr::from(0u64)); depth()],
+	};
+	InputNote { note, path }
+}
+
+/// Unproven transaction spending and creating notes in `currency_id`
+fn transaction(
+	pool_id: u32,
+	currency_id: CurrencyId,
+	inputs: &[InputNote<Bn254Fr>; 2],
+	outputs: &[Note<Bn254Fr>; 2],
+	deposit_amount: u64,
+	withdraw_amount: u64,
+	fee: u64,
+) -> Transaction<Test> {
+	let (leaf_params, _) = params();
+	Transaction {
+		pool_id,
+		currency_id: if deposit_amount > 0 || withdraw_amount > 0 {
+			Some(currency_id)
+		} else {
+			None
+		},
+		cached_block: System::block_number(),
+		cached_root: MerkleTrees::get_merkle_root(pool_id).unwrap(),
+		input_nullifiers: inputs
+			.iter()
+			.map(|input| field_bytes(input.note.nullifier_hash(&leaf_params).unwrap()))
+			.collect(),
+		output_commitments: outputs
+			.iter()
+			.map(|output| field_bytes(output.commitment(&leaf_params, currency(currency_id)).unwrap()))
+			.collect(),
+		ext_data: ExtData {
+			recipient: 3,
+			relayer: 4,
+			deposit_amount,
+			withdraw_amount,
+			fee,
+		},
+		proof_bytes: Vec::new(),
+	}
+}
+
+/// Circuit of the transaction, whose notes are in `currency_id`
+fn circuit(
+	transaction: &Transaction<Test>,
+	currency_id: CurrencyId,
+	inputs: &[InputNote<Bn254Fr>; 2],
+	outputs: &[Note<Bn254Fr>; 2],
+) -> TransactionCircuit<Bn254Fr> {
+	let extra_inputs = ShieldedPool::public_inputs(transaction).unwrap();
+	let public_inputs = TransactionInputs {
+		nullifier_hashes: [
+			field(&transaction.input_nullifiers[0]),
+			field(&transaction.input_nullifiers[1]),
+		],
+		root: field(&transaction.cached_root),
+		recipient: field(&public_input_to_field(&transaction.ext_data.recipient.encode())),
+		relayer: field(&public_input_to_field(&transaction.ext_data.relayer.encode())),
+		currency: field(&extra_inputs[0]),
+		deposit_amount: field(&extra_inputs[1]),
+		withdraw_amount: field(&extra_inputs[2]),
+		ext_data_hash: field(&extra_inputs[3]),
+		output_commitments: [field(&extra_inputs[5]), field(&extra_inputs[6])],
+	};
+	TransactionCircuit::new(
+		params(),
+		depth(),
+		public_inputs,
+		currency(currency_id),
+		inputs.clone(),
+		outputs.clone(),
+	)
+	.unwrap()
+}
+
+fn is_satisfied(circuit: TransactionCircuit<Bn254Fr>) -> bool {
+	let cs = ConstraintSystem::<Bn254Fr>::new_ref();
+	circuit.generate_constraints(cs.clone()).unwrap();
+	cs.is_satisfied().unwrap()
+}
+
+fn prove(
+	pk: &ProvingKey<Bn254>,
+	mut transaction: Transaction<Test>,
+	currency_id: CurrencyId,
+	inputs: &[InputNote<Bn254Fr>; 2],
+	outputs: &[Note<Bn254Fr>; 2],
+) -> Transaction<Test> {
+	let circuit = circuit(&transaction, currency_id, inputs, outputs);
+	let proof = create_random_proof(circuit, pk, &mut test_rng()).unwrap();
+	proof.serialize(&mut transaction.proof_bytes).unwrap();
+	transaction
+}
+
+/// Proves and submits a deposit of `amount` into a new note, and adds the
+/// notes it creates to `tree`
+fn deposit<R: Rng>(
+	rng: &mut R,
+	pk: &ProvingKey<Bn254>,
+	tree: &mut LocalTree,
+	pool_id: u32,
+	currency_id: CurrencyId,
+	amount: u64,
+) -> Note<Bn254Fr> {
+	let deposit = note(rng, amount);
+	let inputs = [spend(tree, note(rng, 0), None), spend(tree, note(rng, 0), None)];
+	let outputs = [deposit.clone(), note(rng, 0)];
+	let tx = transaction(pool_id, currency_id, &inputs, &outputs, amount, 0, 0);
+	let tx = prove(pk, tx, currency_id, &inputs, &outputs);
+	assert_ok!(ShieldedPool::transact(Origin::signed(1), tx.clone()));
+	for commitment in tx.output_commitments {
+		tree.insert(commitment).unwrap();
+	}
+	deposit
+}
+
+#[test]
+fn should_create_pool_with_admin_origin() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let pk = proving_key();
+		assert_err!(ShieldedPool::create_pool(Origin::signed(1), Some(0), vec![]), BadOrigin);
+		assert_err!(
+			ShieldedPool::create_pool(Origin::root(), Some(0), vec![]),
+			Error::<Test>::InvalidVerifyingKey
+		);
+		// a key for a circuit without the transaction's public inputs
+		let mixer_pk = generate_random_parameters::<Bn254, _, _>(MixerInputs, &mut test_rng()).unwrap();
+		assert_err!(
+			ShieldedPool::create_pool(Origin::root(), Some(0), verifying_key_bytes(&mixer_pk)),
+			Error::<Test>::InvalidVerifyingKey
+		);
+
+		let pool_id = create_pool(&pk, Some(0));
+		System::assert_has_event(crate::mock::Event::ShieldedPool(crate::Event::PoolCreated(
+			pool_id,
+			Some(0),
+		)));
+		assert_eq!(ShieldedPool::pools(pool_id), Some(PoolInfo { currency_id: Some(0) }));
+		let tree = MerkleTrees::get_tree(pool_id).unwrap();
+		assert!(tree.initialized);
+		assert_eq!(tree.depth, PoolTreeDepth::get());
+		assert_eq!(tree.setup, <Test as Config>::Setup::get());
+	})
+}
+
+#[test]
+fn should_derive_note_commitments_of_the_circuit() {
+	new_test_ext().execute_with(|| {
+		let mut rng = test_rng();
+		let note = note(&mut rng, 100);
+		let (leaf_params, _) = params();
+		let (commitment, nullifier_hash) =
+			ShieldedPool::note_commitment(100, 1, &field_bytes(note.secret), &field_bytes(note.nullifier)).unwrap();
+		assert_eq!(
+			commitment,
+			field_bytes(note.commitment(&leaf_params, currency(1)).unwrap())
+		);
+		assert_eq!(nullifier_hash, field_bytes(note.nullifier_hash(&leaf_params).unwrap()));
+		// the commitment binds the currency
+		let (other_commitment, _) =
+			ShieldedPool::note_commitment(100, 0, &field_bytes(note.secret), &field_bytes(note.nullifier)).unwrap();
+		assert_ne!(commitment, other_commitment);
+	})
+}
+
+#[test]
+fn should_deposit_transfer_and_withdraw_any_amount() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let mut rng = test_rng();
+		let pk = proving_key();
+		let pool_id = create_pool(&pk, Some(0));
+		let mut tree = local_tree(&pk);
+		let mut spent = Vec::new();
+
+		// deposit 100 into a new note, spending two empty notes
+		let balance_before = Balances::free_balance(1);
+		let hundred = deposit(&mut rng, &pk, &mut tree, pool_id, 0, 100);
+		assert_eq!(Balances::free_balance(1), balance_before - 100);
+		assert_eq!(ShieldedPool::total_value_locked(pool_id, 0), 100);
+		assert_eq!(MerkleTrees::get_tree(pool_id).unwrap().leaf_count, 2);
+		assert_eq!(*tree.root(), MerkleTrees::get_merkle_root(pool_id).unwrap());
+
+		// transfer it into two notes of 60 and 40, without revealing the currency
+		let sixty = note(&mut rng, 60);
+		let inputs = [spend(&tree, hundred, Some(0)), spend(&tree, note(&mut rng, 0), None)];
+		let outputs = [sixty.clone(), note(&mut rng, 40)];
+		let tx = prove(
+			&pk,
+			transaction(pool_id, 0, &inputs, &outputs, 0, 0, 0),
+			0,
+			&inputs,
+			&outputs,
+		);
+		assert_eq!(ShieldedPool::public_inputs(&tx).unwrap()[0], vec![0u8; 32]);
+		assert_ok!(ShieldedPool::transact(Origin::signed(2), tx.clone()));
+		System::assert_last_event(crate::mock::Event::ShieldedPool(crate::Event::Transaction(
+			pool_id, 2, None, 0, 0, 0,
+		)));
+		assert_eq!(ShieldedPool::total_value_locked(pool_id, 0), 100);
+		spent.extend(tx.input_nullifiers);
+		for commitment in tx.output_commitments {
+			tree.insert(commitment).unwrap();
+		}
+
+		// withdraw 25 of the 60 through a relayer, keeping 35 as change
+		let recipient_before = Balances::free_balance(3);
+		let relayer_before = Balances::free_balance(4);
+		let inputs = [spend(&tree, sixty, Some(2)), spend(&tree, note(&mut rng, 0), None)];
+		let outputs = [note(&mut rng, 35), note(&mut rng, 0)];
+		let tx = prove(
+			&pk,
+			transaction(pool_id, 0, &inputs, &outputs, 0, 25, 5),
+			0,
+			&inputs,
+			&outputs,
+		);
+		assert_ok!(ShieldedPool::transact(Origin::signed(4), tx.clone()));
+		System::assert_last_event(crate::mock::Event::ShieldedPool(crate::Event::Transaction(
+			pool_id,
+			4,
+			Some(0),
+			0,
+			25,
+			5,
+		)));
+		assert_eq!(Balances::free_balance(3), recipient_before + 20);
+		assert_eq!(Balances::free_balance(4), relayer_before + 5);
+		assert_eq!(ShieldedPool::total_value_locked(pool_id, 0), 75);
+		assert_eq!(MerkleTrees::get_tree(pool_id).unwrap().leaf_count, 6);
+		spent.extend(tx.input_nullifiers);
+		for nullifier in spent {
+			assert_err!(
+				MerkleTrees::has_used_nullifier(pool_id, nullifier),
+				merkle::Error::<Test>::AlreadyUsedNullifier
+			);
+		}
+	})
+}
+
+#[test]
+fn should_reject_invalid_transactions() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let mut rng = test_rng();
+		let pk = proving_key();
+		let pool_id = create_pool(&pk, Some(0));
+		let tree = local_tree(&pk);
+		let inputs = [
+			spend(&tree, note(&mut rng, 0), None),
+			spend(&tree, note(&mut rng, 0), None),
+		];
+		let outputs = [note(&mut rng, 100), note(&mut rng, 0)];
+
+		let mut tx = transaction(pool_id, 0, &inputs, &outputs, 100, 0, 0);
+		tx.pool_id = 100;
+		assert_err!(
+			ShieldedPool::transact(Origin::signed(1), tx),
+			Error::<Test>::NoPoolForId
+		);
+
+		let mut tx = transaction(pool_id, 0, &inputs, &outputs, 100, 0, 0);
+		tx.output_commitments.pop();
+		assert_err!(
+			ShieldedPool::transact(Origin::signed(1), tx),
+			Error::<Test>::InvalidNoteCount
+		);
+
+		let same_inputs = [inputs[0].clone(), inputs[0].clone()];
+		let tx = transaction(pool_id, 0, &same_inputs, &outputs, 100, 0, 0);
+		assert_err!(
+			ShieldedPool::transact(Origin::signed(1), tx),
+			Error::<Test>::DuplicateNullifier
+		);
+
+		// only deposits and withdrawals name their currency
+		let mut tx = transaction(pool_id, 0, &inputs, &outputs, 100, 0, 0);
+		tx.currency_id = None;
+		assert_err!(
+			ShieldedPool::transact(Origin::signed(1), tx),
+			Error::<Test>::InvalidPublicCurrency
+		);
+		let mut tx = transaction(pool_id, 0, &inputs, &outputs, 0, 0, 0);
+		tx.currency_id = Some(0);
+		assert_err!(
+			ShieldedPool::transact(Origin::signed(1), tx),
+			Error::<Test>::InvalidPublicCurrency
+		);
+
+		let tx = transaction(pool_id, 0, &inputs, &outputs, 100, 10, 20);
+		assert_err!(
+			ShieldedPool::transact(Origin::signed(1), tx),
+			Error::<Test>::FeeExceedsWithdrawal
+		);
+
+		let tx = transaction(pool_id, 0, &inputs, &outputs, 0, 10, 0);
+		assert_err!(
+			ShieldedPool::transact(Origin::signed(1), tx),
+			Error::<Test>::InsufficientPoolBalance
+		);
+
+		let tx = prove(
+			&pk,
+			transaction(pool_id, 0, &inputs, &outputs, 100, 0, 0),
+			0,
+			&inputs,
+			&outputs,
+		);
+		let mut unknown_root = tx.clone();
+		unknown_root.cached_root = field_bytes(Bn254Fr::from(7u64));
+		assert_err!(
+			ShieldedPool::transact(Origin::signed(1), unknown_root),
+			merkle::Error::<Test>::InvalidMerkleRoot
+		);
+
+		// the proof is bound to the public amounts
+		let mut other_amount = tx.clone();
+		other_amount.ext_data.deposit_amount = 50;
+		assert_err!(
+			ShieldedPool::transact(Origin::signed(1), other_amount),
+			merkle::Error::<Test>::ZkVerificationFailed
+		);
+
+		// the proof is bound to the external data
+		let mut other_recipient = tx.clone();
+		other_recipient.ext_data.recipient = 5;
+		assert_err!(
+			ShieldedPool::transact(Origin::signed(1), other_recipient),
+			merkle::Error::<Test>::ZkVerificationFailed
+		);
+
+		// the amounts have to balance
+		let tx_over = transaction(pool_id, 0, &inputs, &outputs, 50, 0, 0);
+		assert!(!is_satisfied(circuit(&tx_over, 0, &inputs, &outputs)));
+
+		// spent nullifiers can't be used again
+		assert_ok!(ShieldedPool::transact(Origin::signed(1), tx.clone()));
+		assert_err!(
+			ShieldedPool::transact(Origin::signed(1), tx),
+			merkle::Error::<Test>::AlreadyUsedNullifier
+		);
+	})
+}
+
+#[test]
+fn should_reject_notes_not_in_the_tree() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let mut rng = test_rng();
+		let pk = proving_key();
+		let pool_id = create_pool(&pk, Some(0));
+		let (leaf_params, _) = params();
+
+		// a note of 100 that was never added to the pool's tree
+		let hundred = note(&mut rng, 100);
+		let leaf = field_bytes(hundred.commitment(&leaf_params, currency(0)).unwrap());
+		let mut forged_tree = local_tree(&pk);
+		forged_tree.insert(leaf.clone()).unwrap();
+		let inputs = [
+			spend(&forged_tree, hundred, Some(0)),
+			spend(&forged_tree, note(&mut rng, 0), None),
+		];
+		let outputs = [note(&mut rng, 60), note(&mut rng, 40)];
+
+		// it can't be spent against the pool's root
+		let tx = transaction(pool_id, 0, &inputs, &outputs, 0, 0, 0);
+		assert!(!is_satisfied(circuit(&tx, 0, &inputs, &outputs)));
+
+		// and a proof against another root is rejected
+		let mut tx = tx;
+		tx.cached_root = forged_tree.root().clone();
+		let tx = prove(&pk, tx, 0, &inputs, &outputs);
+		assert_err!(
+			ShieldedPool::transact(Origin::signed(1), tx.clone()),
+			merkle::Error::<Test>::InvalidMerkleRoot
+		);
+		let mut forged = tx.clone();
+		forged.cached_root = MerkleTrees::get_merkle_root(pool_id).unwrap();
+		assert_err!(
+			ShieldedPool::transact(Origin::signed(1), forged),
+			merkle::Error::<Test>::ZkVerificationFailed
+		);
+
+		// once the note is in the tree the same proof is accepted
+		assert_ok!(<MerkleTrees as TreeTrait<Test>>::add_members(
+			ShieldedPool::account_id(),
+			pool_id,
+			vec![leaf]
+		));
+		assert_eq!(MerkleTrees::get_merkle_root(pool_id).unwrap(), tx.cached_root);
+		assert_ok!(ShieldedPool::transact(Origin::signed(1), tx));
+		assert_eq!(MerkleTrees::get_tree(pool_id).unwrap().leaf_count, 3);
+	})
+}
+
+#[test]
+fn should_share_one_tree_between_currencies_in_multi_asset_pool() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let mut rng = test_rng();
+		let token: CurrencyId = 1;
+		assert_ok!(<Tokens as ExtendedTokenSystem<AccountId, CurrencyId, Balance>>::create(
+			token, 1, 1, 1
+		));
+		assert_ok!(<Tokens as ExtendedTokenSystem<AccountId, CurrencyId, Balance>>::mint(
+			token, 1, 1_000
+		));
+		let pk = proving_key();
+		let single_pool_id = create_pool(&pk, Some(0));
+		let pool_id = create_pool(&pk, None);
+		let mut tree = local_tree(&pk);
+
+		// single-asset pools only accept their currency
+		let inputs = [
+			spend(&tree, note(&mut rng, 0), None),
+			spend(&tree, note(&mut rng, 0), None),
+		];
+		let outputs = [note(&mut rng, 100), note(&mut rng, 0)];
+		let tx = transaction(single_pool_id, token, &inputs, &outputs, 100, 0, 0);
+		assert_err!(
+			ShieldedPool::transact(Origin::signed(1), tx),
+			Error::<Test>::CurrencyNotInPool
+		);
+
+		// deposits of both currencies go into the same tree
+		deposit(&mut rng, &pk, &mut tree, pool_id, 0, 100);
+		let token_note = deposit(&mut rng, &pk, &mut tree, pool_id, token, 300);
+		assert_eq!(MerkleTrees::get_tree(pool_id).unwrap().leaf_count, 4);
+		assert_eq!(ShieldedPool::total_value_locked(pool_id, 0), 100);
+		assert_eq!(ShieldedPool::total_value_locked(pool_id, token), 300);
+		assert_eq!(Tokens::free_balance(token, &1), 700);
+
+		// the proof is bound to the currency
+		let inputs = [spend(&tree, token_note, Some(2)), spend(&tree, note(&mut rng, 0), None)];
+		let outputs = [note(&mut rng, 250), note(&mut rng, 0)];
+		let tx = transaction(pool_id, token, &inputs, &outputs, 0, 50, 0);
+		let mut tx = prove(&pk, tx, token, &inputs, &outputs);
+		tx.currency_id = Some(0);
+		assert_err!(
+			ShieldedPool::transact(Origin::signed(1), tx.clone()),
+			merkle::Error::<Test>::ZkVerificationFailed
+		);
+
+		// withdrawals pay out the proven currency
+		tx.currency_id = Some(token);
+		assert_ok!(ShieldedPool::transact(Origin::signed(1), tx));
+		System::assert_last_event(crate::mock::Event::ShieldedPool(crate::Event::Transaction(
+			pool_id,
+			1,
+			Some(token),
+			0,
+			50,
+			0,
+		)));
+		assert_eq!(Tokens::free_balance(token, &3), 50);
+		assert_eq!(ShieldedPool::total_value_locked(pool_id, token), 250);
+		assert_eq!(ShieldedPool::total_value_locked(pool_id, 0), 100);
+	})
+}
+
+#[test]
+fn should_reject_cross_currency_withdrawal() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let mut rng = test_rng();
+		let token: CurrencyId = 1;
+		assert_ok!(<Tokens as ExtendedTokenSystem<AccountId, CurrencyId, Balance>>::create(
+			token, 1, 1, 1
+		));
+		assert_ok!(<Tokens as ExtendedTokenSystem<AccountId, CurrencyId, Balance>>::mint(
+			token, 1, 1_000
+		));
+		let pk = proving_key();
+		let pool_id = create_pool(&pk, None);
+		let mut tree = local_tree(&pk);
+		deposit(&mut rng, &pk, &mut tree, pool_id, 0, 300);
+		let token_note = deposit(&mut rng, &pk, &mut tree, pool_id, token, 300);
+
+		let inputs = [spend(&tree, token_note, Some(2)), spend(&tree, note(&mut rng, 0), None)];
+		let outputs = [note(&mut rng, 0), note(&mut rng, 0)];
+
+		// a token note can't be withdrawn as the native currency
+		let mut tx = transaction(pool_id, token, &inputs, &outputs, 0, 300, 0);
+		tx.currency_id = Some(0);
+		assert!(!is_satisfied(circuit(&tx, token, &inputs, &outputs)));
+
+		// nor can it be passed off as a native note, which isn't in the tree
+		let tx = transaction(pool_id, 0, &inputs, &outputs, 0, 300, 0);
+		assert!(!is_satisfied(circuit(&tx, 0, &inputs, &outputs)));
+
+		// it is only withdrawn as the token
+		let tx = transaction(pool_id, token, &inputs, &outputs, 0, 300, 0);
+		assert!(is_satisfied(circuit(&tx, token, &inputs, &outputs)));
+		let tx = prove(&pk, tx, token, &inputs, &outputs);
+		assert_ok!(ShieldedPool::transact(Origin::signed(1), tx));
+		assert_eq!(Tokens::free_balance(token, &3), 300);
+		assert_eq!(ShieldedPool::total_value_locked(pool_id, 0), 300);
+		assert_eq!(ShieldedPool::total_value_locked(pool_id, token), 0);
+	})
+}
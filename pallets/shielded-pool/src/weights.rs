@@ -0,0 +1,37 @@
+//! Weights for pallet_shielded_pool
+//!
+//! Estimated from the mixer weights until the pallet is benchmarked: a
+//! transaction verifies one Groth16 proof and inserts two leaves.
+
+#![allow(unused_parens)]
+#![allow(unused_imports)]
+
+use crate::Config;
+use frame_support::{
+	traits::Get,
+	weights::{constants::RocksDbWeight, Weight},
+};
+use sp_std::marker::PhantomData;
+
+/// Weight functions needed for pallet_shielded_pool.
+pub trait WeightInfo {
+	fn create_pool() -> Weight;
+	fn transact() -> Weight;
+}
+
+/// Weight functions for pallet_shielded_pool.
+/// For use in production
+pub struct Weights<T>(PhantomData<T>);
+impl<T: frame_system::Config + Config + merkle::Config> WeightInfo for Weights<T> {
+	fn create_pool() -> Weight {
+		(500_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(3 as Weight))
+			.saturating_add(T::DbWeight::get().writes(8 as Weight))
+	}
+
+	fn transact() -> Weight {
+		(1_120_000_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(12 as Weight))
+			.saturating_add(T::DbWeight::get().writes(9 as Weight))
+	}
+}
@@ -1,5 +1,8 @@
 use codec::FullCodec;
-use sp_runtime::traits::{AtLeast32BitUnsigned, MaybeSerializeDeserialize};
+use sp_runtime::{
+	traits::{AtLeast32BitUnsigned, MaybeSerializeDeserialize},
+	DispatchResult,
+};
 use sp_std::fmt::Debug;
 
 /// Hooks to manage reward pool
@@ -10,6 +13,6 @@ pub trait RewardHandler<AccountId> {
 	/// The reward pool ID type
 	type PoolId: FullCodec;
 
-	/// Payout the reward to `who`
-	fn payout(who: &AccountId, pool: &Self::PoolId, amount: Self::Balance);
+	/// Payout the reward to `who`, failing if the reward pool can't cover it
+	fn payout(who: &AccountId, pool: &Self::PoolId, amount: Self::Balance) -> DispatchResult;
 }
@@ -28,25 +28,13 @@ use bulletproofs_gadgets::poseidon::{
 };
 use evm::{executor::PrecompileOutput, Context, ExitError, ExitSucceed};
 use fp_evm::Precompile;
-use frame_support::{
-	traits::{MaxEncodedLen, Randomness},
-	Parameter,
-};
 use lazy_static::lazy_static;
-use rand_chacha::{rand_core::SeedableRng, ChaChaRng};
-use sp_runtime::traits::{
-	AtLeast32BitUnsigned, Bounded, CheckEqual, MaybeDisplay, MaybeMallocSizeOf, MaybeSerializeDeserialize, Member,
-	SimpleBitOps,
-};
-use sp_std::{fmt::Debug, marker::PhantomData};
-
-pub struct BulletproofMerkleTreeMembershipPrecompile<O, B, R: Randomness<O, B>>(
-	PhantomData<O>,
-	PhantomData<B>,
-	PhantomData<R>,
-);
 
-use codec::Input;
+/// Precompile verifying bulletproofs zero-knowledge membership proofs.
+///
+/// Verification randomness is derived from the proof itself, so the outcome
+/// doesn't depend on the block it is executed in.
+pub struct BulletproofMerkleTreeMembershipPrecompile;
 
 mod encoding;
 mod types;
@@ -65,60 +53,23 @@ pub fn default_bulletproofs_poseidon_hasher() -> Poseidon {
 		.build()
 }
 
-impl<O, B, R: Randomness<O, B>> Precompile for BulletproofMerkleTreeMembershipPrecompile<O, B, R>
-where
-	O: Parameter
-		+ Member
-		+ MaybeSerializeDeserialize
-		+ Debug
-		+ MaybeDisplay
-		+ SimpleBitOps
-		+ Ord
-		+ Default
-		+ Copy
-		+ CheckEqual
-		+ sp_std::hash::Hash
-		+ AsRef<[u8]>
-		+ AsMut<[u8]>
-		+ MaybeMallocSizeOf
-		+ MaxEncodedLen,
-	B: Parameter
-		+ Member
-		+ MaybeSerializeDeserialize
-		+ Debug
-		+ MaybeDisplay
-		+ AtLeast32BitUnsigned
-		+ Default
-		+ Bounded
-		+ Copy
-		+ sp_std::hash::Hash
-		+ sp_std::str::FromStr
-		+ MaybeMallocSizeOf
-		+ MaxEncodedLen,
-{
+impl Precompile for BulletproofMerkleTreeMembershipPrecompile {
 	fn execute(
 		input: &[u8],
 		_target_gas: Option<u64>,
 		_context: &Context,
 	) -> core::result::Result<PrecompileOutput, ExitError> {
-		let mut seed = [0u8; 32];
-		seed.copy_from_slice(&input[0..32]);
-
+		if input.len() < 32 {
+			return Err(ExitError::Other("Input is too short".into()));
+		}
+		// The first 32 bytes are reserved, they used to carry a seed
 		let withdraw_proof_res = WithdrawProof::decode(&mut input[32..].to_vec());
 		let withdraw_proof = match withdraw_proof_res {
 			Ok(wp) => wp,
 			Err(_) => return Err(ExitError::Other("Failed to decode withdraw proof".into())),
 		};
-		let random_seed = R::random_seed();
-		let random_bytes = random_seed.0.encode();
-		let mut buf = [0u8; 32];
-		for (buff_element, data) in buf.iter_mut().zip(random_bytes.iter()) {
-			*buff_element = *data
-		}
-		println!("{:?}", buf);
-		let mut rng = ChaChaRng::from_seed(buf);
+		let mut rng = withdraw_proof.verifier_rng();
 		let verify_res = withdraw_proof.verify(&POSEIDON_HASHER, &mut rng);
-		println!("{:?}", verify_res);
 		if verify_res.is_err() {
 			return Err(verify_res.err().unwrap());
 		}
@@ -140,16 +91,8 @@ mod test {
 		types::{test::generate_proof_data, WithdrawProof},
 	};
 	use rand_chacha::{rand_core::SeedableRng, ChaChaRng};
-	use sp_core::{uint::U256, H160, H256};
-
-	struct Rng;
-	impl Randomness<H256, u64> for Rng {
-		fn random(_: &[u8]) -> (H256, u64) {
-			(H256::from([1u8; 32]), 12)
-		}
-	}
 
-	type TestPrecompile = BulletproofMerkleTreeMembershipPrecompile<H256, u64, Rng>;
+	type TestPrecompile = BulletproofMerkleTreeMembershipPrecompile;
 
 	// TODO: Not passing, needs investigation.
 	// #[test]
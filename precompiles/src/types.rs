@@ -10,8 +10,15 @@ use bulletproofs_gadgets::{
 };
 use curve25519_dalek::{ristretto::CompressedRistretto, scalar::Scalar};
 use evm::ExitError;
+use merkle_client::{
+	keys::public_input_to_field,
+	setup::{verifier_rng, ZK_MEMBERSHIP_PROOF_LABEL},
+};
 use merlin::Transcript;
-use rand_chacha::rand_core::{CryptoRng, RngCore};
+use rand_chacha::{
+	rand_core::{CryptoRng, RngCore},
+	ChaChaRng,
+};
 use sp_std::prelude::Vec;
 
 #[derive(Debug)]
@@ -22,15 +29,44 @@ pub struct WithdrawProof {
 	pub index_private_inputs: Vec<CompressedRistretto>,
 	pub root: Scalar,
 	pub nullifier_hash: Scalar,
-	pub recipient: Scalar,
-	pub relayer: Scalar,
+	/// Recipient word of the call, mapped to the field by
+	/// [`WithdrawProof::public_input`] when verifying.
+	pub recipient: [u8; 32],
+	/// Relayer word of the call, mapped like the recipient.
+	pub relayer: [u8; 32],
 	pub proof: R1CSProof,
 }
 
 impl WithdrawProof {
+	/// Field element bound into the proof for a recipient or relayer word,
+	/// the same mapping the mixer pallet applies to accounts.
+	pub fn public_input(word: &[u8; 32]) -> Scalar {
+		let mut bytes = [0u8; 32];
+		bytes.copy_from_slice(&public_input_to_field(word));
+		Scalar::from_bytes_mod_order(bytes)
+	}
+
+	/// Builds the verification RNG from the proof contents with the merkle
+	/// pallet's [`verifier_rng`], so both agree on every proof.
+	pub fn verifier_rng(&self) -> ChaChaRng {
+		let recipient = Self::public_input(&self.recipient);
+		let relayer = Self::public_input(&self.relayer);
+		let proof = self.proof.to_bytes();
+		let mut inputs: Vec<&[u8]> = vec![
+			self.root.as_bytes(),
+			self.nullifier_hash.as_bytes(),
+			recipient.as_bytes(),
+			relayer.as_bytes(),
+		];
+		inputs.extend(self.private_inputs.iter().map(|c| &c.as_bytes()[..]));
+		inputs.extend(self.index_private_inputs.iter().map(|c| &c.as_bytes()[..]));
+		inputs.extend(self.node_private_inputs.iter().map(|c| &c.as_bytes()[..]));
+		inputs.push(&proof);
+		verifier_rng(&inputs)
+	}
+
 	pub fn verify<T: RngCore + CryptoRng>(&self, hasher: &Poseidon, rng: &mut T) -> Result<(), ExitError> {
-		let label = b"zk_membership_proof";
-		let mut verifier_transcript = Transcript::new(label);
+		let mut verifier_transcript = Transcript::new(ZK_MEMBERSHIP_PROOF_LABEL);
 		let mut verifier = Verifier::new(&mut verifier_transcript);
 
 		if self.private_inputs.len() != 3 {
@@ -75,8 +111,8 @@ impl WithdrawProof {
 		let statics = allocate_statics_for_verifier(&mut verifier, num_statics, &hasher.pc_gens);
 		let gadget_res = mixer_verif_gadget(
 			&mut verifier,
-			&self.recipient,
-			&self.relayer,
+			&Self::public_input(&self.recipient),
+			&Self::public_input(&self.relayer),
 			self.depth as usize,
 			&self.root,
 			&self.nullifier_hash,
@@ -93,7 +129,6 @@ impl WithdrawProof {
 		}
 
 		let verify_res = verifier.verify_with_rng(&self.proof, &hasher.pc_gens, &hasher.bp_gens, rng);
-		println!("{:?}", verify_res);
 		if !verify_res.is_ok() {
 			return Err(ExitError::Other("ZkVerificationFailed".into()));
 		}
@@ -125,8 +160,8 @@ impl Decode for WithdrawProof {
 		// public inputs
 		let root_bytes = <[u8; 32] as Decode>::decode(input)?;
 		let nullifier_hash_bytes = <[u8; 32] as Decode>::decode(input)?;
-		let recipient_bytes = <[u8; 32] as Decode>::decode(input)?;
-		let relayer_bytes = <[u8; 32] as Decode>::decode(input)?;
+		let recipient = <[u8; 32] as Decode>::decode(input)?;
+		let relayer = <[u8; 32] as Decode>::decode(input)?;
 
 		// Taking the rest of bytes for R1CSProof
 		let proof_vec = <Vec<u8>>::decode(input)?;
@@ -145,8 +180,6 @@ impl Decode for WithdrawProof {
 		// public inputs
 		let root = Scalar::from_bytes_mod_order(root_bytes);
 		let nullifier_hash = Scalar::from_bytes_mod_order(nullifier_hash_bytes);
-		let recipient = Scalar::from_bytes_mod_order(recipient_bytes);
-		let relayer = Scalar::from_bytes_mod_order(relayer_bytes);
 
 		// proof
 		let proof = R1CSProof::from_bytes(&proof_vec).map_err(|_| "Invalid proof bytes")?;
@@ -192,8 +225,8 @@ impl Encode for WithdrawProof {
 		// public inputs
 		encoded.extend(&self.root.to_bytes());
 		encoded.extend(&self.nullifier_hash.to_bytes());
-		encoded.extend(&self.recipient.to_bytes());
-		encoded.extend(&self.relayer.to_bytes());
+		encoded.extend(&self.recipient);
+		encoded.extend(&self.relayer);
 		//proof
 		encoded.extend(&self.proof.to_bytes());
 		encoded
@@ -220,15 +253,17 @@ pub mod test {
 		Vec<CompressedRistretto>,
 		Vec<CompressedRistretto>,
 		Scalar,
-		Scalar,
-		Scalar,
+		[u8; 32],
+		[u8; 32],
 		Scalar,
 		R1CSProof,
 		Poseidon,
 	) {
 		let hasher = default_bulletproofs_poseidon_hasher();
-		let recipient = Scalar::random(test_rng);
-		let relayer = Scalar::random(test_rng);
+		let mut recipient = [0u8; 32];
+		test_rng.fill_bytes(&mut recipient);
+		let mut relayer = [0u8; 32];
+		test_rng.fill_bytes(&mut relayer);
 		let r = Scalar::random(test_rng);
 		let nullifier = Scalar::random(test_rng);
 		let expected_output = Poseidon_hash_2(r, nullifier, &hasher);
@@ -314,8 +349,8 @@ pub mod test {
 
 		assert!(mixer_verif_gadget(
 			&mut prover,
-			&recipient,
-			&relayer,
+			&WithdrawProof::public_input(&recipient),
+			&WithdrawProof::public_input(&relayer),
 			tree.depth,
 			&tree.root,
 			&nullifier_hash,
@@ -410,6 +445,39 @@ pub mod test {
 		assert!(verify_res.is_err());
 	}
 
+	#[test]
+	fn should_not_verify_other_recipient() {
+		let mut test_rng = ChaChaRng::from_seed([1u8; 32]);
+		let (
+			tree_depth,
+			comms,
+			leaf_index_comms,
+			proof_comms,
+			nullifier_hash,
+			mut recipient,
+			relayer,
+			root,
+			proof,
+			poseidon,
+		) = generate_proof_data(&mut test_rng);
+		// the proof was made for another recipient
+		recipient[31] ^= 0x80;
+		let withdraw_proof = WithdrawProof {
+			depth: tree_depth,
+			private_inputs: comms,
+			index_private_inputs: leaf_index_comms,
+			node_private_inputs: proof_comms,
+			nullifier_hash,
+			recipient,
+			relayer,
+			root,
+			proof,
+		};
+
+		let mut rng = withdraw_proof.verifier_rng();
+		assert!(withdraw_proof.verify(&poseidon, &mut rng).is_err());
+	}
+
 	#[test]
 	fn should_not_decode_invalid_proof() {
 		let mut test_rng = ChaChaRng::from_seed([1u8; 32]);
@@ -433,4 +501,64 @@ pub mod test {
 		let decoded_wp = WithdrawProof::decode(&mut encoded_wp);
 		assert!(decoded_wp.is_err());
 	}
+
+	#[test]
+	fn should_verify_independently_of_rng() {
+		let mut test_rng = ChaChaRng::from_seed([1u8; 32]);
+		let (
+			tree_depth,
+			comms,
+			leaf_index_comms,
+			proof_comms,
+			nullifier_hash,
+			recipient,
+			relayer,
+			root,
+			proof,
+			poseidon,
+		) = generate_proof_data(&mut test_rng);
+		let withdraw_proof = WithdrawProof {
+			depth: tree_depth,
+			private_inputs: comms.clone(),
+			index_private_inputs: leaf_index_comms.clone(),
+			node_private_inputs: proof_comms.clone(),
+			nullifier_hash,
+			recipient,
+			relayer,
+			root,
+			proof: proof.clone(),
+		};
+
+		// The derived RNG is stable for the same proof
+		let mut first = withdraw_proof.verifier_rng();
+		let mut second = withdraw_proof.verifier_rng();
+		assert_eq!(first.next_u64(), second.next_u64());
+
+		assert!(withdraw_proof
+			.verify(&poseidon, &mut withdraw_proof.verifier_rng())
+			.is_ok());
+		for seed in [[2u8; 32], [3u8; 32], [42u8; 32]].iter() {
+			let mut rng = ChaChaRng::from_seed(*seed);
+			assert!(withdraw_proof.verify(&poseidon, &mut rng).is_ok());
+		}
+
+		let invalid_proof = WithdrawProof {
+			depth: tree_depth,
+			private_inputs: comms,
+			index_private_inputs: leaf_index_comms,
+			node_private_inputs: proof_comms,
+			nullifier_hash: Scalar::random(&mut test_rng),
+			recipient,
+			relayer,
+			root,
+			proof,
+		};
+		assert!(invalid_proof
+			.verify(&poseidon, &mut invalid_proof.verifier_rng())
+			.is_err());
+		for seed in [[2u8; 32], [3u8; 32], [42u8; 32]].iter() {
+			let mut rng = ChaChaRng::from_seed(*seed);
+			assert!(invalid_proof.verify(&poseidon, &mut rng).is_err());
+		}
+	}
 }
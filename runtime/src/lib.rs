@@ -13,16 +13,13 @@ pub use pallet_transaction_payment::{CurrencyAdapter, Multiplier, TargetedFeeAdj
 use pallet_transaction_payment::{FeeDetails, RuntimeDispatchInfo};
 use sp_api::impl_runtime_apis;
 use sp_consensus_aura::sr25519::AuthorityId as AuraId;
-use sp_core::{
-	crypto::{AccountId32, KeyTypeId},
-	OpaqueMetadata, H160, H256, U256,
-};
+use sp_core::{crypto::KeyTypeId, OpaqueMetadata, H160, H256, U256};
 use sp_inherents::{CheckInherentsResult, InherentData};
 use sp_runtime::{
 	create_runtime_str, generic, impl_opaque_keys,
-	traits::{BlakeTwo256, Block as BlockT, IdentifyAccount, IdentityLookup, NumberFor, Verify},
+	traits::{AccountIdConversion, BlakeTwo256, Block as BlockT, IdentifyAccount, IdentityLookup, NumberFor, Verify},
 	transaction_validity::{TransactionSource, TransactionValidity},
-	ApplyExtrinsicResult, FixedPointNumber, MultiSignature, Perbill, Perquintill,
+	ApplyExtrinsicResult, FixedPointNumber, MultiSignature, Perbill, Permill, Perquintill,
 };
 use sp_std::prelude::*;
 #[cfg(feature = "std")]
@@ -75,6 +72,9 @@ pub mod currency {
 
 use currency::*;
 
+/// Importing a mixers pallet for `pallet_assets` assets
+pub use assets_mixer;
+use assets_mixer::weights::Weights as AssetsMixerWeights;
 /// Importing a merkle trees pallet
 pub use merkle;
 use merkle::weights::Weights as MerkleWeights;
@@ -143,10 +143,10 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	spec_name: create_runtime_str!("webb-node"),
 	impl_name: create_runtime_str!("webb-node"),
 	authoring_version: 1,
-	spec_version: 1,
+	spec_version: 4,
 	impl_version: 1,
 	apis: RUNTIME_API_VERSIONS,
-	transaction_version: 1,
+	transaction_version: 2,
 };
 
 pub const MILLISECS_PER_BLOCK: u64 = 6000;
@@ -346,17 +346,44 @@ impl pallet_sudo::Config for Runtime {
 	type Event = Event;
 }
 
+parameter_types! {
+	pub const TreasuryPalletId: PalletId = PalletId(*b"py/trsry");
+	pub const ProposalBond: Permill = Permill::from_percent(5);
+	pub const ProposalBondMinimum: Balance = DOLLARS;
+	pub const SpendPeriod: BlockNumber = DAYS;
+	pub const Burn: Permill = Permill::from_percent(0);
+	pub const MaxApprovals: u32 = 100;
+}
+
+impl pallet_treasury::Config for Runtime {
+	type ApproveOrigin = frame_system::EnsureRoot<AccountId>;
+	type Burn = Burn;
+	type BurnDestination = ();
+	type Currency = Balances;
+	type Event = Event;
+	type MaxApprovals = MaxApprovals;
+	type OnSlash = Treasury;
+	type PalletId = TreasuryPalletId;
+	type ProposalBond = ProposalBond;
+	type ProposalBondMinimum = ProposalBondMinimum;
+	type RejectOrigin = frame_system::EnsureRoot<AccountId>;
+	type SpendFunds = ();
+	type SpendPeriod = SpendPeriod;
+	type WeightInfo = pallet_treasury::weights::SubstrateWeight<Runtime>;
+}
+
 parameter_types! {
 	pub const MaxTreeDepth: u8 = 32;
 	pub const CacheBlockLength: BlockNumber = 100;
+	pub const MaxPrunedRoots: u32 = 256;
 }
 
 impl merkle::Config for Runtime {
 	type CacheBlockLength = CacheBlockLength;
 	type Event = Event;
 	type KeyId = u32;
+	type MaxPrunedRoots = MaxPrunedRoots;
 	type MaxTreeDepth = MaxTreeDepth;
-	type Randomness = RandomnessCollectiveFlip;
 	type TreeId = u32;
 	type WeightInfo = MerkleWeights<Self>;
 }
@@ -400,27 +427,122 @@ impl webb_currencies::Config for Runtime {
 parameter_types! {
 	pub const MixerPalletId: PalletId = PalletId(*b"py/mixer");
 	pub const MinimumDepositLength: BlockNumber = 10 * 60 * 24 * 28;
-	pub const DefaultAdminKey: AccountId32 = AccountId32::new([0; 32]);
 	pub MixerSizes: Vec<Balance> = [
 		DOLLARS * 1_000,
 		DOLLARS * 10_000,
 		DOLLARS * 100_000,
 		DOLLARS * 1_000_000
 	].to_vec();
+	pub const DepositReward: Balance = DOLLARS;
+	pub const MixerRewardsPalletId: PalletId = PalletId(*b"py/mxrwd");
+	pub const MixerTreeDepth: u8 = 32;
+	pub const MixerActivityWindow: BlockNumber = DAYS;
+	pub const MixerMaxMemoLength: u32 = 256;
+	pub MixerTreasury: AccountId = Treasury::account_id();
+	pub const MixerMaxPendingPayouts: u32 = 50;
+	pub const MixerMaxPayoutPostponement: u32 = 100;
+	pub const MixerMinUnsignedFee: Permill = Permill::from_percent(1);
+	pub const MixerDefaultMaxFee: Permill = Permill::from_percent(5);
+}
+
+/// Pays the mixer deposit rewards in the native currency out of the reward
+/// pot, which has to be funded, e.g. by governance, for claims to succeed
+pub struct MixerRewards;
+impl MixerRewards {
+	/// Account of the reward pot
+	pub fn account_id() -> AccountId {
+		MixerRewardsPalletId::get().into_account()
+	}
+}
+impl webb_traits::RewardHandler<AccountId> for MixerRewards {
+	type Balance = Balance;
+	type PoolId = u32;
+
+	fn payout(who: &AccountId, _pool: &Self::PoolId, amount: Self::Balance) -> sp_runtime::DispatchResult {
+		<Balances as Currency<AccountId>>::transfer(
+			&Self::account_id(),
+			who,
+			amount,
+			frame_support::traits::ExistenceRequirement::AllowDeath,
+		)
+	}
+}
+
+/// Pays mixer withdrawals to EVM addresses into their EVM balance
+pub struct MixerEvmAddressMapping;
+impl sp_runtime::traits::Convert<H160, AccountId> for MixerEvmAddressMapping {
+	fn convert(address: H160) -> AccountId {
+		<HashedAddressMapping<BlakeTwo256> as pallet_evm::AddressMapping<AccountId>>::into_account_id(address)
+	}
 }
 
 impl mixer::Config for Runtime {
+	type ActivityWindow = MixerActivityWindow;
+	type AdminOrigin = frame_system::EnsureRoot<AccountId>;
 	type Currency = Currencies;
-	type DefaultAdmin = DefaultAdminKey;
+	type DefaultMaxFee = MixerDefaultMaxFee;
+	type DefaultSetup = mixer::BulletproofsSetup;
+	type DefaultVerifyingKey = mixer::BulletproofsVerifyingKey;
 	type DepositLength = MinimumDepositLength;
+	type DepositReward = DepositReward;
 	type Event = Event;
+	type EvmAddressMapping = MixerEvmAddressMapping;
+	type MaxMemoLength = MixerMaxMemoLength;
+	type MaxPayoutPostponement = MixerMaxPayoutPostponement;
+	type MaxPendingPayouts = MixerMaxPendingPayouts;
+	type MinUnsignedFee = MixerMinUnsignedFee;
 	type MixerSizes = MixerSizes;
 	type NativeCurrencyId = NativeCurrencyId;
 	type PalletId = MixerPalletId;
+	type RewardHandler = MixerRewards;
+	type Treasury = MixerTreasury;
 	type Tree = Merkle;
+	type TreeDepth = MixerTreeDepth;
 	type WeightInfo = MixerWeights<Self>;
 }
 
+parameter_types! {
+	pub const AssetDeposit: Balance = 100 * DOLLARS;
+	pub const AssetsApprovalDeposit: Balance = DOLLARS;
+	pub const AssetsMetadataDepositBase: Balance = 10 * DOLLARS;
+	pub const AssetsMetadataDepositPerByte: Balance = DOLLARS;
+}
+
+impl pallet_assets::Config for Runtime {
+	type ApprovalDeposit = AssetsApprovalDeposit;
+	type AssetDeposit = AssetDeposit;
+	type AssetId = u32;
+	type Balance = Balance;
+	type Currency = Balances;
+	type Event = Event;
+	type Extra = ();
+	type ForceOrigin = frame_system::EnsureRoot<AccountId>;
+	type Freezer = ();
+	type MetadataDepositBase = AssetsMetadataDepositBase;
+	type MetadataDepositPerByte = AssetsMetadataDepositPerByte;
+	type StringLimit = StringLimit;
+	type WeightInfo = pallet_assets::weights::SubstrateWeight<Runtime>;
+}
+
+parameter_types! {
+	pub const AssetsMixerPalletId: PalletId = PalletId(*b"py/amixr");
+	pub const AssetsMixerDefaultCurrencyId: u32 = 0;
+}
+
+impl assets_mixer::Config for Runtime {
+	type AdminOrigin = frame_system::EnsureRoot<AccountId>;
+	type AssetSystem = Assets;
+	type Currency = Balances;
+	type DefaultCurrencyId = AssetsMixerDefaultCurrencyId;
+	type DepositLength = MinimumDepositLength;
+	type Event = Event;
+	type MixerSizes = MixerSizes;
+	type PalletId = AssetsMixerPalletId;
+	type Treasury = MixerTreasury;
+	type Tree = Merkle;
+	type WeightInfo = AssetsMixerWeights<Self>;
+}
+
 /// Current approximation of the gas/s consumption considering
 /// EVM execution over compiled WASM (on 4.4Ghz CPU).
 /// Given the 500ms Weight, from which 75% only are used for transactions,
@@ -526,8 +648,11 @@ construct_runtime!(
 
 		Currencies: webb_currencies::{Pallet, Storage, Event<T>},
 		Tokens: webb_tokens::{Pallet, Storage, Event<T>},
-		Mixer: mixer::{Pallet, Call, Storage, Event<T>},
+		Mixer: mixer::{Pallet, Call, Storage, Event<T>, Config<T>, ValidateUnsigned},
 		Merkle: merkle::{Pallet, Call, Storage, Event<T>},
+		Treasury: pallet_treasury::{Pallet, Call, Storage, Config, Event<T>},
+		Assets: pallet_assets::{Pallet, Call, Storage, Event<T>},
+		AssetsMixer: assets_mixer::{Pallet, Call, Storage, Event<T>},
 	}
 );
 
@@ -565,14 +690,78 @@ pub type SignedExtra = (
 	frame_system::CheckNonce<Runtime>,
 	frame_system::CheckWeight<Runtime>,
 	pallet_transaction_payment::ChargeTransactionPayment<Runtime>,
+	mixer::CheckNullifier<Runtime>,
 );
 /// Unchecked extrinsic type as expected by this runtime.
 pub type UncheckedExtrinsic = generic::UncheckedExtrinsic<Address, Call, Signature, SignedExtra>;
 /// Extrinsic type that has already been checked.
 pub type CheckedExtrinsic = generic::CheckedExtrinsic<AccountId, Call, SignedExtra>;
+/// Brings up the default mixers on chains started before they were built at
+/// genesis
+pub struct MixerStagedInitialization;
+impl frame_support::traits::OnRuntimeUpgrade for MixerStagedInitialization {
+	fn on_runtime_upgrade() -> Weight {
+		mixer::migrations::initialize_staged::<Runtime>()
+	}
+}
+
+/// Removes the mixer `Admin` account, replaced by the root origin
+pub struct MixerRemoveAdmin;
+impl frame_support::traits::OnRuntimeUpgrade for MixerRemoveAdmin {
+	fn on_runtime_upgrade() -> Weight {
+		mixer::migrations::remove_admin::<Runtime>()
+	}
+}
+
+/// Counts the deposits made into the mixers before the counters existed
+pub struct MixerDepositCounts;
+impl frame_support::traits::OnRuntimeUpgrade for MixerDepositCounts {
+	fn on_runtime_upgrade() -> Weight {
+		mixer::migrations::initialize_deposit_counts::<Runtime>()
+	}
+}
+
+/// Sets the max fees of the mixers created before they were set at creation
+pub struct MixerMaxFees;
+impl frame_support::traits::OnRuntimeUpgrade for MixerMaxFees {
+	fn on_runtime_upgrade() -> Weight {
+		mixer::migrations::initialize_max_fees::<Runtime>()
+	}
+}
+
+/// Indexes the merkle roots cached before the index existed
+pub struct MerkleIndexCachedRoots;
+impl frame_support::traits::OnRuntimeUpgrade for MerkleIndexCachedRoots {
+	fn on_runtime_upgrade() -> Weight {
+		merkle::migrations::index_cached_roots::<Runtime>()
+	}
+}
+
+/// Removes the assets mixer `Admin` account, replaced by the root origin
+pub struct AssetsMixerRemoveAdmin;
+impl frame_support::traits::OnRuntimeUpgrade for AssetsMixerRemoveAdmin {
+	fn on_runtime_upgrade() -> Weight {
+		assets_mixer::migrations::remove_admin::<Runtime>()
+	}
+}
+
 /// Executive: handles dispatch to the various modules.
-pub type Executive =
-	frame_executive::Executive<Runtime, Block, frame_system::ChainContext<Runtime>, Runtime, AllPallets>;
+pub type Executive = frame_executive::Executive<
+	Runtime,
+	Block,
+	frame_system::ChainContext<Runtime>,
+	Runtime,
+	AllPallets,
+	// In this order, each runs once as it moves its pallet's storage version on
+	(
+		MixerStagedInitialization,
+		MixerRemoveAdmin,
+		MixerDepositCounts,
+		MixerMaxFees,
+		MerkleIndexCachedRoots,
+		AssetsMixerRemoveAdmin,
+	),
+>;
 
 impl_runtime_apis! {
 	impl sp_api::Core<Block> for Runtime {
@@ -854,7 +1043,7 @@ impl_runtime_apis! {
 		}
 	}
 
-	impl merkle::MerkleApi<Block> for Runtime {
+	impl merkle::MerkleApi<Block, BlockNumber> for Runtime {
 		fn get_leaf(tree_id: u32, index: u32) -> Option<ScalarBytes> {
 			let v = Merkle::leaves(tree_id, index);
 			if v == ScalarBytes::default() {
@@ -863,6 +1052,79 @@ impl_runtime_apis! {
 				Some(v)
 			}
 		}
+
+		fn verify_path(
+			tree_id: u32,
+			leaf: ScalarBytes,
+			path: Vec<(bool, ScalarBytes)>,
+		) -> Result<(BlockNumber, ScalarBytes), sp_runtime::DispatchError> {
+			<Merkle as merkle::Tree<Runtime>>::verify_with_history(tree_id, leaf, path)
+		}
+
+		fn hash(
+			setup: merkle::utils::setup::Setup,
+			left: ScalarBytes,
+			right: ScalarBytes,
+		) -> Result<ScalarBytes, sp_runtime::DispatchError> {
+			Merkle::hash_nodes(setup, left, right)
+		}
+
+		fn zero_tree(
+			setup: merkle::utils::setup::Setup,
+			depth: u8,
+			key_id: u32,
+		) -> Result<(Vec<ScalarBytes>, ScalarBytes), sp_runtime::DispatchError> {
+			Merkle::zero_tree(setup, depth, key_id)
+		}
+
+		fn commitment(
+			setup: merkle::utils::setup::Setup,
+			secret: ScalarBytes,
+			nullifier: ScalarBytes,
+		) -> Result<(ScalarBytes, ScalarBytes), sp_runtime::DispatchError> {
+			Merkle::commitment(setup, secret, nullifier)
+		}
+	}
+
+	impl mixer::MixerApi<Block, AccountId, mixer::WithdrawProof<Runtime>, CurrencyId, Balance, BlockNumber> for Runtime {
+		fn check_withdraw(
+			sender: AccountId,
+			withdraw_proof: mixer::WithdrawProof<Runtime>,
+		) -> Result<(), sp_runtime::DispatchError> {
+			Mixer::check_withdraw(sender, withdraw_proof)
+		}
+
+		fn mixer_state(mixer_id: u32) -> Result<mixer::MixerState, sp_runtime::DispatchError> {
+			Mixer::mixer_state(mixer_id)
+		}
+
+		fn mixers() -> Vec<mixer::MixerStats<u32, CurrencyId, Balance, BlockNumber>> {
+			Mixer::mixers()
+		}
+
+		fn mixer_stats(mixer_id: u32) -> Result<mixer::MixerStats<u32, CurrencyId, Balance, BlockNumber>, sp_runtime::DispatchError> {
+			Mixer::mixer_stats(mixer_id)
+		}
+
+		fn mixer_activity(mixer_id: u32) -> Result<Vec<mixer::BlockActivity<BlockNumber>>, sp_runtime::DispatchError> {
+			Mixer::mixer_activity(mixer_id)
+		}
+
+		fn pending_withdrawals(
+			mixer_id: u32,
+		) -> Result<Vec<(u32, mixer::PendingWithdrawal<u32, AccountId, Balance, BlockNumber>)>, sp_runtime::DispatchError> {
+			Mixer::pending_withdrawals(mixer_id)
+		}
+	}
+
+	impl assets_mixer::AssetsMixerApi<Block, Balance> for Runtime {
+		fn mixer_state(mixer_id: u32) -> Result<assets_mixer::MixerState, sp_runtime::DispatchError> {
+			AssetsMixer::mixer_state(mixer_id)
+		}
+
+		fn mixer_limits(mixer_id: u32) -> Result<assets_mixer::MixerLimits<Balance>, sp_runtime::DispatchError> {
+			AssetsMixer::mixer_limits(mixer_id)
+		}
 	}
 
 	#[cfg(feature = "runtime-benchmarks")]